@@ -0,0 +1,39 @@
+//! 変更履歴の記録者情報
+//! 履歴には変更したアプリのモード（管理者・作業者）と端末名を残す
+
+/// 変更を行ったアプリ・端末
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub app_mode: String,
+    pub machine_name: String,
+}
+
+impl Actor {
+    /// 実行中のアプリ・端末
+    pub fn current() -> Self {
+        Self {
+            app_mode: app_mode().to_string(),
+            machine_name: machine_name(),
+        }
+    }
+}
+
+/// アプリのモード（admin / worker）
+pub fn app_mode() -> &'static str {
+    if cfg!(feature = "worker-mode") {
+        "worker"
+    } else {
+        "admin"
+    }
+}
+
+/// 端末名（Windowsは COMPUTERNAME、それ以外は HOSTNAME か /etc/hostname）
+pub fn machine_name() -> String {
+    ["COMPUTERNAME", "HOSTNAME"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .chain(std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
@@ -0,0 +1,532 @@
+//! 需給バランス（在庫・出荷予定・生産予定）の計算
+//! 在庫はID354（山積表）、出荷予定はID514（積込予定）の scheduleJson、生産予定はローカルのスケジュールから求める
+//!
+//! 製品の照合ルール
+//! - 全角英数字・空白は半角にそろえ、英字は大文字にそろえて比較する
+//! - 品番・品目に含まれるキーのうち最も長いものを採用する（FS250CE を FS250C と数えない）
+//! - 英数字で終わるキーは、直後に英数字が続く場合は一致とみなさない（FS450K が FS450KX に一致しない）
+//!
+//! 出荷予定のルール
+//! - 積込予定1件は planDate から7日分（values の先頭7列）
+//! - 同じ日を複数の積込予定が含む場合は planDate が新しいものを採用する（古い予定と二重に数えない）
+//! - セルの数値はすべて合計する（「※」や全角空白は区切り、カンマは桁区切り）
+//! - 日本ロックのグループは対象外
+
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+
+use crate::calendar;
+use crate::database::{LocalSchedule, StockLevel};
+use crate::scheduling;
+
+/// 追跡対象の主要製品
+pub const BALANCE_PRODUCTS: &[&str] = &["FS450NR", "FS450K", "FS450S", "FS450D", "FS250C", "FS250CE", "FS360F"];
+
+/// 積込予定の品目 → 在庫品番
+const SHIPPING_PRODUCT_MAP: &[(&str, &str)] = &[
+    ("450NR", "FS450NR"),
+    ("450K", "FS450K"),
+    ("高ダイ", "FS450K"),
+    ("低ショット", "FS450S"),
+    ("大建", "FS450D"),
+    ("FS250CE", "FS250CE"),
+    ("FS250C", "FS250C"),
+    ("FS360F", "FS360F"),
+];
+
+/// 既定の予測日数
+pub const DEFAULT_PROJECTION_DAYS: usize = 14;
+
+/// 積込予定1件に含まれる日数
+const SHIPPING_PLAN_DAYS: usize = 7;
+
+/// 出荷予定から除くグループ
+const EXCLUDED_SHIPPING_GROUP: &str = "日本ロック";
+
+/// ID354（山積表）で在庫に数える山状況
+pub const INVENTORY_STATUSES: &[&str] = &["出荷待ち", "一部出荷済"];
+
+/// 在庫に反映済みとみなす生産状況（生産予定に数えない）
+const FINISHED_STATUSES: &[&str] = &["生産終了", "完了"];
+
+/// 製品・日ごとの需給
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct BalanceDay {
+    pub date: String,
+    /// 日初の在庫
+    pub opening: f64,
+    pub production: f64,
+    pub shipping: f64,
+    /// 日末の在庫予測（opening + production - shipping）
+    pub closing: f64,
+}
+
+/// 製品ごとの需給予測
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ProductBalance {
+    pub product: String,
+    /// 現在庫
+    pub opening_stock: f64,
+    pub days: Vec<BalanceDay>,
+}
+
+/// 全角英数字・記号・空白を半角にそろえ、英字を大文字にする
+fn normalize(text: &str) -> String {
+    text.trim()
+        .chars()
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            _ => c,
+        })
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// text に key が含まれるか（英数字で終わるキーは直後が英数字なら不一致）
+fn contains_key(text: &str, key: &str) -> bool {
+    let key_ends_alnum = key.chars().last().is_some_and(|c| c.is_ascii_alphanumeric());
+    text.match_indices(key).any(|(pos, _)| {
+        let next = text[pos + key.len()..].chars().next();
+        !(key_ends_alnum && next.is_some_and(|c| c.is_ascii_alphanumeric()))
+    })
+}
+
+/// 最も長く一致するキーの値
+fn match_longest<'a>(text: &str, entries: impl Iterator<Item = (&'a str, &'a str)>) -> Option<&'a str> {
+    let text = normalize(text);
+    if text.is_empty() {
+        return None;
+    }
+    entries
+        .filter(|(key, _)| contains_key(&text, &normalize(key)))
+        .max_by_key(|(key, _)| key.chars().count())
+        .map(|(_, value)| value)
+}
+
+/// 在庫・スケジュールの品番から追跡対象の製品を特定
+pub fn match_product(name: &str) -> Option<&'static str> {
+    match_longest(name, BALANCE_PRODUCTS.iter().map(|p| (*p, *p)))
+}
+
+/// 積込予定の品目から追跡対象の製品を特定
+pub fn match_shipping_item(item: &str) -> Option<&'static str> {
+    match_longest(item, SHIPPING_PRODUCT_MAP.iter().copied())
+}
+
+/// 文字列に含まれる数値をすべて取り出す（カンマは桁区切りとして除く）
+fn extract_numbers(text: &str) -> Vec<f64> {
+    let text = normalize(&text.replace('※', " "));
+    let chars: Vec<char> = text.chars().collect();
+    let mut numbers = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+        let negative = i > 0 && chars[i - 1] == '-';
+        let mut digits = String::new();
+        while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == ',') {
+            if chars[i] != ',' {
+                digits.push(chars[i]);
+            }
+            i += 1;
+        }
+        if i + 1 < chars.len() && chars[i] == '.' && chars[i + 1].is_ascii_digit() {
+            digits.push('.');
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                digits.push(chars[i]);
+                i += 1;
+            }
+        }
+        if let Ok(value) = digits.parse::<f64>() {
+            numbers.push(if negative { -value } else { value });
+        }
+    }
+    numbers
+}
+
+/// 積込予定のセルから出荷数量を取り出す
+/// セルは文字列・数値、または左・中央・右に分かれたオブジェクト（{left, center, right}）
+pub fn extract_shipping_total(cell: &serde_json::Value) -> f64 {
+    match cell {
+        serde_json::Value::Number(n) => n.as_f64().unwrap_or(0.0),
+        serde_json::Value::String(s) => extract_numbers(s).iter().sum(),
+        serde_json::Value::Array(values) => values.iter().map(extract_shipping_total).sum(),
+        serde_json::Value::Object(parts) => ["left", "center", "right"]
+            .iter()
+            .filter_map(|key| parts.get(*key))
+            .map(extract_shipping_total)
+            .sum(),
+        _ => 0.0,
+    }
+}
+
+/// kintoneレコードのフィールド値（文字列）
+fn field_text<'a>(record: &'a serde_json::Value, field: &str) -> &'a str {
+    record[field]["value"].as_str().unwrap_or("")
+}
+
+/// 数量フィールドを解釈（先頭の数値。カンマ区切り・単位付きも可）
+fn parse_quantity(text: &str) -> f64 {
+    extract_numbers(text).first().copied().unwrap_or(0.0)
+}
+
+/// ID354（山積表）のレコード1件分の在庫（一部出荷済は残数の 総個数_数値 を使う）
+pub fn inventory_quantity(record: &serde_json::Value) -> Option<(&'static str, f64)> {
+    let product = match_product(field_text(record, "品番"))?;
+    let field = if field_text(record, "山状況") == "一部出荷済" { "総個数_数値" } else { "総個数" };
+    Some((product, parse_quantity(field_text(record, field))))
+}
+
+/// ID354（山積表）のレコードから製品ごとの現在庫を集計
+pub fn inventory_by_product(records: &[serde_json::Value]) -> HashMap<&'static str, f64> {
+    let mut inventory = HashMap::new();
+    for (product, quantity) in records.iter().filter_map(inventory_quantity) {
+        *inventory.entry(product).or_insert(0.0) += quantity;
+    }
+    inventory
+}
+
+/// ID514（積込予定）のレコードから製品・日ごとの出荷予定を集計
+/// 解釈できなかったレコードは warnings に理由を追加する
+pub fn shipping_by_day(
+    records: &[serde_json::Value],
+    start: NaiveDate,
+    days: usize,
+    warnings: &mut Vec<String>,
+) -> HashMap<&'static str, Vec<f64>> {
+    // 日ごとに採用する積込予定（planDateが新しいもの）を決める
+    let mut plans: Vec<(NaiveDate, serde_json::Value)> = Vec::new();
+    for record in records {
+        let Some(plan_date) = calendar::parse_date(field_text(record, "planDate")) else { continue };
+        let json = field_text(record, "scheduleJson");
+        if json.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<serde_json::Value>(json) {
+            Ok(parsed) => plans.push((plan_date, parsed)),
+            Err(e) => warnings.push(format!("積込予定（{}）の scheduleJson を解釈できません: {}", plan_date, e)),
+        }
+    }
+    plans.sort_by_key(|(plan_date, _)| std::cmp::Reverse(*plan_date));
+
+    let mut shipping: HashMap<&'static str, Vec<f64>> = HashMap::new();
+    let mut covered = vec![false; days];
+    for (plan_date, parsed) in &plans {
+        let offsets: Vec<(usize, usize)> = (0..SHIPPING_PLAN_DAYS)
+            .filter_map(|col| {
+                let day = (*plan_date + Duration::days(col as i64) - start).num_days();
+                (day >= 0 && (day as usize) < days && !covered[day as usize]).then_some((col, day as usize))
+            })
+            .collect();
+        if offsets.is_empty() {
+            continue;
+        }
+
+        let rows = parsed["rows"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        for row in rows {
+            if row["group"].as_str() == Some(EXCLUDED_SHIPPING_GROUP) {
+                continue;
+            }
+            let Some(product) = row["item"].as_str().and_then(match_shipping_item) else { continue };
+            let values = row["values"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+            let totals = shipping.entry(product).or_insert_with(|| vec![0.0; days]);
+            for &(col, day) in &offsets {
+                if let Some(cell) = values.get(col) {
+                    totals[day] += extract_shipping_total(cell);
+                }
+            }
+        }
+        for &(_, day) in &offsets {
+            covered[day] = true;
+        }
+    }
+    shipping
+}
+
+/// ローカルのスケジュールから製品・日ごとの生産予定を集計（開始した生産日に計上。06:00より前は前日）
+pub fn production_by_day(schedules: &[LocalSchedule], start: NaiveDate, days: usize) -> HashMap<&'static str, Vec<f64>> {
+    let mut production: HashMap<&'static str, Vec<f64>> = HashMap::new();
+    for schedule in schedules {
+        if FINISHED_STATUSES.contains(&schedule.production_status.as_str()) {
+            continue;
+        }
+        let Some(product) = match_product(&schedule.product_name) else { continue };
+        let Some(start_at) = scheduling::parse_datetime(&schedule.start_datetime) else { continue };
+        let day = (calendar::production_date(start_at) - start).num_days();
+        if day < 0 || day as usize >= days {
+            continue;
+        }
+        let quantity = schedule.total_quantity.filter(|q| *q > 0.0).or(schedule.quantity1).unwrap_or(0.0);
+        production.entry(product).or_insert_with(|| vec![0.0; days])[day as usize] += quantity;
+    }
+    production
+}
+
+/// 製品・日ごとの在庫予測を組み立てる
+pub fn project(
+    start: NaiveDate,
+    days: usize,
+    inventory: &HashMap<&'static str, f64>,
+    production: &HashMap<&'static str, Vec<f64>>,
+    shipping: &HashMap<&'static str, Vec<f64>>,
+) -> Vec<ProductBalance> {
+    let daily = |totals: &HashMap<&'static str, Vec<f64>>, product: &str, day: usize| {
+        totals.get(product).and_then(|v| v.get(day)).copied().unwrap_or(0.0)
+    };
+
+    BALANCE_PRODUCTS
+        .iter()
+        .map(|product| {
+            let opening_stock = inventory.get(product).copied().unwrap_or(0.0);
+            let mut stock = opening_stock;
+            let days = (0..days)
+                .map(|day| {
+                    let production = daily(production, product, day);
+                    let shipping = daily(shipping, product, day);
+                    let opening = stock;
+                    stock += production - shipping;
+                    BalanceDay {
+                        date: (start + Duration::days(day as i64)).format("%Y-%m-%d").to_string(),
+                        opening,
+                        production,
+                        shipping,
+                        closing: stock,
+                    }
+                })
+                .collect();
+            ProductBalance { product: product.to_string(), opening_stock, days }
+        })
+        .collect()
+}
+
+/// 予測済みの在庫・出荷予定はそのまま使い、生産予定だけローカルのスケジュールから計算し直す
+pub fn reproject(start: NaiveDate, balances: &[ProductBalance], schedules: &[LocalSchedule]) -> Vec<ProductBalance> {
+    let days = balances.first().map(|b| b.days.len()).unwrap_or(DEFAULT_PROJECTION_DAYS);
+    let mut inventory = HashMap::new();
+    let mut shipping = HashMap::new();
+    for balance in balances {
+        let Some(product) = BALANCE_PRODUCTS.iter().find(|p| **p == balance.product) else { continue };
+        inventory.insert(*product, balance.opening_stock);
+        shipping.insert(*product, balance.days.iter().map(|d| d.shipping).collect());
+    }
+    project(start, days, &inventory, &production_by_day(schedules, start, days), &shipping)
+}
+
+/// 在庫アラートの種類
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StockAlertKind {
+    /// 安全在庫を下回る
+    Shortage,
+    /// 最大在庫を上回る
+    Overstock,
+}
+
+/// 在庫アラート（製品ごと・種類ごとに最初に基準を外れる日）
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StockAlert {
+    pub product: String,
+    pub kind: StockAlertKind,
+    pub date: String,
+    /// その日の在庫予測（日末）
+    pub projected: f64,
+    /// 外れた基準（安全在庫または最大在庫）
+    pub threshold: f64,
+}
+
+/// 在庫予測が安全在庫を下回る・最大在庫を上回る最初の日を探す
+pub fn find_stock_alerts(balances: &[ProductBalance], levels: &[StockLevel]) -> Vec<StockAlert> {
+    let mut alerts = Vec::new();
+    for balance in balances {
+        let Some(level) = levels.iter().find(|l| l.product == balance.product) else { continue };
+        let checks = [
+            (StockAlertKind::Shortage, level.safety_stock, (|stock, limit| stock < limit) as fn(f64, f64) -> bool),
+            (StockAlertKind::Overstock, level.max_stock, |stock, limit| stock > limit),
+        ];
+        for (kind, threshold, breaches) in checks {
+            let Some(threshold) = threshold else { continue };
+            if let Some(day) = balance.days.iter().find(|d| breaches(d.closing, threshold)) {
+                alerts.push(StockAlert {
+                    product: balance.product.clone(),
+                    kind,
+                    date: day.date.clone(),
+                    projected: day.closing,
+                    threshold,
+                });
+            }
+        }
+    }
+    alerts.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.product.cmp(&b.product)));
+    alerts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schedule(product: &str, start: &str, status: &str, total: Option<f64>, q1: Option<f64>) -> LocalSchedule {
+        serde_json::from_value(json!({
+            "id": null, "kintone_record_id": null, "schedule_number": null,
+            "product_name": product, "product_display_name": null, "category": null, "line": "A",
+            "start_datetime": start, "end_datetime": start,
+            "quantity1": q1, "quantity2": null, "quantity3": null, "quantity4": null,
+            "quantity5": null, "quantity6": null, "quantity7": null, "quantity8": null,
+            "total_quantity": total,
+            "efficiency1": null, "efficiency2": null, "efficiency3": null, "efficiency4": null,
+            "efficiency5": null, "efficiency6": null, "efficiency7": null, "efficiency8": null,
+            "production_status": status, "notes": null, "sync_status": "synced",
+            "created_at": "", "updated_at": ""
+        }))
+        .unwrap()
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        calendar::parse_date(value).unwrap()
+    }
+
+    #[test]
+    fn matches_longest_product_code() {
+        assert_eq!(match_product("FS250CE"), Some("FS250CE"));
+        assert_eq!(match_product("FS250C"), Some("FS250C"));
+        assert_eq!(match_product(" ｆｓ４５０ｎｒ "), Some("FS450NR"));
+        assert_eq!(match_product("FS450NR-2"), Some("FS450NR"));
+        assert_eq!(match_product("FS450KX"), None);
+        assert_eq!(match_product("FS999"), None);
+        assert_eq!(match_product(""), None);
+    }
+
+    #[test]
+    fn matches_shipping_items() {
+        assert_eq!(match_shipping_item("450NR（10t）"), Some("FS450NR"));
+        assert_eq!(match_shipping_item("高ダイ"), Some("FS450K"));
+        assert_eq!(match_shipping_item("大建向け"), Some("FS450D"));
+        assert_eq!(match_shipping_item("FS250CE"), Some("FS250CE"));
+        assert_eq!(match_shipping_item("FS250C"), Some("FS250C"));
+        assert_eq!(match_shipping_item("4500NR"), None);
+        assert_eq!(match_shipping_item("その他"), None);
+    }
+
+    #[test]
+    fn extracts_shipping_totals() {
+        assert_eq!(extract_shipping_total(&json!("1,200")), 1200.0);
+        assert_eq!(extract_shipping_total(&json!("10※5")), 15.0);
+        assert_eq!(extract_shipping_total(&json!("２０　３０")), 50.0);
+        assert_eq!(extract_shipping_total(&json!("12.5t")), 12.5);
+        assert_eq!(extract_shipping_total(&json!("-3")), -3.0);
+        assert_eq!(extract_shipping_total(&json!(40)), 40.0);
+        assert_eq!(extract_shipping_total(&json!({ "left": "10", "center": 5, "right": "2,000" })), 2015.0);
+        assert_eq!(extract_shipping_total(&json!("")), 0.0);
+        assert_eq!(extract_shipping_total(&json!(null)), 0.0);
+    }
+
+    #[test]
+    fn inventory_uses_remaining_count_for_partial_shipments() {
+        let records = vec![
+            json!({ "品番": { "value": "FS450NR" }, "山状況": { "value": "出荷待ち" }, "総個数": { "value": "1,000" }, "総個数_数値": { "value": "0" } }),
+            json!({ "品番": { "value": "FS450NR" }, "山状況": { "value": "一部出荷済" }, "総個数": { "value": "500" }, "総個数_数値": { "value": "200" } }),
+            json!({ "品番": { "value": "FS250CE" }, "山状況": { "value": "出荷待ち" }, "総個数": { "value": "30" } }),
+            json!({ "品番": { "value": "ABC" }, "山状況": { "value": "出荷待ち" }, "総個数": { "value": "99" } }),
+        ];
+        let inventory = inventory_by_product(&records);
+        assert_eq!(inventory.get("FS450NR"), Some(&1200.0));
+        assert_eq!(inventory.get("FS250CE"), Some(&30.0));
+        assert_eq!(inventory.get("FS250C"), None);
+        assert_eq!(inventory.len(), 2);
+    }
+
+    #[test]
+    fn newer_shipping_plan_wins_for_overlapping_days() {
+        let plan = |plan_date: &str, values: serde_json::Value| {
+            let json = json!({ "rows": [
+                { "group": "A", "item": "450NR", "values": values },
+                { "group": "日本ロック", "item": "450NR", "values": [1000, 1000, 1000, 1000, 1000, 1000, 1000] }
+            ] });
+            json!({ "planDate": { "value": plan_date }, "scheduleJson": { "value": json.to_string() } })
+        };
+        let records = vec![
+            plan("2026-03-01", json!([1, 2, 3, 4, 5, 6, 7])),
+            plan("2026-03-03", json!(["10", "20"])),
+            json!({ "planDate": { "value": "2026-03-02" }, "scheduleJson": { "value": "{broken" } }),
+        ];
+        let mut warnings = Vec::new();
+        let shipping = shipping_by_day(&records, date("2026-03-02"), 5, &mut warnings);
+        // 3/2 は 3/1 の予定、3/3 以降は 3/3 の予定（値がない列は0）
+        assert_eq!(shipping["FS450NR"], vec![2.0, 10.0, 20.0, 0.0, 0.0]);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn production_skips_finished_and_out_of_range() {
+        let schedules = vec![
+            schedule("FS450K", "2026-03-02 08:00:00", "未生産", Some(100.0), Some(50.0)),
+            schedule("FS450K", "2026-03-02 20:00:00", "生産中", None, Some(30.0)),
+            schedule("FS450K", "2026-03-03 05:00:00", "未生産", Some(7.0), None),
+            schedule("FS450K", "2026-03-03 08:00:00", "未生産", Some(0.0), Some(40.0)),
+            schedule("FS450K", "2026-03-03 08:00:00", "生産終了", Some(500.0), None),
+            schedule("FS450K", "2026-03-01 08:00:00", "未生産", Some(500.0), None),
+            schedule("FS450K", "2026-03-02 05:00:00", "未生産", Some(500.0), None),
+            schedule("MMO", "2026-03-02 08:00:00", "未生産", Some(500.0), None),
+        ];
+        let production = production_by_day(&schedules, date("2026-03-02"), 3);
+        assert_eq!(production["FS450K"], vec![137.0, 40.0, 0.0]);
+        assert_eq!(production.len(), 1);
+    }
+
+    #[test]
+    fn projects_running_balance() {
+        let inventory = HashMap::from([("FS360F", 100.0)]);
+        let production = HashMap::from([("FS360F", vec![0.0, 50.0, 0.0])]);
+        let shipping = HashMap::from([("FS360F", vec![30.0, 0.0, 200.0])]);
+        let balances = project(date("2026-03-02"), 3, &inventory, &production, &shipping);
+        assert_eq!(balances.len(), BALANCE_PRODUCTS.len());
+
+        let fs360f = balances.iter().find(|b| b.product == "FS360F").unwrap();
+        assert_eq!(fs360f.opening_stock, 100.0);
+        let closings: Vec<f64> = fs360f.days.iter().map(|d| d.closing).collect();
+        assert_eq!(closings, vec![70.0, 120.0, -80.0]);
+        assert_eq!(fs360f.days[1].opening, 70.0);
+        assert_eq!(fs360f.days[2].date, "2026-03-04");
+
+        let other = balances.iter().find(|b| b.product == "FS450S").unwrap();
+        assert!(other.days.iter().all(|d| d.closing == 0.0));
+    }
+
+    #[test]
+    fn reprojects_with_new_production_only() {
+        let inventory = HashMap::from([("FS360F", 100.0)]);
+        let shipping = HashMap::from([("FS360F", vec![30.0, 0.0, 200.0])]);
+        let before = project(date("2026-03-02"), 3, &inventory, &HashMap::new(), &shipping);
+
+        let schedules = vec![schedule("FS360F", "2026-03-03 08:00:00", "未生産", Some(50.0), None)];
+        let after = reproject(date("2026-03-02"), &before, &schedules);
+        let expected = project(date("2026-03-02"), 3, &inventory, &HashMap::from([("FS360F", vec![0.0, 50.0, 0.0])]), &shipping);
+        assert_eq!(after, expected);
+    }
+
+    #[test]
+    fn flags_first_day_outside_stock_levels() {
+        let inventory = HashMap::from([("FS360F", 100.0), ("FS450K", 100.0)]);
+        let production = HashMap::from([("FS360F", vec![0.0, 50.0, 0.0, 0.0]), ("FS450K", vec![0.0, 0.0, 300.0, 0.0])]);
+        let shipping = HashMap::from([("FS360F", vec![30.0, 0.0, 200.0, 10.0])]);
+        let balances = project(date("2026-03-02"), 4, &inventory, &production, &shipping);
+        let levels = vec![
+            StockLevel { product: "FS360F".into(), safety_stock: Some(80.0), max_stock: Some(110.0), lot_size: None },
+            StockLevel { product: "FS450K".into(), safety_stock: Some(50.0), max_stock: Some(350.0), lot_size: None },
+            StockLevel { product: "FS450S".into(), safety_stock: None, max_stock: None, lot_size: None },
+        ];
+        let alerts = find_stock_alerts(&balances, &levels);
+        // FS360F: 70 → 120 → -80 → -90、FS450K: 100 → 100 → 400 → 400
+        assert_eq!(alerts.len(), 3);
+        assert_eq!((alerts[0].product.as_str(), alerts[0].kind, alerts[0].date.as_str()), ("FS360F", StockAlertKind::Shortage, "2026-03-02"));
+        assert_eq!(alerts[0].projected, 70.0);
+        assert_eq!((alerts[1].product.as_str(), alerts[1].kind, alerts[1].date.as_str()), ("FS360F", StockAlertKind::Overstock, "2026-03-03"));
+        assert_eq!((alerts[2].product.as_str(), alerts[2].kind, alerts[2].threshold), ("FS450K", StockAlertKind::Overstock, 350.0));
+    }
+}
@@ -0,0 +1,326 @@
+//! 稼働カレンダーモジュール
+//! 生産日は06:00始まり（翌06:00まで）。シフト・休日・保全時間帯から稼働時間帯を求める
+
+use std::collections::HashSet;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use serde::Serialize;
+
+use crate::database::{Holiday, MaintenanceWindow, ShiftPattern};
+use crate::scheduling;
+
+/// 生産日の開始時刻（時）
+pub const DAY_START_HOUR: u32 = 6;
+
+/// 稼働時間帯を探す最大日数（これを超えたら稼働時間なしとみなす）
+const MAX_SEARCH_DAYS: u32 = 366;
+
+/// 時間帯（開始・終了）
+pub type Window = (NaiveDateTime, NaiveDateTime);
+
+/// 日時が属する生産日（06:00より前は前日扱い）
+pub fn production_date(value: NaiveDateTime) -> NaiveDate {
+    (value - Duration::hours(DAY_START_HOUR as i64)).date()
+}
+
+/// 生産日の範囲（当日06:00〜翌06:00）
+pub fn day_bounds(date: NaiveDate) -> Window {
+    let start = date.and_hms_opt(DAY_START_HOUR, 0, 0).unwrap_or_default();
+    (start, start + Duration::days(1))
+}
+
+/// 画面・APIに返す稼働時間帯
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkingWindow {
+    pub start: String,
+    pub end: String,
+    pub minutes: i64,
+}
+
+impl From<Window> for WorkingWindow {
+    fn from((start, end): Window) -> Self {
+        Self {
+            start: start.format("%Y-%m-%d %H:%M:%S").to_string(),
+            end: end.format("%Y-%m-%d %H:%M:%S").to_string(),
+            minutes: (end - start).num_minutes(),
+        }
+    }
+}
+
+/// ラインごとの1日の稼働時間帯
+#[derive(Debug, Clone, Serialize)]
+pub struct LineWorkingWindows {
+    pub line: String,
+    /// 生産日（YYYY-MM-DD）
+    pub date: String,
+    pub is_holiday: bool,
+    pub windows: Vec<WorkingWindow>,
+    pub working_minutes: i64,
+}
+
+/// 解釈済みのシフトパターン
+#[derive(Debug, Clone)]
+struct Shift {
+    weekday: Option<u32>,
+    line: Option<String>,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+/// 稼働カレンダー
+/// シフトパターンが1件もないラインは終日稼働として扱う
+#[derive(Debug, Clone, Default)]
+pub struct PlantCalendar {
+    shifts: Vec<Shift>,
+    holidays: HashSet<NaiveDate>,
+    maintenance: Vec<(Option<String>, Window)>,
+}
+
+/// 時刻（HH:MM）を解釈
+pub fn parse_time(value: &str) -> Option<NaiveTime> {
+    let value = value.trim();
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
+        .ok()
+}
+
+/// 生産日（YYYY-MM-DD）を解釈
+pub fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d").ok()
+}
+
+/// ライン指定が対象ラインに当てはまるか（Noneや空欄は全ライン）
+fn applies_to(target: Option<&str>, line: &str) -> bool {
+    match target.map(str::trim) {
+        None | Some("") => true,
+        Some(t) => t == line.trim(),
+    }
+}
+
+/// 重なる時間帯をまとめる
+fn merge(mut windows: Vec<Window>) -> Vec<Window> {
+    windows.sort();
+    let mut merged: Vec<Window> = Vec::new();
+    for (start, end) in windows {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// 時間帯から停止時間帯を除く
+fn subtract(windows: Vec<Window>, cut: Window) -> Vec<Window> {
+    let (cut_start, cut_end) = cut;
+    let mut result = Vec::new();
+    for (start, end) in windows {
+        if cut_end <= start || end <= cut_start {
+            result.push((start, end));
+            continue;
+        }
+        if start < cut_start {
+            result.push((start, cut_start));
+        }
+        if cut_end < end {
+            result.push((cut_end, end));
+        }
+    }
+    result
+}
+
+impl PlantCalendar {
+    /// DBのシフト・休日・保全からカレンダーを作成（解釈できない行は無視）
+    pub fn new(shifts: &[ShiftPattern], holidays: &[Holiday], maintenance: &[MaintenanceWindow]) -> Self {
+        let shifts = shifts
+            .iter()
+            .filter_map(|s| {
+                let parsed = parse_time(&s.start_time).zip(parse_time(&s.end_time));
+                if parsed.is_none() {
+                    eprintln!("シフト {} の時刻を解釈できません: {} - {}", s.name, s.start_time, s.end_time);
+                }
+                parsed.map(|(start, end)| Shift {
+                    weekday: s.weekday,
+                    line: s.line.clone(),
+                    start,
+                    end,
+                })
+            })
+            .collect();
+
+        let holidays = holidays.iter().filter_map(|h| parse_date(&h.date)).collect();
+
+        let maintenance = maintenance
+            .iter()
+            .filter_map(|m| {
+                let start = scheduling::parse_datetime(&m.start_datetime)?;
+                let end = scheduling::parse_datetime(&m.end_datetime)?;
+                (end > start).then(|| (m.line.clone(), (start, end)))
+            })
+            .collect();
+
+        Self { shifts, holidays, maintenance }
+    }
+
+    /// 休日か
+    pub fn is_holiday(&self, date: NaiveDate) -> bool {
+        self.holidays.contains(&date)
+    }
+
+    /// シフトから求めた時間帯（休日・保全は考慮しない）
+    fn shift_windows(&self, date: NaiveDate, line: &str) -> Vec<Window> {
+        // ライン専用のシフトがあればそれを、なければ全ライン共通のシフトを使う
+        let dedicated: Vec<&Shift> = self
+            .shifts
+            .iter()
+            .filter(|s| s.line.as_deref().is_some_and(|l| !l.trim().is_empty() && l.trim() == line.trim()))
+            .collect();
+        let candidates: Vec<&Shift> = if dedicated.is_empty() {
+            self.shifts.iter().filter(|s| applies_to(s.line.as_deref(), "")).collect()
+        } else {
+            dedicated
+        };
+
+        let bounds = day_bounds(date);
+        if candidates.is_empty() {
+            return vec![bounds];
+        }
+
+        let weekday = date.weekday().num_days_from_sunday();
+        let next_day = date.succ_opt().unwrap_or(date);
+        let anchor = |t: NaiveTime| {
+            if t.hour() < DAY_START_HOUR { next_day.and_time(t) } else { date.and_time(t) }
+        };
+
+        candidates
+            .into_iter()
+            .filter(|s| s.weekday.is_none_or(|w| w == weekday))
+            .filter_map(|s| {
+                let start = anchor(s.start);
+                let mut end = anchor(s.end);
+                if end <= start {
+                    end += Duration::days(1);
+                }
+                let start = start.max(bounds.0);
+                let end = end.min(bounds.1);
+                (start < end).then_some((start, end))
+            })
+            .collect()
+    }
+
+    /// 生産日の稼働時間帯（休日は空、保全時間帯は除く）
+    pub fn working_windows(&self, date: NaiveDate, line: &str) -> Vec<Window> {
+        if self.is_holiday(date) {
+            return Vec::new();
+        }
+
+        let mut windows = merge(self.shift_windows(date, line));
+        for (target, cut) in &self.maintenance {
+            if applies_to(target.as_deref(), line) {
+                windows = subtract(windows, *cut);
+            }
+        }
+        windows
+    }
+
+    /// 開始日時から稼働時間だけを数えて指定分数を進めた日時
+    /// 開始日時が停止中なら次の稼働開始から数える。稼働時間が見つからなければNone
+    pub fn add_working_minutes(&self, start: NaiveDateTime, minutes: i64, line: &str) -> Option<NaiveDateTime> {
+        if minutes <= 0 {
+            return Some(start);
+        }
+
+        let mut remaining = minutes;
+        let mut date = production_date(start);
+        for _ in 0..MAX_SEARCH_DAYS {
+            for (window_start, window_end) in self.working_windows(date, line) {
+                if window_end <= start {
+                    continue;
+                }
+                let from = window_start.max(start);
+                let available = (window_end - from).num_minutes();
+                if available >= remaining {
+                    return Some(from + Duration::minutes(remaining));
+                }
+                remaining -= available;
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+
+    /// 終了日時から稼働時間だけを数えて指定分数を戻した日時（その日時に開始すれば終了日時までに終わる最遅の開始）
+    /// 稼働時間が見つからなければNone
+    pub fn subtract_working_minutes(&self, end: NaiveDateTime, minutes: i64, line: &str) -> Option<NaiveDateTime> {
+        if minutes <= 0 {
+            return Some(end);
+        }
+
+        let mut remaining = minutes;
+        let mut date = production_date(end);
+        for _ in 0..MAX_SEARCH_DAYS {
+            for (window_start, window_end) in self.working_windows(date, line).into_iter().rev() {
+                if window_start >= end {
+                    continue;
+                }
+                let to = window_end.min(end);
+                let available = (to - window_start).num_minutes();
+                if available >= remaining {
+                    return Some(to - Duration::minutes(remaining));
+                }
+                remaining -= available;
+            }
+            date = date.pred_opt()?;
+        }
+        None
+    }
+
+    /// 指定日時以降で最初に稼働している日時（稼働中ならそのまま）
+    pub fn next_working_time(&self, value: NaiveDateTime, line: &str) -> Option<NaiveDateTime> {
+        let mut date = production_date(value);
+        for _ in 0..MAX_SEARCH_DAYS {
+            if let Some((start, _)) = self.working_windows(date, line).into_iter().find(|(_, end)| *end > value) {
+                return Some(start.max(value));
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+
+    /// 2つの日時の間の稼働時間（分）
+    pub fn working_minutes_between(&self, from: NaiveDateTime, to: NaiveDateTime, line: &str) -> i64 {
+        if to <= from {
+            return 0;
+        }
+
+        let mut total = 0;
+        let mut date = production_date(from);
+        while date <= production_date(to) {
+            for (start, end) in self.working_windows(date, line) {
+                let overlap_start = start.max(from);
+                let overlap_end = end.min(to);
+                if overlap_start < overlap_end {
+                    total += (overlap_end - overlap_start).num_minutes();
+                }
+            }
+            match date.succ_opt() {
+                Some(next) => date = next,
+                None => break,
+            }
+        }
+        total
+    }
+
+    /// 1日分の稼働時間帯（API用）
+    pub fn line_day(&self, date: NaiveDate, line: &str) -> LineWorkingWindows {
+        let windows = self.working_windows(date, line);
+        let working_minutes = windows.iter().map(|(s, e)| (*e - *s).num_minutes()).sum();
+        LineWorkingWindows {
+            line: line.to_string(),
+            date: date.format("%Y-%m-%d").to_string(),
+            is_holiday: self.is_holiday(date),
+            windows: windows.into_iter().map(WorkingWindow::from).collect(),
+            working_minutes,
+        }
+    }
+}
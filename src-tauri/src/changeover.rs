@@ -0,0 +1,143 @@
+//! 段取り替え（品種切替）時間
+//! 同じラインで続けて生産する製品が変わるときに必要な清掃・段取り時間を
+//! （切替前の製品, 切替後の製品, ライン）ごとのマトリクスで管理する
+
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::calendar::PlantCalendar;
+use crate::database::{Changeover, LocalSchedule};
+use crate::scheduling;
+
+/// 段取り替えマトリクス
+/// ライン指定のない行は全ライン共通。ライン専用の行があればそちらを優先する
+#[derive(Debug, Clone, Default)]
+pub struct ChangeoverMatrix {
+    minutes: HashMap<(String, String, String), i64>,
+}
+
+/// ガントチャートに表示する段取り替え時間帯
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeoverBlock {
+    pub line: String,
+    pub from_schedule_id: i64,
+    pub to_schedule_id: i64,
+    pub from_product: String,
+    pub to_product: String,
+    /// 段取り替えの開始（前のスケジュールの終了日時）
+    pub start_datetime: String,
+    /// 必要な段取り時間を稼働時間で数えた終了日時
+    pub end_datetime: String,
+    pub required_minutes: i64,
+    /// 次のスケジュール開始までに確保されている稼働時間
+    pub available_minutes: i64,
+    /// 段取り時間が足りているか
+    pub sufficient: bool,
+}
+
+impl ChangeoverMatrix {
+    pub fn new(changeovers: &[Changeover]) -> Self {
+        let minutes = changeovers
+            .iter()
+            .map(|c| {
+                let key = (
+                    c.from_product.trim().to_string(),
+                    c.to_product.trim().to_string(),
+                    c.line.as_deref().unwrap_or("").trim().to_string(),
+                );
+                (key, c.minutes)
+            })
+            .collect();
+        Self { minutes }
+    }
+
+    /// 切替に必要な段取り時間（分）。同じ製品や未登録の組み合わせは0
+    pub fn minutes(&self, from_product: &str, to_product: &str, line: &str) -> i64 {
+        let (from, to, line) = (from_product.trim(), to_product.trim(), line.trim());
+        if from == to {
+            return 0;
+        }
+        let lookup = |line: &str| self.minutes.get(&(from.to_string(), to.to_string(), line.to_string())).copied();
+        lookup(line).or_else(|| lookup("")).unwrap_or(0).max(0)
+    }
+}
+
+/// ガントチャート上の時間枠（メモ・図形や日時を解釈できないものは対象外）
+fn interval(schedule: &LocalSchedule) -> Option<(i64, NaiveDateTime, NaiveDateTime)> {
+    if scheduling::is_fixed_duration(&schedule.product_name) {
+        return None;
+    }
+    let id = schedule.id?;
+    let start = scheduling::parse_datetime(&schedule.start_datetime)?;
+    let end = scheduling::parse_datetime(schedule.end_datetime.as_deref()?)?;
+    Some((id, start, end))
+}
+
+/// ラインごとに連続するスケジュールの段取り替え時間帯を算出
+pub fn compute_blocks(
+    schedules: &[LocalSchedule],
+    matrix: &ChangeoverMatrix,
+    calendar: &PlantCalendar,
+) -> Vec<ChangeoverBlock> {
+    let mut by_line: HashMap<&str, Vec<(i64, NaiveDateTime, NaiveDateTime, &LocalSchedule)>> = HashMap::new();
+    for schedule in schedules {
+        if let Some((id, start, end)) = interval(schedule) {
+            by_line.entry(schedule.line.trim()).or_default().push((id, start, end, schedule));
+        }
+    }
+
+    let mut blocks = Vec::new();
+    for (line, mut items) in by_line {
+        items.sort_by_key(|(id, start, _, _)| (*start, *id));
+        for pair in items.windows(2) {
+            let (from_id, _, from_end, from) = pair[0];
+            let (to_id, to_start, _, to) = pair[1];
+            let required = matrix.minutes(&from.product_name, &to.product_name, line);
+            if required == 0 {
+                continue;
+            }
+
+            let end = calendar.add_working_minutes(from_end, required, line).unwrap_or(from_end);
+            let available = calendar.working_minutes_between(from_end, to_start, line);
+            blocks.push(ChangeoverBlock {
+                line: line.to_string(),
+                from_schedule_id: from_id,
+                to_schedule_id: to_id,
+                from_product: from.product_name.clone(),
+                to_product: to.product_name.clone(),
+                start_datetime: from_end.format("%Y-%m-%d %H:%M:%S").to_string(),
+                end_datetime: end.format("%Y-%m-%d %H:%M:%S").to_string(),
+                required_minutes: required,
+                available_minutes: available,
+                sufficient: available >= required,
+            });
+        }
+    }
+
+    blocks.sort_by(|a, b| (&a.line, &a.start_datetime).cmp(&(&b.line, &b.start_datetime)));
+    blocks
+}
+
+/// 指定スケジュールの前後で段取り時間が足りない箇所
+pub fn shortfalls_for(blocks: Vec<ChangeoverBlock>, schedule_id: i64) -> Vec<ChangeoverBlock> {
+    blocks
+        .into_iter()
+        .filter(|b| !b.sufficient && (b.from_schedule_id == schedule_id || b.to_schedule_id == schedule_id))
+        .collect()
+}
+
+/// 段取り不足の内容をメッセージにまとめる
+pub fn describe_shortfalls(blocks: &[ChangeoverBlock]) -> String {
+    let details: Vec<String> = blocks
+        .iter()
+        .map(|b| {
+            format!(
+                "{} → {}：段取り{}分に対して{}分",
+                b.from_product, b.to_product, b.required_minutes, b.available_minutes
+            )
+        })
+        .collect();
+    format!("段取り替え時間が不足しています: {}", details.join(" / "))
+}
@@ -1,20 +1,43 @@
 //! Tauriコマンド
 //! フロントエンドから呼び出せるAPI
 
-use tauri::State;
+use tauri::{AppHandle, Emitter, State};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::Mutex;
-use crate::database::{Database, LocalSchedule};
-use crate::kintone_client::{KintoneClient, KintoneConfig};
+use crate::audit;
+use crate::balance::{self, ProductBalance, StockAlert};
+use crate::database::{differing_fields, overlay_columns, remote_over_local, Changeover, Database, DeletedSchedule, DownstreamSync, Holiday, Scenario, ScheduleHistoryEntry, ImportOutcome, LocalSchedule, MaintenanceWindow, Product, ShiftPattern, StockLevel, SyncConflict, SyncState, MERGEABLE_FIELDS, SCHEMA_VERSION, TRASH_RETENTION_DAYS};
+use crate::calendar::{self, LineWorkingWindows, PlantCalendar};
+use crate::changeover::{self, ChangeoverBlock, ChangeoverMatrix};
+use crate::credentials;
+use crate::downstream::{self, DownstreamApp};
+use crate::field_mapping::{AppMapping, FieldMappings};
+use crate::schema_check::{self, AppSchemaReport, SchemaReport};
+use crate::optimizer::{self, Job, LineState};
+use crate::products;
+use crate::scenario::{self, ScenarioDiff, ScenarioSchedule};
+use crate::reschedule::{self, ReschedulePlan};
+use crate::undo::{self, ScheduleChange, UndoEntry, UndoStack, UndoState};
+use crate::scheduling::{self, ProductionEstimate, SchedulingError};
+use crate::validation::{self, ScheduleOverlap, ScheduleSlot};
+use crate::mrp;
+use crate::kintone_client::{BulkUpdateRecord, KintoneClient, KintoneConfig, KintoneError, BULK_CHUNK_SIZE};
 
 /// アプリケーション状態
 pub struct AppState {
     pub db: Mutex<Database>,
     pub kintone_client: Mutex<Option<KintoneClient>>,
+    /// スケジュール編集の取り消し・やり直し履歴
+    pub undo: Mutex<UndoStack>,
+    /// 直近の需給バランス予測（在庫アラートの判定に使う）
+    pub balance: Mutex<Option<BalanceProjection>>,
+    /// kintoneアプリのフィールド対応付け（起動時に読み込む）
+    pub field_mappings: FieldMappings,
 }
 
 /// スケジュール追加リクエスト
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AddScheduleRequest {
     pub product_name: String,
     pub product_display_name: Option<String>,
@@ -35,14 +58,19 @@ pub struct AddScheduleRequest {
     pub production_status: Option<String>,
     pub notes: Option<String>,
     pub efficiency: Option<String>,
+    /// 同じラインの重複を警告のみとして保存する
+    #[serde(default)]
+    pub allow_overlap: bool,
 }
 
 /// kintone設定リクエスト
+/// APIトークンが空欄の場合は保存済みのトークンをそのまま使う
 #[derive(Debug, Deserialize)]
 pub struct KintoneConfigRequest {
     pub subdomain: String,
     pub app_id: u32,
-    pub api_token: String,
+    #[serde(default)]
+    pub api_token: Option<String>,
     pub memo_app_id: Option<u32>,
     pub memo_api_token: Option<String>,
     pub yamazumi_app_id: Option<u32>,
@@ -51,15 +79,238 @@ pub struct KintoneConfigRequest {
     pub kobukuro_api_token: Option<String>,
     pub tsumikomi_app_id: Option<u32>,
     pub tsumikomi_api_token: Option<String>,
+    #[serde(default)]
+    pub legacy_app_id: Option<u32>,
+    #[serde(default)]
+    pub legacy_api_token: Option<String>,
+}
+
+/// 画面表示用のkintone設定（APIトークンは返さず、設定済みかどうかのみ）
+#[derive(Debug, Serialize)]
+pub struct KintoneConfigView {
+    pub configured: bool,
+    pub subdomain: String,
+    pub app_id: Option<u32>,
+    pub memo_app_id: Option<u32>,
+    pub yamazumi_app_id: Option<u32>,
+    pub kobukuro_app_id: Option<u32>,
+    pub tsumikomi_app_id: Option<u32>,
+    pub legacy_app_id: Option<u32>,
+    pub has_api_token: bool,
+    pub has_memo_api_token: bool,
+    pub has_yamazumi_api_token: bool,
+    pub has_kobukuro_api_token: bool,
+    pub has_tsumikomi_api_token: bool,
+    pub has_legacy_api_token: bool,
 }
 
 /// スケジュール更新リクエスト
+/// 製品・生産数量・製綿能率のいずれかが指定された場合は終了日時を再計算する
 #[derive(Debug, Deserialize)]
 pub struct UpdateScheduleRequest {
     pub id: i64,
     pub start_datetime: Option<String>,
     pub end_datetime: Option<String>,
     pub notes: Option<String>,
+    pub product_name: Option<String>,
+    pub quantity1: Option<f64>,
+    pub quantity2: Option<f64>,
+    pub quantity3: Option<f64>,
+    pub quantity4: Option<f64>,
+    pub quantity5: Option<f64>,
+    pub quantity6: Option<f64>,
+    pub quantity7: Option<f64>,
+    pub quantity8: Option<f64>,
+    pub efficiency1: Option<String>,
+    pub efficiency2: Option<String>,
+    pub efficiency3: Option<String>,
+    pub efficiency4: Option<String>,
+    pub efficiency5: Option<String>,
+    pub efficiency6: Option<String>,
+    pub efficiency7: Option<String>,
+    pub efficiency8: Option<String>,
+    /// 同じラインの重複を警告のみとして保存する
+    #[serde(default)]
+    pub allow_overlap: bool,
+}
+
+/// ラインの連鎖リスケジュール要求
+#[derive(Debug, Deserialize)]
+pub struct RescheduleLineRequest {
+    /// 移動・延長したスケジュール
+    pub schedule_id: i64,
+    pub start_datetime: String,
+    /// 未指定なら元の生産時間を保つ
+    pub end_datetime: Option<String>,
+    /// trueなら移動内容を返すだけでDBは変更しない
+    #[serde(default)]
+    pub preview: bool,
+}
+
+/// 自動計画の対象となる受注
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanningOrder {
+    pub product_name: String,
+    pub quantity: f64,
+    /// 納期（未指定なら納期遅れを評価しない）
+    #[serde(default)]
+    pub due_datetime: Option<String>,
+    /// 生産できるライン（空なら全ライン）
+    #[serde(default)]
+    pub allowed_lines: Vec<String>,
+    /// 製綿能率（未指定なら製品マスタの既定能率）
+    #[serde(default)]
+    pub efficiency: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+/// 自動計画リクエスト
+#[derive(Debug, Deserialize)]
+pub struct OptimizeOrdersRequest {
+    pub orders: Vec<PlanningOrder>,
+    /// この日時以降に割り付ける
+    pub start_datetime: String,
+    /// 計画対象のライン（空なら使用中の全ライン）
+    #[serde(default)]
+    pub lines: Vec<String>,
+    #[serde(default)]
+    pub max_iterations: Option<usize>,
+}
+
+/// 計画案の1行（確定するとスケジュールになる）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DraftScheduleItem {
+    /// ordersのインデックス
+    pub order_index: usize,
+    pub product_name: String,
+    pub line: String,
+    pub start_datetime: String,
+    pub end_datetime: String,
+    pub quantity: f64,
+    pub efficiency: String,
+    pub due_datetime: Option<String>,
+    pub changeover_minutes: i64,
+    pub lateness_minutes: i64,
+    pub notes: Option<String>,
+}
+
+/// 計画できなかった受注
+#[derive(Debug, Clone, Serialize)]
+pub struct UnplacedOrder {
+    pub order_index: usize,
+    pub product_name: String,
+    pub reason: String,
+}
+
+/// 自動計画の結果（確認してから commit_draft_plan で確定する）
+#[derive(Debug, Clone, Serialize)]
+pub struct DraftPlan {
+    pub items: Vec<DraftScheduleItem>,
+    pub unplaced: Vec<UnplacedOrder>,
+    pub total_changeover_minutes: i64,
+    pub total_lateness_minutes: i64,
+}
+
+/// シナリオ昇格の結果
+#[derive(Debug, Serialize)]
+pub struct ScenarioPromoteReport {
+    pub updated: usize,
+    pub added: usize,
+    pub removed: usize,
+    /// kintoneへ送信・削除できた件数
+    pub synced: u32,
+    /// 同じラインの重複（重複のため昇格しなかった場合のみ）
+    pub overlaps: Vec<ScheduleOverlap>,
+}
+
+/// 稼働カレンダーの内容
+#[derive(Debug, Serialize)]
+pub struct PlantCalendarView {
+    pub shifts: Vec<ShiftPattern>,
+    pub holidays: Vec<Holiday>,
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+}
+
+/// スケジュール追加・更新の結果
+/// 重複で保存しなかった場合も overlaps に重複内容を返す
+#[derive(Debug, Serialize)]
+pub struct ScheduleSaveResult {
+    pub id: Option<i64>,
+    pub overlaps: Vec<ScheduleOverlap>,
+    /// 保存したスケジュールの前後で段取り替え時間が足りない箇所（警告）
+    pub changeover_shortfalls: Vec<ChangeoverBlock>,
+}
+
+/// 製品マスタの追加・編集リクエスト
+#[derive(Debug, Deserialize)]
+pub struct ProductRequest {
+    pub product_name: String,
+    pub display_name: Option<String>,
+    pub category: Option<String>,
+    pub weight_kg: f64,
+    pub default_efficiency: Option<String>,
+    #[serde(default = "default_true")]
+    pub active: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl ProductRequest {
+    fn into_product(self) -> Product {
+        Product {
+            id: None,
+            product_name: self.product_name,
+            display_name: self.display_name,
+            category: self.category,
+            weight_kg: self.weight_kg,
+            default_efficiency: self.default_efficiency,
+            active: self.active,
+            updated_at: None,
+        }
+    }
+}
+
+/// 製品マスタCSV取込の結果
+#[derive(Debug, Serialize)]
+pub struct ProductImportReport {
+    pub inserted: u32,
+    pub updated: u32,
+}
+
+/// 生産時間計算リクエスト（入力フォームのプレビュー用）
+#[derive(Debug, Deserialize)]
+pub struct CalculateDurationRequest {
+    pub product_name: String,
+    #[serde(default)]
+    pub line: String,
+    pub start_datetime: Option<String>,
+    /// 生産数量1〜8
+    #[serde(default)]
+    pub quantities: Vec<Option<f64>>,
+    /// 製綿能率1〜8（未設定の工程は製綿能率1を使う）
+    #[serde(default)]
+    pub efficiencies: Vec<Option<String>>,
+}
+
+/// 同期競合の解決リクエスト
+#[derive(Debug, Deserialize)]
+pub struct ResolveConflictRequest {
+    pub conflict_id: i64,
+    /// "keep_local" / "keep_remote" / "merge"
+    pub resolution: String,
+    /// merge時にフィールドごとに採用する側（"local" / "remote"）。未指定のフィールドはローカルを採用
+    pub fields: Option<HashMap<String, String>>,
+}
+
+/// 同期競合の一覧項目（差分フィールド付き）
+#[derive(Debug, Serialize)]
+pub struct SyncConflictView {
+    #[serde(flatten)]
+    pub conflict: SyncConflict,
+    pub differing_fields: Vec<String>,
 }
 
 /// レスポンス
@@ -70,6 +321,218 @@ pub struct ApiResponse<T> {
     pub error: Option<String>,
 }
 
+/// スキーマバージョン情報
+#[derive(Debug, Serialize)]
+pub struct SchemaVersionInfo {
+    /// DBに適用済みのバージョン
+    pub current: i64,
+    /// このアプリが対応するバージョン
+    pub latest: i64,
+}
+
+/// 製品マスタの重量から生産時間と終了日時を算出
+/// メモ・図形や生産数量が未入力の場合はNone
+fn estimate_production(
+    db: &Database,
+    product_name: &str,
+    line: &str,
+    start_datetime: Option<&str>,
+    quantities: &[Option<f64>],
+    efficiencies: &[Option<String>],
+) -> Result<Option<ProductionEstimate>, SchedulingError> {
+    if scheduling::is_fixed_duration(product_name) || quantities.iter().all(|q| q.unwrap_or(0.0) == 0.0) {
+        return Ok(None);
+    }
+
+    let weight = db
+        .get_product_weight(product_name)
+        .ok()
+        .flatten()
+        .filter(|w| *w > 0.0)
+        .ok_or_else(|| SchedulingError::UnknownWeight(product_name.to_string()))?;
+
+    let calendar = load_calendar(db).unwrap_or_else(|e| {
+        eprintln!("稼働カレンダーの読み込みに失敗（終日稼働として計算）: {}", e);
+        PlantCalendar::default()
+    });
+    scheduling::estimate(weight, start_datetime, quantities, efficiencies, &calendar, line).map(Some)
+}
+
+/// 稼働カレンダーを読み込み
+fn load_calendar(db: &Database) -> Result<PlantCalendar, String> {
+    let shifts = db.get_shift_patterns().map_err(|e| e.to_string())?;
+    let holidays = db.get_holidays().map_err(|e| e.to_string())?;
+    let maintenance = db.get_maintenance_windows().map_err(|e| e.to_string())?;
+    Ok(PlantCalendar::new(&shifts, &holidays, &maintenance))
+}
+
+/// 算出結果を受け取る
+/// 製品マスタに重量がない製品は算出できないため、入力された終了日時をそのまま使う（None）
+fn accept_estimate(
+    product_name: &str,
+    result: Result<Option<ProductionEstimate>, SchedulingError>,
+) -> Result<Option<ProductionEstimate>, String> {
+    match result {
+        Ok(Some(estimate)) => {
+            eprintln!("=== Production time: {:.2}h ({}) ===", estimate.production_hours, product_name);
+            Ok(Some(estimate))
+        }
+        Ok(None) => Ok(None),
+        Err(SchedulingError::UnknownWeight(product)) => {
+            eprintln!("=== 終了日時を算出できません（製品マスタに重量なし: {}） ===", product);
+            Ok(None)
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// 終了日時・合計数量を生産時間から算出し直す
+/// 製品マスタに重量がない製品は追加時と同じく、設定済みの終了日時をそのまま使う
+fn apply_production_estimate(db: &Database, schedule: &mut LocalSchedule) -> Result<(), String> {
+    let result = estimate_production(
+        db,
+        &schedule.product_name,
+        &schedule.line,
+        Some(&schedule.start_datetime),
+        &schedule.quantities(),
+        &schedule.efficiencies(),
+    );
+
+    if let Some(estimate) = accept_estimate(&schedule.product_name, result)? {
+        if estimate.end_datetime.is_some() {
+            schedule.end_datetime = estimate.end_datetime;
+        }
+        schedule.total_quantity = Some(estimate.total_quantity);
+    }
+    Ok(())
+}
+
+/// 開始日時を移したときの終了日時（元の所要時間を保つ）
+fn shifted_end(schedule: &LocalSchedule, new_start: &str) -> Option<String> {
+    let old_start = scheduling::parse_datetime(&schedule.start_datetime)?;
+    let old_end = scheduling::parse_datetime(schedule.end_datetime.as_deref()?)?;
+    let start = scheduling::parse_datetime(new_start)?;
+    Some(scheduling::format_datetime_like(new_start, start + (old_end - old_start)))
+}
+
+/// 製品マスタから品名・分類・製綿能率の未入力分を補完
+fn fill_from_product_master(db: &Database, request: &mut AddScheduleRequest) {
+    let Ok(Some(product)) = db.get_product(&request.product_name) else {
+        return;
+    };
+
+    let is_blank = |v: &Option<String>| v.as_deref().map(|s| s.trim().is_empty()).unwrap_or(true);
+    if is_blank(&request.product_display_name) {
+        request.product_display_name = product.display_name;
+    }
+    if is_blank(&request.category) {
+        request.category = product.category;
+    }
+    if is_blank(&request.efficiency) {
+        request.efficiency = product.default_efficiency;
+    }
+}
+
+/// 追加リクエストを製品マスタで補完し、終了日時・合計数量を生産時間から算出し直す
+fn prepare_add_request(db: &Database, request: &mut AddScheduleRequest) -> Result<(), String> {
+    fill_from_product_master(db, request);
+
+    let quantities = [
+        request.quantity1, request.quantity2, request.quantity3, request.quantity4,
+        request.quantity5, request.quantity6, request.quantity7, request.quantity8,
+    ];
+    let result = estimate_production(
+        db,
+        &request.product_name,
+        &request.line,
+        Some(&request.start_datetime),
+        &quantities,
+        std::slice::from_ref(&request.efficiency),
+    );
+
+    if let Some(estimate) = accept_estimate(&request.product_name, result)? {
+        if estimate.end_datetime.is_some() {
+            request.end_datetime = estimate.end_datetime;
+        }
+        request.total_quantity = Some(estimate.total_quantity);
+    }
+    Ok(())
+}
+
+/// 追加リクエストの時間枠
+fn request_slot(request: &AddScheduleRequest) -> ScheduleSlot<'_> {
+    ScheduleSlot {
+        id: None,
+        line: &request.line,
+        product_name: &request.product_name,
+        start_datetime: &request.start_datetime,
+        end_datetime: request.end_datetime.as_deref(),
+    }
+}
+
+/// 同じラインで時間帯が重なるスケジュールを取得
+fn check_overlaps(db: &Database, slot: &ScheduleSlot) -> Result<Vec<ScheduleOverlap>, String> {
+    let schedules = db.get_all_schedules().map_err(|e| e.to_string())?;
+    Ok(validation::find_overlaps(slot, &schedules))
+}
+
+/// 段取り替えマトリクスを読み込み
+fn load_changeovers(db: &Database) -> Result<ChangeoverMatrix, String> {
+    let changeovers = db.get_changeovers().map_err(|e| e.to_string())?;
+    Ok(ChangeoverMatrix::new(&changeovers))
+}
+
+/// 保存したスケジュールの前後で段取り替え時間が足りない箇所（取得できなければ空）
+fn find_changeover_shortfalls(db: &Database, id: i64) -> Vec<ChangeoverBlock> {
+    let result = (|| -> Result<Vec<ChangeoverBlock>, String> {
+        let schedules = db.get_all_schedules().map_err(|e| e.to_string())?;
+        let blocks = changeover::compute_blocks(&schedules, &load_changeovers(db)?, &load_calendar(db)?);
+        Ok(changeover::shortfalls_for(blocks, id))
+    })();
+
+    match result {
+        Ok(shortfalls) => {
+            if !shortfalls.is_empty() {
+                eprintln!("=== Warning: {} ===", changeover::describe_shortfalls(&shortfalls));
+            }
+            shortfalls
+        }
+        Err(e) => {
+            eprintln!("段取り替えチェックに失敗: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// 編集操作を取り消し履歴に記録
+/// beforeは変更前に控えた内容。変更後の内容は現在のDBから読み、変わっていないスケジュールは記録しない
+fn record_undo(state: &AppState, db: &Database, label: String, before: Vec<(i64, Option<LocalSchedule>)>) {
+    let changes = before
+        .into_iter()
+        .map(|(id, before)| db.get_schedule(id).map(|after| ScheduleChange { schedule_id: id, before, after }))
+        .collect::<rusqlite::Result<Vec<_>>>();
+    match changes {
+        Ok(changes) => {
+            let changes = changes
+                .into_iter()
+                .filter(|c| !undo::matches_recorded(c.after.as_ref(), c.before.as_ref()))
+                .collect();
+            state.undo.lock().unwrap().record(UndoEntry { label, changes });
+        }
+        Err(e) => eprintln!("取り消し履歴の記録に失敗しました: {}", e),
+    }
+}
+
+/// 重複のため保存しなかったことを返す
+fn overlap_rejection(id: Option<i64>, overlaps: Vec<ScheduleOverlap>) -> ApiResponse<ScheduleSaveResult> {
+    eprintln!("=== Rejected: {} overlapping schedules ===", overlaps.len());
+    ApiResponse {
+        success: false,
+        error: Some(validation::describe_overlaps(&overlaps)),
+        data: Some(ScheduleSaveResult { id, overlaps, changeover_shortfalls: Vec::new() }),
+    }
+}
+
 /// スケジュール一覧を取得
 #[tauri::command]
 pub fn get_schedules(state: State<AppState>) -> ApiResponse<Vec<LocalSchedule>> {
@@ -92,7 +555,7 @@ pub fn get_schedules(state: State<AppState>) -> ApiResponse<Vec<LocalSchedule>>
 /// kintone-immediate-sync feature が有効な場合はkintoneに即座に追加
 /// 無効な場合はローカルDBのみに保存（後でsync_to_kintoneで同期）
 #[tauri::command]
-pub async fn add_schedule(request: AddScheduleRequest, state: State<'_, AppState>) -> Result<ApiResponse<i64>, ()> {
+pub async fn add_schedule(request: AddScheduleRequest, state: State<'_, AppState>) -> Result<ApiResponse<ScheduleSaveResult>, ()> {
     #[cfg(feature = "kintone-immediate-sync")]
     {
         // 方法B: kintone即時同期
@@ -108,11 +571,23 @@ pub async fn add_schedule(request: AddScheduleRequest, state: State<'_, AppState
 
 /// 方法B: kintone即時同期版
 #[tauri::command]
-pub async fn add_schedule_with_kintone_sync(request: AddScheduleRequest, state: State<'_, AppState>) -> Result<ApiResponse<i64>, ()> {
-    // MMO（メモ）とSHAP（図形）もkintone同期する
-    // if request.product_name == "MMO" || request.product_name == "SHAP" {
-    //    return add_memo_local_only(request, state);
-    // }
+pub async fn add_schedule_with_kintone_sync(mut request: AddScheduleRequest, state: State<'_, AppState>) -> Result<ApiResponse<ScheduleSaveResult>, ()> {
+    // MMO（メモ）とSHAP（図形）もkintone同期する（ID507）
+
+    // 製品マスタで補完し、終了日時は生産時間から算出
+    let overlaps = {
+        let db = state.db.lock().unwrap();
+        if let Err(e) = prepare_add_request(&db, &mut request) {
+            return Ok(ApiResponse { success: false, data: None, error: Some(e) });
+        }
+        match check_overlaps(&db, &request_slot(&request)) {
+            Ok(overlaps) => overlaps,
+            Err(e) => return Ok(ApiResponse { success: false, data: None, error: Some(e) }),
+        }
+    };
+    if !overlaps.is_empty() && !request.allow_overlap {
+        return Ok(overlap_rejection(None, overlaps));
+    }
 
     // kintoneクライアントを取得
     let client_opt = {
@@ -139,90 +614,10 @@ pub async fn add_schedule_with_kintone_sync(request: AddScheduleRequest, state:
     eprintln!("=== Generated schedule number: {} ===", schedule_number);
 
     let is_memo = request.product_name == "MMO" || request.product_name == "SHAP";
-    let record = if is_memo {
-        // ID 507: メモ・図形用
-        serde_json::json!({
-            "product_name": { "value": request.product_name }, // 種別
-            "notes": { "value": request.notes.clone().unwrap_or_default() }, // 内容
-            "start_datetime": { "value": request.start_datetime },
-            "end_datetime": { "value": request.end_datetime },
-            "production_status": { "value": request.production_status.clone().unwrap_or("未生産".to_string()) },
-        })
-    } else {
-        // ID 506: スケジュール用
-        serde_json::json!({
-            "product_name": { "value": request.product_name },
-            "start_datetime": { "value": request.start_datetime },
-            "end_datetime": { "value": request.end_datetime },
-            "quantity": { "value": request.quantity1.map(|v| v.to_string()) },
-            "status": { "value": request.production_status.clone().unwrap_or("未生産".to_string()) },
-            "schedule_number": { "value": schedule_number.clone() },
-            "製造備考": { "value": request.notes.clone().unwrap_or_default() },
-            "製綿能率": { "value": request.efficiency.clone().unwrap_or_default() },
-        })
-    };
-
-    let kintone_id = match client.add_record(record, is_memo).await {
-        Ok(id) => id,
-        Err(e) => {
-            eprintln!("kintone add_record error: {}", e);
-            return Ok(ApiResponse {
-                success: false,
-                data: None,
-                error: Some(format!("kintoneへの追加に失敗しました: {}", e)),
-            });
-        }
-    };
-
-    eprintln!("=== kintone record created: id={} ===", kintone_id);
-
-    // 分類に応じてID354（山積表）またはID368（小袋実績）に送信
-    if let Some(ref category) = request.category {
-        if let Some(ref prod_status) = request.production_status {
-            if prod_status == "未生産" {
-                let is_kobukuro = category == "小袋";
-                let is_baler = category == "ベーラー";
-
-                if is_kobukuro || is_baler {
-                    let secondary_record = if is_kobukuro {
-                        // ID368（小袋実績）用のレコード
-                        serde_json::json!({
-                            "品番": { "value": request.product_name.clone() },
-                            "品名": { "value": request.product_display_name.clone().unwrap_or_default() },
-                            "製造予定日時": { "value": request.start_datetime.clone() },
-                            "製造数量": { "value": request.quantity1.map(|v| v.to_string()).unwrap_or_default() },
-                            "生産状況": { "value": prod_status.clone() },
-                            "スケジュール番号": { "value": schedule_number.clone() },
-                            "製造備考": { "value": request.notes.clone().unwrap_or_default() },
-                        })
-                    } else {
-                        // ID354（山積表）用のレコード
-                        serde_json::json!({
-                            "品番": { "value": request.product_name.clone() },
-                            "品名": { "value": request.product_display_name.clone().unwrap_or_default() },
-                            "製造予定日時": { "value": request.start_datetime.clone() },
-                            "生産状況": { "value": prod_status.clone() },
-                            "スケジュール番号": { "value": schedule_number.clone() },
-                            "コメント": { "value": request.notes.clone().unwrap_or_default() },
-                        })
-                    };
-
-                    eprintln!("=== Syncing to {} ===", if is_kobukuro { "ID368（小袋実績）" } else { "ID354（山積表）" });
-                    
-                    if let Err(e) = client.sync_to_yamazumi_or_kobukuro(&schedule_number, secondary_record, is_kobukuro).await {
-                        eprintln!("=== Secondary sync warning: {} ===", e);
-                        // エラーがあっても処理は続行（メインのID506は成功しているため）
-                    }
-                }
-            }
-        }
-    }
-
-    // ローカルDBに保存
     let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-    let schedule = LocalSchedule {
+    let mut schedule = LocalSchedule {
         id: None,
-        kintone_record_id: Some(kintone_id),
+        kintone_record_id: None,
         schedule_number: Some(schedule_number),
         product_name: request.product_name,
         product_display_name: request.product_display_name,
@@ -252,32 +647,85 @@ pub async fn add_schedule_with_kintone_sync(request: AddScheduleRequest, state:
         sync_status: "synced".to_string(),
         created_at: now.clone(),
         updated_at: now,
+        kintone_revision: None,
     };
 
-    let db = state.db.lock().unwrap();
-    eprintln!("=== Saving to local DB: {} ===", schedule.product_name);
-    match db.add_schedule(&schedule) {
-        Ok(id) => {
-            eprintln!("=== Local DB saved: id={} ===", id);
-            Ok(ApiResponse {
-                success: true,
-                data: Some(id),
-                error: None,
-            })
-        },
+    // ID 506（スケジュール）・ID 507（メモ・図形）のフィールド対応付けでレコードを作成
+    let record = state.field_mappings.schedule(is_memo).build_record(&schedule);
+
+    let saved = match client.add_record(record, is_memo).await {
+        Ok(saved) => saved,
         Err(e) => {
-            eprintln!("=== Local DB error: {} ===", e);
-            Ok(ApiResponse {
+            eprintln!("kintone add_record error: {}", e);
+            return Ok(ApiResponse {
                 success: false,
                 data: None,
-                error: Some(e.to_string()),
-            })
-        },
-    }
+                error: Some(format!("kintoneへの追加に失敗しました: {}", e)),
+            });
+        }
+    };
+
+    eprintln!("=== kintone record created: id={} ===", saved.id);
+
+    // ローカルDBに保存
+    schedule.kintone_record_id = Some(saved.id);
+    schedule.kintone_revision = saved.revision;
+
+    let id = {
+        let db = state.db.lock().unwrap();
+        eprintln!("=== Saving to local DB: {} ===", schedule.product_name);
+        match db.add_schedule(&schedule) {
+            Ok(id) => {
+                eprintln!("=== Local DB saved: id={} ===", id);
+                record_undo(&state, &db, format!("「{}」の追加", schedule.product_name), vec![(id, None)]);
+                id
+            },
+            Err(e) => {
+                eprintln!("=== Local DB error: {} ===", e);
+                return Ok(ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some(e.to_string()),
+                });
+            },
+        }
+    };
+
+    // 分類に応じてID354（山積表）またはID368（小袋実績）に反映
+    // 失敗しても追加は完了している（連携状態に記録し、次回のkintone送信で再送する）
+    let mut report = PushReport::default();
+    push_downstream(&client, &state, &[id], &mut report).await;
+
+    let db = state.db.lock().unwrap();
+    Ok(ApiResponse {
+        success: true,
+        data: Some(ScheduleSaveResult {
+            id: Some(id),
+            overlaps,
+            changeover_shortfalls: find_changeover_shortfalls(&db, id),
+        }),
+        error: if report.errors.is_empty() { None } else { Some(report.errors.join("\n")) },
+    })
 }
 
-/// MEMO専用: kintone同期せずローカルのみ保存
-fn add_memo_local_only(request: AddScheduleRequest, state: State<'_, AppState>) -> Result<ApiResponse<i64>, ()> {
+/// 方法A: ローカルのみ保存版（後でsync_to_kintoneで同期）
+#[cfg(not(feature = "kintone-immediate-sync"))]
+fn add_schedule_local_only(mut request: AddScheduleRequest, state: State<'_, AppState>) -> Result<ApiResponse<ScheduleSaveResult>, ()> {
+    let db = state.db.lock().unwrap();
+
+    // 製品マスタで補完し、終了日時は生産時間から算出
+    if let Err(e) = prepare_add_request(&db, &mut request) {
+        return Ok(ApiResponse { success: false, data: None, error: Some(e) });
+    }
+
+    let overlaps = match check_overlaps(&db, &request_slot(&request)) {
+        Ok(overlaps) => overlaps,
+        Err(e) => return Ok(ApiResponse { success: false, data: None, error: Some(e) }),
+    };
+    if !overlaps.is_empty() && !request.allow_overlap {
+        return Ok(overlap_rejection(None, overlaps));
+    }
+
     let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
     let schedule = LocalSchedule {
         id: None,
@@ -298,7 +746,7 @@ fn add_memo_local_only(request: AddScheduleRequest, state: State<'_, AppState>)
         quantity7: request.quantity7,
         quantity8: request.quantity8,
         total_quantity: request.total_quantity,
-        efficiency1: None,
+        efficiency1: request.efficiency,
         efficiency2: None,
         efficiency3: None,
         efficiency4: None,
@@ -308,24 +756,29 @@ fn add_memo_local_only(request: AddScheduleRequest, state: State<'_, AppState>)
         efficiency8: None,
         production_status: request.production_status.unwrap_or("未生産".to_string()),
         notes: request.notes,
-        sync_status: "local_only".to_string(),
+        sync_status: "pending".to_string(),
         created_at: now.clone(),
         updated_at: now,
+        kintone_revision: None,
     };
 
-    let db = state.db.lock().unwrap();
-    eprintln!("=== Saving MEMO to local DB only ===");
+    eprintln!("=== Saving to local DB: {} ===", schedule.product_name);
     match db.add_schedule(&schedule) {
         Ok(id) => {
-            eprintln!("=== MEMO saved: id={} ===", id);
+            eprintln!("=== Local DB saved: id={} ===", id);
+            record_undo(&state, &db, format!("「{}」の追加", schedule.product_name), vec![(id, None)]);
             Ok(ApiResponse {
                 success: true,
-                data: Some(id),
+                data: Some(ScheduleSaveResult {
+                    id: Some(id),
+                    overlaps,
+                    changeover_shortfalls: find_changeover_shortfalls(&db, id),
+                }),
                 error: None,
             })
         },
         Err(e) => {
-            eprintln!("=== MEMO save error: {} ===", e);
+            eprintln!("=== Local DB error: {} ===", e);
             Ok(ApiResponse {
                 success: false,
                 data: None,
@@ -335,74 +788,115 @@ fn add_memo_local_only(request: AddScheduleRequest, state: State<'_, AppState>)
     }
 }
 
-/// 方法A: ローカルのみ保存版（後でsync_to_kintoneで同期）
-#[cfg(not(feature = "kintone-immediate-sync"))]
-fn add_schedule_local_only(request: AddScheduleRequest, state: State<'_, AppState>) -> Result<ApiResponse<i64>, ()> {
-    let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-    let schedule = LocalSchedule {
-        id: None,
-        kintone_record_id: None,
-        schedule_number: None,
-        product_name: request.product_name,
-        product_display_name: request.product_display_name,
-        category: request.category,
-        line: request.line,
-        start_datetime: request.start_datetime,
-        end_datetime: request.end_datetime,
-        quantity1: request.quantity1,
-        quantity2: request.quantity2,
-        quantity3: request.quantity3,
-        quantity4: request.quantity4,
-        quantity5: request.quantity5,
-        quantity6: request.quantity6,
-        quantity7: request.quantity7,
-        quantity8: request.quantity8,
-        total_quantity: request.total_quantity,
-        efficiency1: request.efficiency,
-        efficiency2: None,
-        efficiency3: None,
-        efficiency4: None,
-        efficiency5: None,
-        efficiency6: None,
-        efficiency7: None,
-        efficiency8: None,
-        production_status: request.production_status.unwrap_or("未生産".to_string()),
-        notes: request.notes,
-        sync_status: "pending".to_string(),
-        created_at: now.clone(),
-        updated_at: now,
-    };
-
+/// スケジュールを更新
+/// 製品・生産数量・製綿能率・日時が変わった場合は終了日時を生産時間から再計算する
+/// （送られた終了日時を使うのは、メモ・図形や重量未登録の製品など生産時間を算出できないスケジュールのみ）
+/// 開始日時だけが送られた場合は元の所要時間を保って終了日時を移す
+/// 日時が変わる場合は同じラインの重複をチェックし、allow_overlapがなければ保存しない
+#[tauri::command]
+pub fn update_schedule(request: UpdateScheduleRequest, state: State<AppState>) -> ApiResponse<ScheduleSaveResult> {
     let db = state.db.lock().unwrap();
-    eprintln!("=== Saving to local DB: {} ===", schedule.product_name);
-    match db.add_schedule(&schedule) {
-        Ok(id) => {
-            eprintln!("=== Local DB saved: id={} ===", id);
-            Ok(ApiResponse {
-                success: true,
-                data: Some(id),
-                error: None,
-            })
-        },
-        Err(e) => {
-            eprintln!("=== Local DB error: {} ===", e);
-            Ok(ApiResponse {
+    let before = db.get_schedule(request.id).ok().flatten();
+
+    let quantities = [
+        request.quantity1, request.quantity2, request.quantity3, request.quantity4,
+        request.quantity5, request.quantity6, request.quantity7, request.quantity8,
+    ];
+    let efficiencies = [
+        &request.efficiency1, &request.efficiency2, &request.efficiency3, &request.efficiency4,
+        &request.efficiency5, &request.efficiency6, &request.efficiency7, &request.efficiency8,
+    ];
+    let production_changed = request.product_name.is_some()
+        || quantities.iter().any(|q| q.is_some())
+        || efficiencies.iter().any(|e| e.is_some());
+
+    let mut overlaps = Vec::new();
+
+    if production_changed || request.start_datetime.is_some() || request.end_datetime.is_some() {
+        let mut schedule = match db.get_schedule(request.id) {
+            Ok(Some(s)) => s,
+            Ok(None) => {
+                return ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some("スケジュールが見つかりません".to_string()),
+                };
+            }
+            Err(e) => {
+                return ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some(e.to_string()),
+                };
+            }
+        };
+
+        if let Some(ref product_name) = request.product_name {
+            if *product_name != schedule.product_name {
+                schedule.product_name = product_name.clone();
+                if let Ok(Some(product)) = db.get_product(product_name) {
+                    schedule.product_display_name = product.display_name;
+                    schedule.category = product.category;
+                }
+            }
+        }
+        let slots = [
+            &mut schedule.quantity1, &mut schedule.quantity2, &mut schedule.quantity3, &mut schedule.quantity4,
+            &mut schedule.quantity5, &mut schedule.quantity6, &mut schedule.quantity7, &mut schedule.quantity8,
+        ];
+        for (slot, value) in slots.into_iter().zip(quantities) {
+            if value.is_some() {
+                *slot = value;
+            }
+        }
+        let slots = [
+            &mut schedule.efficiency1, &mut schedule.efficiency2, &mut schedule.efficiency3, &mut schedule.efficiency4,
+            &mut schedule.efficiency5, &mut schedule.efficiency6, &mut schedule.efficiency7, &mut schedule.efficiency8,
+        ];
+        for (slot, value) in slots.into_iter().zip(efficiencies) {
+            if value.is_some() {
+                *slot = value.clone();
+            }
+        }
+        if let Some(ref start) = request.start_datetime {
+            if request.end_datetime.is_none() {
+                schedule.end_datetime = shifted_end(&schedule, start);
+            }
+            schedule.start_datetime = start.clone();
+        }
+        if request.end_datetime.is_some() {
+            schedule.end_datetime = request.end_datetime.clone();
+        }
+
+        if let Err(e) = apply_production_estimate(&db, &mut schedule) {
+            return ApiResponse {
                 success: false,
                 data: None,
-                error: Some(e.to_string()),
-            })
-        },
-    }
-}
+                error: Some(e),
+            };
+        }
 
-/// スケジュールを更新
-#[tauri::command]
-pub fn update_schedule(request: UpdateScheduleRequest, state: State<AppState>) -> ApiResponse<()> {
-    let db = state.db.lock().unwrap();
-    
-    // 日時の更新
-    if let Some(ref start) = request.start_datetime {
-        if let Err(e) = db.update_schedule_datetime(request.id, start, request.end_datetime.as_deref()) {
+        overlaps = match check_overlaps(&db, &ScheduleSlot::from(&schedule)) {
+            Ok(overlaps) => overlaps,
+            Err(e) => {
+                return ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some(e),
+                };
+            }
+        };
+        if !overlaps.is_empty() && !request.allow_overlap {
+            return overlap_rejection(Some(request.id), overlaps);
+        }
+
+        let result = if production_changed {
+            db.update_schedule_production(&schedule)
+        } else {
+            // 日時の更新
+            db.update_schedule_datetime(request.id, &schedule.start_datetime, schedule.end_datetime.as_deref())
+        };
+        if let Err(e) = result {
             return ApiResponse {
                 success: false,
                 data: None,
@@ -422,40 +916,67 @@ pub fn update_schedule(request: UpdateScheduleRequest, state: State<AppState>) -
         }
     }
     
+    let label = format!("「{}」の変更", before.as_ref().map(|s| s.product_name.as_str()).unwrap_or_default());
+    record_undo(&state, &db, label, vec![(request.id, before)]);
+
     ApiResponse {
         success: true,
-        data: Some(()),
+        data: Some(ScheduleSaveResult {
+            id: Some(request.id),
+            overlaps,
+            changeover_shortfalls: find_changeover_shortfalls(&db, request.id),
+        }),
         error: None,
     }
 }
 
+/// 入力されたトークンを採用し、空欄なら保存済みのトークンを使う
+fn merge_token(input: Option<String>, saved: Option<String>) -> Option<String> {
+    input
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .or(saved.filter(|t| !t.is_empty()))
+}
+
 /// kintone設定を保存
 #[tauri::command]
 pub fn save_kintone_config(config: KintoneConfigRequest, state: State<AppState>) -> ApiResponse<()> {
+    let saved = credentials::load_kintone_config();
+    let saved_token = |f: fn(&KintoneConfig) -> Option<String>| saved.as_ref().and_then(f);
+
     let kintone_config = KintoneConfig {
-        subdomain: config.subdomain.clone(),
+        subdomain: config.subdomain.trim().to_string(),
         app_id: config.app_id,
-        api_token: config.api_token.clone(),
+        api_token: merge_token(config.api_token, saved_token(|c| Some(c.api_token.clone())))
+            .unwrap_or_default(),
         memo_app_id: config.memo_app_id,
-        memo_api_token: config.memo_api_token.clone(),
+        memo_api_token: merge_token(config.memo_api_token, saved_token(|c| c.memo_api_token.clone())),
         yamazumi_app_id: config.yamazumi_app_id,
-        yamazumi_api_token: config.yamazumi_api_token.clone(),
+        yamazumi_api_token: merge_token(config.yamazumi_api_token, saved_token(|c| c.yamazumi_api_token.clone())),
         kobukuro_app_id: config.kobukuro_app_id,
-        kobukuro_api_token: config.kobukuro_api_token.clone(),
+        kobukuro_api_token: merge_token(config.kobukuro_api_token, saved_token(|c| c.kobukuro_api_token.clone())),
         tsumikomi_app_id: config.tsumikomi_app_id,
-        tsumikomi_api_token: config.tsumikomi_api_token.clone(),
+        tsumikomi_api_token: merge_token(config.tsumikomi_api_token, saved_token(|c| c.tsumikomi_api_token.clone())),
+        legacy_app_id: config.legacy_app_id,
+        legacy_api_token: merge_token(config.legacy_api_token, saved_token(|c| c.legacy_api_token.clone())),
     };
 
-    // 設定をJSONファイルに保存
-    if let Some(config_dir) = dirs::data_local_dir() {
-        let config_path = config_dir.join("production-scheduler").join("kintone_config.json");
-        if let Ok(json) = serde_json::to_string_pretty(&kintone_config) {
-            if let Err(e) = std::fs::write(&config_path, json) {
-                eprintln!("設定ファイルの保存に失敗: {}", e);
-            } else {
-                eprintln!("設定を保存しました: {:?}", config_path);
-            }
-        }
+    if !credentials::is_configured(&kintone_config) {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some("サブドメイン・アプリID・スケジュール用APIトークンは必須です".to_string()),
+        };
+    }
+
+    // APIトークンは暗号化して保存
+    if let Err(e) = credentials::save_kintone_config(&kintone_config) {
+        eprintln!("設定ファイルの保存に失敗: {}", e);
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some(format!("設定の保存に失敗しました: {}", e)),
+        };
     }
 
     match KintoneClient::new(kintone_config) {
@@ -476,6 +997,59 @@ pub fn save_kintone_config(config: KintoneConfigRequest, state: State<AppState>)
     }
 }
 
+/// 現在のkintone設定を取得（APIトークンは伏せる）
+#[tauri::command]
+pub fn get_kintone_config(state: State<AppState>) -> ApiResponse<KintoneConfigView> {
+    let config = {
+        let kintone = state.kintone_client.lock().unwrap();
+        kintone.as_ref().map(|c| c.config().clone())
+    }
+    .or_else(credentials::load_kintone_config);
+
+    let has = |token: Option<&String>| token.map(|t| !t.is_empty()).unwrap_or(false);
+
+    let view = match config {
+        Some(c) => KintoneConfigView {
+            configured: credentials::is_configured(&c),
+            subdomain: c.subdomain.clone(),
+            app_id: Some(c.app_id).filter(|id| *id != 0),
+            memo_app_id: c.memo_app_id,
+            yamazumi_app_id: c.yamazumi_app_id,
+            kobukuro_app_id: c.kobukuro_app_id,
+            tsumikomi_app_id: c.tsumikomi_app_id,
+            legacy_app_id: c.legacy_app_id,
+            has_api_token: !c.api_token.is_empty(),
+            has_memo_api_token: has(c.memo_api_token.as_ref()),
+            has_yamazumi_api_token: has(c.yamazumi_api_token.as_ref()),
+            has_kobukuro_api_token: has(c.kobukuro_api_token.as_ref()),
+            has_tsumikomi_api_token: has(c.tsumikomi_api_token.as_ref()),
+            has_legacy_api_token: has(c.legacy_api_token.as_ref()),
+        },
+        None => KintoneConfigView {
+            configured: false,
+            subdomain: String::new(),
+            app_id: None,
+            memo_app_id: None,
+            yamazumi_app_id: None,
+            kobukuro_app_id: None,
+            tsumikomi_app_id: None,
+            legacy_app_id: None,
+            has_api_token: false,
+            has_memo_api_token: false,
+            has_yamazumi_api_token: false,
+            has_kobukuro_api_token: false,
+            has_tsumikomi_api_token: false,
+            has_legacy_api_token: false,
+        },
+    };
+
+    ApiResponse {
+        success: true,
+        data: Some(view),
+        error: None,
+    }
+}
+
 /// 文字列またはnullの値を取得するヘルパー
 fn get_string_value(record: &serde_json::Value, field: &str) -> String {
     if let Some(field_obj) = record.get(field) {
@@ -496,72 +1070,269 @@ fn get_optional_string_value(record: &serde_json::Value, field: &str) -> Option<
     if val.is_empty() { None } else { Some(val) }
 }
 
-/// 数値フィールドの値を取得するヘルパー
-fn get_number_value(record: &serde_json::Value, field: &str) -> Option<f64> {
-    if let Some(field_obj) = record.get(field) {
-        if let Some(val) = field_obj.get("value") {
-            match val {
-                serde_json::Value::String(s) if !s.is_empty() => return s.parse().ok(),
-                serde_json::Value::Number(n) => return n.as_f64(),
-                _ => return None,
+/// kintoneレコードの$id / $revisionを取得するヘルパー
+fn get_record_meta(record: &serde_json::Value) -> (Option<u32>, Option<i64>) {
+    let id = get_string_value(record, "$id").parse().ok();
+    let revision = get_string_value(record, "$revision").parse().ok();
+    (id, revision)
+}
+
+/// 対応付けの値を入れる前の空のスケジュール
+fn blank_schedule(sync_status: &str) -> LocalSchedule {
+    let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    LocalSchedule {
+        id: None,
+        kintone_record_id: None,
+        schedule_number: None,
+        product_name: String::new(),
+        product_display_name: None,
+        category: None,
+        line: String::new(),
+        start_datetime: String::new(),
+        end_datetime: None,
+        quantity1: None, quantity2: None, quantity3: None, quantity4: None,
+        quantity5: None, quantity6: None, quantity7: None, quantity8: None,
+        total_quantity: None,
+        efficiency1: None, efficiency2: None, efficiency3: None, efficiency4: None,
+        efficiency5: None, efficiency6: None, efficiency7: None, efficiency8: None,
+        production_status: String::new(),
+        notes: None,
+        sync_status: sync_status.to_string(),
+        created_at: now.clone(),
+        updated_at: now,
+        kintone_revision: None,
+    }
+}
+
+/// ID506（スケジュール）・ID507（メモ・図形）のレコードを対応付けに従ってLocalScheduleに変換
+fn parse_schedule_record(mapping: &AppMapping, record: &serde_json::Value) -> Option<LocalSchedule> {
+    let (kintone_record_id, kintone_revision) = get_record_meta(record);
+    kintone_record_id?;
+
+    let base = LocalSchedule { kintone_record_id, kintone_revision, ..blank_schedule("synced") };
+    match mapping.parse_record(record, base) {
+        Ok(schedule) if !schedule.product_name.is_empty() => Some(schedule),
+        Ok(_) => None,
+        Err(e) => {
+            eprintln!("Failed to parse kintone record {:?} ({}): {}", kintone_record_id, mapping.app, e);
+            None
+        }
+    }
+}
+
+/// kintoneからの取得結果
+#[derive(Debug, Default, Serialize)]
+pub struct FetchReport {
+    /// 追加・更新したスケジュールの件数
+    pub imported: u32,
+    /// kintone側で削除されていたためゴミ箱へ移した件数
+    pub removed: usize,
+    /// 取り込んだスケジュールと同じラインで時間が重なるもの（取り込みは行い、警告として返す）
+    pub overlaps: Vec<ScheduleOverlap>,
+}
+
+/// 1アプリ分の差分を取得してローカルDBに反映（件数・重複は report に追加する）
+async fn pull_app_changes(client: &KintoneClient, state: &State<'_, AppState>, is_memo: bool, report: &mut FetchReport) -> Result<(), String> {
+    let (app_id, _) = client.get_app_credentials(is_memo);
+    let mapping = state.field_mappings.schedule(is_memo);
+    let updated_field = mapping.updated_time_field.as_str();
+
+    let previous = {
+        let db = state.db.lock().unwrap();
+        db.get_sync_state(app_id).map_err(|e| e.to_string())?
+    };
+
+    // 更新日時は分単位のため、同時刻のレコードを取りこぼさないよう「以上」で取得する
+    let query = match previous.as_ref().and_then(|s| s.last_modified.as_deref()) {
+        Some(last) => format!("{0} >= \"{1}\" order by {0} asc, $id asc", updated_field, last),
+        None => format!("order by {} asc, $id asc", updated_field),
+    };
+
+    let records = client.get_all_records(Some(&query), is_memo).await.map_err(|e| e.to_string())?;
+    eprintln!("=== App {}: {} changed records ===", app_id, records.len());
+
+    let mut imported = Vec::new();
+    let mut latest = previous.clone().unwrap_or(SyncState {
+        app_id,
+        last_modified: None,
+        last_record_id: None,
+        last_revision: None,
+    });
+
+    let columns = mapping.pulled_columns();
+    let mut failed = None;
+    let db = state.db.lock().unwrap();
+    for record in &records {
+        let (record_id, revision) = get_record_meta(record);
+
+        // 前回最後に取得したレコードが変わっていなければスキップ
+        let unchanged = previous.as_ref().is_some_and(|p| {
+            p.last_record_id == record_id && p.last_revision == revision
+        });
+
+        if !unchanged {
+            if let Some(schedule) = parse_schedule_record(mapping, record) {
+                match db.import_from_kintone(&schedule, &columns) {
+                    Ok(ImportOutcome::Inserted(id)) | Ok(ImportOutcome::Updated(id)) => {
+                        imported.push(id);
+                        report.imported += 1;
+                    }
+                    Ok(ImportOutcome::SkippedLocalChanges(id)) => {
+                        eprintln!("=== Skipped kintone record {:?}: local id {} has unsent changes ===", record_id, id);
+                    }
+                    Ok(ImportOutcome::Conflict(id)) => {
+                        eprintln!("=== Conflict on kintone record {:?}: local id {} changed on both sides ===", record_id, id);
+                    }
+                    Ok(ImportOutcome::SkippedDeleted(id)) => {
+                        eprintln!("=== Skipped kintone record {:?}: local id {} is in the trash ===", record_id, id);
+                    }
+                    Err(e) => {
+                        // 取り込めなかったレコードより先に進めない（次回の取得でこのレコードから取り直す）
+                        eprintln!("Import error (kintone id {:?}): {}", record_id, e);
+                        failed = Some(format!("kintoneのレコード {} を取り込めませんでした: {}", record_id.unwrap_or_default(), e));
+                        break;
+                    }
+                }
             }
         }
+
+        latest.last_modified = get_optional_string_value(record, updated_field).or(latest.last_modified);
+        latest.last_record_id = record_id;
+        latest.last_revision = revision;
+    }
+
+    // kintone側の内容は取り込んだうえで、同じラインの重複を警告する
+    if !imported.is_empty() {
+        let schedules = db.get_all_schedules().map_err(|e| e.to_string())?;
+        for schedule in schedules.iter().filter(|s| s.id.is_some_and(|id| imported.contains(&id))) {
+            // 取り込んだスケジュール同士の重複は1件にまとめる
+            let overlaps: Vec<ScheduleOverlap> = validation::find_overlaps(&ScheduleSlot::from(schedule), &schedules)
+                .into_iter()
+                .filter(|o| !imported.contains(&o.other_id) || o.schedule_id.is_some_and(|id| id < o.other_id))
+                .collect();
+            if !overlaps.is_empty() {
+                eprintln!("=== Overlap warning (local id {:?}): {} ===", schedule.id, validation::describe_overlaps(&overlaps));
+                report.overlaps.extend(overlaps);
+            }
+        }
+    }
+
+    db.save_sync_state(&latest).map_err(|e| e.to_string())?;
+    match failed {
+        Some(message) => Err(message),
+        None => Ok(()),
+    }
+}
+
+/// kintone側で削除されたレコードを検出し、ローカルのスケジュールをゴミ箱へ移す（移した件数を返す）
+/// 差分取得では削除を検出できないため、アプリの全レコードIDとローカルのkintone_record_idを突き合わせる
+/// belongs はこのアプリに保存するスケジュールか。未送信の変更がある行（pending/modified/conflict）は対象にしない
+async fn trash_remote_deletions(client: &KintoneClient, state: &State<'_, AppState>, is_memo: bool, belongs: impl Fn(&LocalSchedule) -> bool) -> Result<usize, String> {
+    let remote_ids: HashSet<u32> = client.get_all_record_ids(is_memo).await.map_err(|e| e.to_string())?.into_iter().collect();
+
+    let db = state.db.lock().unwrap();
+    let missing: Vec<i64> = db
+        .get_all_schedules()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|s| belongs(s) && s.sync_status == "synced")
+        .filter(|s| s.kintone_record_id.is_some_and(|kid| !remote_ids.contains(&kid)))
+        .filter_map(|s| s.id)
+        .collect();
+    if !missing.is_empty() {
+        eprintln!("=== {} schedules were deleted in kintone: {:?} ===", missing.len(), missing);
+        db.trash_remote_deletions(&missing).map_err(|e| e.to_string())?;
     }
-    None
+    Ok(missing.len())
 }
 
-/// kintoneからスケジュールを取得して保存
+/// kintoneから変更分のスケジュールを取得して保存（差分同期）
+/// アプリごとに前回の更新日時以降のレコードのみ取得し、ローカルIDを維持したまま反映する
+/// 取り込んだスケジュールが同じラインの他のスケジュールと重なる場合は結果の overlaps で知らせる
 #[tauri::command]
-pub async fn fetch_from_kintone(state: State<'_, AppState>) -> Result<ApiResponse<u32>, ()> {
-    
-    // Hardcoded migration source (App 351)
-    let migration_config = KintoneConfig {
-        subdomain: "jfe-rockfiber".to_string(),
-        app_id: 351,
-        api_token: "xZ85wdaTlqTnSpOxvaLEJrR8E5pCaJaX0jDcdpd7".to_string(),
-        memo_app_id: None,
-        memo_api_token: None,
-        yamazumi_app_id: None,
-        yamazumi_api_token: None,
-        kobukuro_app_id: None,
-        kobukuro_api_token: None,
-        tsumikomi_app_id: None,
-        tsumikomi_api_token: None,
-    };
-
-    eprintln!("=== MIGRATION START: Fetching from App 351 ===");
-
-    // 0. 既存データを全削除
-    {
-        let db = state.db.lock().unwrap();
-        eprintln!("=== Clearing local database before fetch ===");
-        if let Err(e) = db.delete_all_schedules() {
-            eprintln!("Failed to clear database: {}", e);
-            return Ok(ApiResponse { success: false, data: None, error: Some(e.to_string()) });
+pub async fn fetch_from_kintone(app: AppHandle, state: State<'_, AppState>) -> Result<ApiResponse<FetchReport>, ()> {
+    let client_opt = {
+        let kintone = state.kintone_client.lock().unwrap();
+        kintone.clone()
+    };
+
+    let client = match client_opt {
+        Some(c) => c,
+        None => {
+            return Ok(ApiResponse {
+                success: false,
+                data: None,
+                error: Some("kintone設定が未設定です".to_string()),
+            });
         }
+    };
+
+    let mut report = FetchReport::default();
+
+    // スケジュール（ID506）
+    if let Err(e) = pull_app_changes(&client, &state, false, &mut report).await {
+        return Ok(ApiResponse { success: false, data: None, error: Some(e) });
     }
 
-    // 1. App 351から取得
-    let src_client = match KintoneClient::new(migration_config) {
-        Ok(c) => c,
-        Err(e) => return Ok(ApiResponse { success: false, data: None, error: Some(e.to_string()) }),
+    // メモ・図形（ID507）※メモアプリ未設定時はメインアプリと同一のため取得しない
+    let (main_app_id, _) = client.get_app_credentials(false);
+    let (memo_app_id, _) = client.get_app_credentials(true);
+    let separate_memo_app = memo_app_id != main_app_id;
+    if separate_memo_app {
+        if let Err(e) = pull_app_changes(&client, &state, true, &mut report).await {
+            return Ok(ApiResponse { success: false, data: None, error: Some(e) });
+        }
+    }
+
+    // kintone側で削除されたレコードをゴミ箱へ移す
+    match trash_remote_deletions(&client, &state, false, |s| !separate_memo_app || !is_memo_schedule(s)).await {
+        Ok(count) => report.removed += count,
+        Err(e) => return Ok(ApiResponse { success: false, data: None, error: Some(e) }),
+    }
+    if separate_memo_app {
+        match trash_remote_deletions(&client, &state, true, is_memo_schedule).await {
+            Ok(count) => report.removed += count,
+            Err(e) => return Ok(ApiResponse { success: false, data: None, error: Some(e) }),
+        }
+    }
+    if report.removed > 0 {
+        eprintln!("=== Moved {} schedules deleted in kintone to the trash ===", report.removed);
+    }
+
+    eprintln!("=== Pulled {} changed records from kintone ===", report.imported);
+    check_stock_after_sync(&app, &client, &state).await;
+    Ok(ApiResponse {
+        success: true,
+        data: Some(report),
+        error: None,
+    })
+}
+
+/// 旧アプリ（App 351）からスケジュールを移行
+/// 移行済みのスケジュール番号はスキップし、新規分はID506へ送信待ちとして保存する
+#[tauri::command]
+pub async fn migrate_from_legacy_app(state: State<'_, AppState>) -> Result<ApiResponse<u32>, ()> {
+    let client = {
+        let kintone = state.kintone_client.lock().unwrap();
+        kintone.clone()
+    };
+    let Some(src_client) = client else {
+        return Ok(ApiResponse { success: false, data: None, error: Some("kintone設定が未設定です".to_string()) });
     };
 
-    let mut all_records = Vec::new();
+    eprintln!("=== MIGRATION START: Fetching from legacy app ===");
 
-    // Limit to recent/active records if possible, but fetching all for now
-    match src_client.get_records(None, false).await {
-        Ok(json) => {
-            if let Some(records) = json["records"].as_array() {
-                eprintln!("=== App 351: Found {} records ===", records.len());
-                all_records.extend(records.clone());
-            }
+    // 移行元アプリのトークンは設定画面で登録する
+    let all_records = match src_client.get_all_records_by_name("legacy", None).await {
+        Ok(records) => {
+            eprintln!("=== Legacy app: Found {} records ===", records.len());
+            records
         }
         Err(e) => {
-            eprintln!("App 351 fetch error: {}", e);
+            eprintln!("Legacy app fetch error: {}", e);
             return Ok(ApiResponse { success: false, data: None, error: Some(e.to_string()) });
         }
-    }
+    };
 
     {
         let records = all_records;
@@ -574,71 +1345,26 @@ pub async fn fetch_from_kintone(state: State<'_, AppState>) -> Result<ApiRespons
             if i % 10 == 0 {
                 eprintln!("Processing record {}/{}", i, records.len());
             }
-            // Field mapping (Legacy 351 -> LocalSchedule)
-            
-            // Product Name: 製品名 (FS450K etc) or 製品名_アプリ (品番)
-            let mut product_name = get_string_value(&record, "製品名");
-            if product_name.is_empty() { product_name = get_string_value(&record, "製品名_アプリ"); }
-            if product_name.is_empty() { continue; } // Skip invalid
-
-            // Product Display Name: 品名
-            let product_display_name = get_optional_string_value(&record, "品名");
-
-            // Category: 分類 (ベーラー/小袋)
-            let category = get_optional_string_value(&record, "分類");
-
-            // Dates: 開始日時1, 総終了日時, 内終了日時1
-            let start_datetime = get_string_value(&record, "開始日時1");
-            
-            let mut end_datetime = get_optional_string_value(&record, "総終了日時");
-            if end_datetime.is_none() { end_datetime = get_optional_string_value(&record, "内終了日時1"); }
-
-            // Status: 生産状況 (未生産 etc)
-            let mut status = get_string_value(&record, "生産状況");
-            if status.is_empty() { status = "未生産".to_string(); }
-
-            // Quantity: 総個数 or 生産数量1
-            let qty_val = get_number_value(&record, "総個数")
-                .or(get_number_value(&record, "生産数量1"));
-            
-            // Notes: 製造備考 or 特記事項
-            let mut notes = get_optional_string_value(&record, "製造備考");
-            if notes.is_none() { notes = get_optional_string_value(&record, "特記事項"); }
-
-            // Efficiency: 製綿能率1
-            let efficiency = get_optional_string_value(&record, "製綿能率1");
-
-            // Schedule Number: スケジュール番号
-            let schedule_number = get_optional_string_value(&record, "スケジュール番号");
-
-            let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-
-            let schedule = LocalSchedule {
-                id: None,
-                kintone_record_id: None, // Reset ID for migration (New record for 506)
-                schedule_number: schedule_number,
-                product_name: product_name.clone(),
-                product_display_name: product_display_name,
-                category: category,
-                line: "".to_string(),
-                start_datetime: start_datetime,
-                end_datetime: end_datetime,
-                quantity1: qty_val,
-                quantity2: None, quantity3: None, quantity4: None, 
-                quantity5: None, quantity6: None, quantity7: None, quantity8: None,
-                total_quantity: None,
-                efficiency1: efficiency,
-                efficiency2: None, efficiency3: None, efficiency4: None,
-                efficiency5: None, efficiency6: None, efficiency7: None, efficiency8: None,
-                production_status: status,
-                notes: notes,
-                sync_status: "pending".to_string(), // Mark as pending to sync to 506
-                created_at: now.clone(),
-                updated_at: now,
+            // フィールド対応付け（旧アプリ351 → LocalSchedule）
+            // kintone_record_idは持たない（ID506へ新規レコードとして送信待ちにする）
+            let schedule = match state.field_mappings.legacy.parse_record(record, blank_schedule("pending")) {
+                Ok(schedule) => schedule,
+                Err(e) => {
+                    eprintln!("Legacy record {} skipped: {}", i, e);
+                    continue;
+                }
             };
+            if schedule.product_name.is_empty() { continue; } // Skip invalid
+
+            // 移行済みならスキップ
+            if let Some(ref number) = schedule.schedule_number {
+                if db.schedule_number_exists(number).unwrap_or(false) {
+                    continue;
+                }
+            }
 
             // Don't use import_from_kintone (it checks kintone_id), use add_schedule directly
-            if let Ok(_) = db.add_schedule(&schedule) {
+            if db.add_schedule(&schedule).is_ok() {
                 count += 1;
             }
         }
@@ -652,141 +1378,2511 @@ pub async fn fetch_from_kintone(state: State<'_, AppState>) -> Result<ApiRespons
     }
 }
 
-/// kintoneにスケジュールを送信
+/// kintoneアプリのフォームを確認（起動時・設定の保存後に呼ぶ）
+/// 設定済みの各アプリについて、読み書きするフィールドの有無・種類と、ドロップダウンの選択肢を確認する
 #[tauri::command]
-pub async fn sync_to_kintone(state: State<'_, AppState>) -> Result<ApiResponse<u32>, ()> {
-    let pending_schedules = {
-        let db = state.db.lock().unwrap();
-        db.get_pending_schedules().unwrap_or_default()
-    };
-
-    let client_opt = {
+pub async fn check_kintone_schema(state: State<'_, AppState>) -> Result<ApiResponse<SchemaReport>, ()> {
+    let client = {
         let kintone = state.kintone_client.lock().unwrap();
         kintone.clone()
     };
+    let Some(client) = client else {
+        return Ok(ApiResponse { success: false, data: None, error: Some("kintone設定が未設定です".to_string()) });
+    };
 
-    if let Some(client) = client_opt {
-        let mut synced_count = 0u32;
-
-        for schedule in pending_schedules {
-            let is_memo = schedule.product_name == "MMO" || schedule.product_name == "SHAP";
-            
-            let record = if is_memo {
-                serde_json::json!({
-                    "product_name": { "value": schedule.product_name },
-                    "notes": { "value": schedule.notes.clone().unwrap_or_default() },
-                    "start_datetime": { "value": schedule.start_datetime },
-                    "end_datetime": { "value": schedule.end_datetime },
-                    "production_status": { "value": schedule.production_status.clone() },
-                })
-            } else {
-                serde_json::json!({
-                    "product_name": { "value": schedule.product_name },
-                    "start_datetime": { "value": schedule.start_datetime },
-                    "end_datetime": { "value": schedule.end_datetime },
-                    "quantity": { "value": schedule.quantity1.map(|v| v.to_string()) },
-                    "status": { "value": schedule.production_status.clone() },
-                    "製造備考": { "value": schedule.notes.clone().unwrap_or_default() },
-                    "製綿能率": { "value": schedule.efficiency1.clone().unwrap_or_default() },
-                })
-            };
+    // 送信する値はローカルのスケジュールと製品マスタの既定能率から求める
+    let (schedules, efficiencies) = {
+        let db = state.db.lock().unwrap();
+        let schedules = match db.get_all_schedules() {
+            Ok(schedules) => schedules,
+            Err(e) => return Ok(ApiResponse { success: false, data: None, error: Some(e.to_string()) }),
+        };
+        let efficiencies: Vec<String> = db.get_products(false)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|p| p.default_efficiency)
+            .collect();
+        (schedules, efficiencies)
+    };
+    let extra_values = HashMap::from([("efficiency1", efficiencies)]);
 
-            if let Some(kintone_id) = schedule.kintone_record_id {
-                if client.update_record(kintone_id, record, is_memo).await.is_ok() {
-                    let db = state.db.lock().unwrap();
-                    let _ = db.update_sync_status(schedule.id.unwrap(), "synced", Some(kintone_id));
-                    synced_count += 1;
-                }
-            } else {
-                if let Ok(new_id) = client.add_record(record, is_memo).await {
-                    let db = state.db.lock().unwrap();
-                    let _ = db.update_sync_status(schedule.id.unwrap(), "synced", Some(new_id));
-                    synced_count += 1;
+    let mappings = &state.field_mappings;
+    let mut apps = Vec::new();
+    for (app, app_id, form) in client.get_configured_forms().await {
+        // 対応付けファイルのないアプリ（ID514の積込予定）は需給バランスで読むフィールドのみ確認する
+        let mapping = mappings.all().into_iter().find(|m| m.app == app);
+        let (issues, error) = match form {
+            Ok(form) => {
+                let mut expected = Vec::new();
+                if let Some(mapping) = mapping {
+                    let targets: Vec<&LocalSchedule> = schedules
+                        .iter()
+                        .filter(|s| match app {
+                            "main" => !is_memo_schedule(s),
+                            "memo" => is_memo_schedule(s),
+                            "yamazumi" | "kobukuro" => downstream::target_app(s).map(DownstreamApp::name) == Some(app),
+                            _ => false,
+                        })
+                        .collect();
+                    expected = schema_check::mapping_fields(mapping, &targets, &extra_values);
                 }
+                expected.extend(schema_check::balance_fields(app));
+                (schema_check::compare(&expected, &form), None)
             }
-        }
-
-        Ok(ApiResponse {
-            success: true,
-            data: Some(synced_count),
-            error: None,
-        })
-    } else {
-        Ok(ApiResponse {
-            success: false,
-            data: None,
-            error: Some("kintone設定が未設定です".to_string()),
-        })
+            Err(e) => (Vec::new(), Some(format!("フォームを取得できませんでした: {}", e))),
+        };
+        eprintln!("=== Schema check: {} (app {}) issues={} ===", app, app_id, issues.len());
+        apps.push(AppSchemaReport {
+            app: app.to_string(),
+            label: schema_check::app_label(app).to_string(),
+            app_id,
+            mapping_source: mapping.and_then(|m| m.source.clone()),
+            error,
+            issues,
+        });
     }
+
+    Ok(ApiResponse {
+        success: true,
+        data: Some(SchemaReport::new(apps, mappings.warnings.clone())),
+        error: None,
+    })
 }
 
-/// スケジュールを削除
-#[tauri::command]
-pub async fn delete_schedule(id: i64, state: State<'_, AppState>) -> Result<ApiResponse<()>, ()> {
-    // まずkintone_record_idを取得
-    let kintone_record_id = {
-        let db = state.db.lock().unwrap();
-        db.get_kintone_record_id(id).unwrap_or(None)
-    };
+/// メモ・図形（ID507）のスケジュールか
+fn is_memo_schedule(schedule: &LocalSchedule) -> bool {
+    schedule.product_name == "MMO" || schedule.product_name == "SHAP"
+}
 
-    // kintoneからも削除（kintone-immediate-sync feature有効時）
-    #[cfg(feature = "kintone-immediate-sync")]
-    if let Some(kintone_id) = kintone_record_id {
-        // メモ/図形かどうかを判定するためにproduct_nameが必要
-        // すでに削除済みかもしれないが、DBにはあるはず
-        // ここでは簡単な判定として、DBから取得しておいた product_name (MMO/SHAP) を使うなどのロジックが必要だが
-        // 現状の delete_scheduleシグネチャでは id しかわからないため
-        // まずDBから取得する
-        let product_name = {
-            let db = state.db.lock().unwrap();
-            db.get_product_name_for_schedule(id).ok().flatten().unwrap_or_default()
-        };
+/// 送信結果の集計
+#[derive(Default)]
+struct PushReport {
+    synced: u32,
+    errors: Vec<String>,
+}
 
-        let is_memo = product_name == "MMO" || product_name == "SHAP";
+impl PushReport {
+    /// エラーを記録（同じ文言は1回だけ）
+    fn add_error(&mut self, message: String) {
+        eprintln!("{}", message);
+        if !self.errors.contains(&message) {
+            self.errors.push(message);
+        }
+    }
+}
 
-        let client_opt = {
+/// 1件ずつ更新（一括更新でリビジョン不一致があった場合に競合レコードを特定するため）
+/// 送信できればtrue
+async fn push_single_update(client: &KintoneClient, state: &State<'_, AppState>, schedule: &LocalSchedule, is_memo: bool, report: &mut PushReport) -> bool {
+    let Some(kintone_id) = schedule.kintone_record_id else { return false };
+    let record = state.field_mappings.schedule(is_memo).build_record(schedule);
+
+    match client.update_record(kintone_id, schedule.kintone_revision, record, is_memo).await {
+        Ok(saved) => {
+            let db = state.db.lock().unwrap();
+            match db.update_sync_status(schedule.id.unwrap(), "synced", Some(kintone_id), saved.revision) {
+                Ok(_) => report.synced += 1,
+                Err(e) => report.add_error(format!("同期状態の保存に失敗しました: {}", e)),
+            }
+            true
+        }
+        Err(KintoneError::RevisionConflict { .. }) => {
+            // kintone側が他で更新済み: 最新内容を取得して競合として記録
+            eprintln!("=== Revision conflict on kintone record {} ===", kintone_id);
+            match client.get_record(kintone_id, is_memo).await {
+                Ok(remote_record) => {
+                    let mapping = state.field_mappings.schedule(is_memo);
+                    if let Some(remote) = parse_schedule_record(mapping, &remote_record) {
+                        let remote = remote_over_local(schedule, &remote, &mapping.pulled_columns());
+                        let db = state.db.lock().unwrap();
+                        if let Err(e) = db.record_conflict(schedule, &remote) {
+                            eprintln!("Failed to record conflict: {}", e);
+                        }
+                    }
+                    report.add_error("kintone側でも変更されたスケジュールがあります。競合一覧から解決してください".to_string());
+                }
+                Err(e) => report.add_error(e.to_string()),
+            }
+            false
+        }
+        Err(e) => {
+            report.add_error(e.to_string());
+            false
+        }
+    }
+}
+
+/// 1アプリ分の送信待ちスケジュールを一括APIで送信
+/// 100件ごとのチャンク単位で送信し、kintoneに拒否されたチャンクはローカルも未送信のまま残す
+/// 送信できたスケジュールはID354（山積表）・ID368（小袋実績）にも反映する
+async fn push_app_schedules(client: &KintoneClient, state: &State<'_, AppState>, schedules: Vec<LocalSchedule>, is_memo: bool, report: &mut PushReport) {
+    let (updates, inserts): (Vec<LocalSchedule>, Vec<LocalSchedule>) =
+        schedules.into_iter().partition(|s| s.kintone_record_id.is_some());
+    let mut pushed: Vec<i64> = Vec::new();
+
+    // 新規追加
+    for chunk in inserts.chunks(BULK_CHUNK_SIZE) {
+        let records: Vec<serde_json::Value> = chunk.iter().map(|s| state.field_mappings.schedule(is_memo).build_record(s)).collect();
+        match client.add_records(&records, is_memo).await {
+            Ok(saved) => {
+                let entries: Vec<(i64, u32, Option<i64>)> = chunk.iter().zip(saved.iter())
+                    .map(|(s, r)| (s.id.unwrap(), r.id, r.revision))
+                    .collect();
+                let db = state.db.lock().unwrap();
+                match db.mark_synced_batch(&entries) {
+                    Ok(_) => {
+                        report.synced += entries.len() as u32;
+                        pushed.extend(entries.iter().map(|(id, _, _)| *id));
+                    }
+                    Err(e) => report.add_error(format!("同期状態の保存に失敗しました: {}", e)),
+                }
+            }
+            Err(e) => report.add_error(e.to_string()),
+        }
+    }
+
+    // 更新
+    for chunk in updates.chunks(BULK_CHUNK_SIZE) {
+        let records: Vec<BulkUpdateRecord> = chunk.iter().map(|s| BulkUpdateRecord {
+            id: s.kintone_record_id.unwrap_or_default(),
+            revision: s.kintone_revision,
+            record: state.field_mappings.schedule(is_memo).build_record(s),
+        }).collect();
+
+        match client.update_records(&records, is_memo).await {
+            Ok(saved) => {
+                let entries: Vec<(i64, u32, Option<i64>)> = chunk.iter().zip(saved.iter())
+                    .map(|(s, r)| (s.id.unwrap(), r.id, r.revision))
+                    .collect();
+                let db = state.db.lock().unwrap();
+                match db.mark_synced_batch(&entries) {
+                    Ok(_) => {
+                        report.synced += entries.len() as u32;
+                        pushed.extend(entries.iter().map(|(id, _, _)| *id));
+                    }
+                    Err(e) => report.add_error(format!("同期状態の保存に失敗しました: {}", e)),
+                }
+            }
+            Err(KintoneError::RevisionConflict { .. }) => {
+                // チャンク内に他で更新済みのレコードがある: 1件ずつ送信して競合を特定
+                eprintln!("=== Revision conflict in bulk update, retrying {} records one by one ===", chunk.len());
+                for schedule in chunk {
+                    if push_single_update(client, state, schedule, is_memo, report).await {
+                        pushed.extend(schedule.id);
+                    }
+                }
+            }
+            Err(e) => report.add_error(e.to_string()),
+        }
+    }
+
+    if !is_memo {
+        push_downstream(client, state, &pushed, report).await;
+    }
+}
+
+/// スケジュールの内容をID354（山積表）・ID368（小袋実績）に反映し、連携状態をDBに記録
+/// 削除済みのスケジュールは送信先から削除する。失敗した場合は理由を返す（次回のkintone送信で再送）
+async fn sync_downstream(client: &KintoneClient, state: &State<'_, AppState>, id: i64) -> Result<(), String> {
+    let (schedule, deleted, previous_app) = {
+        let db = state.db.lock().unwrap();
+        let previous_app = db.get_downstream_sync(id).ok().flatten()
+            .and_then(|d| d.app)
+            .and_then(|app| DownstreamApp::from_name(&app));
+        let (schedule, deleted) = match db.get_schedule(id).ok().flatten() {
+            Some(s) => (s, false),
+            None => match db.get_deleted_schedule(id).ok().flatten() {
+                Some(s) => (s, true),
+                None => return Ok(()),
+            },
+        };
+        (schedule, deleted, previous_app)
+    };
+    let Some(schedule_number) = schedule.schedule_number.clone() else { return Ok(()) };
+
+    let ops = downstream::plan(&schedule, deleted, previous_app, &state.field_mappings);
+    if ops.is_empty() {
+        return Ok(());
+    }
+
+    let mut settled = (previous_app, "synced", None);
+    for op in &ops {
+        eprintln!("=== Syncing {} to {} ===", schedule_number, op.app.label());
+        // 検索キー（スケジュール番号）と生産状況のフィールドは対応付けから決める（読み込み時に必須として検証済み）
+        let mapping = op.app.mapping(&state.field_mappings);
+        let fields = mapping.pull_field("schedule_number").zip(mapping.pull_field("production_status"));
+        let Some((key_field, status_field)) = fields else {
+            let message = format!("{}のフィールド対応付けにスケジュール番号・生産状況がありません", op.app.label());
+            settled = (Some(op.app), "error", Some(message));
+            break;
+        };
+        match client.sync_to_yamazumi_or_kobukuro(&schedule_number, &op.write, op.app.is_kobukuro(), key_field, status_field).await {
+            Ok(outcome) => settled = downstream::settle(op.app, &op.write, &outcome),
+            Err(e) => {
+                let message = format!("「{}」（{}）を{}に反映できませんでした: {}", schedule.product_name, schedule_number, op.app.label(), e);
+                settled = (Some(op.app), "error", Some(message));
+                break;
+            }
+        }
+    }
+
+    let (app, status, message) = settled;
+    let db = state.db.lock().unwrap();
+    if let Err(e) = db.save_downstream_sync(id, app.map(DownstreamApp::name), status, message.as_deref()) {
+        eprintln!("Failed to save downstream status for {}: {}", id, e);
+    }
+    match (status, message) {
+        ("error", Some(message)) => Err(message),
+        _ => Ok(()),
+    }
+}
+
+/// 複数のスケジュールをID354（山積表）・ID368（小袋実績）に反映し、失敗したものをreportに記録
+async fn push_downstream(client: &KintoneClient, state: &State<'_, AppState>, ids: &[i64], report: &mut PushReport) {
+    for &id in ids {
+        if let Err(message) = sync_downstream(client, state, id).await {
+            report.add_error(format!("{}（次回のkintone送信で再送します）", message));
+        }
+    }
+}
+
+/// kintoneからの削除結果（削除待ち1件分）
+#[derive(Debug, Serialize)]
+pub struct DeletionResult {
+    pub schedule_id: i64,
+    pub kintone_record_id: u32,
+    pub product_name: String,
+    pub schedule_number: Option<String>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// kintoneへの送信結果
+#[derive(Debug, Serialize)]
+pub struct SyncReport {
+    /// 追加・更新を送信した件数
+    pub synced: u32,
+    /// 削除待ちの送信結果
+    pub deletions: Vec<DeletionResult>,
+}
+
+/// ローカルで削除したスケジュールのkintoneレコードを削除
+/// kintone側で既に削除されていれば成功とし、失敗したものは理由を記録して次回の同期で再送する
+async fn push_pending_deletions(client: &KintoneClient, state: &State<'_, AppState>) -> Vec<DeletionResult> {
+    let pending = {
+        let db = state.db.lock().unwrap();
+        db.get_pending_deletions().unwrap_or_default()
+    };
+    if !pending.is_empty() {
+        eprintln!("=== Pushing {} pending deletions to kintone ===", pending.len());
+    }
+
+    let mut results = Vec::new();
+    for schedule in pending {
+        let (Some(id), Some(kintone_id)) = (schedule.id, schedule.kintone_record_id) else { continue };
+        let outcome = match client.delete_record(kintone_id, is_memo_schedule(&schedule)).await {
+            Ok(_) | Err(KintoneError::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        let db = state.db.lock().unwrap();
+        let saved = match &outcome {
+            Ok(()) => db.mark_kintone_deleted(id),
+            Err(e) => db.record_kintone_delete_error(id, e),
+        };
+        if let Err(e) = saved {
+            eprintln!("Failed to save deletion status for {}: {}", id, e);
+        }
+
+        results.push(DeletionResult {
+            schedule_id: id,
+            kintone_record_id: kintone_id,
+            product_name: schedule.product_name,
+            schedule_number: schedule.schedule_number,
+            success: outcome.is_ok(),
+            error: outcome.err(),
+        });
+    }
+    results
+}
+
+/// kintoneにスケジュールを送信（削除待ちのレコードは先に削除する）
+/// 一部が失敗した場合も送信できた件数をdataに返し、errorに失敗理由をまとめる
+#[tauri::command]
+pub async fn sync_to_kintone(app: AppHandle, state: State<'_, AppState>) -> Result<ApiResponse<SyncReport>, ()> {
+    let pending_schedules = {
+        let db = state.db.lock().unwrap();
+        db.get_pending_schedules().unwrap_or_default()
+    };
+
+    let client_opt = {
+        let kintone = state.kintone_client.lock().unwrap();
+        kintone.clone()
+    };
+
+    if let Some(client) = client_opt {
+        // ID354・ID368への反映に失敗したもの（今回送信するものは送信時に反映する）
+        let downstream_retries: Vec<i64> = {
+            let db = state.db.lock().unwrap();
+            let pending_deletions = db.get_pending_deletions().unwrap_or_default();
+            db.get_downstream_retry_ids()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|id| !pending_schedules.iter().chain(pending_deletions.iter()).any(|s| s.id == Some(*id)))
+                .collect()
+        };
+
+        let deletions = push_pending_deletions(&client, &state).await;
+
+        let (memos, schedules): (Vec<LocalSchedule>, Vec<LocalSchedule>) =
+            pending_schedules.into_iter().partition(is_memo_schedule);
+
+        let mut report = PushReport::default();
+        push_app_schedules(&client, &state, schedules, false, &mut report).await;
+        push_app_schedules(&client, &state, memos, true, &mut report).await;
+
+        let deleted_ids: Vec<i64> = deletions.iter().filter(|d| d.success).map(|d| d.schedule_id).collect();
+        push_downstream(&client, &state, &deleted_ids, &mut report).await;
+        push_downstream(&client, &state, &downstream_retries, &mut report).await;
+
+        for failed in deletions.iter().filter(|d| !d.success) {
+            report.add_error(format!(
+                "{}（{}）をkintoneから削除できませんでした: {}",
+                failed.product_name,
+                failed.schedule_number.as_deref().unwrap_or("番号なし"),
+                failed.error.as_deref().unwrap_or_default()
+            ));
+        }
+
+        check_stock_after_sync(&app, &client, &state).await;
+
+        Ok(ApiResponse {
+            success: report.errors.is_empty(),
+            data: Some(SyncReport { synced: report.synced, deletions }),
+            error: if report.errors.is_empty() { None } else { Some(report.errors.join("\n")) },
+        })
+    } else {
+        Ok(ApiResponse {
+            success: false,
+            data: None,
+            error: Some("kintone設定が未設定です".to_string()),
+        })
+    }
+}
+
+/// スケジュールを削除
+#[tauri::command]
+pub async fn delete_schedule(id: i64, state: State<'_, AppState>) -> Result<ApiResponse<()>, ()> {
+    // まずkintone_record_idを取得（取り消し用に削除前の内容も控える）
+    let (kintone_record_id, before) = {
+        let db = state.db.lock().unwrap();
+        (db.get_kintone_record_id(id).unwrap_or(None), db.get_schedule(id).ok().flatten())
+    };
+    if before.is_none() {
+        return Ok(ApiResponse {
+            success: false,
+            data: None,
+            error: Some("スケジュールが見つかりません".to_string()),
+        });
+    }
+
+    // kintoneからも削除（kintone-immediate-sync feature有効時。無効時はkintone送信時に削除する）
+    #[cfg(feature = "kintone-immediate-sync")]
+    let kintone_deleted = match kintone_record_id {
+        Some(kintone_id) => {
+            // メモ/図形（ID507）かどうかを製品名で判定
+            let is_memo = before.as_ref().is_some_and(is_memo_schedule);
+
+            let client_opt = {
+                let kintone = state.kintone_client.lock().unwrap();
+                kintone.clone()
+            };
+
+            match client_opt {
+                Some(client) => match client.delete_record(kintone_id, is_memo).await {
+                    Ok(_) | Err(KintoneError::NotFound { .. }) => true,
+                    Err(e) => {
+                        // kintone削除失敗でもローカルは削除を進める（削除待ちとして次回のkintone送信で再送）
+                        eprintln!("Failed to delete from kintone: {}", e);
+                        false
+                    }
+                },
+                None => false,
+            }
+        }
+        None => false,
+    };
+    #[cfg(not(feature = "kintone-immediate-sync"))]
+    let kintone_deleted = false;
+
+    // ローカルDBから削除（ゴミ箱へ）
+    {
+        let db = state.db.lock().unwrap();
+        if let Err(e) = db.delete_schedule(id) {
+            return Ok(ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e.to_string()),
+            });
+        }
+        if kintone_deleted || kintone_record_id.is_none() {
+            if let Err(e) = db.mark_kintone_deleted(id) {
+                eprintln!("Failed to mark kintone record deleted: {}", e);
+            }
+        }
+        let label = format!("「{}」の削除", before.as_ref().map(|s| s.product_name.as_str()).unwrap_or_default());
+        record_undo(&state, &db, label, vec![(id, before)]);
+    }
+
+    // ID354・ID368からも削除（kintoneから削除できなかった場合は削除待ちとして次回の送信で行う）
+    let mut report = PushReport::default();
+    if kintone_deleted {
+        let client_opt = {
             let kintone = state.kintone_client.lock().unwrap();
             kintone.clone()
         };
-
         if let Some(client) = client_opt {
-            if let Err(e) = client.delete_record(kintone_id, is_memo).await {
-                eprintln!("Failed to delete from kintone: {}", e);
-                // kintone削除失敗でもローカルは削除を進める（不整合防止のため）
+            push_downstream(&client, &state, &[id], &mut report).await;
+        }
+    }
+
+    Ok(ApiResponse {
+        success: true,
+        data: Some(()),
+        error: if report.errors.is_empty() { None } else { Some(report.errors.join("\n")) },
+    })
+}
+
+/// 取り消し・やり直しの結果
+#[derive(Debug, Serialize)]
+pub struct UndoResult {
+    pub label: String,
+    /// 戻したスケジュール数
+    pub applied: usize,
+    /// kintoneへ送信・削除した件数
+    pub synced: u32,
+    pub state: UndoState,
+}
+
+/// 記録した変更をbeforeの状態からafterの状態へ適用する（1トランザクション）
+/// kintoneに送信済みだった変更は、kintone即時同期が有効なら打ち消す変更をkintoneにも送る
+async fn apply_undo_entry(entry: &UndoEntry, state: &State<'_, AppState>) -> Result<PushReport, String> {
+    let mut targets: Vec<(i64, Option<LocalSchedule>)> = Vec::new();
+    let mut push_ids: Vec<i64> = Vec::new();
+    let mut kintone_deletes: Vec<(i64, u32, bool)> = Vec::new();
+
+    {
+        let db = state.db.lock().unwrap();
+        for change in &entry.changes {
+            let current = db.get_schedule(change.schedule_id).map_err(|e| e.to_string())?;
+            if !undo::matches_recorded(current.as_ref(), change.before.as_ref()) {
+                return Err(format!("「{}」のあとに同じスケジュールが変更されているため戻せません", entry.label));
+            }
+
+            let target = match (current, change.after.clone()) {
+                (Some(current), None) => {
+                    if let Some(kintone_id) = current.kintone_record_id {
+                        kintone_deletes.push((change.schedule_id, kintone_id, is_memo_schedule(&current)));
+                    }
+                    None
+                }
+                (None, Some(target)) => {
+                    // kintoneのレコードが削除済みなら、新しいレコードとして送信し直す
+                    // （ゴミ箱に残っていればそのkintoneレコードID、なければ即時同期の有無で判断）
+                    let kintone_gone = match db.get_deleted_schedule(change.schedule_id).map_err(|e| e.to_string())? {
+                        Some(trashed) => trashed.kintone_record_id.is_none(),
+                        None => cfg!(feature = "kintone-immediate-sync"),
+                    };
+                    if kintone_gone && target.kintone_record_id.is_some() {
+                        push_ids.push(change.schedule_id);
+                        Some(LocalSchedule {
+                            kintone_record_id: None,
+                            kintone_revision: None,
+                            sync_status: "pending".to_string(),
+                            ..target
+                        })
+                    } else {
+                        Some(target)
+                    }
+                }
+                (Some(current), Some(target)) => {
+                    if current.sync_status == "synced" {
+                        push_ids.push(change.schedule_id);
+                    }
+                    Some(LocalSchedule {
+                        sync_status: undo::restored_sync_status(&current, &target),
+                        kintone_record_id: current.kintone_record_id,
+                        kintone_revision: current.kintone_revision,
+                        ..target
+                    })
+                }
+                (None, None) => continue,
+            };
+            targets.push((change.schedule_id, target));
+        }
+
+        db.apply_schedule_states(&targets).map_err(|e| e.to_string())?;
+    }
+
+    #[cfg(feature = "kintone-immediate-sync")]
+    let report = push_undo_to_kintone(state, push_ids, kintone_deletes).await;
+    #[cfg(not(feature = "kintone-immediate-sync"))]
+    let report = {
+        // 即時同期が無効なら次回のsync_to_kintoneで送信する
+        let _ = (push_ids, kintone_deletes);
+        PushReport::default()
+    };
+
+    Ok(report)
+}
+
+/// 取り消し・やり直しで戻したスケジュールをkintoneに送信し、不要になったレコードを削除
+#[cfg(feature = "kintone-immediate-sync")]
+async fn push_undo_to_kintone(state: &State<'_, AppState>, push_ids: Vec<i64>, kintone_deletes: Vec<(i64, u32, bool)>) -> PushReport {
+    let mut report = PushReport::default();
+    if push_ids.is_empty() && kintone_deletes.is_empty() {
+        return report;
+    }
+
+    let client_opt = {
+        let kintone = state.kintone_client.lock().unwrap();
+        kintone.clone()
+    };
+    let Some(client) = client_opt else {
+        report.add_error("kintone設定が未設定のため、kintoneには反映していません".to_string());
+        return report;
+    };
+
+    for (id, kintone_id, is_memo) in kintone_deletes {
+        match client.delete_record(kintone_id, is_memo).await {
+            Ok(_) => {
+                report.synced += 1;
+                {
+                    let db = state.db.lock().unwrap();
+                    if let Err(e) = db.mark_kintone_deleted(id) {
+                        eprintln!("Failed to mark kintone record deleted: {}", e);
+                    }
+                }
+                push_downstream(&client, state, &[id], &mut report).await;
             }
+            Err(e) => report.add_error(format!("kintoneレコード{}の削除に失敗しました: {}", kintone_id, e)),
+        }
+    }
+
+    let schedules: Vec<LocalSchedule> = {
+        let db = state.db.lock().unwrap();
+        push_ids.iter().filter_map(|id| db.get_schedule(*id).ok().flatten()).collect()
+    };
+    let (memos, schedules): (Vec<LocalSchedule>, Vec<LocalSchedule>) = schedules.into_iter().partition(is_memo_schedule);
+    push_app_schedules(&client, state, schedules, false, &mut report).await;
+    push_app_schedules(&client, state, memos, true, &mut report).await;
+    report
+}
+
+/// 取り消し（redoがfalse）またはやり直しを1操作分実行
+async fn run_undo_redo(redo: bool, state: State<'_, AppState>) -> ApiResponse<UndoResult> {
+    let entry = {
+        let mut stack = state.undo.lock().unwrap();
+        if redo { stack.pop_redo() } else { stack.pop_undo() }
+    };
+    let Some(entry) = entry else {
+        let message = if redo { "やり直せる操作がありません" } else { "取り消せる操作がありません" };
+        return ApiResponse { success: false, data: None, error: Some(message.to_string()) };
+    };
+    eprintln!("=== {}: {} ({}件) ===", if redo { "redo" } else { "undo" }, entry.label, entry.changes.len());
+
+    let transition = if redo { entry.clone() } else { entry.inverse() };
+    let report = match apply_undo_entry(&transition, &state).await {
+        Ok(report) => report,
+        Err(e) => {
+            // 適用できなかった操作は元の履歴に戻す（ローカルDBは変更されていない）
+            let mut stack = state.undo.lock().unwrap();
+            if redo { stack.push_redo(entry) } else { stack.push_undo(entry) }
+            return ApiResponse { success: false, data: None, error: Some(e) };
         }
+    };
+
+    let undo_state = {
+        let mut stack = state.undo.lock().unwrap();
+        if redo { stack.push_undo(entry.clone()) } else { stack.push_redo(entry.clone()) }
+        stack.state()
+    };
+
+    ApiResponse {
+        success: report.errors.is_empty(),
+        data: Some(UndoResult {
+            label: entry.label,
+            applied: transition.changes.len(),
+            synced: report.synced,
+            state: undo_state,
+        }),
+        error: if report.errors.is_empty() { None } else { Some(report.errors.join("\n")) },
+    }
+}
+
+/// 直前の編集操作を取り消す
+#[tauri::command]
+pub async fn undo(state: State<'_, AppState>) -> Result<ApiResponse<UndoResult>, ()> {
+    Ok(run_undo_redo(false, state).await)
+}
+
+/// 取り消した編集操作をやり直す
+#[tauri::command]
+pub async fn redo(state: State<'_, AppState>) -> Result<ApiResponse<UndoResult>, ()> {
+    Ok(run_undo_redo(true, state).await)
+}
+
+/// 取り消し・やり直しできる操作
+#[tauri::command]
+pub fn get_undo_state(state: State<AppState>) -> ApiResponse<UndoState> {
+    ApiResponse {
+        success: true,
+        data: Some(state.undo.lock().unwrap().state()),
+        error: None,
     }
-    // ローカルDBから削除
+}
+
+/// スケジュールのID354（山積表）・ID368（小袋実績）への連携状態（連携していなければNone）
+#[tauri::command]
+pub fn get_downstream_status(schedule_id: i64, state: State<AppState>) -> ApiResponse<Option<DownstreamSync>> {
     let db = state.db.lock().unwrap();
-    match db.delete_schedule(id) {
-        Ok(_) => Ok(ApiResponse {
+    match db.get_downstream_sync(schedule_id) {
+        Ok(status) => ApiResponse {
+            success: true,
+            data: Some(status),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// ゴミ箱のスケジュール一覧を取得
+#[tauri::command]
+pub fn get_trash(state: State<AppState>) -> ApiResponse<Vec<DeletedSchedule>> {
+    let db = state.db.lock().unwrap();
+    match db.get_deleted_schedules() {
+        Ok(trash) => ApiResponse {
+            success: true,
+            data: Some(trash),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// ゴミ箱のスケジュールを復元
+/// kintoneのレコードが削除済みなら新しいレコードとして作り直す（即時同期が無効なら次回のsync_to_kintoneで送信）
+/// 削除後に追加されたスケジュールと重なる場合も復元し、重複は警告として返す
+#[tauri::command]
+pub async fn restore_deleted_schedule(id: i64, state: State<'_, AppState>) -> Result<ApiResponse<ScheduleSaveResult>, ()> {
+    let trashed = {
+        let db = state.db.lock().unwrap();
+        db.get_deleted_schedule(id)
+    };
+    let trashed = match trashed {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            return Ok(ApiResponse { success: false, data: None, error: Some("ゴミ箱にスケジュールが見つかりません".to_string()) });
+        }
+        Err(e) => return Ok(ApiResponse { success: false, data: None, error: Some(e.to_string()) }),
+    };
+    let is_memo = is_memo_schedule(&trashed);
+
+    let client_opt = {
+        let kintone = state.kintone_client.lock().unwrap();
+        kintone.clone()
+    };
+
+    // kintoneのレコードがまだ残っているか確認（確認できなければ残っているものとして扱う）
+    let mut kintone_record_id = trashed.kintone_record_id;
+    if let (Some(kintone_id), Some(client)) = (kintone_record_id, client_opt.as_ref()) {
+        match client.get_record(kintone_id, is_memo).await {
+            Ok(_) => {}
+            Err(KintoneError::NotFound { .. }) => kintone_record_id = None,
+            Err(e) => eprintln!("Failed to check kintone record {}: {}", kintone_id, e),
+        }
+    }
+    let sync_status = match (kintone_record_id, trashed.sync_status.as_str()) {
+        (Some(_), status) => status.to_string(),
+        (None, "local_only") => "local_only".to_string(),
+        (None, _) => "pending".to_string(),
+    };
+
+    let (schedule, overlaps) = {
+        let db = state.db.lock().unwrap();
+        if let Err(e) = db.undelete_schedule(id, kintone_record_id, &sync_status) {
+            return Ok(ApiResponse { success: false, data: None, error: Some(e.to_string()) });
+        }
+        eprintln!("=== Restored schedule {} from trash (kintone: {:?}) ===", id, kintone_record_id);
+        record_undo(&state, &db, format!("「{}」の復元", trashed.product_name), vec![(id, None)]);
+        let schedule = db.get_schedule(id).ok().flatten();
+        let overlaps = schedule
+            .as_ref()
+            .map(|s| check_overlaps(&db, &ScheduleSlot::from(s)).unwrap_or_default())
+            .unwrap_or_default();
+        (schedule, overlaps)
+    };
+
+    #[cfg(feature = "kintone-immediate-sync")]
+    let report = {
+        let mut report = PushReport::default();
+        if let (Some(schedule), Some(client)) = (schedule.filter(|s| s.sync_status == "pending"), client_opt.as_ref()) {
+            push_app_schedules(client, &state, vec![schedule], is_memo, &mut report).await;
+        }
+        report
+    };
+    #[cfg(not(feature = "kintone-immediate-sync"))]
+    let report = {
+        let _ = schedule;
+        PushReport::default()
+    };
+
+    let db = state.db.lock().unwrap();
+    Ok(ApiResponse {
+        success: report.errors.is_empty(),
+        data: Some(ScheduleSaveResult {
+            id: Some(id),
+            overlaps,
+            changeover_shortfalls: find_changeover_shortfalls(&db, id),
+        }),
+        error: if report.errors.is_empty() { None } else { Some(report.errors.join("\n")) },
+    })
+}
+
+/// ゴミ箱のスケジュールを完全に削除（kintoneにレコードが残っていれば先に削除する）
+#[tauri::command]
+pub async fn purge_deleted_schedule(id: i64, state: State<'_, AppState>) -> Result<ApiResponse<()>, ()> {
+    let trashed = {
+        let db = state.db.lock().unwrap();
+        db.get_deleted_schedule(id)
+    };
+    let trashed = match trashed {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            return Ok(ApiResponse { success: false, data: None, error: Some("ゴミ箱にスケジュールが見つかりません".to_string()) });
+        }
+        Err(e) => return Ok(ApiResponse { success: false, data: None, error: Some(e.to_string()) }),
+    };
+
+    if let Some(kintone_id) = trashed.kintone_record_id {
+        let client_opt = {
+            let kintone = state.kintone_client.lock().unwrap();
+            kintone.clone()
+        };
+        let Some(client) = client_opt else {
+            return Ok(ApiResponse {
+                success: false,
+                data: None,
+                error: Some("kintoneにレコードが残っていますが、kintone設定が未設定のため削除できません".to_string()),
+            });
+        };
+        match client.delete_record(kintone_id, is_memo_schedule(&trashed)).await {
+            Ok(_) | Err(KintoneError::NotFound { .. }) => {}
+            Err(e) => return Ok(ApiResponse { success: false, data: None, error: Some(e.to_string()) }),
+        }
+        // 完全に削除すると再送できないため、ID354・ID368から削除できなければ残す
+        if let Err(e) = sync_downstream(&client, &state, id).await {
+            let db = state.db.lock().unwrap();
+            if let Err(e) = db.mark_kintone_deleted(id) {
+                eprintln!("Failed to mark kintone record deleted: {}", e);
+            }
+            return Ok(ApiResponse { success: false, data: None, error: Some(e) });
+        }
+    }
+
+    let db = state.db.lock().unwrap();
+    match db.purge_schedule(id) {
+        Ok(_) => Ok(ApiResponse {
+            success: true,
+            data: Some(()),
+            error: None,
+        }),
+        Err(e) => Ok(ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        }),
+    }
+}
+
+/// 指定日数（省略時はTRASH_RETENTION_DAYS）を過ぎたゴミ箱のスケジュールを完全に削除し、件数を返す
+#[tauri::command]
+pub fn purge_trash(older_than_days: Option<i64>, state: State<AppState>) -> ApiResponse<usize> {
+    let days = older_than_days.filter(|d| *d >= 0).unwrap_or(TRASH_RETENTION_DAYS);
+    let db = state.db.lock().unwrap();
+    match db.purge_deleted_schedules(days) {
+        Ok(count) => ApiResponse {
+            success: true,
+            data: Some(count),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 製品の重量を取得
+#[tauri::command]
+pub fn get_product_weight(product_name: String, state: State<AppState>) -> ApiResponse<f64> {
+    let db = state.db.lock().unwrap();
+    match db.get_product_weight(&product_name) {
+        Ok(Some(weight)) => ApiResponse {
+            success: true,
+            data: Some(weight),
+            error: None,
+        },
+        Ok(None) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(format!("製品 '{}' が見つかりません", product_name)),
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 全スケジュールの重複を取得（ガントチャートの強調表示用）
+#[tauri::command]
+pub fn get_schedule_overlaps(state: State<AppState>) -> ApiResponse<Vec<ScheduleOverlap>> {
+    let db = state.db.lock().unwrap();
+    match db.get_all_schedules() {
+        Ok(schedules) => ApiResponse {
+            success: true,
+            data: Some(validation::find_all_overlaps(&schedules)),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// スケジュールを移動・延長し、同じラインの後続スケジュールを順序と間隔を保ったままずらす
+/// 非稼働時間は飛ばす。previewなら移動内容だけを返し、そうでなければ1トランザクションで反映する
+#[tauri::command]
+pub fn reschedule_line(request: RescheduleLineRequest, state: State<AppState>) -> ApiResponse<ReschedulePlan> {
+    eprintln!("=== reschedule_line: id={}, start={}, preview={} ===", request.schedule_id, request.start_datetime, request.preview);
+    let db = state.db.lock().unwrap();
+
+    let anchor = match db.get_schedule(request.schedule_id) {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(format!("スケジュールが見つかりません: {}", request.schedule_id)),
+            };
+        }
+        Err(e) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e.to_string()),
+            };
+        }
+    };
+
+    let plan = db
+        .get_all_schedules()
+        .map_err(|e| e.to_string())
+        .and_then(|schedules| {
+            let calendar = load_calendar(&db)?;
+            let matrix = load_changeovers(&db)?;
+            reschedule::plan_line(
+                &anchor,
+                &request.start_datetime,
+                request.end_datetime.as_deref(),
+                &schedules,
+                &calendar,
+                &matrix,
+            )
+            .map_err(|e| e.to_string())
+        });
+    let moves = match plan {
+        Ok(moves) => moves,
+        Err(e) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e),
+            };
+        }
+    };
+
+    if !request.preview {
+        let before: Vec<(i64, Option<LocalSchedule>)> = moves
+            .iter()
+            .map(|m| (m.schedule_id, db.get_schedule(m.schedule_id).ok().flatten()))
+            .collect();
+        let entries: Vec<(i64, &str, Option<&str>)> = moves
+            .iter()
+            .map(|m| (m.schedule_id, m.new_start.as_str(), m.new_end.as_deref()))
+            .collect();
+        if let Err(e) = db.update_schedule_datetimes(&entries) {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(format!("リスケジュールの反映に失敗しました（変更は取り消されました）: {}", e)),
+            };
+        }
+        eprintln!("=== reschedule_line: {}件を移動 ===", moves.len());
+        let label = match moves.len() {
+            1 => format!("「{}」の移動", anchor.product_name),
+            n => format!("「{}」ほか{}件の移動", anchor.product_name, n - 1),
+        };
+        record_undo(&state, &db, label, before);
+    }
+
+    ApiResponse {
+        success: true,
+        data: Some(ReschedulePlan {
+            line: anchor.line,
+            moves,
+            applied: !request.preview,
+        }),
+        error: None,
+    }
+}
+
+/// 計画対象のラインと、計画開始時点の空き日時・直前の製品
+fn planning_lines(
+    lines: &[String],
+    start: chrono::NaiveDateTime,
+    schedules: &[LocalSchedule],
+) -> Vec<LineState> {
+    lines
+        .iter()
+        .map(|line| {
+            let mut state = LineState { line: line.clone(), ready: start, last_product: None };
+            let mut last_end = None;
+            for schedule in schedules.iter().filter(|s| s.line.trim() == line.trim()) {
+                if scheduling::is_fixed_duration(&schedule.product_name) {
+                    continue;
+                }
+                let Some(end) = schedule.end_datetime.as_deref().and_then(scheduling::parse_datetime) else {
+                    continue;
+                };
+                // 計画開始より後に終わるスケジュールの後ろから割り付ける
+                state.ready = state.ready.max(end);
+                if last_end.is_none_or(|last| end > last) {
+                    last_end = Some(end);
+                    state.last_product = Some(schedule.product_name.clone());
+                }
+            }
+            state
+        })
+        .collect()
+}
+
+/// 受注の生産時間を算出して計画対象の作業にする
+fn planning_job(db: &Database, order: &PlanningOrder, calendar: &PlantCalendar) -> Result<(Job, String), String> {
+    if !order.quantity.is_finite() || order.quantity <= 0.0 {
+        return Err("数量は0より大きい値を入力してください".to_string());
+    }
+    let product = db
+        .get_product(&order.product_name)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| SchedulingError::UnknownWeight(order.product_name.clone()).to_string())?;
+    let efficiency = order
+        .efficiency
+        .clone()
+        .filter(|e| !e.trim().is_empty())
+        .or(product.default_efficiency)
+        .ok_or_else(|| "製綿能率が未設定です（製品マスタの既定能率を登録してください）".to_string())?;
+
+    let estimate = scheduling::estimate(
+        product.weight_kg,
+        None,
+        &[Some(order.quantity)],
+        std::slice::from_ref(&Some(efficiency.clone())),
+        calendar,
+        "",
+    )
+    .map_err(|e| e.to_string())?;
+    let due = match order.due_datetime.as_deref().filter(|d| !d.trim().is_empty()) {
+        Some(d) => Some(scheduling::parse_datetime(d).ok_or_else(|| SchedulingError::InvalidDatetime(d.to_string()).to_string())?),
+        None => None,
+    };
+
+    let job = Job {
+        product_name: order.product_name.clone(),
+        minutes: estimate.production_minutes,
+        due,
+        lines: order.allowed_lines.iter().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect(),
+    };
+    Ok((job, efficiency))
+}
+
+/// 未計画の受注をラインに割り付けた計画案を作成（DBは変更しない）
+/// 段取り替え時間と納期遅れが小さくなるよう、納期順の貪欲法と局所探索で順序を決める
+#[tauri::command]
+pub fn optimize_orders(request: OptimizeOrdersRequest, state: State<AppState>) -> ApiResponse<DraftPlan> {
+    eprintln!("=== optimize_orders: {} orders from {} ===", request.orders.len(), request.start_datetime);
+    let Some(start) = scheduling::parse_datetime(&request.start_datetime) else {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some(SchedulingError::InvalidDatetime(request.start_datetime).to_string()),
+        };
+    };
+
+    let db = state.db.lock().unwrap();
+    let loaded = (|| -> Result<(PlantCalendar, ChangeoverMatrix, Vec<LocalSchedule>, Vec<String>), String> {
+        let schedules = db.get_all_schedules().map_err(|e| e.to_string())?;
+        let lines = db.get_lines().map_err(|e| e.to_string())?;
+        Ok((load_calendar(&db)?, load_changeovers(&db)?, schedules, lines))
+    })();
+    let (calendar, matrix, schedules, known_lines) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e),
+            };
+        }
+    };
+
+    // ライン未指定なら使用中のラインと受注で指定されたラインを対象にする
+    let mut lines: Vec<String> = if request.lines.is_empty() {
+        known_lines
+            .into_iter()
+            .chain(request.orders.iter().flat_map(|o| o.allowed_lines.clone()))
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect()
+    } else {
+        request.lines.iter().map(|l| l.trim().to_string()).collect()
+    };
+    lines.sort();
+    lines.dedup();
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+
+    let mut jobs = Vec::new();
+    let mut job_orders = Vec::new();
+    let mut unplaced = Vec::new();
+    for (index, order) in request.orders.iter().enumerate() {
+        match planning_job(&db, order, &calendar) {
+            Ok((job, efficiency)) => {
+                jobs.push(job);
+                job_orders.push((index, efficiency));
+            }
+            Err(reason) => unplaced.push(UnplacedOrder {
+                order_index: index,
+                product_name: order.product_name.clone(),
+                reason,
+            }),
+        }
+    }
+
+    let line_states = planning_lines(&lines, start, &schedules);
+    let max_iterations = request.max_iterations.unwrap_or(optimizer::DEFAULT_MAX_ITERATIONS);
+    let placements = match optimizer::optimize(&jobs, &line_states, &calendar, &matrix, max_iterations) {
+        Ok(p) => p,
+        Err(e) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e.to_string()),
+            };
+        }
+    };
+
+    let placed: std::collections::HashSet<usize> = placements.iter().map(|p| p.job).collect();
+    for (job, (index, _)) in job_orders.iter().enumerate() {
+        if !placed.contains(&job) {
+            unplaced.push(UnplacedOrder {
+                order_index: *index,
+                product_name: request.orders[*index].product_name.clone(),
+                reason: "割り付け可能なラインがありません".to_string(),
+            });
+        }
+    }
+    unplaced.sort_by_key(|u| u.order_index);
+
+    let items: Vec<DraftScheduleItem> = placements
+        .iter()
+        .map(|p| {
+            let (index, ref efficiency) = job_orders[p.job];
+            let order = &request.orders[index];
+            DraftScheduleItem {
+                order_index: index,
+                product_name: order.product_name.clone(),
+                line: p.line.clone(),
+                start_datetime: scheduling::format_datetime_like(&request.start_datetime, p.start),
+                end_datetime: scheduling::format_datetime_like(&request.start_datetime, p.end),
+                quantity: order.quantity,
+                efficiency: efficiency.clone(),
+                due_datetime: order.due_datetime.clone(),
+                changeover_minutes: p.changeover_minutes,
+                lateness_minutes: p.lateness_minutes,
+                notes: order.notes.clone(),
+            }
+        })
+        .collect();
+
+    eprintln!("=== optimize_orders: placed={}, unplaced={} ===", items.len(), unplaced.len());
+    ApiResponse {
+        success: true,
+        data: Some(DraftPlan {
+            total_changeover_minutes: items.iter().map(|i| i.changeover_minutes).sum(),
+            total_lateness_minutes: items.iter().map(|i| i.lateness_minutes).sum(),
+            items,
+            unplaced,
+        }),
+        error: None,
+    }
+}
+
+/// 計画案をスケジュールとして確定（1トランザクション、kintoneへは通常の同期で送信）
+/// 計画案の作成後に追加されたスケジュールと重なる場合は確定しない
+#[tauri::command]
+pub fn commit_draft_plan(items: Vec<DraftScheduleItem>, state: State<AppState>) -> ApiResponse<Vec<i64>> {
+    eprintln!("=== commit_draft_plan: {} items ===", items.len());
+    let db = state.db.lock().unwrap();
+    let existing = match db.get_all_schedules() {
+        Ok(s) => s,
+        Err(e) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e.to_string()),
+            };
+        }
+    };
+
+    let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut schedules = Vec::new();
+    for item in items {
+        let product = db.get_product(&item.product_name).ok().flatten();
+        let schedule = LocalSchedule {
+            id: None,
+            kintone_record_id: None,
+            schedule_number: None,
+            product_display_name: product.as_ref().and_then(|p| p.display_name.clone()),
+            category: product.as_ref().and_then(|p| p.category.clone()),
+            product_name: item.product_name,
+            line: item.line,
+            start_datetime: item.start_datetime,
+            end_datetime: Some(item.end_datetime),
+            quantity1: Some(item.quantity),
+            quantity2: None,
+            quantity3: None,
+            quantity4: None,
+            quantity5: None,
+            quantity6: None,
+            quantity7: None,
+            quantity8: None,
+            total_quantity: Some(item.quantity),
+            efficiency1: Some(item.efficiency),
+            efficiency2: None,
+            efficiency3: None,
+            efficiency4: None,
+            efficiency5: None,
+            efficiency6: None,
+            efficiency7: None,
+            efficiency8: None,
+            production_status: "未生産".to_string(),
+            notes: item.notes,
+            sync_status: "pending".to_string(),
+            created_at: now.clone(),
+            updated_at: now.clone(),
+            kintone_revision: None,
+        };
+
+        let overlaps = validation::find_overlaps(&ScheduleSlot::from(&schedule), &existing);
+        if !overlaps.is_empty() {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(format!(
+                    "計画案の {} は既存のスケジュールと重なるため確定できません。計画を作り直してください。{}",
+                    schedule.product_name,
+                    validation::describe_overlaps(&overlaps)
+                )),
+            };
+        }
+        schedules.push(schedule);
+    }
+
+    match db.add_schedules(&schedules) {
+        Ok(ids) => {
+            let before = ids.iter().map(|id| (*id, None)).collect();
+            record_undo(&state, &db, format!("計画案{}件の確定", ids.len()), before);
+            ApiResponse {
+                success: true,
+                data: Some(ids),
+                error: None,
+            }
+        }
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// シナリオを読み込み（存在しなければエラー）
+fn load_scenario_changes(db: &Database, scenario_id: i64) -> Result<Vec<crate::database::ScenarioChange>, String> {
+    if !db.scenario_exists(scenario_id).map_err(|e| e.to_string())? {
+        return Err(format!("シナリオが見つかりません: {}", scenario_id));
+    }
+    db.get_scenario_changes(scenario_id).map_err(|e| e.to_string())
+}
+
+/// シナリオ一覧を取得
+#[tauri::command]
+pub fn get_scenarios(state: State<AppState>) -> ApiResponse<Vec<Scenario>> {
+    let db = state.db.lock().unwrap();
+    match db.get_scenarios() {
+        Ok(scenarios) => ApiResponse {
+            success: true,
+            data: Some(scenarios),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// シナリオを作成（作成時点では本番と同じ内容）
+#[tauri::command]
+pub fn create_scenario(name: String, description: Option<String>, state: State<AppState>) -> ApiResponse<i64> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some("シナリオ名を入力してください".to_string()),
+        };
+    }
+
+    let db = state.db.lock().unwrap();
+    match db.create_scenario(&name, description.as_deref().filter(|d| !d.trim().is_empty())) {
+        Ok(id) => ApiResponse {
+            success: true,
+            data: Some(id),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(format!("シナリオを作成できません（同じ名前がないか確認してください）: {}", e)),
+        },
+    }
+}
+
+/// シナリオ上のスケジュール一覧（本番にシナリオの変更を重ねたもの）
+#[tauri::command]
+pub fn get_scenario_schedules(scenario_id: i64, state: State<AppState>) -> ApiResponse<Vec<ScenarioSchedule>> {
+    let db = state.db.lock().unwrap();
+    let result = load_scenario_changes(&db, scenario_id).and_then(|changes| {
+        let live = db.get_all_schedules().map_err(|e| e.to_string())?;
+        Ok(scenario::apply(&live, &changes))
+    });
+
+    match result {
+        Ok(schedules) => ApiResponse {
+            success: true,
+            data: Some(schedules),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e),
+        },
+    }
+}
+
+/// シナリオ上のスケジュールを保存（本番には反映しない）
+/// schedule.idがあれば本番スケジュールの変更、なければシナリオで追加したスケジュール（change_idで上書き）
+#[tauri::command]
+pub fn save_scenario_schedule(scenario_id: i64, change_id: Option<i64>, schedule: LocalSchedule, state: State<AppState>) -> ApiResponse<i64> {
+    let db = state.db.lock().unwrap();
+    let result = load_scenario_changes(&db, scenario_id).and_then(|_| match schedule.id {
+        Some(id) => {
+            let live = db
+                .get_schedule(id)
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("スケジュールが見つかりません: {}", id))?;
+            db.save_scenario_override(scenario_id, &live, "modify", Some(&schedule))
+                .map_err(|e| e.to_string())
+        }
+        None => db
+            .save_scenario_addition(scenario_id, change_id, &schedule)
+            .map_err(|e| e.to_string()),
+    });
+
+    match result {
+        Ok(id) => ApiResponse {
+            success: true,
+            data: Some(id),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e),
+        },
+    }
+}
+
+/// シナリオにスケジュールを追加（製品マスタでの補完・終了日時の算出は通常の追加と同じ）
+#[tauri::command]
+pub fn add_scenario_schedule(scenario_id: i64, mut request: AddScheduleRequest, state: State<AppState>) -> ApiResponse<i64> {
+    let db = state.db.lock().unwrap();
+    if let Err(e) = load_scenario_changes(&db, scenario_id).and_then(|_| prepare_add_request(&db, &mut request)) {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e),
+        };
+    }
+
+    let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let schedule = LocalSchedule {
+        id: None,
+        kintone_record_id: None,
+        schedule_number: None,
+        product_name: request.product_name,
+        product_display_name: request.product_display_name,
+        category: request.category,
+        line: request.line,
+        start_datetime: request.start_datetime,
+        end_datetime: request.end_datetime,
+        quantity1: request.quantity1,
+        quantity2: request.quantity2,
+        quantity3: request.quantity3,
+        quantity4: request.quantity4,
+        quantity5: request.quantity5,
+        quantity6: request.quantity6,
+        quantity7: request.quantity7,
+        quantity8: request.quantity8,
+        total_quantity: request.total_quantity,
+        efficiency1: request.efficiency,
+        efficiency2: None,
+        efficiency3: None,
+        efficiency4: None,
+        efficiency5: None,
+        efficiency6: None,
+        efficiency7: None,
+        efficiency8: None,
+        production_status: request.production_status.unwrap_or("未生産".to_string()),
+        notes: request.notes,
+        sync_status: "pending".to_string(),
+        created_at: now.clone(),
+        updated_at: now,
+        kintone_revision: None,
+    };
+
+    match db.save_scenario_addition(scenario_id, None, &schedule) {
+        Ok(id) => ApiResponse {
+            success: true,
+            data: Some(id),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// シナリオからスケジュールを削除
+/// 本番スケジュール（schedule_id）は削除予定として記録し、シナリオで追加したもの（change_id）は取り消す
+#[tauri::command]
+pub fn remove_scenario_schedule(scenario_id: i64, schedule_id: Option<i64>, change_id: Option<i64>, state: State<AppState>) -> ApiResponse<()> {
+    let db = state.db.lock().unwrap();
+    let result = load_scenario_changes(&db, scenario_id).and_then(|_| match (schedule_id, change_id) {
+        (Some(id), _) => {
+            let live = db
+                .get_schedule(id)
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("スケジュールが見つかりません: {}", id))?;
+            db.save_scenario_override(scenario_id, &live, "remove", None)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+        (None, Some(change_id)) => db
+            .delete_scenario_change(scenario_id, change_id)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        (None, None) => Err("削除するスケジュールを指定してください".to_string()),
+    });
+
+    match result {
+        Ok(()) => ApiResponse {
+            success: true,
+            data: Some(()),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e),
+        },
+    }
+}
+
+/// シナリオ上の変更を1件取り消して本番の内容に戻す
+#[tauri::command]
+pub fn revert_scenario_change(scenario_id: i64, change_id: i64, state: State<AppState>) -> ApiResponse<()> {
+    let db = state.db.lock().unwrap();
+    match db.delete_scenario_change(scenario_id, change_id) {
+        Ok(_) => ApiResponse {
+            success: true,
+            data: Some(()),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// シナリオと本番の差分（移動・変更・追加・削除）
+#[tauri::command]
+pub fn diff_scenario(scenario_id: i64, state: State<AppState>) -> ApiResponse<ScenarioDiff> {
+    let db = state.db.lock().unwrap();
+    let result = load_scenario_changes(&db, scenario_id).and_then(|changes| {
+        let live = db.get_all_schedules().map_err(|e| e.to_string())?;
+        Ok(scenario::diff(&live, &changes))
+    });
+
+    match result {
+        Ok(diff) => ApiResponse {
+            success: true,
+            data: Some(diff),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e),
+        },
+    }
+}
+
+/// シナリオを破棄（本番は変更しない）
+#[tauri::command]
+pub fn discard_scenario(scenario_id: i64, state: State<AppState>) -> ApiResponse<()> {
+    let db = state.db.lock().unwrap();
+    match db.delete_scenario(scenario_id) {
+        Ok(_) => ApiResponse {
+            success: true,
+            data: Some(()),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// シナリオを本番に昇格してkintoneへ送信
+/// 本番への反映は1トランザクションで行い、シナリオ作成後に本番側で変更されたスケジュールがあれば昇格しない
+/// 反映後に同じラインで重複するスケジュールがあれば、allow_overlapがない限り昇格しない
+#[tauri::command]
+pub async fn promote_scenario(scenario_id: i64, allow_overlap: bool, state: State<'_, AppState>) -> Result<ApiResponse<ScenarioPromoteReport>, ()> {
+    eprintln!("=== promote_scenario: {} ===", scenario_id);
+    let promoted = {
+        let db = state.db.lock().unwrap();
+        let result = load_scenario_changes(&db, scenario_id).and_then(|changes| {
+            let live = db.get_all_schedules().map_err(|e| e.to_string())?;
+            let conflicts = scenario::conflicts(&live, &changes);
+            if !conflicts.is_empty() {
+                return Err(format!("本番側の変更と競合するため昇格できません: {}", conflicts.join(" / ")));
+            }
+            Ok((scenario::overlaps(&live, &changes), changes))
+        });
+        let (overlaps, changes) = match result {
+            Ok(checked) => checked,
+            Err(e) => {
+                return Ok(ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some(e),
+                });
+            }
+        };
+
+        if !overlaps.is_empty() && !allow_overlap {
+            eprintln!("=== Rejected: {} overlapping schedules ===", overlaps.len());
+            return Ok(ApiResponse {
+                success: false,
+                error: Some(validation::describe_overlaps(&overlaps)),
+                data: Some(ScenarioPromoteReport { updated: 0, added: 0, removed: 0, synced: 0, overlaps }),
+            });
+        }
+
+        match db.promote_scenario(scenario_id, &changes) {
+            Ok(p) => p,
+            Err(e) => {
+                return Ok(ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some(e.to_string()),
+                });
+            }
+        }
+    };
+
+    let mut report = PushReport::default();
+    let client_opt = {
+        let kintone = state.kintone_client.lock().unwrap();
+        kintone.clone()
+    };
+
+    match client_opt {
+        Some(client) => {
+            let to_push: Vec<LocalSchedule> = {
+                let db = state.db.lock().unwrap();
+                promoted
+                    .updated
+                    .iter()
+                    .chain(promoted.added.iter())
+                    .filter_map(|id| db.get_schedule(*id).ok().flatten())
+                    .filter(|s| s.sync_status == "pending" || s.sync_status == "modified")
+                    .collect()
+            };
+            let (memos, schedules): (Vec<LocalSchedule>, Vec<LocalSchedule>) =
+                to_push.into_iter().partition(is_memo_schedule);
+            push_app_schedules(&client, &state, schedules, false, &mut report).await;
+            push_app_schedules(&client, &state, memos, true, &mut report).await;
+
+            for removed in &promoted.removed {
+                let Some(kintone_id) = removed.kintone_record_id else { continue };
+                match client.delete_record(kintone_id, is_memo_schedule(removed)).await {
+                    Ok(_) => {
+                        report.synced += 1;
+                        let id = removed.id.unwrap_or_default();
+                        {
+                            let db = state.db.lock().unwrap();
+                            if let Err(e) = db.mark_kintone_deleted(id) {
+                                eprintln!("Failed to mark kintone record deleted: {}", e);
+                            }
+                        }
+                        push_downstream(&client, &state, &[id], &mut report).await;
+                    }
+                    Err(e) => report.add_error(format!("kintoneから削除できませんでした（レコード{}）: {}", kintone_id, e)),
+                }
+            }
+        }
+        None => report.add_error("kintone設定が未設定のため送信していません（本番には反映済み）".to_string()),
+    }
+
+    eprintln!(
+        "=== promote_scenario: updated={}, added={}, removed={}, synced={} ===",
+        promoted.updated.len(), promoted.added.len(), promoted.removed.len(), report.synced
+    );
+    Ok(ApiResponse {
+        success: report.errors.is_empty(),
+        data: Some(ScenarioPromoteReport {
+            updated: promoted.updated.len(),
+            added: promoted.added.len(),
+            removed: promoted.removed.len(),
+            synced: report.synced,
+            overlaps: Vec::new(),
+        }),
+        error: if report.errors.is_empty() { None } else { Some(report.errors.join("\n")) },
+    })
+}
+
+/// 段取り替え時間の入力値をチェックして正規化
+fn validate_changeover(changeover: Changeover) -> Result<Changeover, String> {
+    let changeover = Changeover {
+        from_product: changeover.from_product.trim().to_string(),
+        to_product: changeover.to_product.trim().to_string(),
+        line: changeover.line.map(|l| l.trim().to_string()).filter(|l| !l.is_empty()),
+        ..changeover
+    };
+    if changeover.from_product.is_empty() || changeover.to_product.is_empty() {
+        return Err("切替前・切替後の品番は必須です".to_string());
+    }
+    if changeover.from_product == changeover.to_product {
+        return Err("同じ品番どうしの段取り替えは登録できません".to_string());
+    }
+    if changeover.minutes < 0 {
+        return Err("段取り時間は0分以上で入力してください".to_string());
+    }
+    Ok(changeover)
+}
+
+/// 段取り替え時間一覧を取得
+#[tauri::command]
+pub fn get_changeovers(state: State<AppState>) -> ApiResponse<Vec<Changeover>> {
+    let db = state.db.lock().unwrap();
+    match db.get_changeovers() {
+        Ok(changeovers) => ApiResponse {
+            success: true,
+            data: Some(changeovers),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 段取り替え時間を追加（同じ組み合わせがあれば上書き）
+#[tauri::command]
+pub fn add_changeover(changeover: Changeover, state: State<AppState>) -> ApiResponse<i64> {
+    let changeover = match validate_changeover(changeover) {
+        Ok(c) => c,
+        Err(e) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e),
+            };
+        }
+    };
+
+    let db = state.db.lock().unwrap();
+    match db.add_changeover(&changeover) {
+        Ok(id) => ApiResponse {
+            success: true,
+            data: Some(id),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 段取り替え時間を更新
+#[tauri::command]
+pub fn update_changeover(id: i64, changeover: Changeover, state: State<AppState>) -> ApiResponse<()> {
+    let changeover = match validate_changeover(changeover) {
+        Ok(c) => c,
+        Err(e) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e),
+            };
+        }
+    };
+
+    let db = state.db.lock().unwrap();
+    match db.update_changeover(id, &changeover) {
+        Ok(true) => ApiResponse {
+            success: true,
+            data: Some(()),
+            error: None,
+        },
+        Ok(false) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(format!("段取り替え時間が見つかりません: {}", id)),
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 段取り替え時間を削除
+#[tauri::command]
+pub fn delete_changeover(id: i64, state: State<AppState>) -> ApiResponse<()> {
+    let db = state.db.lock().unwrap();
+    match db.delete_changeover(id) {
+        Ok(_) => ApiResponse {
+            success: true,
+            data: Some(()),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// ラインごとの段取り替え時間帯を取得（ガントチャート表示用）
+#[tauri::command]
+pub fn get_changeover_blocks(state: State<AppState>) -> ApiResponse<Vec<ChangeoverBlock>> {
+    let db = state.db.lock().unwrap();
+    let result = (|| -> Result<Vec<ChangeoverBlock>, String> {
+        let schedules = db.get_all_schedules().map_err(|e| e.to_string())?;
+        Ok(changeover::compute_blocks(&schedules, &load_changeovers(&db)?, &load_calendar(&db)?))
+    })();
+
+    match result {
+        Ok(blocks) => ApiResponse {
+            success: true,
+            data: Some(blocks),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e),
+        },
+    }
+}
+
+/// 製品マスタ一覧を取得
+#[tauri::command]
+pub fn get_products(include_inactive: Option<bool>, state: State<AppState>) -> ApiResponse<Vec<Product>> {
+    let db = state.db.lock().unwrap();
+    match db.get_products(include_inactive.unwrap_or(false)) {
+        Ok(products) => ApiResponse {
+            success: true,
+            data: Some(products),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 製品を追加
+#[tauri::command]
+pub fn add_product(request: ProductRequest, state: State<AppState>) -> ApiResponse<i64> {
+    let mut product = request.into_product();
+    if let Err(e) = products::validate_product(&mut product) {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e),
+        };
+    }
+
+    let db = state.db.lock().unwrap();
+    if let Ok(Some(_)) = db.get_product(&product.product_name) {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some(format!("品番 {} は登録済みです", product.product_name)),
+        };
+    }
+
+    match db.add_product(&product) {
+        Ok(id) => {
+            eprintln!("=== Product added: {} ===", product.product_name);
+            ApiResponse {
+                success: true,
+                data: Some(id),
+                error: None,
+            }
+        }
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 製品を編集
+#[tauri::command]
+pub fn update_product(id: i64, request: ProductRequest, state: State<AppState>) -> ApiResponse<()> {
+    let mut product = request.into_product();
+    if let Err(e) = products::validate_product(&mut product) {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e),
+        };
+    }
+
+    let db = state.db.lock().unwrap();
+    if let Ok(Some(existing)) = db.get_product(&product.product_name) {
+        if existing.id != Some(id) {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(format!("品番 {} は登録済みです", product.product_name)),
+            };
+        }
+    }
+
+    match db.update_product(id, &product) {
+        Ok(true) => ApiResponse {
+            success: true,
+            data: Some(()),
+            error: None,
+        },
+        Ok(false) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some("製品が見つかりません".to_string()),
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 製品の有効・無効を切り替え（無効化した製品は入力候補から外れる）
+#[tauri::command]
+pub fn set_product_active(id: i64, active: bool, state: State<AppState>) -> ApiResponse<()> {
+    let db = state.db.lock().unwrap();
+    match db.set_product_active(id, active) {
+        Ok(true) => ApiResponse {
+            success: true,
+            data: Some(()),
+            error: None,
+        },
+        Ok(false) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some("製品が見つかりません".to_string()),
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 製品マスタをCSVから取り込み（品番が既存なら更新）
+/// エラーのある行が1つでもあれば何も取り込まない
+#[tauri::command]
+pub fn import_products_csv(content: String, state: State<AppState>) -> ApiResponse<ProductImportReport> {
+    let (parsed, errors) = products::parse_products_csv(&content);
+    if !errors.is_empty() {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some(errors.join("\n")),
+        };
+    }
+
+    let db = state.db.lock().unwrap();
+    match db.upsert_products(&parsed) {
+        Ok((inserted, updated)) => {
+            eprintln!("=== Product CSV imported: {} inserted, {} updated ===", inserted, updated);
+            ApiResponse {
+                success: true,
+                data: Some(ProductImportReport { inserted, updated }),
+                error: None,
+            }
+        }
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 製品マスタをCSVで出力（無効な製品も含む）
+#[tauri::command]
+pub fn export_products_csv(state: State<AppState>) -> ApiResponse<String> {
+    let db = state.db.lock().unwrap();
+    let result = db
+        .get_products(true)
+        .map_err(|e| e.to_string())
+        .and_then(|list| products::products_to_csv(&list));
+
+    match result {
+        Ok(csv) => ApiResponse {
+            success: true,
+            data: Some(csv),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e),
+        },
+    }
+}
+
+/// 稼働カレンダー（シフト・休日・保全）を取得
+#[tauri::command]
+pub fn get_plant_calendar(state: State<AppState>) -> ApiResponse<PlantCalendarView> {
+    let db = state.db.lock().unwrap();
+    let result = (|| -> rusqlite::Result<PlantCalendarView> {
+        Ok(PlantCalendarView {
+            shifts: db.get_shift_patterns()?,
+            holidays: db.get_holidays()?,
+            maintenance_windows: db.get_maintenance_windows()?,
+        })
+    })();
+
+    match result {
+        Ok(view) => ApiResponse {
+            success: true,
+            data: Some(view),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// シフトパターンを追加
+#[tauri::command]
+pub fn add_shift_pattern(shift: ShiftPattern, state: State<AppState>) -> ApiResponse<i64> {
+    let (Some(start), Some(end)) = (calendar::parse_time(&shift.start_time), calendar::parse_time(&shift.end_time)) else {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some("開始・終了時刻は HH:MM 形式で入力してください".to_string()),
+        };
+    };
+    if shift.weekday.is_some_and(|w| w > 6) {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some("曜日は 0（日曜）〜 6（土曜）で指定してください".to_string()),
+        };
+    }
+
+    let shift = ShiftPattern {
+        start_time: start.format("%H:%M").to_string(),
+        end_time: end.format("%H:%M").to_string(),
+        line: shift.line.filter(|l| !l.trim().is_empty()),
+        ..shift
+    };
+
+    let db = state.db.lock().unwrap();
+    match db.add_shift_pattern(&shift) {
+        Ok(id) => ApiResponse {
+            success: true,
+            data: Some(id),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// シフトパターンを削除
+#[tauri::command]
+pub fn delete_shift_pattern(id: i64, state: State<AppState>) -> ApiResponse<()> {
+    let db = state.db.lock().unwrap();
+    match db.delete_shift_pattern(id) {
+        Ok(_) => ApiResponse {
+            success: true,
+            data: Some(()),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 休日を追加
+#[tauri::command]
+pub fn add_holiday(holiday: Holiday, state: State<AppState>) -> ApiResponse<i64> {
+    let Some(date) = calendar::parse_date(&holiday.date) else {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some("日付は YYYY-MM-DD 形式で入力してください".to_string()),
+        };
+    };
+
+    let holiday = Holiday {
+        date: date.format("%Y-%m-%d").to_string(),
+        ..holiday
+    };
+
+    let db = state.db.lock().unwrap();
+    match db.add_holiday(&holiday) {
+        Ok(id) => ApiResponse {
+            success: true,
+            data: Some(id),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 休日を削除
+#[tauri::command]
+pub fn delete_holiday(id: i64, state: State<AppState>) -> ApiResponse<()> {
+    let db = state.db.lock().unwrap();
+    match db.delete_holiday(id) {
+        Ok(_) => ApiResponse {
+            success: true,
+            data: Some(()),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 保全時間帯を追加
+#[tauri::command]
+pub fn add_maintenance_window(window: MaintenanceWindow, state: State<AppState>) -> ApiResponse<i64> {
+    let start = scheduling::parse_datetime(&window.start_datetime);
+    let end = scheduling::parse_datetime(&window.end_datetime);
+    if !matches!((start, end), (Some(s), Some(e)) if e > s) {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some("保全の終了日時は開始日時より後にしてください".to_string()),
+        };
+    }
+
+    let window = MaintenanceWindow {
+        line: window.line.filter(|l| !l.trim().is_empty()),
+        ..window
+    };
+
+    let db = state.db.lock().unwrap();
+    match db.add_maintenance_window(&window) {
+        Ok(id) => ApiResponse {
+            success: true,
+            data: Some(id),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 保全時間帯を削除
+#[tauri::command]
+pub fn delete_maintenance_window(id: i64, state: State<AppState>) -> ApiResponse<()> {
+    let db = state.db.lock().unwrap();
+    match db.delete_maintenance_window(id) {
+        Ok(_) => ApiResponse {
+            success: true,
+            data: Some(()),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 生産日の稼働時間帯をラインごとに取得（ガントチャート背景・能力計算用）
+/// ライン未指定なら使用中の全ライン
+#[tauri::command]
+pub fn get_working_windows(date: String, line: Option<String>, state: State<AppState>) -> ApiResponse<Vec<LineWorkingWindows>> {
+    let Some(date) = calendar::parse_date(&date) else {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some("日付は YYYY-MM-DD 形式で指定してください".to_string()),
+        };
+    };
+
+    let db = state.db.lock().unwrap();
+    let plant_calendar = match load_calendar(&db) {
+        Ok(c) => c,
+        Err(e) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e),
+            };
+        }
+    };
+
+    let lines = match line {
+        Some(l) => vec![l],
+        None => {
+            let mut lines = db.get_lines().unwrap_or_default();
+            if lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines
+        }
+    };
+
+    ApiResponse {
+        success: true,
+        data: Some(lines.iter().map(|l| plant_calendar.line_day(date, l)).collect()),
+        error: None,
+    }
+}
+
+/// 生産時間と終了日時を計算（入力フォームのプレビュー用）
+#[tauri::command]
+pub fn calculate_schedule_duration(request: CalculateDurationRequest, state: State<AppState>) -> ApiResponse<ProductionEstimate> {
+    if scheduling::is_fixed_duration(&request.product_name) {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some("メモ・図形は終了日時を入力してください".to_string()),
+        };
+    }
+
+    let db = state.db.lock().unwrap();
+    let weight = match db.get_product_weight(&request.product_name) {
+        Ok(Some(weight)) if weight > 0.0 => weight,
+        Ok(_) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(SchedulingError::UnknownWeight(request.product_name.clone()).to_string()),
+            };
+        }
+        Err(e) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e.to_string()),
+            };
+        }
+    };
+
+    let calendar = match load_calendar(&db) {
+        Ok(c) => c,
+        Err(e) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e),
+            };
+        }
+    };
+
+    match scheduling::estimate(
+        weight,
+        request.start_datetime.as_deref(),
+        &request.quantities,
+        &request.efficiencies,
+        &calendar,
+        &request.line,
+    ) {
+        Ok(estimate) => ApiResponse {
+            success: true,
+            data: Some(estimate),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 汎用kintoneレコード取得（アプリ名指定）
+#[tauri::command]
+pub async fn fetch_kintone_records(
+    app_name: String,
+    query: Option<String>,
+    state: State<'_, AppState>
+) -> Result<ApiResponse<serde_json::Value>, ()> {
+    let client_opt = {
+        let kintone = state.kintone_client.lock().unwrap();
+        kintone.clone()
+    };
+
+    match client_opt {
+        Some(client) => {
+            match client.get_all_records_by_name(&app_name, query.as_deref()).await {
+                Ok(records) => Ok(ApiResponse {
+                    success: true,
+                    data: Some(serde_json::json!({
+                        "totalCount": records.len().to_string(),
+                        "records": records,
+                    })),
+                    error: None,
+                }),
+                Err(e) => Ok(ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some(e.to_string()),
+                })
+            }
+        },
+        None => Ok(ApiResponse {
+            success: false,
+            data: None,
+            error: Some("kintone設定が未設定です".into()),
+        })
+    }
+}
+
+/// 需給バランスの予測日数の上限
+const MAX_PROJECTION_DAYS: usize = 60;
+
+/// 在庫アラートを通知するイベント
+pub const STOCK_ALERTS_EVENT: &str = "stock-alerts";
+
+/// 需給バランスの予測
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceProjection {
+    pub start_date: String,
+    pub products: Vec<ProductBalance>,
+    pub inventory_records: usize,
+    pub shipping_records: usize,
+    pub production_schedules: usize,
+    /// 取得・解釈できなかったデータ（取得できた分だけで計算している）
+    pub warnings: Vec<String>,
+}
+
+/// 在庫アラートの一覧（直近の需給予測から判定）
+#[derive(Debug, Clone, Serialize)]
+pub struct StockAlertReport {
+    /// 判定に使った予測の開始日（まだ予測していなければNone）
+    pub start_date: Option<String>,
+    pub alerts: Vec<StockAlert>,
+}
+
+/// 需給予測の開始日（現在の生産日。06:00より前は前日）
+fn projection_start() -> chrono::NaiveDate {
+    calendar::production_date(chrono::Local::now().naive_local())
+}
+
+/// 在庫・出荷予定をkintoneから取得して需給バランスを予測し、直近の予測として保持
+async fn compute_balance_projection(client: &KintoneClient, state: &State<'_, AppState>, days: usize) -> Result<BalanceProjection, String> {
+    let start = projection_start();
+    let mut warnings = Vec::new();
+
+    eprintln!("=== Balance: fetching inventory (ID354) ===");
+    let inventory_query = format!(
+        "山状況 in ({})",
+        balance::INVENTORY_STATUSES.iter().map(|s| format!("\"{}\"", s)).collect::<Vec<_>>().join(", ")
+    );
+    let inventory_records = client
+        .get_all_records_by_name("yamazumi", Some(&inventory_query))
+        .await
+        .unwrap_or_else(|e| {
+            warnings.push(format!("在庫（ID354）を取得できませんでした: {}", e));
+            Vec::new()
+        });
+
+    // 積込予定は1件で7日分のため、開始日の6日前からの予定を取得する
+    eprintln!("=== Balance: fetching shipping plans (ID514) ===");
+    let shipping_query = format!(
+        "planDate >= \"{}\" and planDate <= \"{}\"",
+        (start - chrono::Duration::days(6)).format("%Y-%m-%d"),
+        (start + chrono::Duration::days(days as i64)).format("%Y-%m-%d")
+    );
+    let shipping_records = client
+        .get_all_records_by_name("tsumikomi", Some(&shipping_query))
+        .await
+        .unwrap_or_else(|e| {
+            warnings.push(format!("出荷予定（ID514）を取得できませんでした: {}", e));
+            Vec::new()
+        });
+
+    let schedules = {
+        let db = state.db.lock().unwrap();
+        db.get_all_schedules().map_err(|e| e.to_string())?
+    };
+
+    let inventory = balance::inventory_by_product(&inventory_records);
+    let shipping = balance::shipping_by_day(&shipping_records, start, days, &mut warnings);
+    let production = balance::production_by_day(&schedules, start, days);
+    let products = balance::project(start, days, &inventory, &production, &shipping);
+
+    eprintln!(
+        "=== Balance: inventory={}, shipping={}, schedules={}, warnings={} ===",
+        inventory_records.len(), shipping_records.len(), schedules.len(), warnings.len()
+    );
+    let projection = BalanceProjection {
+        start_date: start.format("%Y-%m-%d").to_string(),
+        products,
+        inventory_records: inventory_records.len(),
+        shipping_records: shipping_records.len(),
+        production_schedules: schedules.iter().filter(|s| balance::match_product(&s.product_name).is_some()).count(),
+        warnings,
+    };
+    *state.balance.lock().unwrap() = Some(projection.clone());
+    Ok(projection)
+}
+
+/// 直近の需給予測と在庫基準から在庫アラートを判定
+fn stock_alert_report(state: &AppState) -> Result<StockAlertReport, String> {
+    let levels = {
+        let db = state.db.lock().unwrap();
+        db.get_stock_levels().map_err(|e| e.to_string())?
+    };
+    let balance = state.balance.lock().unwrap();
+    Ok(match balance.as_ref() {
+        Some(projection) => StockAlertReport {
+            start_date: Some(projection.start_date.clone()),
+            alerts: balance::find_stock_alerts(&projection.products, &levels),
+        },
+        None => StockAlertReport { start_date: None, alerts: Vec::new() },
+    })
+}
+
+/// 在庫アラートを判定して画面に通知
+fn emit_stock_alerts(app: &AppHandle, state: &AppState) {
+    match stock_alert_report(state) {
+        Ok(report) => {
+            eprintln!("=== Stock alerts: {} ===", report.alerts.len());
+            if let Err(e) = app.emit(STOCK_ALERTS_EVENT, report) {
+                eprintln!("Failed to emit stock alerts: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Failed to check stock alerts: {}", e),
+    }
+}
+
+/// 直近の予測の在庫・出荷予定を使い、生産予定だけ計算し直して直近の予測として保持
+fn reproject_cached_balance(state: &AppState, start: chrono::NaiveDate, projection: BalanceProjection) -> Result<BalanceProjection, String> {
+    let schedules = {
+        let db = state.db.lock().unwrap();
+        db.get_all_schedules().map_err(|e| e.to_string())?
+    };
+    let projection = BalanceProjection {
+        products: balance::reproject(start, &projection.products, &schedules),
+        production_schedules: schedules.iter().filter(|s| balance::match_product(&s.product_name).is_some()).count(),
+        ..projection
+    };
+    *state.balance.lock().unwrap() = Some(projection.clone());
+    Ok(projection)
+}
+
+/// 同期後の在庫チェック（需給を予測し直して在庫アラートを通知）
+/// 今日の生産日の予測があれば在庫（ID354）・出荷予定（ID514）は取得し直さず、生産予定だけ反映する
+/// （在庫・出荷予定の取得し直しは需給バランス画面の更新で行う）
+/// 予測に失敗しても同期の結果には影響させない
+async fn check_stock_after_sync(app: &AppHandle, client: &KintoneClient, state: &State<'_, AppState>) {
+    let start = projection_start();
+    let today = start.format("%Y-%m-%d").to_string();
+    let cached = state.balance.lock().unwrap().clone().filter(|p| p.start_date == today);
+    let result = match cached {
+        Some(projection) => reproject_cached_balance(state, start, projection),
+        None => compute_balance_projection(client, state, balance::DEFAULT_PROJECTION_DAYS).await,
+    };
+    match result {
+        Ok(_) => emit_stock_alerts(app, state),
+        Err(e) => eprintln!("Failed to project balance after sync: {}", e),
+    }
+}
+
+/// 今日から指定日数（省略時14日）分の製品・日ごとの需給バランスを予測
+/// 在庫はID354（山積表）、出荷予定はID514（積込予定）、生産予定はローカルのスケジュールから求める
+#[tauri::command]
+pub async fn get_balance_projection(days: Option<usize>, state: State<'_, AppState>) -> Result<ApiResponse<BalanceProjection>, ()> {
+    let days = days.unwrap_or(balance::DEFAULT_PROJECTION_DAYS).clamp(1, MAX_PROJECTION_DAYS);
+
+    let client_opt = {
+        let kintone = state.kintone_client.lock().unwrap();
+        kintone.clone()
+    };
+    let Some(client) = client_opt else {
+        return Ok(ApiResponse {
+            success: false,
+            data: None,
+            error: Some("kintone設定が未設定です".to_string()),
+        });
+    };
+
+    match compute_balance_projection(&client, &state, days).await {
+        Ok(projection) => Ok(ApiResponse {
             success: true,
-            data: Some(()),
+            data: Some(projection),
             error: None,
         }),
         Err(e) => Ok(ApiResponse {
             success: false,
             data: None,
-            error: Some(e.to_string()),
+            error: Some(e),
         }),
     }
 }
 
-/// 製品の重量を取得
+/// 在庫アラート一覧を取得（直近の同期・需給予測の結果から判定）
 #[tauri::command]
-pub fn get_product_weight(product_name: String, state: State<AppState>) -> ApiResponse<f64> {
-    let db = state.db.lock().unwrap();
-    match db.get_product_weight(&product_name) {
-        Ok(Some(weight)) => ApiResponse {
+pub fn get_stock_alerts(state: State<AppState>) -> ApiResponse<StockAlertReport> {
+    match stock_alert_report(&state) {
+        Ok(report) => ApiResponse {
             success: true,
-            data: Some(weight),
+            data: Some(report),
             error: None,
         },
-        Ok(None) => ApiResponse {
+        Err(e) => ApiResponse {
             success: false,
             data: None,
-            error: Some(format!("製品 '{}' が見つかりません", product_name)),
+            error: Some(e),
         },
+    }
+}
+
+/// 追跡対象の製品ごとの在庫基準を取得（未設定の製品も含める）
+#[tauri::command]
+pub fn get_stock_levels(state: State<AppState>) -> ApiResponse<Vec<StockLevel>> {
+    let db = state.db.lock().unwrap();
+    match db.get_stock_levels() {
+        Ok(saved) => {
+            let levels = balance::BALANCE_PRODUCTS
+                .iter()
+                .map(|product| {
+                    saved.iter().find(|l| l.product == *product).cloned().unwrap_or(StockLevel {
+                        product: product.to_string(),
+                        safety_stock: None,
+                        max_stock: None,
+                        lot_size: None,
+                    })
+                })
+                .collect();
+            ApiResponse {
+                success: true,
+                data: Some(levels),
+                error: None,
+            }
+        }
         Err(e) => ApiResponse {
             success: false,
             data: None,
@@ -795,61 +3891,583 @@ pub fn get_product_weight(product_name: String, state: State<AppState>) -> ApiRe
     }
 }
 
+/// 在庫基準の入力値をチェック
+fn validate_stock_level(level: &StockLevel) -> Result<(), String> {
+    if !balance::BALANCE_PRODUCTS.contains(&level.product.as_str()) {
+        return Err(format!("需給バランスの対象外の製品です: {}", level.product));
+    }
+    for (name, value) in [("安全在庫", level.safety_stock), ("最大在庫", level.max_stock)] {
+        if value.is_some_and(|v| !v.is_finite() || v < 0.0) {
+            return Err(format!("{}の{}は0以上で入力してください", level.product, name));
+        }
+    }
+    if level.lot_size.is_some_and(|v| !v.is_finite() || v <= 0.0) {
+        return Err(format!("{}の生産ロットは0より大きい値を入力してください", level.product));
+    }
+    if let (Some(safety), Some(max)) = (level.safety_stock, level.max_stock) {
+        if safety > max {
+            return Err(format!("{}の安全在庫が最大在庫を上回っています", level.product));
+        }
+    }
+    Ok(())
+}
 
-/// 汎用kintoneレコード取得（アプリ名指定）
+/// 在庫基準を保存し、直近の需給予測で在庫アラートを判定し直す
 #[tauri::command]
-pub async fn fetch_kintone_records(
-    app_name: String,
-    query: Option<String>,
-    state: State<'_, AppState>
-) -> Result<ApiResponse<serde_json::Value>, ()> {
-    let client_opt = {
-        let kintone = state.kintone_client.lock().unwrap();
-        kintone.clone()
+pub fn save_stock_levels(levels: Vec<StockLevel>, app: AppHandle, state: State<AppState>) -> ApiResponse<()> {
+    if let Err(e) = levels.iter().try_for_each(validate_stock_level) {
+        return ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e),
+        };
+    }
+
+    let saved = {
+        let db = state.db.lock().unwrap();
+        levels.iter().try_for_each(|level| db.save_stock_level(level))
     };
+    match saved {
+        Ok(()) => {
+            emit_stock_alerts(&app, &state);
+            ApiResponse {
+                success: true,
+                data: Some(()),
+                error: None,
+            }
+        }
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
 
-    match client_opt {
-        Some(client) => {
-            match client.get_records_by_name(&app_name, query.as_deref()).await {
-                Ok(json) => Ok(ApiResponse {
-                    success: true,
-                    data: Some(json),
-                    error: None,
-                }),
-                Err(e) => Ok(ApiResponse {
+/// 生産提案1件（需給予測で安全在庫を下回る日に向けた生産案）
+#[derive(Debug, Clone, Serialize)]
+pub struct ProductionSuggestion {
+    pub product: String,
+    /// 安全在庫を下回る日
+    pub shortage_date: String,
+    /// それより前の提案分を加えた日末の在庫予測
+    pub projected: f64,
+    pub safety_stock: f64,
+    /// この日時までに生産を終える（不足する生産日の開始）
+    pub due_datetime: String,
+    pub production_minutes: i64,
+    /// 期限までに間に合わない（現在以降の空き時間に置いた）
+    pub late: bool,
+    /// 登録するスケジュール（accept_production_suggestions にそのまま渡す）
+    pub draft: AddScheduleRequest,
+}
+
+/// 生産提案の一覧
+#[derive(Debug, Clone, Serialize)]
+pub struct ProductionSuggestionReport {
+    /// 提案に使った需給予測の開始日
+    pub start_date: String,
+    pub suggestions: Vec<ProductionSuggestion>,
+    /// 提案できなかった製品と理由
+    pub warnings: Vec<String>,
+}
+
+/// 生産提案に使う製品の情報（製品マスタと直近のスケジュールから決める）
+struct SuggestionSource {
+    product: Product,
+    efficiency: String,
+    line: String,
+}
+
+/// 追跡対象の製品に対応する製品マスタ・製綿能率・ラインを決める
+/// 製綿能率は製品マスタの既定能率、なければ直近のスケジュールの製綿能率1
+/// ラインは直近のスケジュールのライン、なければ登録済みの最初のライン
+fn suggestion_source(
+    db: &Database,
+    product: &str,
+    products: &[Product],
+    schedules: &[LocalSchedule],
+    lines: &[String],
+) -> Result<SuggestionSource, String> {
+    let master = match db.get_product(product).map_err(|e| e.to_string())? {
+        Some(p) => p,
+        None => products
+            .iter()
+            .find(|p| balance::match_product(&p.product_name) == Some(product))
+            .cloned()
+            .ok_or_else(|| SchedulingError::UnknownWeight(product.to_string()).to_string())?,
+    };
+
+    let latest = schedules
+        .iter()
+        .filter(|s| balance::match_product(&s.product_name) == Some(product))
+        .filter_map(|s| scheduling::parse_datetime(&s.start_datetime).map(|start| (start, s)))
+        .max_by_key(|(start, _)| *start)
+        .map(|(_, s)| s);
+
+    let efficiency = master
+        .default_efficiency
+        .clone()
+        .or_else(|| latest.and_then(|s| s.efficiency1.clone()))
+        .filter(|e| !e.trim().is_empty())
+        .ok_or_else(|| "製綿能率が未設定です（製品マスタの既定能率を登録してください）".to_string())?;
+    let line = latest
+        .map(|s| s.line.clone())
+        .filter(|l| !l.trim().is_empty())
+        .or_else(|| lines.first().cloned())
+        .unwrap_or_default();
+
+    Ok(SuggestionSource { product: master, efficiency, line })
+}
+
+/// ラインごとの使用中の時間帯（メモ・図形は除く）
+fn busy_windows(schedules: &[LocalSchedule]) -> HashMap<String, Vec<calendar::Window>> {
+    let mut busy: HashMap<String, Vec<calendar::Window>> = HashMap::new();
+    for schedule in schedules {
+        if scheduling::is_fixed_duration(&schedule.product_name) {
+            continue;
+        }
+        let start = scheduling::parse_datetime(&schedule.start_datetime);
+        let end = schedule.end_datetime.as_deref().and_then(scheduling::parse_datetime);
+        if let (Some(start), Some(end)) = (start, end) {
+            busy.entry(schedule.line.trim().to_string()).or_default().push((start, end));
+        }
+    }
+    busy
+}
+
+/// 需給予測から生産提案を作成（DBは変更しない）
+/// 期限の遅いものから、同じラインの既存スケジュール・他の提案と重ならない最も遅い時間帯に置く
+fn plan_production_suggestions(db: &Database, projection: &BalanceProjection) -> Result<ProductionSuggestionReport, String> {
+    let levels = db.get_stock_levels().map_err(|e| e.to_string())?;
+    let schedules = db.get_all_schedules().map_err(|e| e.to_string())?;
+    let products = db.get_products(false).map_err(|e| e.to_string())?;
+    let lines = db.get_lines().map_err(|e| e.to_string())?;
+    let calendar = load_calendar(db)?;
+
+    let mut warnings = Vec::new();
+    let mut sources = Vec::new();
+    let mut planned = Vec::new();
+    for product_balance in &projection.products {
+        let level = levels.iter().find(|l| l.product == product_balance.product);
+        let requirements = mrp::requirements(product_balance, level);
+        if requirements.is_empty() {
+            continue;
+        }
+        let source = match suggestion_source(db, &product_balance.product, &products, &schedules, &lines) {
+            Ok(source) => source,
+            Err(e) => {
+                warnings.push(format!("{}: {}", product_balance.product, e));
+                continue;
+            }
+        };
+        for requirement in requirements {
+            let estimate = scheduling::estimate(
+                source.product.weight_kg,
+                None,
+                &[Some(requirement.quantity)],
+                std::slice::from_ref(&Some(source.efficiency.clone())),
+                &calendar,
+                &source.line,
+            );
+            match estimate {
+                Ok(estimate) => planned.push((requirement, estimate.production_minutes, sources.len())),
+                Err(e) => warnings.push(format!("{}: {}", product_balance.product, e)),
+            }
+        }
+        sources.push(source);
+    }
+
+    // 期限の遅いものから置くと、早い期限の提案が後ろの提案に押し出されない
+    planned.sort_by(|a, b| b.0.date.cmp(&a.0.date).then_with(|| a.0.product.cmp(&b.0.product)));
+
+    let now = chrono::Local::now().naive_local();
+    let mut busy = busy_windows(&schedules);
+    let mut suggestions = Vec::new();
+    for (requirement, minutes, source_index) in planned {
+        let source = &sources[source_index];
+        let due = calendar::day_bounds(requirement.date).0;
+        let line_busy = busy.entry(source.line.trim().to_string()).or_default();
+        let Some(slot) = mrp::latest_slot(&calendar, due, minutes, &source.line, line_busy, now) else {
+            warnings.push(format!("{}: {} のライン{}に稼働時間がありません", requirement.product, requirement.date, source.line));
+            continue;
+        };
+        line_busy.push((slot.start, slot.end));
+
+        let format = |value: chrono::NaiveDateTime| value.format("%Y-%m-%dT%H:%M").to_string();
+        let draft = AddScheduleRequest {
+            product_name: source.product.product_name.clone(),
+            product_display_name: source.product.display_name.clone(),
+            category: source.product.category.clone(),
+            line: source.line.clone(),
+            start_datetime: format(slot.start),
+            end_datetime: Some(format(slot.end)),
+            quantity1: Some(requirement.quantity),
+            quantity2: None,
+            quantity3: None,
+            quantity4: None,
+            quantity5: None,
+            quantity6: None,
+            quantity7: None,
+            quantity8: None,
+            total_quantity: Some(requirement.quantity),
+            production_status: Some("未生産".to_string()),
+            notes: Some(format!("生産提案: {} の在庫不足に対応", requirement.date.format("%m/%d"))),
+            efficiency: Some(source.efficiency.clone()),
+            allow_overlap: false,
+        };
+        suggestions.push(ProductionSuggestion {
+            product: requirement.product,
+            shortage_date: requirement.date.format("%Y-%m-%d").to_string(),
+            projected: requirement.projected,
+            safety_stock: requirement.target,
+            due_datetime: format(due),
+            production_minutes: minutes,
+            late: slot.late,
+            draft,
+        });
+    }
+    suggestions.sort_by(|a, b| a.draft.start_datetime.cmp(&b.draft.start_datetime).then_with(|| a.product.cmp(&b.product)));
+
+    Ok(ProductionSuggestionReport {
+        start_date: projection.start_date.clone(),
+        suggestions,
+        warnings,
+    })
+}
+
+/// 在庫不足を解消する生産案を提案（DBは変更しない）
+/// 今日の需給予測がなければkintoneから取得して予測し直す
+#[tauri::command]
+pub async fn suggest_production_orders(state: State<'_, AppState>) -> Result<ApiResponse<ProductionSuggestionReport>, ()> {
+    let today = projection_start().format("%Y-%m-%d").to_string();
+    let cached = state.balance.lock().unwrap().clone().filter(|p| p.start_date == today);
+
+    let projection = match cached {
+        Some(projection) => projection,
+        None => {
+            let client_opt = {
+                let kintone = state.kintone_client.lock().unwrap();
+                kintone.clone()
+            };
+            let Some(client) = client_opt else {
+                return Ok(ApiResponse {
                     success: false,
                     data: None,
-                    error: Some(e.to_string()),
-                })
+                    error: Some("kintone設定が未設定です".to_string()),
+                });
+            };
+            match compute_balance_projection(&client, &state, balance::DEFAULT_PROJECTION_DAYS).await {
+                Ok(projection) => projection,
+                Err(e) => {
+                    return Ok(ApiResponse {
+                        success: false,
+                        data: None,
+                        error: Some(e),
+                    });
+                }
             }
-        },
-        None => Ok(ApiResponse {
+        }
+    };
+
+    let db = state.db.lock().unwrap();
+    match plan_production_suggestions(&db, &projection) {
+        Ok(report) => {
+            eprintln!("=== Production suggestions: {} (warnings={}) ===", report.suggestions.len(), report.warnings.len());
+            Ok(ApiResponse {
+                success: true,
+                data: Some(report),
+                error: None,
+            })
+        }
+        Err(e) => Ok(ApiResponse {
             success: false,
             data: None,
-            error: Some("kintone設定が未設定です".into()),
-        })
+            error: Some(e),
+        }),
     }
 }
 
+/// 生産提案をまとめてスケジュールとして登録（1トランザクション、kintoneへは通常の同期で送信）
+/// 提案の作成後に追加されたスケジュールと重なる場合は登録しない
+#[tauri::command]
+pub fn accept_production_suggestions(drafts: Vec<AddScheduleRequest>, state: State<AppState>) -> ApiResponse<Vec<i64>> {
+    eprintln!("=== accept_production_suggestions: {} drafts ===", drafts.len());
+    let db = state.db.lock().unwrap();
+    let mut existing = match db.get_all_schedules() {
+        Ok(s) => s,
+        Err(e) => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e.to_string()),
+            };
+        }
+    };
+
+    let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut schedules = Vec::new();
+    for mut request in drafts {
+        if let Err(e) = prepare_add_request(&db, &mut request) {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(format!("{}: {}", request.product_name, e)),
+            };
+        }
+
+        let overlaps = validation::find_overlaps(&request_slot(&request), &existing);
+        if !overlaps.is_empty() && !request.allow_overlap {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(format!(
+                    "提案の {} は既存のスケジュールと重なるため登録できません。提案を作り直してください。{}",
+                    request.product_name,
+                    validation::describe_overlaps(&overlaps)
+                )),
+            };
+        }
+
+        let schedule = LocalSchedule {
+            id: None,
+            kintone_record_id: None,
+            schedule_number: None,
+            product_name: request.product_name,
+            product_display_name: request.product_display_name,
+            category: request.category,
+            line: request.line,
+            start_datetime: request.start_datetime,
+            end_datetime: request.end_datetime,
+            quantity1: request.quantity1,
+            quantity2: request.quantity2,
+            quantity3: request.quantity3,
+            quantity4: request.quantity4,
+            quantity5: request.quantity5,
+            quantity6: request.quantity6,
+            quantity7: request.quantity7,
+            quantity8: request.quantity8,
+            total_quantity: request.total_quantity,
+            efficiency1: request.efficiency,
+            efficiency2: None,
+            efficiency3: None,
+            efficiency4: None,
+            efficiency5: None,
+            efficiency6: None,
+            efficiency7: None,
+            efficiency8: None,
+            production_status: request.production_status.unwrap_or("未生産".to_string()),
+            notes: request.notes,
+            sync_status: "pending".to_string(),
+            created_at: now.clone(),
+            updated_at: now.clone(),
+            kintone_revision: None,
+        };
+        // 同時に登録する提案どうしの重なりも確認する
+        existing.push(schedule.clone());
+        schedules.push(schedule);
+    }
+
+    match db.add_schedules(&schedules) {
+        Ok(ids) => {
+            let before = ids.iter().map(|id| (*id, None)).collect();
+            record_undo(&state, &db, format!("生産提案{}件の登録", ids.len()), before);
+            ApiResponse {
+                success: true,
+                data: Some(ids),
+                error: None,
+            }
+        }
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
 
 /// アプリモードを取得
 #[tauri::command]
 pub fn get_app_mode() -> ApiResponse<String> {
-    #[cfg(feature = "admin-mode")]
-    let mode = "admin".to_string();
-    
-    #[cfg(feature = "worker-mode")]
-    let mode = "worker".to_string();
-    
-    #[cfg(not(any(feature = "admin-mode", feature = "worker-mode")))]
-    let mode = "admin".to_string();
-    
     ApiResponse {
         success: true,
-        data: Some(mode),
+        data: Some(audit::app_mode().to_string()),
         error: None,
     }
 }
 
+/// アクティビティフィードの既定の最大件数
+const ACTIVITY_FEED_LIMIT: i64 = 500;
+
+/// スケジュール1件の変更履歴を取得（新しい順）
+#[tauri::command]
+pub fn get_schedule_history(schedule_id: i64, state: State<AppState>) -> ApiResponse<Vec<ScheduleHistoryEntry>> {
+    let db = state.db.lock().unwrap();
+    match db.get_schedule_history(schedule_id) {
+        Ok(history) => ApiResponse {
+            success: true,
+            data: Some(history),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 期間内（YYYY-MM-DD、両端を含む）の全スケジュールの変更履歴を取得（新しい順）
+#[tauri::command]
+pub fn get_activity_feed(
+    from_date: String,
+    to_date: String,
+    limit: Option<i64>,
+    state: State<AppState>,
+) -> ApiResponse<Vec<ScheduleHistoryEntry>> {
+    let (from, to) = match (calendar::parse_date(&from_date), calendar::parse_date(&to_date)) {
+        (Some(from), Some(to)) if from <= to => (from, to),
+        (Some(_), Some(_)) => {
+            return ApiResponse { success: false, data: None, error: Some("開始日が終了日より後になっています".into()) };
+        }
+        _ => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(format!("日付の形式が不正です: {} 〜 {}", from_date, to_date)),
+            };
+        }
+    };
+
+    let db = state.db.lock().unwrap();
+    let limit = limit.filter(|l| *l > 0).unwrap_or(ACTIVITY_FEED_LIMIT);
+    match db.get_activity_feed(&from.to_string(), &to.to_string(), limit) {
+        Ok(history) => ApiResponse {
+            success: true,
+            data: Some(history),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// DBのスキーマバージョンを取得
+#[tauri::command]
+pub fn get_schema_version(state: State<AppState>) -> ApiResponse<SchemaVersionInfo> {
+    let db = state.db.lock().unwrap();
+    match db.schema_version() {
+        Ok(current) => ApiResponse {
+            success: true,
+            data: Some(SchemaVersionInfo { current, latest: SCHEMA_VERSION }),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// 未解決の同期競合一覧を取得
+#[tauri::command]
+pub fn get_sync_conflicts(state: State<AppState>) -> ApiResponse<Vec<SyncConflictView>> {
+    let db = state.db.lock().unwrap();
+    match db.get_open_conflicts() {
+        Ok(conflicts) => ApiResponse {
+            success: true,
+            data: Some(conflicts.into_iter().map(|conflict| {
+                // kintoneから受信しない列（ローカルにしかない列）は競合として扱わない
+                let columns = state.field_mappings.schedule(is_memo_schedule(&conflict.local)).pulled_columns();
+                let differing_fields = differing_fields(&conflict.local, &conflict.remote)
+                    .into_iter()
+                    .filter(|f| columns.contains(&f.as_str()))
+                    .collect();
+                SyncConflictView { conflict, differing_fields }
+            }).collect()),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// フィールドごとにローカル/kintoneの値を選んでマージ
+/// columns はkintoneから受信する列（それ以外の列はローカルの値のまま）
+fn merge_schedules(local: &LocalSchedule, remote: &LocalSchedule, fields: &HashMap<String, String>, columns: &[&str]) -> Result<LocalSchedule, String> {
+    let mut merged = serde_json::to_value(local).map_err(|e| e.to_string())?;
+    let remote_value = serde_json::to_value(remote).map_err(|e| e.to_string())?;
+
+    for (field, side) in fields {
+        if !MERGEABLE_FIELDS.contains(&field.as_str()) {
+            return Err(format!("マージできないフィールドです: {}", field));
+        }
+        if side == "remote" && !columns.contains(&field.as_str()) {
+            return Err(format!("kintoneから受信しないフィールドです: {}", field));
+        }
+        match side.as_str() {
+            "local" => {}
+            "remote" => merged[field.as_str()] = remote_value[field.as_str()].clone(),
+            other => return Err(format!("不明な採用元です: {} ({})", other, field)),
+        }
+    }
+
+    serde_json::from_value(merged).map_err(|e| e.to_string())
+}
+
+/// 同期競合を解決
+/// keep_local: ローカルの内容を次回送信 / keep_remote: kintoneの内容で上書き / merge: フィールド単位で選択
+#[tauri::command]
+pub fn resolve_sync_conflict(request: ResolveConflictRequest, state: State<AppState>) -> ApiResponse<()> {
+    let db = state.db.lock().unwrap();
+
+    let conflict = match db.get_open_conflicts() {
+        Ok(conflicts) => conflicts.into_iter().find(|c| c.id == request.conflict_id),
+        Err(e) => return ApiResponse { success: false, data: None, error: Some(e.to_string()) },
+    };
+    let conflict = match conflict {
+        Some(c) => c,
+        None => {
+            return ApiResponse {
+                success: false,
+                data: None,
+                error: Some(format!("競合 {} が見つからないか、解決済みです", request.conflict_id)),
+            };
+        }
+    };
+
+    // kintone側の内容は対応付けで受信する列だけを採用し、ローカルにしかない列は残す
+    let columns = state.field_mappings.schedule(is_memo_schedule(&conflict.local)).pulled_columns();
+    let resolved = match request.resolution.as_str() {
+        "keep_local" => Ok(conflict.local.clone()),
+        "keep_remote" => Ok(overlay_columns(&conflict.local, &conflict.remote, &columns)),
+        "merge" => merge_schedules(&conflict.local, &conflict.remote, &request.fields.unwrap_or_default(), &columns),
+        other => Err(format!("不明な解決方法です: {}", other)),
+    };
 
+    let mut resolved = match resolved {
+        Ok(s) => s,
+        Err(e) => return ApiResponse { success: false, data: None, error: Some(e) },
+    };
+    resolved.id = Some(conflict.schedule_id);
 
+    match db.resolve_conflict(conflict.id, &resolved, conflict.remote_revision, &request.resolution) {
+        Ok(_) => ApiResponse {
+            success: true,
+            data: Some(()),
+            error: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
@@ -0,0 +1,246 @@
+//! kintone接続設定の保存・読み込みモジュール
+//! APIトークンはインストールごとに生成したランダムな鍵で暗号化し、設定ファイルとは別に保存する
+//!
+//! 暗号鍵の保存先
+//! - Windows: 資格情報マネージャー（DPAPIでユーザーごとに保護される）
+//! - macOS: キーチェーン
+//! - それ以外: データフォルダの鍵ファイル（所有ユーザーだけが読み書きできる権限）。
+//!   暗号化ファイルと同じフォルダにあるため、そのユーザーとしてファイルを読める相手からはトークンを守れない
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::kintone_client::KintoneConfig;
+
+/// 設定ファイル（APIトークン以外）
+const CONFIG_FILE: &str = "kintone_config.json";
+
+/// 暗号化したAPIトークンの保存ファイル
+const SECRETS_FILE: &str = "kintone_secrets.json";
+
+/// 暗号鍵の保存ファイル（OSの資格情報ストアを使わない環境のみ）
+#[cfg(not(any(windows, target_os = "macos")))]
+const KEY_FILE: &str = "kintone_secrets.key";
+
+/// OSの資格情報ストアに保存する暗号鍵のサービス名・ユーザー名
+#[cfg(any(windows, target_os = "macos"))]
+const KEYRING_SERVICE: &str = "production-scheduler";
+#[cfg(any(windows, target_os = "macos"))]
+const KEYRING_USER: &str = "kintone-secrets-key";
+
+/// 暗号化ファイルの形式バージョン
+const SECRETS_VERSION: u32 = 1;
+
+/// 暗号化したシークレットの保存形式
+#[derive(Serialize, Deserialize)]
+struct SecretsFile {
+    version: u32,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// アプリのデータディレクトリ
+pub fn app_data_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .map(|p| p.join("production-scheduler"))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// 所有ユーザーだけが読み書きできるファイルとして書き込む
+/// Windowsではユーザーごとのデータディレクトリ（%LOCALAPPDATA%）の権限に従う
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // 既存ファイルは作成時の権限のままなので付け直す
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
+
+/// 保存済みの暗号鍵（Base64）を解釈
+fn decode_key(encoded: &str) -> Result<Key<Aes256Gcm>> {
+    let bytes = BASE64.decode(encoded.trim())?;
+    if bytes.len() != 32 {
+        return Err(anyhow::anyhow!("暗号鍵が破損しています"));
+    }
+    Ok(*Key::<Aes256Gcm>::from_slice(&bytes))
+}
+
+/// 暗号鍵を読み込み（未作成ならランダムに生成してOSの資格情報ストアに保存）
+#[cfg(any(windows, target_os = "macos"))]
+fn load_or_create_key() -> Result<Key<Aes256Gcm>> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?;
+    match entry.get_password() {
+        Ok(encoded) => decode_key(&encoded),
+        Err(keyring::Error::NoEntry) => {
+            let key = Aes256Gcm::generate_key(&mut OsRng);
+            entry.set_password(&BASE64.encode(key))?;
+            eprintln!("暗号鍵を作成しました（OSの資格情報ストア）");
+            Ok(key)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 暗号鍵を読み込み（未作成ならランダムに生成して鍵ファイルに保存）
+#[cfg(not(any(windows, target_os = "macos")))]
+fn load_or_create_key() -> Result<Key<Aes256Gcm>> {
+    let path = app_data_dir().join(KEY_FILE);
+    if path.exists() {
+        return decode_key(&std::fs::read_to_string(&path)?);
+    }
+
+    std::fs::create_dir_all(app_data_dir())?;
+    let key = Aes256Gcm::generate_key(&mut OsRng);
+    write_private(&path, BASE64.encode(key).as_bytes())?;
+    eprintln!("暗号鍵を作成しました: {:?}", path);
+    Ok(key)
+}
+
+/// シークレットを暗号化
+fn encrypt_secrets(secrets: &BTreeMap<String, String>) -> Result<SecretsFile> {
+    let cipher = Aes256Gcm::new(&load_or_create_key()?);
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let plaintext = serde_json::to_vec(secrets)?;
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_ref())
+        .map_err(|_| anyhow::anyhow!("APIトークンの暗号化に失敗しました"))?;
+
+    Ok(SecretsFile {
+        version: SECRETS_VERSION,
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+/// シークレットを復号
+fn decrypt_secrets(file: &SecretsFile) -> Result<BTreeMap<String, String>> {
+    if file.version != SECRETS_VERSION {
+        return Err(anyhow::anyhow!("未対応の暗号化ファイル形式です (v{})", file.version));
+    }
+
+    let nonce_bytes = BASE64.decode(&file.nonce)?;
+    let ciphertext = BASE64.decode(&file.ciphertext)?;
+    if nonce_bytes.len() != 12 {
+        return Err(anyhow::anyhow!("暗号化ファイルが破損しています"));
+    }
+
+    let cipher = Aes256Gcm::new(&load_or_create_key()?);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce_bytes), ciphertext.as_ref())
+        .map_err(|_| anyhow::anyhow!("APIトークンを復号できません（暗号鍵が作り直されたか、別のPC・ユーザーで作成された可能性があります）"))?;
+
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// 設定中のAPIトークンを名前付きで取り出す
+fn collect_tokens(config: &KintoneConfig) -> BTreeMap<String, String> {
+    let mut tokens = BTreeMap::new();
+    let mut put = |name: &str, token: Option<&String>| {
+        if let Some(t) = token.filter(|t| !t.is_empty()) {
+            tokens.insert(name.to_string(), t.clone());
+        }
+    };
+    put("api_token", Some(&config.api_token));
+    put("memo_api_token", config.memo_api_token.as_ref());
+    put("yamazumi_api_token", config.yamazumi_api_token.as_ref());
+    put("kobukuro_api_token", config.kobukuro_api_token.as_ref());
+    put("tsumikomi_api_token", config.tsumikomi_api_token.as_ref());
+    put("legacy_api_token", config.legacy_api_token.as_ref());
+    tokens
+}
+
+/// 名前付きのAPIトークンを設定に戻す（設定ファイル側に値があればそちらを優先）
+fn apply_tokens(config: &mut KintoneConfig, tokens: &BTreeMap<String, String>) {
+    if config.api_token.is_empty() {
+        config.api_token = tokens.get("api_token").cloned().unwrap_or_default();
+    }
+    let slots = [
+        ("memo_api_token", &mut config.memo_api_token),
+        ("yamazumi_api_token", &mut config.yamazumi_api_token),
+        ("kobukuro_api_token", &mut config.kobukuro_api_token),
+        ("tsumikomi_api_token", &mut config.tsumikomi_api_token),
+        ("legacy_api_token", &mut config.legacy_api_token),
+    ];
+    for (name, slot) in slots {
+        if slot.as_deref().unwrap_or_default().is_empty() {
+            *slot = tokens.get(name).cloned();
+        }
+    }
+}
+
+/// 暗号化ファイルからAPIトークンを読み込み
+fn load_tokens() -> Result<BTreeMap<String, String>> {
+    let path = app_data_dir().join(SECRETS_FILE);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let file: SecretsFile = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+    decrypt_secrets(&file)
+}
+
+/// kintone接続設定が使用可能か（サブドメインとスケジュール用トークンが必須）
+pub fn is_configured(config: &KintoneConfig) -> bool {
+    !config.subdomain.is_empty() && config.app_id != 0 && !config.api_token.is_empty()
+}
+
+/// kintone接続設定を読み込み（未設定ならNone）
+/// 旧バージョンの平文トークンが設定ファイルに残っていれば暗号化ファイルへ移す
+pub fn load_kintone_config() -> Option<KintoneConfig> {
+    let config_path = app_data_dir().join(CONFIG_FILE);
+    let content = std::fs::read_to_string(&config_path).ok()?;
+    let mut config: KintoneConfig = match serde_json::from_str(&content) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("設定ファイルを読み込めません: {}", e);
+            return None;
+        }
+    };
+
+    let has_plaintext_tokens = !collect_tokens(&config).is_empty();
+
+    match load_tokens() {
+        Ok(tokens) => apply_tokens(&mut config, &tokens),
+        Err(e) => eprintln!("APIトークンの読み込みに失敗: {}", e),
+    }
+
+    if has_plaintext_tokens {
+        eprintln!("平文のAPIトークンを暗号化ファイルへ移行します");
+        if let Err(e) = save_kintone_config(&config) {
+            eprintln!("APIトークンの移行に失敗: {}", e);
+        }
+    }
+
+    eprintln!("設定ファイルから読み込みました: {:?}", config_path);
+    Some(config)
+}
+
+/// kintone接続設定を保存（APIトークンは暗号化ファイル、それ以外は設定ファイル）
+pub fn save_kintone_config(config: &KintoneConfig) -> Result<()> {
+    let dir = app_data_dir();
+    std::fs::create_dir_all(&dir)?;
+
+    let secrets = encrypt_secrets(&collect_tokens(config))?;
+    write_private(&dir.join(SECRETS_FILE), serde_json::to_string_pretty(&secrets)?.as_bytes())?;
+
+    // KintoneConfigのトークン類はシリアライズされない
+    std::fs::write(dir.join(CONFIG_FILE), serde_json::to_string_pretty(config)?)?;
+
+    eprintln!("設定を保存しました: {:?}", dir.join(CONFIG_FILE));
+    Ok(())
+}
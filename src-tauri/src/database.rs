@@ -1,9 +1,11 @@
 //! ローカルデータベースモジュール
 //! SQLiteでスケジュールデータを管理
 
-use rusqlite::{Connection, Result, params};
+use rusqlite::{Connection, OptionalExtension, Result, Transaction, params};
 use serde::{Deserialize, Serialize};
 
+use crate::audit::Actor;
+
 /// ローカルスケジュールレコード
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LocalSchedule {
@@ -38,115 +40,731 @@ pub struct LocalSchedule {
     pub sync_status: String,
     pub created_at: String,
     pub updated_at: String,
+    /// kintone側の$revision（更新時の競合検出に使用）
+    #[serde(default)]
+    pub kintone_revision: Option<i64>,
+}
+
+impl LocalSchedule {
+    /// 生産数量1〜8
+    pub fn quantities(&self) -> Vec<Option<f64>> {
+        vec![
+            self.quantity1, self.quantity2, self.quantity3, self.quantity4,
+            self.quantity5, self.quantity6, self.quantity7, self.quantity8,
+        ]
+    }
+
+    /// 製綿能率1〜8
+    pub fn efficiencies(&self) -> Vec<Option<String>> {
+        vec![
+            self.efficiency1.clone(), self.efficiency2.clone(), self.efficiency3.clone(), self.efficiency4.clone(),
+            self.efficiency5.clone(), self.efficiency6.clone(), self.efficiency7.clone(), self.efficiency8.clone(),
+        ]
+    }
+}
+
+/// アプリごとのkintone差分同期の状態
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncState {
+    pub app_id: u32,
+    /// 最後に取得したレコードの更新日時
+    pub last_modified: Option<String>,
+    /// 最後に取得したレコードの$id
+    pub last_record_id: Option<u32>,
+    /// 最後に取得したレコードの$revision
+    pub last_revision: Option<i64>,
+}
+
+/// kintoneからのインポート結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportOutcome {
+    /// 新規追加した
+    Inserted(i64),
+    /// 既存レコードを更新した
+    Updated(i64),
+    /// 未送信のローカル変更があるため上書きしなかった
+    SkippedLocalChanges(i64),
+    /// ローカルとkintoneの両方が変更されていたため競合として記録した
+    Conflict(i64),
+    /// ローカルで削除済み（ゴミ箱にある）のため取り込まなかった
+    SkippedDeleted(i64),
+}
+
+/// ローカル変更とkintone側変更の競合
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConflict {
+    pub id: i64,
+    pub schedule_id: i64,
+    pub kintone_record_id: Option<u32>,
+    /// 競合検出時のローカルの内容
+    pub local: LocalSchedule,
+    /// 競合検出時のkintone側の内容
+    pub remote: LocalSchedule,
+    pub remote_revision: Option<i64>,
+    pub detected_at: String,
+}
+
+/// スケジュールの変更履歴
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleHistoryEntry {
+    pub id: i64,
+    pub schedule_id: i64,
+    /// insert / update / delete / sync_import
+    pub operation: String,
+    /// 変更前の内容（追加ならNone）
+    pub before: Option<LocalSchedule>,
+    /// 変更後の内容（削除ならNone）
+    pub after: Option<LocalSchedule>,
+    /// 値が変わったフィールド
+    pub changed_fields: Vec<String>,
+    /// admin / worker
+    pub app_mode: String,
+    pub machine_name: String,
+    /// 変更日時（端末のローカル時刻）
+    pub changed_at: String,
+}
+
+/// 検討用のシナリオ（本番スケジュールとの差分だけを持つ）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    /// 本番から変更したスケジュールの件数
+    pub change_count: i64,
+}
+
+/// シナリオ上の変更1件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioChange {
+    pub id: i64,
+    pub scenario_id: i64,
+    /// 本番スケジュールのID（シナリオで追加したものはNone）
+    pub schedule_id: Option<i64>,
+    /// add / modify / remove
+    pub change_type: String,
+    /// シナリオ上の内容（removeはNone）
+    pub data: Option<LocalSchedule>,
+    /// シナリオに取り込んだ時点の本番の内容（昇格時の競合検出に使用）
+    pub base: Option<LocalSchedule>,
+}
+
+/// 製品ごとの在庫基準（未設定ならNone）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockLevel {
+    pub product: String,
+    /// 安全在庫（これを下回ると欠品の恐れ）
+    pub safety_stock: Option<f64>,
+    /// 最大在庫（これを上回ると過剰在庫）
+    pub max_stock: Option<f64>,
+    /// 生産ロット（生産提案の数量をこの倍数に切り上げる）
+    #[serde(default)]
+    pub lot_size: Option<f64>,
+}
+
+/// ID354（山積表）・ID368（小袋実績）への連携状態
+/// status: synced / skipped（送信先で生産が進んでいる等で変更しない） / deleted / error（次回のkintone送信で再送）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownstreamSync {
+    pub schedule_id: i64,
+    /// 送信先にレコードがある連携先（yamazumi / kobukuro）
+    pub app: Option<String>,
+    pub status: String,
+    pub message: Option<String>,
+    pub updated_at: String,
+}
+
+/// ゴミ箱のスケジュール（削除済み）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeletedSchedule {
+    pub schedule: LocalSchedule,
+    pub deleted_at: String,
+    /// kintoneからの削除に失敗した理由（削除待ちで失敗したもの）
+    pub kintone_delete_error: Option<String>,
+}
+
+/// ゴミ箱に残す日数（これより古いものは完全に削除）
+pub const TRASH_RETENTION_DAYS: i64 = 30;
+
+/// シナリオ昇格で本番に反映した内容
+#[derive(Debug, Clone, Default)]
+pub struct PromotedChanges {
+    pub updated: Vec<i64>,
+    pub added: Vec<i64>,
+    /// 削除したスケジュール（kintoneからも削除するため）
+    pub removed: Vec<LocalSchedule>,
+}
+
+/// 製品マスタ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Product {
+    pub id: Option<i64>,
+    /// 品番（FS450K など）
+    pub product_name: String,
+    /// 品名
+    pub display_name: Option<String>,
+    /// 分類（ベーラー / 小袋）
+    pub category: Option<String>,
+    pub weight_kg: f64,
+    /// 既定の製綿能率 (t/h)
+    pub default_efficiency: Option<String>,
+    /// 無効化した製品は入力候補に出さない
+    pub active: bool,
+    pub updated_at: Option<String>,
+}
+
+/// 製品マスタのSELECT対象カラム（product_from_rowと順序を合わせること）
+const PRODUCT_COLUMNS: &str =
+    "id, product_name, display_name, category, weight_kg, default_efficiency, active, updated_at";
+
+/// SELECT結果の1行をProductに変換
+fn product_from_row(row: &rusqlite::Row) -> Result<Product> {
+    Ok(Product {
+        id: row.get(0)?,
+        product_name: row.get(1)?,
+        display_name: row.get(2)?,
+        category: row.get(3)?,
+        weight_kg: row.get(4)?,
+        default_efficiency: row.get(5)?,
+        active: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+/// シフトパターン（稼働時間帯）
+/// 時刻が06:00より前の場合は翌日（同じ生産日の後半）として扱う
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShiftPattern {
+    pub id: Option<i64>,
+    pub name: String,
+    /// 曜日（0=日曜〜6=土曜、生産日基準）。Noneは毎日
+    pub weekday: Option<u32>,
+    /// 対象ライン。Noneは全ライン
+    pub line: Option<String>,
+    /// 開始時刻（HH:MM）
+    pub start_time: String,
+    /// 終了時刻（HH:MM、開始以前なら翌日）
+    pub end_time: String,
+}
+
+/// 休日（生産日単位、全ライン停止）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Holiday {
+    pub id: Option<i64>,
+    /// 生産日（YYYY-MM-DD）
+    pub date: String,
+    pub name: Option<String>,
+}
+
+/// 段取り替え時間（切替前の製品 → 切替後の製品、ライン別）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Changeover {
+    pub id: Option<i64>,
+    pub from_product: String,
+    pub to_product: String,
+    /// 対象ライン。Noneは全ライン
+    pub line: Option<String>,
+    /// 段取り時間（分）
+    pub minutes: i64,
+    pub notes: Option<String>,
+}
+
+/// ラインの保全（計画停止）時間帯
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    pub id: Option<i64>,
+    /// 対象ライン。Noneは全ライン
+    pub line: Option<String>,
+    pub start_datetime: String,
+    pub end_datetime: String,
+    pub reason: Option<String>,
+}
+
+/// 競合マージで比較対象とするフィールド（ID・同期管理用のフィールドは除く）
+pub const MERGEABLE_FIELDS: &[&str] = &[
+    "schedule_number", "product_name", "product_display_name", "category", "line",
+    "start_datetime", "end_datetime",
+    "quantity1", "quantity2", "quantity3", "quantity4", "quantity5", "quantity6", "quantity7", "quantity8",
+    "total_quantity",
+    "efficiency1", "efficiency2", "efficiency3", "efficiency4", "efficiency5", "efficiency6", "efficiency7", "efficiency8",
+    "production_status", "notes",
+];
+
+/// 2つのスケジュールで値が異なるフィールド名を返す
+pub fn differing_fields(a: &LocalSchedule, b: &LocalSchedule) -> Vec<String> {
+    let a = serde_json::to_value(a).unwrap_or_default();
+    let b = serde_json::to_value(b).unwrap_or_default();
+    MERGEABLE_FIELDS
+        .iter()
+        .filter(|f| a.get(**f) != b.get(**f))
+        .map(|f| f.to_string())
+        .collect()
+}
+
+/// baseのうち columns の列だけを source の値に置き換えたスケジュール
+/// kintoneから受信する列だけを反映し、ローカルにしかない列（ライン・分類など）を残すために使う
+pub fn overlay_columns(base: &LocalSchedule, source: &LocalSchedule, columns: &[&str]) -> LocalSchedule {
+    let mut merged = serde_json::to_value(base).unwrap_or_default();
+    let source = serde_json::to_value(source).unwrap_or_default();
+    for column in columns.iter().filter(|c| MERGEABLE_FIELDS.contains(c)) {
+        merged[*column] = source[*column].clone();
+    }
+    serde_json::from_value(merged).unwrap_or_else(|_| base.clone())
+}
+
+/// kintoneのレコードをローカルのスケジュールに重ねた内容（受信する列とリビジョンだけをkintone側の値にする）
+pub fn remote_over_local(local: &LocalSchedule, remote: &LocalSchedule, columns: &[&str]) -> LocalSchedule {
+    let mut merged = overlay_columns(local, remote, columns);
+    merged.kintone_revision = remote.kintone_revision;
+    merged
+}
+
+/// スケジュールのSELECT対象カラム（schedule_from_rowと順序を合わせること）
+const SCHEDULE_COLUMNS: &str =
+    "id, kintone_record_id, schedule_number, product_name, product_display_name, category, line, start_datetime, end_datetime,
+     quantity1, quantity2, quantity3, quantity4, quantity5, quantity6, quantity7, quantity8,
+     total_quantity, efficiency1, efficiency2, efficiency3, efficiency4, efficiency5, efficiency6, efficiency7, efficiency8,
+     production_status, notes, sync_status, created_at, updated_at, kintone_revision";
+
+/// SELECT結果の1行をLocalScheduleに変換
+fn schedule_from_row(row: &rusqlite::Row) -> Result<LocalSchedule> {
+    Ok(LocalSchedule {
+        id: row.get(0)?,
+        kintone_record_id: row.get(1)?,
+        schedule_number: row.get(2)?,
+        product_name: row.get(3)?,
+        product_display_name: row.get(4)?,
+        category: row.get(5)?,
+        line: row.get(6)?,
+        start_datetime: row.get(7)?,
+        end_datetime: row.get(8)?,
+        quantity1: row.get(9)?,
+        quantity2: row.get(10)?,
+        quantity3: row.get(11)?,
+        quantity4: row.get(12)?,
+        quantity5: row.get(13)?,
+        quantity6: row.get(14)?,
+        quantity7: row.get(15)?,
+        quantity8: row.get(16)?,
+        total_quantity: row.get(17)?,
+        efficiency1: row.get(18)?,
+        efficiency2: row.get(19)?,
+        efficiency3: row.get(20)?,
+        efficiency4: row.get(21)?,
+        efficiency5: row.get(22)?,
+        efficiency6: row.get(23)?,
+        efficiency7: row.get(24)?,
+        efficiency8: row.get(25)?,
+        production_status: row.get(26)?,
+        notes: row.get(27)?,
+        sync_status: row.get(28)?,
+        created_at: row.get(29)?,
+        updated_at: row.get(30)?,
+        kintone_revision: row.get(31)?,
+    })
 }
 
+/// マイグレーションの手順
+enum MigrationStep {
+    /// SQLをそのまま実行
+    Sql(&'static str),
+    /// カラムがなければ追加（旧バージョンで追加済みのDBに対応）
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+}
+
+/// 番号付きマイグレーション
+struct Migration {
+    version: i64,
+    description: &'static str,
+    steps: &'static [MigrationStep],
+}
+
+/// マイグレーション一覧（バージョン順に追記すること。適用済みの内容は変更しない）
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "初期テーブル作成",
+        steps: &[
+            MigrationStep::Sql(
+                "CREATE TABLE IF NOT EXISTS schedules (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    kintone_record_id INTEGER,
+                    product_name TEXT NOT NULL,
+                    line TEXT NOT NULL,
+                    start_datetime TEXT NOT NULL,
+                    end_datetime TEXT,
+                    quantity1 REAL,
+                    quantity2 REAL,
+                    quantity3 REAL,
+                    quantity4 REAL,
+                    quantity5 REAL,
+                    quantity6 REAL,
+                    quantity7 REAL,
+                    quantity8 REAL,
+                    total_quantity REAL,
+                    production_status TEXT DEFAULT '予定',
+                    sync_status TEXT DEFAULT 'pending',
+                    created_at TEXT NOT NULL,
+                    updated_at TEXT NOT NULL
+                );
+                CREATE TABLE IF NOT EXISTS kintone_config (
+                    id INTEGER PRIMARY KEY,
+                    subdomain TEXT NOT NULL,
+                    app_id INTEGER NOT NULL,
+                    api_token TEXT NOT NULL
+                );
+                CREATE TABLE IF NOT EXISTS product_master (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    product_name TEXT NOT NULL UNIQUE,
+                    weight_kg REAL NOT NULL
+                );",
+            ),
+        ],
+    },
+    Migration {
+        version: 2,
+        description: "スケジュール番号・製綿能率・備考カラム追加",
+        steps: &[
+            MigrationStep::AddColumn { table: "schedules", column: "schedule_number", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "schedules", column: "efficiency1", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "schedules", column: "efficiency2", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "schedules", column: "efficiency3", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "schedules", column: "efficiency4", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "schedules", column: "efficiency5", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "schedules", column: "efficiency6", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "schedules", column: "efficiency7", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "schedules", column: "efficiency8", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "schedules", column: "notes", definition: "TEXT" },
+        ],
+    },
+    Migration {
+        version: 3,
+        description: "品名・分類カラム追加",
+        steps: &[
+            MigrationStep::AddColumn { table: "schedules", column: "product_display_name", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "schedules", column: "category", definition: "TEXT" },
+        ],
+    },
+    Migration {
+        version: 4,
+        description: "製品マスタ初期データ",
+        steps: &[
+            MigrationStep::Sql(
+                "INSERT OR IGNORE INTO product_master (product_name, weight_kg) VALUES
+                    ('FS450D', 450.0), ('FS450K', 450.0), ('FS450NR', 450.0), ('FS450S', 450.0),
+                    ('FS250C', 250.0), ('FS250CE', 250.0),
+                    ('FS360F', 360.0),
+                    ('FS021B', 20.0), ('FS021F', 20.0), ('FS021P', 20.0), ('FS021NR', 20.0),
+                    ('FS021', 20.0), ('FS021S', 20.0), ('FS021PF', 20.0), ('FS021PS', 20.0),
+                    ('FS021EMF', 20.0), ('FS021EMS', 20.0), ('FS021NRF', 20.0), ('FS021NRS', 20.0),
+                    ('小袋', 20.0);",
+            ),
+        ],
+    },
+    Migration {
+        version: 5,
+        description: "kintone差分同期の状態テーブル",
+        steps: &[
+            MigrationStep::Sql(
+                "CREATE TABLE IF NOT EXISTS sync_state (
+                    app_id INTEGER PRIMARY KEY,
+                    last_modified TEXT,
+                    last_record_id INTEGER,
+                    last_revision INTEGER,
+                    synced_at TEXT NOT NULL
+                );",
+            ),
+        ],
+    },
+    Migration {
+        version: 6,
+        description: "kintoneリビジョンと同期競合テーブル",
+        steps: &[
+            MigrationStep::AddColumn { table: "schedules", column: "kintone_revision", definition: "INTEGER" },
+            MigrationStep::Sql(
+                "CREATE TABLE IF NOT EXISTS sync_conflicts (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    schedule_id INTEGER NOT NULL,
+                    kintone_record_id INTEGER,
+                    local_data TEXT NOT NULL,
+                    remote_data TEXT NOT NULL,
+                    remote_revision INTEGER,
+                    detected_at TEXT NOT NULL,
+                    resolved_at TEXT,
+                    resolution TEXT
+                );
+                CREATE INDEX IF NOT EXISTS idx_sync_conflicts_schedule ON sync_conflicts(schedule_id);",
+            ),
+        ],
+    },
+    Migration {
+        version: 7,
+        description: "製品マスタの品名・分類・既定能率・有効フラグ",
+        steps: &[
+            MigrationStep::AddColumn { table: "product_master", column: "display_name", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "product_master", column: "category", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "product_master", column: "default_efficiency", definition: "TEXT" },
+            MigrationStep::AddColumn { table: "product_master", column: "active", definition: "INTEGER NOT NULL DEFAULT 1" },
+            MigrationStep::AddColumn { table: "product_master", column: "updated_at", definition: "TEXT" },
+            MigrationStep::Sql(
+                "UPDATE product_master SET category = '小袋'
+                    WHERE category IS NULL AND (product_name LIKE 'FS021%' OR product_name = '小袋');
+                 UPDATE product_master SET category = 'ベーラー'
+                    WHERE category IS NULL AND weight_kg >= 250;
+                 UPDATE product_master SET updated_at = datetime('now') WHERE updated_at IS NULL;",
+            ),
+        ],
+    },
+    Migration {
+        version: 8,
+        description: "稼働カレンダー（シフト・休日・保全）テーブル",
+        steps: &[
+            MigrationStep::Sql(
+                "CREATE TABLE IF NOT EXISTS shift_patterns (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    weekday INTEGER,
+                    line TEXT,
+                    start_time TEXT NOT NULL,
+                    end_time TEXT NOT NULL
+                );
+                CREATE TABLE IF NOT EXISTS holidays (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    date TEXT NOT NULL UNIQUE,
+                    name TEXT
+                );
+                CREATE TABLE IF NOT EXISTS maintenance_windows (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    line TEXT,
+                    start_datetime TEXT NOT NULL,
+                    end_datetime TEXT NOT NULL,
+                    reason TEXT
+                );",
+            ),
+        ],
+    },
+    Migration {
+        version: 9,
+        description: "段取り替え時間マトリクス",
+        steps: &[
+            MigrationStep::Sql(
+                "CREATE TABLE IF NOT EXISTS changeovers (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    from_product TEXT NOT NULL,
+                    to_product TEXT NOT NULL,
+                    line TEXT NOT NULL DEFAULT '',
+                    minutes INTEGER NOT NULL,
+                    notes TEXT,
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    UNIQUE(from_product, to_product, line)
+                );",
+            ),
+        ],
+    },
+    Migration {
+        version: 10,
+        description: "検討用シナリオ（本番との差分）テーブル",
+        steps: &[
+            MigrationStep::Sql(
+                "CREATE TABLE IF NOT EXISTS scenarios (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE,
+                    description TEXT,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+                CREATE TABLE IF NOT EXISTS scenario_changes (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    scenario_id INTEGER NOT NULL,
+                    schedule_id INTEGER,
+                    change_type TEXT NOT NULL,
+                    data TEXT,
+                    base_data TEXT,
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    UNIQUE(scenario_id, schedule_id)
+                );
+                CREATE INDEX IF NOT EXISTS idx_scenario_changes_scenario ON scenario_changes(scenario_id);",
+            ),
+        ],
+    },
+    Migration {
+        version: 11,
+        description: "スケジュール変更履歴テーブル",
+        steps: &[
+            MigrationStep::Sql(
+                "CREATE TABLE IF NOT EXISTS schedule_history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    schedule_id INTEGER NOT NULL,
+                    operation TEXT NOT NULL,
+                    before_data TEXT,
+                    after_data TEXT,
+                    changed_fields TEXT NOT NULL DEFAULT '',
+                    app_mode TEXT NOT NULL,
+                    machine_name TEXT NOT NULL,
+                    changed_at TEXT NOT NULL DEFAULT (datetime('now', 'localtime'))
+                );
+                CREATE INDEX IF NOT EXISTS idx_schedule_history_schedule ON schedule_history(schedule_id);
+                CREATE INDEX IF NOT EXISTS idx_schedule_history_changed_at ON schedule_history(changed_at);",
+            ),
+        ],
+    },
+    Migration {
+        version: 12,
+        description: "スケジュールの論理削除（ゴミ箱）",
+        steps: &[
+            MigrationStep::AddColumn {
+                table: "schedules",
+                column: "deleted_at",
+                definition: "TEXT",
+            },
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_schedules_deleted_at ON schedules(deleted_at);"),
+        ],
+    },
+    Migration {
+        version: 13,
+        description: "kintoneへの削除送信エラー",
+        steps: &[MigrationStep::AddColumn {
+            table: "schedules",
+            column: "kintone_delete_error",
+            definition: "TEXT",
+        }],
+    },
+    Migration {
+        version: 14,
+        description: "ID354・ID368への連携状態",
+        steps: &[MigrationStep::Sql(
+            "CREATE TABLE IF NOT EXISTS downstream_sync (
+                schedule_id INTEGER PRIMARY KEY,
+                app TEXT,
+                status TEXT NOT NULL,
+                message TEXT,
+                updated_at TEXT NOT NULL DEFAULT (datetime('now', 'localtime'))
+            );",
+        )],
+    },
+    Migration {
+        version: 15,
+        description: "製品ごとの安全在庫・最大在庫",
+        steps: &[MigrationStep::Sql(
+            "CREATE TABLE IF NOT EXISTS stock_levels (
+                product TEXT PRIMARY KEY,
+                safety_stock REAL,
+                max_stock REAL,
+                updated_at TEXT NOT NULL DEFAULT (datetime('now', 'localtime'))
+            );",
+        )],
+    },
+    Migration {
+        version: 16,
+        description: "製品ごとの生産ロット",
+        steps: &[MigrationStep::AddColumn {
+            table: "stock_levels",
+            column: "lot_size",
+            definition: "REAL",
+        }],
+    },
+];
+
+/// このバイナリが対応するスキーマバージョン
+pub const SCHEMA_VERSION: i64 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
 /// データベース管理
 pub struct Database {
     conn: Connection,
+    /// 変更履歴に記録するアプリ・端末
+    actor: Actor,
 }
 
 impl Database {
     /// データベースを開く（なければ作成）
-    pub fn open(db_path: &str) -> Result<Self> {
-        let conn = Connection::open(db_path)?;
-        let db = Self { conn };
-        db.init_tables()?;
-        Ok(db)
+    /// スキーマはマイグレーションで最新バージョンまで更新する
+    pub fn open(db_path: &str) -> anyhow::Result<Self> {
+        let mut conn = Connection::open(db_path)?;
+        Self::run_migrations(&mut conn)?;
+        Ok(Self { conn, actor: Actor::current() })
     }
 
-    /// テーブルを初期化
-    fn init_tables(&self) -> Result<()> {
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS schedules (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                kintone_record_id INTEGER,
-                schedule_number TEXT,
-                product_name TEXT NOT NULL,
-                product_display_name TEXT,
-                category TEXT,
-                line TEXT NOT NULL,
-                start_datetime TEXT NOT NULL,
-                end_datetime TEXT,
-                quantity1 REAL,
-                quantity2 REAL,
-                quantity3 REAL,
-                quantity4 REAL,
-                quantity5 REAL,
-                quantity6 REAL,
-                quantity7 REAL,
-                quantity8 REAL,
-                total_quantity REAL,
-                efficiency1 TEXT,
-                efficiency2 TEXT,
-                efficiency3 TEXT,
-                efficiency4 TEXT,
-                efficiency5 TEXT,
-                efficiency6 TEXT,
-                efficiency7 TEXT,
-                efficiency8 TEXT,
-                production_status TEXT DEFAULT '予定',
-                notes TEXT,
-                sync_status TEXT DEFAULT 'pending',
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            )",
+    /// 現在のスキーマバージョンを取得（schema_versionテーブルがなければ0）
+    fn read_schema_version(conn: &Connection) -> Result<i64> {
+        let has_table: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
             [],
+            |row| row.get(0),
         )?;
+        if !has_table {
+            return Ok(0);
+        }
+        conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))
+    }
 
-        // 既存テーブルにカラム追加（エラーは無視）
-        let _ = self.conn.execute("ALTER TABLE schedules ADD COLUMN schedule_number TEXT", []);
-        let _ = self.conn.execute("ALTER TABLE schedules ADD COLUMN efficiency1 TEXT", []);
-        let _ = self.conn.execute("ALTER TABLE schedules ADD COLUMN efficiency2 TEXT", []);
-        let _ = self.conn.execute("ALTER TABLE schedules ADD COLUMN efficiency3 TEXT", []);
-        let _ = self.conn.execute("ALTER TABLE schedules ADD COLUMN efficiency4 TEXT", []);
-        let _ = self.conn.execute("ALTER TABLE schedules ADD COLUMN efficiency5 TEXT", []);
-        let _ = self.conn.execute("ALTER TABLE schedules ADD COLUMN efficiency6 TEXT", []);
-        let _ = self.conn.execute("ALTER TABLE schedules ADD COLUMN efficiency7 TEXT", []);
-        let _ = self.conn.execute("ALTER TABLE schedules ADD COLUMN efficiency8 TEXT", []);
-        let _ = self.conn.execute("ALTER TABLE schedules ADD COLUMN notes TEXT", []);
-        let _ = self.conn.execute("ALTER TABLE schedules ADD COLUMN product_display_name TEXT", []);
-        let _ = self.conn.execute("ALTER TABLE schedules ADD COLUMN category TEXT", []);
-
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS kintone_config (
-                id INTEGER PRIMARY KEY,
-                subdomain TEXT NOT NULL,
-                app_id INTEGER NOT NULL,
-                api_token TEXT NOT NULL
+    /// 未適用のマイグレーションを番号順に実行
+    /// 各マイグレーションは1トランザクション内で実行し、失敗時はロールバックする
+    fn run_migrations(conn: &mut Connection) -> anyhow::Result<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS schema_version (
+                version INTEGER PRIMARY KEY,
+                description TEXT NOT NULL,
+                applied_at TEXT NOT NULL
             )",
             [],
         )?;
 
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS product_master (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                product_name TEXT NOT NULL UNIQUE,
-                weight_kg REAL NOT NULL
-            )",
-            [],
-        )?;
+        let current = Self::read_schema_version(conn)?;
+        if current > SCHEMA_VERSION {
+            return Err(anyhow::anyhow!(
+                "データベースのスキーマ(v{})がこのアプリ(v{})より新しいため開けません。アプリを更新してください。",
+                current, SCHEMA_VERSION
+            ));
+        }
 
-        // 製品マスタの初期データ
-        let products = vec![
-            ("FS450D", 450.0), ("FS450K", 450.0), ("FS450NR", 450.0), ("FS450S", 450.0),
-            ("FS250C", 250.0), ("FS250CE", 250.0),
-            ("FS360F", 360.0),
-            ("FS021B", 20.0), ("FS021F", 20.0), ("FS021P", 20.0), ("FS021NR", 20.0),
-            ("FS021", 20.0), ("FS021S", 20.0), ("FS021PF", 20.0), ("FS021PS", 20.0),
-            ("FS021EMF", 20.0), ("FS021EMS", 20.0), ("FS021NRF", 20.0), ("FS021NRS", 20.0),
-            ("小袋", 20.0),
-        ];
-
-        for (name, weight) in products {
-            let _ = self.conn.execute(
-                "INSERT OR IGNORE INTO product_master (product_name, weight_kg) VALUES (?1, ?2)",
-                params![name, weight],
-            );
+        for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+            eprintln!("=== Migrating schema to v{}: {} ===", migration.version, migration.description);
+            let tx = conn.transaction()?;
+            for step in migration.steps {
+                match step {
+                    MigrationStep::Sql(sql) => tx.execute_batch(sql)?,
+                    MigrationStep::AddColumn { table, column, definition } => {
+                        if !Self::column_exists(&tx, table, column)? {
+                            tx.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+                        }
+                    }
+                }
+            }
+            tx.execute(
+                "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, datetime('now'))",
+                params![migration.version, migration.description],
+            )?;
+            tx.commit().map_err(|e| {
+                anyhow::anyhow!("マイグレーション v{} の適用に失敗しました: {}", migration.version, e)
+            })?;
         }
 
         Ok(())
     }
 
+    /// テーブルにカラムが存在するか
+    fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        for name in names {
+            if name? == column {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// スキーマバージョンを取得
+    pub fn schema_version(&self) -> Result<i64> {
+        Self::read_schema_version(&self.conn)
+    }
+
     /// 採番を生成（MMDDYY_XXX形式、日毎リセット）
     pub fn generate_schedule_number(&self) -> Result<String> {
         use chrono::Local;
@@ -170,20 +788,36 @@ impl Database {
 
     /// スケジュールを追加（自動採番）
     pub fn add_schedule(&self, schedule: &LocalSchedule) -> Result<i64> {
+        self.insert_schedule(schedule, None)
+    }
+
+    /// 削除したスケジュールを元のIDで追加し直す（取り消し用。ゴミ箱に残っていれば置き換える）
+    pub fn restore_schedule(&self, schedule: &LocalSchedule) -> Result<i64> {
+        let id = schedule.id.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        let tx = self.begin_if_autocommit()?;
+        self.purge_schedule(id)?;
+        let id = self.insert_schedule(schedule, Some(id))?;
+        Self::commit_if_started(tx)?;
+        Ok(id)
+    }
+
+    /// スケジュールを追加（IDがNoneなら自動採番）
+    fn insert_schedule(&self, schedule: &LocalSchedule, id: Option<i64>) -> Result<i64> {
         // 採番を生成
         let schedule_number = if schedule.schedule_number.is_none() {
             Some(self.generate_schedule_number()?)
         } else {
             schedule.schedule_number.clone()
         };
-        
+
+        let tx = self.begin_if_autocommit()?;
         self.conn.execute(
             "INSERT INTO schedules (
                 kintone_record_id, schedule_number, product_name, product_display_name, category, line, start_datetime, end_datetime,
                 quantity1, quantity2, quantity3, quantity4, quantity5, quantity6, quantity7, quantity8,
                 total_quantity, efficiency1, efficiency2, efficiency3, efficiency4, efficiency5, efficiency6, efficiency7, efficiency8,
-                production_status, notes, sync_status, created_at, updated_at
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30)",
+                production_status, notes, sync_status, created_at, updated_at, kintone_revision, id
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32)",
             params![
                 schedule.kintone_record_id,
                 schedule_number,
@@ -215,146 +849,233 @@ impl Database {
                 schedule.sync_status,
                 schedule.created_at,
                 schedule.updated_at,
+                schedule.kintone_revision,
+                id,
             ],
         )?;
-        Ok(self.conn.last_insert_rowid())
+        let id = self.conn.last_insert_rowid();
+        self.record_history(id, "insert", None, self.get_schedule(id)?.as_ref())?;
+        Self::commit_if_started(tx)?;
+        Ok(id)
     }
 
     /// すべてのスケジュールを取得
     pub fn get_all_schedules(&self) -> Result<Vec<LocalSchedule>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, kintone_record_id, schedule_number, product_name, product_display_name, category, line, start_datetime, end_datetime,
-                    quantity1, quantity2, quantity3, quantity4, quantity5, quantity6, quantity7, quantity8,
-                    total_quantity, efficiency1, efficiency2, efficiency3, efficiency4, efficiency5, efficiency6, efficiency7, efficiency8,
-                    production_status, notes, sync_status, created_at, updated_at
-             FROM schedules ORDER BY start_datetime DESC"
-        )?;
-
-        let schedules = stmt.query_map([], |row| {
-            Ok(LocalSchedule {
-                id: row.get(0)?,
-                kintone_record_id: row.get(1)?,
-                schedule_number: row.get(2)?,
-                product_name: row.get(3)?,
-                product_display_name: row.get(4)?,
-                category: row.get(5)?,
-                line: row.get(6)?,
-                start_datetime: row.get(7)?,
-                end_datetime: row.get(8)?,
-                quantity1: row.get(9)?,
-                quantity2: row.get(10)?,
-                quantity3: row.get(11)?,
-                quantity4: row.get(12)?,
-                quantity5: row.get(13)?,
-                quantity6: row.get(14)?,
-                quantity7: row.get(15)?,
-                quantity8: row.get(16)?,
-                total_quantity: row.get(17)?,
-                efficiency1: row.get(18)?,
-                efficiency2: row.get(19)?,
-                efficiency3: row.get(20)?,
-                efficiency4: row.get(21)?,
-                efficiency5: row.get(22)?,
-                efficiency6: row.get(23)?,
-                efficiency7: row.get(24)?,
-                efficiency8: row.get(25)?,
-                production_status: row.get(26)?,
-                notes: row.get(27)?,
-                sync_status: row.get(28)?,
-                created_at: row.get(29)?,
-                updated_at: row.get(30)?,
-            })
-        })?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM schedules WHERE deleted_at IS NULL ORDER BY start_datetime DESC",
+            SCHEDULE_COLUMNS
+        ))?;
 
+        let schedules = stmt.query_map([], schedule_from_row)?;
         schedules.collect()
     }
 
     /// 同期待ちスケジュールを取得
     pub fn get_pending_schedules(&self) -> Result<Vec<LocalSchedule>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, kintone_record_id, schedule_number, product_name, product_display_name, category, line, start_datetime, end_datetime,
-                    quantity1, quantity2, quantity3, quantity4, quantity5, quantity6, quantity7, quantity8,
-                    total_quantity, efficiency1, efficiency2, efficiency3, efficiency4, efficiency5, efficiency6, efficiency7, efficiency8,
-                    production_status, notes, sync_status, created_at, updated_at
-             FROM schedules WHERE sync_status = 'pending' OR sync_status = 'modified'"
-        )?;
-
-        let schedules = stmt.query_map([], |row| {
-            Ok(LocalSchedule {
-                id: row.get(0)?,
-                kintone_record_id: row.get(1)?,
-                schedule_number: row.get(2)?,
-                product_name: row.get(3)?,
-                product_display_name: row.get(4)?,
-                category: row.get(5)?,
-                line: row.get(6)?,
-                start_datetime: row.get(7)?,
-                end_datetime: row.get(8)?,
-                quantity1: row.get(9)?,
-                quantity2: row.get(10)?,
-                quantity3: row.get(11)?,
-                quantity4: row.get(12)?,
-                quantity5: row.get(13)?,
-                quantity6: row.get(14)?,
-                quantity7: row.get(15)?,
-                quantity8: row.get(16)?,
-                total_quantity: row.get(17)?,
-                efficiency1: row.get(18)?,
-                efficiency2: row.get(19)?,
-                efficiency3: row.get(20)?,
-                efficiency4: row.get(21)?,
-                efficiency5: row.get(22)?,
-                efficiency6: row.get(23)?,
-                efficiency7: row.get(24)?,
-                efficiency8: row.get(25)?,
-                production_status: row.get(26)?,
-                notes: row.get(27)?,
-                sync_status: row.get(28)?,
-                created_at: row.get(29)?,
-                updated_at: row.get(30)?,
-            })
-        })?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM schedules WHERE (sync_status = 'pending' OR sync_status = 'modified') AND deleted_at IS NULL",
+            SCHEDULE_COLUMNS
+        ))?;
 
+        let schedules = stmt.query_map([], schedule_from_row)?;
         schedules.collect()
     }
 
+    /// IDでスケジュールを取得（削除済みは含まない）
+    pub fn get_schedule(&self, id: i64) -> Result<Option<LocalSchedule>> {
+        self.conn.query_row(
+            &format!("SELECT {} FROM schedules WHERE id = ?1 AND deleted_at IS NULL", SCHEDULE_COLUMNS),
+            params![id],
+            schedule_from_row,
+        ).optional()
+    }
+
     /// 同期ステータスを更新
-    pub fn update_sync_status(&self, id: i64, status: &str, kintone_id: Option<u32>) -> Result<()> {
+    pub fn update_sync_status(&self, id: i64, status: &str, kintone_id: Option<u32>, revision: Option<i64>) -> Result<()> {
         self.conn.execute(
-            "UPDATE schedules SET sync_status = ?1, kintone_record_id = ?2, updated_at = datetime('now') WHERE id = ?3",
-            params![status, kintone_id, id],
+            "UPDATE schedules SET sync_status = ?1, kintone_record_id = ?2, kintone_revision = COALESCE(?3, kintone_revision), updated_at = datetime('now') WHERE id = ?4",
+            params![status, kintone_id, revision, id],
         )?;
         Ok(())
     }
 
-    /// 全スケジュールを削除
-    pub fn delete_all_schedules(&self) -> Result<()> {
-        self.conn.execute("DELETE FROM schedules", [])?;
-        Ok(())
+    /// 複数スケジュールをまとめて同期済みにする（1トランザクション、失敗時は全件ロールバック）
+    /// entriesは (ローカルID, kintoneレコードID, リビジョン)
+    pub fn mark_synced_batch(&self, entries: &[(i64, u32, Option<i64>)]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, kintone_id, revision) in entries {
+            tx.execute(
+                "UPDATE schedules SET sync_status = 'synced', kintone_record_id = ?1, kintone_revision = COALESCE(?2, kintone_revision), updated_at = datetime('now') WHERE id = ?3",
+                params![kintone_id, revision, id],
+            )?;
+        }
+        tx.commit()
     }
 
-    /// スケジュールを削除
-    /// IDでスケジュールのkintone_record_idを取得
+    /// IDでスケジュールのkintone_record_idを取得（ゴミ箱のスケジュールはNone）
     pub fn get_kintone_record_id(&self, id: i64) -> Result<Option<u32>> {
         let mut stmt = self.conn.prepare(
-            "SELECT kintone_record_id FROM schedules WHERE id = ?1"
+            "SELECT kintone_record_id FROM schedules WHERE id = ?1 AND deleted_at IS NULL"
         )?;
         
         let result: Option<Option<u32>> = stmt.query_row(params![id], |row| {
-            Ok(row.get::<_, Option<u32>>(0)?)
+            row.get::<_, Option<u32>>(0)
         }).ok();
         
         Ok(result.flatten())
     }
 
+    /// スケジュールを削除（ゴミ箱へ移す。復元・完全削除するまで行は残す）
     pub fn delete_schedule(&self, id: i64) -> Result<()> {
+        self.with_history(id, "delete", || {
+            self.conn.execute(
+                "UPDATE schedules SET deleted_at = datetime('now') WHERE id = ?1 AND deleted_at IS NULL",
+                params![id],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// ゴミ箱のスケジュール一覧（削除が新しい順）
+    pub fn get_deleted_schedules(&self) -> Result<Vec<DeletedSchedule>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}, deleted_at, kintone_delete_error FROM schedules WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC, id DESC",
+            SCHEDULE_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], |row| {
+            Ok(DeletedSchedule {
+                schedule: schedule_from_row(row)?,
+                deleted_at: row.get(32)?,
+                kintone_delete_error: row.get(33)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// ID354・ID368への連携状態を取得
+    pub fn get_downstream_sync(&self, schedule_id: i64) -> Result<Option<DownstreamSync>> {
+        self.conn.query_row(
+            "SELECT schedule_id, app, status, message, updated_at FROM downstream_sync WHERE schedule_id = ?1",
+            params![schedule_id],
+            |row| {
+                Ok(DownstreamSync {
+                    schedule_id: row.get(0)?,
+                    app: row.get(1)?,
+                    status: row.get(2)?,
+                    message: row.get(3)?,
+                    updated_at: row.get(4)?,
+                })
+            },
+        ).optional()
+    }
+
+    /// ID354・ID368への連携状態を記録
+    pub fn save_downstream_sync(&self, schedule_id: i64, app: Option<&str>, status: &str, message: Option<&str>) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO downstream_sync (schedule_id, app, status, message, updated_at)
+             VALUES (?1, ?2, ?3, ?4, datetime('now', 'localtime'))
+             ON CONFLICT(schedule_id) DO UPDATE SET
+                app = excluded.app, status = excluded.status, message = excluded.message, updated_at = excluded.updated_at",
+            params![schedule_id, app, status, message],
+        )?;
+        Ok(())
+    }
+
+    /// ID354・ID368への送信に失敗したスケジュール（再送対象）
+    pub fn get_downstream_retry_ids(&self) -> Result<Vec<i64>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT schedule_id FROM downstream_sync WHERE status = 'error' ORDER BY updated_at, schedule_id"
+        )?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// kintoneからの削除待ち（削除済みでkintoneのレコードが残っているもの、削除が古い順）
+    pub fn get_pending_deletions(&self) -> Result<Vec<LocalSchedule>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM schedules WHERE deleted_at IS NOT NULL AND kintone_record_id IS NOT NULL ORDER BY deleted_at, id",
+            SCHEDULE_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], schedule_from_row)?;
+        rows.collect()
+    }
+
+    /// kintoneからの削除に失敗した理由を記録（次回の同期で再送する）
+    pub fn record_kintone_delete_error(&self, id: i64, error: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE schedules SET kintone_delete_error = ?1 WHERE id = ?2 AND deleted_at IS NOT NULL",
+            params![error, id],
+        )?;
+        Ok(())
+    }
+
+    /// ゴミ箱のスケジュールを取得
+    pub fn get_deleted_schedule(&self, id: i64) -> Result<Option<LocalSchedule>> {
+        self.conn.query_row(
+            &format!("SELECT {} FROM schedules WHERE id = ?1 AND deleted_at IS NOT NULL", SCHEDULE_COLUMNS),
+            params![id],
+            schedule_from_row,
+        ).optional()
+    }
+
+    /// ゴミ箱のスケジュールを元に戻す（kintoneレコードを作り直す場合はkintone_record_idをNoneにして送信待ちにする）
+    pub fn undelete_schedule(&self, id: i64, kintone_record_id: Option<u32>, sync_status: &str) -> Result<bool> {
+        self.with_history(id, "restore", || {
+            self.conn.execute(
+                "UPDATE schedules SET deleted_at = NULL, kintone_delete_error = NULL, kintone_record_id = ?1,
+                    kintone_revision = CASE WHEN ?1 IS NULL THEN NULL ELSE kintone_revision END,
+                    sync_status = ?2, updated_at = datetime('now')
+                 WHERE id = ?3 AND deleted_at IS NOT NULL",
+                params![kintone_record_id, sync_status, id],
+            )
+        })
+        .map(|changed| changed > 0)
+    }
+
+    /// 削除済みスケジュールのkintoneレコードも削除できたことを記録
+    pub fn mark_kintone_deleted(&self, id: i64) -> Result<()> {
         self.conn.execute(
-            "DELETE FROM schedules WHERE id = ?1",
+            "UPDATE schedules SET kintone_record_id = NULL, kintone_revision = NULL, sync_status = 'deleted', kintone_delete_error = NULL
+             WHERE id = ?1 AND deleted_at IS NOT NULL",
             params![id],
         )?;
         Ok(())
     }
 
+    /// kintone側でレコードが削除されたスケジュールをゴミ箱へ移す
+    /// kintoneのレコードはもうないため、kintoneへの削除待ちにはしない
+    pub fn trash_remote_deletions(&self, ids: &[i64]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for &id in ids {
+            self.delete_schedule(id)?;
+            self.mark_kintone_deleted(id)?;
+        }
+        tx.commit()
+    }
+
+    /// ゴミ箱のスケジュールを完全に削除
+    pub fn purge_schedule(&self, id: i64) -> Result<bool> {
+        Ok(self.conn.execute("DELETE FROM schedules WHERE id = ?1 AND deleted_at IS NOT NULL", params![id])? > 0)
+    }
+
+    /// 削除から指定日数を過ぎたゴミ箱のスケジュールを完全に削除
+    /// kintoneのレコードが残っているもの（削除の送信待ち）は、次の取込で復活しないよう残す
+    /// ID354・ID368からの削除に失敗したものも、再送できるよう残す
+    pub fn purge_deleted_schedules(&self, older_than_days: i64) -> Result<usize> {
+        let purged = self.conn.execute(
+            "DELETE FROM schedules
+             WHERE deleted_at IS NOT NULL AND kintone_record_id IS NULL
+               AND deleted_at < datetime('now', ?1)
+               AND id NOT IN (SELECT schedule_id FROM downstream_sync WHERE status = 'error')",
+            params![format!("-{} days", older_than_days)],
+        )?;
+        self.conn.execute(
+            "DELETE FROM downstream_sync WHERE schedule_id NOT IN (SELECT id FROM schedules)",
+            [],
+        )?;
+        Ok(purged)
+    }
+
     /// 製品の重量を取得
     pub fn get_product_weight(&self, product_name: &str) -> Result<Option<f64>> {
         let mut stmt = self.conn.prepare(
@@ -365,56 +1086,491 @@ impl Database {
         Ok(weight)
     }
 
-    /// スケジュールの日時を更新
-    pub fn update_schedule_datetime(&self, id: i64, start: &str, end: Option<&str>) -> Result<()> {
+    /// シフトパターン一覧を取得
+    pub fn get_shift_patterns(&self) -> Result<Vec<ShiftPattern>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, name, weekday, line, start_time, end_time FROM shift_patterns ORDER BY weekday, start_time"
+        )?;
+        let shifts = stmt.query_map([], |row| {
+            Ok(ShiftPattern {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                weekday: row.get(2)?,
+                line: row.get(3)?,
+                start_time: row.get(4)?,
+                end_time: row.get(5)?,
+            })
+        })?.collect::<Result<Vec<_>>>()?;
+        Ok(shifts)
+    }
+
+    /// シフトパターンを追加
+    pub fn add_shift_pattern(&self, shift: &ShiftPattern) -> Result<i64> {
         self.conn.execute(
-            "UPDATE schedules SET start_datetime = ?1, end_datetime = ?2, sync_status = 'modified', updated_at = datetime('now') WHERE id = ?3",
-            params![start, end, id],
+            "INSERT INTO shift_patterns (name, weekday, line, start_time, end_time) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![shift.name, shift.weekday, shift.line, shift.start_time, shift.end_time],
         )?;
-        Ok(())
+        Ok(self.conn.last_insert_rowid())
     }
 
-    /// スケジュールのnotesを更新
-    pub fn update_schedule_notes(&self, id: i64, notes: &str) -> Result<()> {
+    /// シフトパターンを削除
+    pub fn delete_shift_pattern(&self, id: i64) -> Result<bool> {
+        Ok(self.conn.execute("DELETE FROM shift_patterns WHERE id = ?1", params![id])? > 0)
+    }
+
+    /// 休日一覧を取得
+    pub fn get_holidays(&self) -> Result<Vec<Holiday>> {
+        let mut stmt = self.conn.prepare("SELECT id, date, name FROM holidays ORDER BY date")?;
+        let holidays = stmt.query_map([], |row| {
+            Ok(Holiday {
+                id: row.get(0)?,
+                date: row.get(1)?,
+                name: row.get(2)?,
+            })
+        })?.collect::<Result<Vec<_>>>()?;
+        Ok(holidays)
+    }
+
+    /// 休日を追加（同じ日付があれば名称を更新）
+    pub fn add_holiday(&self, holiday: &Holiday) -> Result<i64> {
         self.conn.execute(
-            "UPDATE schedules SET notes = ?1, updated_at = datetime('now') WHERE id = ?2",
-            params![notes, id],
+            "INSERT INTO holidays (date, name) VALUES (?1, ?2)
+             ON CONFLICT(date) DO UPDATE SET name = excluded.name",
+            params![holiday.date, holiday.name],
+        )?;
+        self.conn.query_row("SELECT id FROM holidays WHERE date = ?1", params![holiday.date], |row| row.get(0))
+    }
+
+    /// 休日を削除
+    pub fn delete_holiday(&self, id: i64) -> Result<bool> {
+        Ok(self.conn.execute("DELETE FROM holidays WHERE id = ?1", params![id])? > 0)
+    }
+
+    /// 保全時間帯一覧を取得
+    pub fn get_maintenance_windows(&self) -> Result<Vec<MaintenanceWindow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, line, start_datetime, end_datetime, reason FROM maintenance_windows ORDER BY start_datetime"
+        )?;
+        let windows = stmt.query_map([], |row| {
+            Ok(MaintenanceWindow {
+                id: row.get(0)?,
+                line: row.get(1)?,
+                start_datetime: row.get(2)?,
+                end_datetime: row.get(3)?,
+                reason: row.get(4)?,
+            })
+        })?.collect::<Result<Vec<_>>>()?;
+        Ok(windows)
+    }
+
+    /// 保全時間帯を追加
+    pub fn add_maintenance_window(&self, window: &MaintenanceWindow) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO maintenance_windows (line, start_datetime, end_datetime, reason) VALUES (?1, ?2, ?3, ?4)",
+            params![window.line, window.start_datetime, window.end_datetime, window.reason],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// 保全時間帯を削除
+    pub fn delete_maintenance_window(&self, id: i64) -> Result<bool> {
+        Ok(self.conn.execute("DELETE FROM maintenance_windows WHERE id = ?1", params![id])? > 0)
+    }
+
+    /// 在庫基準一覧を取得
+    pub fn get_stock_levels(&self) -> Result<Vec<StockLevel>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT product, safety_stock, max_stock, lot_size FROM stock_levels ORDER BY product"
+        )?;
+        let levels = stmt.query_map([], |row| {
+            Ok(StockLevel {
+                product: row.get(0)?,
+                safety_stock: row.get(1)?,
+                max_stock: row.get(2)?,
+                lot_size: row.get(3)?,
+            })
+        })?;
+        levels.collect()
+    }
+
+    /// 在庫基準を保存（すべて未設定なら削除）
+    pub fn save_stock_level(&self, level: &StockLevel) -> Result<()> {
+        if level.safety_stock.is_none() && level.max_stock.is_none() && level.lot_size.is_none() {
+            self.conn.execute("DELETE FROM stock_levels WHERE product = ?1", params![level.product])?;
+            return Ok(());
+        }
+        self.conn.execute(
+            "INSERT INTO stock_levels (product, safety_stock, max_stock, lot_size, updated_at)
+             VALUES (?1, ?2, ?3, ?4, datetime('now', 'localtime'))
+             ON CONFLICT(product) DO UPDATE SET
+                safety_stock = excluded.safety_stock, max_stock = excluded.max_stock,
+                lot_size = excluded.lot_size, updated_at = excluded.updated_at",
+            params![level.product, level.safety_stock, level.max_stock, level.lot_size],
         )?;
         Ok(())
     }
 
-    /// kintoneからのデータをインポート（存在すれば更新、なければ追加）
-    pub fn import_from_kintone(&self, schedule: &LocalSchedule) -> Result<()> {
-        let exists: bool = if let Some(kid) = schedule.kintone_record_id {
-            let mut stmt = self.conn.prepare("SELECT EXISTS(SELECT 1 FROM schedules WHERE kintone_record_id = ?)")?;
-            stmt.query_row([kid], |row| row.get(0))?
+    /// 段取り替え時間一覧を取得
+    pub fn get_changeovers(&self) -> Result<Vec<Changeover>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, from_product, to_product, line, minutes, notes FROM changeovers ORDER BY from_product, to_product, line"
+        )?;
+        let changeovers = stmt.query_map([], |row| {
+            let line: String = row.get(3)?;
+            Ok(Changeover {
+                id: row.get(0)?,
+                from_product: row.get(1)?,
+                to_product: row.get(2)?,
+                line: Some(line).filter(|l| !l.is_empty()),
+                minutes: row.get(4)?,
+                notes: row.get(5)?,
+            })
+        })?.collect::<Result<Vec<_>>>()?;
+        Ok(changeovers)
+    }
+
+    /// 段取り替え時間を追加（同じ製品・ラインの組み合わせがあれば上書き）
+    pub fn add_changeover(&self, changeover: &Changeover) -> Result<i64> {
+        let line = changeover.line.as_deref().unwrap_or("");
+        self.conn.execute(
+            "INSERT INTO changeovers (from_product, to_product, line, minutes, notes) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(from_product, to_product, line) DO UPDATE SET
+                minutes = excluded.minutes, notes = excluded.notes, updated_at = datetime('now')",
+            params![changeover.from_product, changeover.to_product, line, changeover.minutes, changeover.notes],
+        )?;
+        self.conn.query_row(
+            "SELECT id FROM changeovers WHERE from_product = ?1 AND to_product = ?2 AND line = ?3",
+            params![changeover.from_product, changeover.to_product, line],
+            |row| row.get(0),
+        )
+    }
+
+    /// 段取り替え時間を更新
+    pub fn update_changeover(&self, id: i64, changeover: &Changeover) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE changeovers SET from_product = ?1, to_product = ?2, line = ?3, minutes = ?4, notes = ?5,
+                updated_at = datetime('now')
+             WHERE id = ?6",
+            params![
+                changeover.from_product, changeover.to_product, changeover.line.as_deref().unwrap_or(""),
+                changeover.minutes, changeover.notes, id,
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// 段取り替え時間を削除
+    pub fn delete_changeover(&self, id: i64) -> Result<bool> {
+        Ok(self.conn.execute("DELETE FROM changeovers WHERE id = ?1", params![id])? > 0)
+    }
+
+    /// 使用中のライン名一覧（スケジュール・シフト・保全から）
+    pub fn get_lines(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT line FROM schedules WHERE line IS NOT NULL AND deleted_at IS NULL
+             UNION SELECT line FROM shift_patterns WHERE line IS NOT NULL
+             UNION SELECT line FROM maintenance_windows WHERE line IS NOT NULL
+             UNION SELECT line FROM changeovers WHERE line <> ''
+             ORDER BY 1"
+        )?;
+        let lines = stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>>>()?;
+        Ok(lines)
+    }
+
+    /// 製品マスタ一覧を取得
+    pub fn get_products(&self, include_inactive: bool) -> Result<Vec<Product>> {
+        let sql = if include_inactive {
+            format!("SELECT {} FROM product_master ORDER BY product_name", PRODUCT_COLUMNS)
         } else {
-            false
+            format!("SELECT {} FROM product_master WHERE active = 1 ORDER BY product_name", PRODUCT_COLUMNS)
         };
+        let mut stmt = self.conn.prepare(&sql)?;
+        let products = stmt.query_map([], product_from_row)?.collect::<Result<Vec<_>>>()?;
+        Ok(products)
+    }
+
+    /// 品番で製品を取得
+    pub fn get_product(&self, product_name: &str) -> Result<Option<Product>> {
+        self.conn.query_row(
+            &format!("SELECT {} FROM product_master WHERE product_name = ?1", PRODUCT_COLUMNS),
+            params![product_name],
+            product_from_row,
+        ).optional()
+    }
+
+    /// 製品を追加
+    pub fn add_product(&self, product: &Product) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO product_master (product_name, display_name, category, weight_kg, default_efficiency, active, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, datetime('now'))",
+            params![
+                product.product_name,
+                product.display_name,
+                product.category,
+                product.weight_kg,
+                product.default_efficiency,
+                product.active,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// 製品を更新（品番の変更も可）
+    pub fn update_product(&self, id: i64, product: &Product) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE product_master SET product_name = ?1, display_name = ?2, category = ?3, weight_kg = ?4,
+                default_efficiency = ?5, active = ?6, updated_at = datetime('now')
+             WHERE id = ?7",
+            params![
+                product.product_name,
+                product.display_name,
+                product.category,
+                product.weight_kg,
+                product.default_efficiency,
+                product.active,
+                id,
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// 製品の有効・無効を切り替え
+    pub fn set_product_active(&self, id: i64, active: bool) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE product_master SET active = ?1, updated_at = datetime('now') WHERE id = ?2",
+            params![active, id],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// 製品をまとめて登録（品番が既存なら更新）
+    /// 戻り値は (追加件数, 更新件数)
+    pub fn upsert_products(&self, products: &[Product]) -> Result<(u32, u32)> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut inserted = 0;
+        let mut updated = 0;
+
+        for product in products {
+            let existing: Option<i64> = tx.query_row(
+                "SELECT id FROM product_master WHERE product_name = ?1",
+                params![product.product_name],
+                |row| row.get(0),
+            ).optional()?;
 
-        if exists {
+            match existing {
+                Some(id) => {
+                    tx.execute(
+                        "UPDATE product_master SET display_name = ?1, category = ?2, weight_kg = ?3,
+                            default_efficiency = ?4, active = ?5, updated_at = datetime('now')
+                         WHERE id = ?6",
+                        params![
+                            product.display_name,
+                            product.category,
+                            product.weight_kg,
+                            product.default_efficiency,
+                            product.active,
+                            id,
+                        ],
+                    )?;
+                    updated += 1;
+                }
+                None => {
+                    tx.execute(
+                        "INSERT INTO product_master (product_name, display_name, category, weight_kg, default_efficiency, active, updated_at)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, datetime('now'))",
+                        params![
+                            product.product_name,
+                            product.display_name,
+                            product.category,
+                            product.weight_kg,
+                            product.default_efficiency,
+                            product.active,
+                        ],
+                    )?;
+                    inserted += 1;
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok((inserted, updated))
+    }
+
+    /// スケジュールの日時を更新
+    pub fn update_schedule_datetime(&self, id: i64, start: &str, end: Option<&str>) -> Result<()> {
+        self.with_history(id, "update", || {
             self.conn.execute(
+                "UPDATE schedules SET start_datetime = ?1, end_datetime = ?2, sync_status = 'modified', updated_at = datetime('now') WHERE id = ?3",
+                params![start, end, id],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// 複数スケジュールをまとめて追加（1トランザクション、失敗時は全件ロールバック）
+    pub fn add_schedules(&self, schedules: &[LocalSchedule]) -> Result<Vec<i64>> {
+        let tx = self.conn.unchecked_transaction()?;
+        let ids = schedules
+            .iter()
+            .map(|schedule| self.add_schedule(schedule))
+            .collect::<Result<Vec<_>>>()?;
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    /// 複数スケジュールの日時をまとめて更新（1トランザクション、失敗時は全件ロールバック）
+    /// entriesは (ローカルID, 開始日時, 終了日時)
+    pub fn update_schedule_datetimes(&self, entries: &[(i64, &str, Option<&str>)]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, start, end) in entries {
+            let changed = self.with_history(*id, "update", || {
+                tx.execute(
+                    "UPDATE schedules SET start_datetime = ?1, end_datetime = ?2, sync_status = 'modified', updated_at = datetime('now') WHERE id = ?3",
+                    params![start, end, id],
+                )
+            })?;
+            if changed == 0 {
+                return Err(rusqlite::Error::QueryReturnedNoRows);
+            }
+        }
+        tx.commit()
+    }
+
+    /// 製品（品名・分類）・生産数量・製綿能率と日時をまとめて更新
+    pub fn update_schedule_production(&self, schedule: &LocalSchedule) -> Result<()> {
+        let id = schedule.id.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        self.with_history(id, "update", || self.conn.execute(
+            "UPDATE schedules SET product_name = ?1, start_datetime = ?2, end_datetime = ?3,
+                quantity1 = ?4, quantity2 = ?5, quantity3 = ?6, quantity4 = ?7,
+                quantity5 = ?8, quantity6 = ?9, quantity7 = ?10, quantity8 = ?11, total_quantity = ?12,
+                efficiency1 = ?13, efficiency2 = ?14, efficiency3 = ?15, efficiency4 = ?16,
+                efficiency5 = ?17, efficiency6 = ?18, efficiency7 = ?19, efficiency8 = ?20,
+                product_display_name = ?22, category = ?23,
+                sync_status = 'modified', updated_at = datetime('now')
+             WHERE id = ?21",
+            params![
+                schedule.product_name, schedule.start_datetime, schedule.end_datetime,
+                schedule.quantity1, schedule.quantity2, schedule.quantity3, schedule.quantity4,
+                schedule.quantity5, schedule.quantity6, schedule.quantity7, schedule.quantity8, schedule.total_quantity,
+                schedule.efficiency1, schedule.efficiency2, schedule.efficiency3, schedule.efficiency4,
+                schedule.efficiency5, schedule.efficiency6, schedule.efficiency7, schedule.efficiency8,
+                id, schedule.product_display_name, schedule.category,
+            ],
+        ))?;
+        Ok(())
+    }
+
+    /// スケジュールの内容と同期情報をまとめて上書き（取り消し用）
+    pub fn overwrite_schedule(&self, schedule: &LocalSchedule) -> Result<()> {
+        let id = schedule.id.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        self.with_history(id, "update", || self.conn.execute(
+            "UPDATE schedules SET
+                schedule_number = ?1, product_name = ?2, product_display_name = ?3, category = ?4, line = ?5, start_datetime = ?6, end_datetime = ?7,
+                quantity1 = ?8, quantity2 = ?9, quantity3 = ?10, quantity4 = ?11, quantity5 = ?12, quantity6 = ?13, quantity7 = ?14, quantity8 = ?15,
+                total_quantity = ?16, efficiency1 = ?17, efficiency2 = ?18, efficiency3 = ?19, efficiency4 = ?20, efficiency5 = ?21, efficiency6 = ?22, efficiency7 = ?23, efficiency8 = ?24,
+                production_status = ?25, notes = ?26, kintone_record_id = ?27, kintone_revision = ?28, sync_status = ?29, updated_at = datetime('now')
+            WHERE id = ?30",
+            params![
+                schedule.schedule_number, schedule.product_name, schedule.product_display_name, schedule.category, schedule.line, schedule.start_datetime, schedule.end_datetime,
+                schedule.quantity1, schedule.quantity2, schedule.quantity3, schedule.quantity4, schedule.quantity5, schedule.quantity6, schedule.quantity7, schedule.quantity8,
+                schedule.total_quantity, schedule.efficiency1, schedule.efficiency2, schedule.efficiency3, schedule.efficiency4, schedule.efficiency5, schedule.efficiency6, schedule.efficiency7, schedule.efficiency8,
+                schedule.production_status, schedule.notes, schedule.kintone_record_id, schedule.kintone_revision, schedule.sync_status, id
+            ],
+        ))?;
+        Ok(())
+    }
+
+    /// 複数スケジュールを指定の状態にまとめて戻す（1トランザクション、失敗時は全件ロールバック）
+    /// Noneなら削除、存在しないスケジュールは元のIDで追加し直す
+    pub fn apply_schedule_states(&self, states: &[(i64, Option<LocalSchedule>)]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, state) in states {
+            match state {
+                None => self.delete_schedule(*id)?,
+                Some(schedule) if self.get_schedule(*id)?.is_some() => self.overwrite_schedule(schedule)?,
+                Some(schedule) => {
+                    self.restore_schedule(schedule)?;
+                }
+            }
+        }
+        tx.commit()
+    }
+
+    /// スケジュールのnotesを更新
+    pub fn update_schedule_notes(&self, id: i64, notes: &str) -> Result<()> {
+        self.with_history(id, "update", || {
+            self.conn.execute(
+                "UPDATE schedules SET notes = ?1, updated_at = datetime('now') WHERE id = ?2",
+                params![notes, id],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// kintoneからのデータをインポート（存在すれば更新、なければ追加）
+    /// ローカルIDは維持し、未送信の変更（pending/modified）がある行は上書きしない
+    /// 更新では columns（対応付けで受信する列）だけを書き換え、ローカルにしかない列は残す
+    pub fn import_from_kintone(&self, schedule: &LocalSchedule, columns: &[&str]) -> Result<ImportOutcome> {
+        let existing: Option<(LocalSchedule, bool)> = match schedule.kintone_record_id {
+            Some(kid) => self.conn.query_row(
+                &format!("SELECT {}, deleted_at IS NOT NULL FROM schedules WHERE kintone_record_id = ?1", SCHEDULE_COLUMNS),
+                params![kid],
+                |row| Ok((schedule_from_row(row)?, row.get(32)?)),
+            ).optional()?,
+            None => None,
+        };
+
+        if let Some((local, deleted)) = existing {
+            let id = local.id.unwrap_or_default();
+            let remote = remote_over_local(&local, schedule, columns);
+            // ローカルで削除したレコードは復活させない（kintoneからの削除は同期時に送信する）
+            if deleted {
+                return Ok(ImportOutcome::SkippedDeleted(id));
+            }
+            match local.sync_status.as_str() {
+                "pending" | "modified" => {
+                    // ローカルが最後に見たリビジョンから変わっていなければkintone側は未変更
+                    let remote_changed = schedule.kintone_revision.is_some()
+                        && schedule.kintone_revision != local.kintone_revision;
+                    if !remote_changed {
+                        return Ok(ImportOutcome::SkippedLocalChanges(id));
+                    }
+                    self.record_conflict(&local, &remote)?;
+                    return Ok(ImportOutcome::Conflict(id));
+                }
+                "conflict" => {
+                    // 未解決の競合がある場合はkintone側の最新内容で競合情報を更新
+                    self.record_conflict(&local, &remote)?;
+                    return Ok(ImportOutcome::Conflict(id));
+                }
+                _ => {}
+            }
+
+            let updated = remote;
+            self.with_history(id, "sync_import", || self.conn.execute(
                 "UPDATE schedules SET
                     schedule_number = ?1, product_name = ?2, product_display_name = ?3, category = ?4, line = ?5, start_datetime = ?6, end_datetime = ?7,
                     quantity1 = ?8, quantity2 = ?9, quantity3 = ?10, quantity4 = ?11, quantity5 = ?12, quantity6 = ?13, quantity7 = ?14, quantity8 = ?15,
                     total_quantity = ?16, efficiency1 = ?17, efficiency2 = ?18, efficiency3 = ?19, efficiency4 = ?20, efficiency5 = ?21, efficiency6 = ?22, efficiency7 = ?23, efficiency8 = ?24,
-                    production_status = ?25, notes = ?26, sync_status = 'synced', updated_at = datetime('now')
-                WHERE kintone_record_id = ?27",
+                    production_status = ?25, notes = ?26, kintone_revision = ?27, sync_status = 'synced', updated_at = datetime('now')
+                WHERE id = ?28",
                 params![
-                    schedule.schedule_number, schedule.product_name, schedule.product_display_name, schedule.category, schedule.line, schedule.start_datetime, schedule.end_datetime,
-                    schedule.quantity1, schedule.quantity2, schedule.quantity3, schedule.quantity4, schedule.quantity5, schedule.quantity6, schedule.quantity7, schedule.quantity8,
-                    schedule.total_quantity, schedule.efficiency1, schedule.efficiency2, schedule.efficiency3, schedule.efficiency4, schedule.efficiency5, schedule.efficiency6, schedule.efficiency7, schedule.efficiency8,
-                    schedule.production_status, schedule.notes, schedule.kintone_record_id
+                    updated.schedule_number, updated.product_name, updated.product_display_name, updated.category, updated.line, updated.start_datetime, updated.end_datetime,
+                    updated.quantity1, updated.quantity2, updated.quantity3, updated.quantity4, updated.quantity5, updated.quantity6, updated.quantity7, updated.quantity8,
+                    updated.total_quantity, updated.efficiency1, updated.efficiency2, updated.efficiency3, updated.efficiency4, updated.efficiency5, updated.efficiency6, updated.efficiency7, updated.efficiency8,
+                    updated.production_status, updated.notes, updated.kintone_revision, id
                 ],
-            )?;
+            ))?;
+            Ok(ImportOutcome::Updated(id))
         } else {
+            let tx = self.begin_if_autocommit()?;
             self.conn.execute(
                 "INSERT INTO schedules (
                     kintone_record_id, schedule_number, product_name, product_display_name, category, line, start_datetime, end_datetime,
                     quantity1, quantity2, quantity3, quantity4, quantity5, quantity6, quantity7, quantity8,
                     total_quantity, efficiency1, efficiency2, efficiency3, efficiency4, efficiency5, efficiency6, efficiency7, efficiency8,
-                    production_status, notes, sync_status, created_at, updated_at
-                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, 'synced', datetime('now'), datetime('now'))",
+                    production_status, notes, kintone_revision, sync_status, created_at, updated_at
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, 'synced', datetime('now'), datetime('now'))",
                 params![
                     schedule.kintone_record_id,
                     schedule.schedule_number,
@@ -442,11 +1598,442 @@ impl Database {
                     schedule.efficiency7,
                     schedule.efficiency8,
                     schedule.production_status,
-                    schedule.notes
+                    schedule.notes,
+                    schedule.kintone_revision
                 ],
             )?;
+            let id = self.conn.last_insert_rowid();
+            self.record_history(id, "sync_import", None, self.get_schedule(id)?.as_ref())?;
+            Self::commit_if_started(tx)?;
+            Ok(ImportOutcome::Inserted(id))
         }
+    }
+
+    /// スケジュール番号が既に存在するか
+    pub fn schedule_number_exists(&self, schedule_number: &str) -> Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM schedules WHERE schedule_number = ?1)",
+            params![schedule_number],
+            |row| row.get(0),
+        )
+    }
+
+    /// アプリの差分同期状態を取得
+    pub fn get_sync_state(&self, app_id: u32) -> Result<Option<SyncState>> {
+        self.conn.query_row(
+            "SELECT app_id, last_modified, last_record_id, last_revision FROM sync_state WHERE app_id = ?1",
+            params![app_id],
+            |row| Ok(SyncState {
+                app_id: row.get(0)?,
+                last_modified: row.get(1)?,
+                last_record_id: row.get(2)?,
+                last_revision: row.get(3)?,
+            }),
+        ).optional()
+    }
+
+    /// アプリの差分同期状態を保存
+    pub fn save_sync_state(&self, state: &SyncState) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO sync_state (app_id, last_modified, last_record_id, last_revision, synced_at)
+             VALUES (?1, ?2, ?3, ?4, datetime('now'))
+             ON CONFLICT(app_id) DO UPDATE SET
+                last_modified = excluded.last_modified,
+                last_record_id = excluded.last_record_id,
+                last_revision = excluded.last_revision,
+                synced_at = excluded.synced_at",
+            params![state.app_id, state.last_modified, state.last_record_id, state.last_revision],
+        )?;
         Ok(())
     }
-}
 
+    /// 競合を記録し、スケジュールを競合状態にする
+    /// 同じスケジュールの未解決の競合があればkintone側の内容を最新に置き換える
+    pub fn record_conflict(&self, local: &LocalSchedule, remote: &LocalSchedule) -> Result<i64> {
+        let schedule_id = local.id.unwrap_or_default();
+        let local_json = serde_json::to_string(local).unwrap_or_default();
+        let remote_json = serde_json::to_string(remote).unwrap_or_default();
+
+        let open_id: Option<i64> = self.conn.query_row(
+            "SELECT id FROM sync_conflicts WHERE schedule_id = ?1 AND resolved_at IS NULL",
+            params![schedule_id],
+            |row| row.get(0),
+        ).optional()?;
+
+        let conflict_id = match open_id {
+            Some(conflict_id) => {
+                self.conn.execute(
+                    "UPDATE sync_conflicts SET remote_data = ?1, remote_revision = ?2, detected_at = datetime('now') WHERE id = ?3",
+                    params![remote_json, remote.kintone_revision, conflict_id],
+                )?;
+                conflict_id
+            }
+            None => {
+                self.conn.execute(
+                    "INSERT INTO sync_conflicts (schedule_id, kintone_record_id, local_data, remote_data, remote_revision, detected_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, datetime('now'))",
+                    params![schedule_id, local.kintone_record_id, local_json, remote_json, remote.kintone_revision],
+                )?;
+                self.conn.last_insert_rowid()
+            }
+        };
+
+        self.conn.execute(
+            "UPDATE schedules SET sync_status = 'conflict', updated_at = datetime('now') WHERE id = ?1",
+            params![schedule_id],
+        )?;
+
+        Ok(conflict_id)
+    }
+
+    /// 未解決の競合一覧を取得
+    pub fn get_open_conflicts(&self) -> Result<Vec<SyncConflict>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, schedule_id, kintone_record_id, local_data, remote_data, remote_revision, detected_at
+             FROM sync_conflicts WHERE resolved_at IS NULL ORDER BY detected_at"
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            let local: String = row.get(3)?;
+            let remote: String = row.get(4)?;
+            Ok(SyncConflict {
+                id: row.get(0)?,
+                schedule_id: row.get(1)?,
+                kintone_record_id: row.get(2)?,
+                local: serde_json::from_str(&local).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e))
+                })?,
+                remote: serde_json::from_str(&remote).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, Box::new(e))
+                })?,
+                remote_revision: row.get(5)?,
+                detected_at: row.get(6)?,
+            })
+        })?;
+
+        rows.collect()
+    }
+
+    /// 競合を解決してスケジュールを解決後の内容で上書き
+    /// sync_statusは keep-remote なら synced、それ以外は modified（次回送信）になる
+    pub fn resolve_conflict(&self, conflict_id: i64, resolved: &LocalSchedule, remote_revision: Option<i64>, resolution: &str) -> Result<()> {
+        let sync_status = if resolution == "keep_remote" { "synced" } else { "modified" };
+        let tx = self.conn.unchecked_transaction()?;
+
+        self.with_history(resolved.id.unwrap_or_default(), "update", || tx.execute(
+            "UPDATE schedules SET
+                schedule_number = ?1, product_name = ?2, product_display_name = ?3, category = ?4, line = ?5, start_datetime = ?6, end_datetime = ?7,
+                quantity1 = ?8, quantity2 = ?9, quantity3 = ?10, quantity4 = ?11, quantity5 = ?12, quantity6 = ?13, quantity7 = ?14, quantity8 = ?15,
+                total_quantity = ?16, efficiency1 = ?17, efficiency2 = ?18, efficiency3 = ?19, efficiency4 = ?20, efficiency5 = ?21, efficiency6 = ?22, efficiency7 = ?23, efficiency8 = ?24,
+                production_status = ?25, notes = ?26, kintone_revision = ?27, sync_status = ?28, updated_at = datetime('now')
+            WHERE id = ?29",
+            params![
+                resolved.schedule_number, resolved.product_name, resolved.product_display_name, resolved.category, resolved.line, resolved.start_datetime, resolved.end_datetime,
+                resolved.quantity1, resolved.quantity2, resolved.quantity3, resolved.quantity4, resolved.quantity5, resolved.quantity6, resolved.quantity7, resolved.quantity8,
+                resolved.total_quantity, resolved.efficiency1, resolved.efficiency2, resolved.efficiency3, resolved.efficiency4, resolved.efficiency5, resolved.efficiency6, resolved.efficiency7, resolved.efficiency8,
+                resolved.production_status, resolved.notes, remote_revision, sync_status, resolved.id
+            ],
+        ))?;
+
+        tx.execute(
+            "UPDATE sync_conflicts SET resolved_at = datetime('now'), resolution = ?1 WHERE id = ?2",
+            params![resolution, conflict_id],
+        )?;
+
+        tx.commit()
+    }
+
+    /// シナリオを作成
+    pub fn create_scenario(&self, name: &str, description: Option<&str>) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO scenarios (name, description) VALUES (?1, ?2)",
+            params![name, description],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// シナリオ一覧を取得
+    pub fn get_scenarios(&self) -> Result<Vec<Scenario>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT s.id, s.name, s.description, s.created_at, s.updated_at,
+                (SELECT COUNT(*) FROM scenario_changes c WHERE c.scenario_id = s.id)
+             FROM scenarios s ORDER BY s.created_at DESC, s.id DESC"
+        )?;
+        let scenarios = stmt.query_map([], |row| {
+            Ok(Scenario {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                description: row.get(2)?,
+                created_at: row.get(3)?,
+                updated_at: row.get(4)?,
+                change_count: row.get(5)?,
+            })
+        })?.collect::<Result<Vec<_>>>()?;
+        Ok(scenarios)
+    }
+
+    /// シナリオが存在するか
+    pub fn scenario_exists(&self, id: i64) -> Result<bool> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM scenarios WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// シナリオ上の変更一覧を取得
+    pub fn get_scenario_changes(&self, scenario_id: i64) -> Result<Vec<ScenarioChange>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, scenario_id, schedule_id, change_type, data, base_data
+             FROM scenario_changes WHERE scenario_id = ?1 ORDER BY id"
+        )?;
+        let changes = stmt.query_map(params![scenario_id], |row| {
+            let data: Option<String> = row.get(4)?;
+            let base: Option<String> = row.get(5)?;
+            Ok(ScenarioChange {
+                id: row.get(0)?,
+                scenario_id: row.get(1)?,
+                schedule_id: row.get(2)?,
+                change_type: row.get(3)?,
+                data: data
+                    .map(|d| serde_json::from_str(&d))
+                    .transpose()
+                    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, Box::new(e)))?,
+                base: base
+                    .map(|b| serde_json::from_str(&b))
+                    .transpose()
+                    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, rusqlite::types::Type::Text, Box::new(e)))?,
+            })
+        })?.collect::<Result<Vec<_>>>()?;
+        Ok(changes)
+    }
+
+    /// 本番スケジュールに対するシナリオ上の変更を保存（modify / remove）
+    /// 取り込み時点の本番の内容は最初に変更したときのものを保つ
+    pub fn save_scenario_override(&self, scenario_id: i64, base: &LocalSchedule, change_type: &str, data: Option<&LocalSchedule>) -> Result<i64> {
+        let schedule_id = base.id.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        let data_json = data.map(|d| serde_json::to_string(d).unwrap_or_default());
+        let base_json = serde_json::to_string(base).unwrap_or_default();
+        self.conn.execute(
+            "INSERT INTO scenario_changes (scenario_id, schedule_id, change_type, data, base_data)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(scenario_id, schedule_id) DO UPDATE SET
+                change_type = excluded.change_type, data = excluded.data, updated_at = datetime('now')",
+            params![scenario_id, schedule_id, change_type, data_json, base_json],
+        )?;
+        self.touch_scenario(scenario_id)?;
+        self.conn.query_row(
+            "SELECT id FROM scenario_changes WHERE scenario_id = ?1 AND schedule_id = ?2",
+            params![scenario_id, schedule_id],
+            |row| row.get(0),
+        )
+    }
+
+    /// シナリオで追加したスケジュールを保存（change_idがあれば上書き）
+    pub fn save_scenario_addition(&self, scenario_id: i64, change_id: Option<i64>, data: &LocalSchedule) -> Result<i64> {
+        let data_json = serde_json::to_string(data).unwrap_or_default();
+        let id = match change_id {
+            Some(change_id) => {
+                let changed = self.conn.execute(
+                    "UPDATE scenario_changes SET data = ?1, updated_at = datetime('now')
+                     WHERE id = ?2 AND scenario_id = ?3 AND change_type = 'add'",
+                    params![data_json, change_id, scenario_id],
+                )?;
+                if changed == 0 {
+                    return Err(rusqlite::Error::QueryReturnedNoRows);
+                }
+                change_id
+            }
+            None => {
+                self.conn.execute(
+                    "INSERT INTO scenario_changes (scenario_id, schedule_id, change_type, data) VALUES (?1, NULL, 'add', ?2)",
+                    params![scenario_id, data_json],
+                )?;
+                self.conn.last_insert_rowid()
+            }
+        };
+        self.touch_scenario(scenario_id)?;
+        Ok(id)
+    }
+
+    /// シナリオ上の変更を取り消す（追加したものは削除、本番の変更は本番の内容に戻る）
+    pub fn delete_scenario_change(&self, scenario_id: i64, change_id: i64) -> Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM scenario_changes WHERE id = ?1 AND scenario_id = ?2",
+            params![change_id, scenario_id],
+        )?;
+        self.touch_scenario(scenario_id)?;
+        Ok(deleted > 0)
+    }
+
+    /// シナリオの更新日時を更新
+    fn touch_scenario(&self, scenario_id: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE scenarios SET updated_at = datetime('now') WHERE id = ?1",
+            params![scenario_id],
+        )?;
+        Ok(())
+    }
+
+    /// シナリオを破棄
+    pub fn delete_scenario(&self, scenario_id: i64) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM scenario_changes WHERE scenario_id = ?1", params![scenario_id])?;
+        tx.execute("DELETE FROM scenarios WHERE id = ?1", params![scenario_id])?;
+        tx.commit()
+    }
+
+    /// シナリオの変更を本番スケジュールに反映してシナリオを削除（1トランザクション、失敗時は全件ロールバック）
+    /// 変更したスケジュールは未送信（modified）、追加したものは新規（pending）になる
+    pub fn promote_scenario(&self, scenario_id: i64, changes: &[ScenarioChange]) -> Result<PromotedChanges> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut promoted = PromotedChanges::default();
+
+        for change in changes {
+            match (change.change_type.as_str(), change.schedule_id, change.data.as_ref()) {
+                ("modify", Some(id), Some(s)) => {
+                    self.with_history(id, "update", || tx.execute(
+                        "UPDATE schedules SET
+                            product_name = ?1, product_display_name = ?2, category = ?3, line = ?4, start_datetime = ?5, end_datetime = ?6,
+                            quantity1 = ?7, quantity2 = ?8, quantity3 = ?9, quantity4 = ?10, quantity5 = ?11, quantity6 = ?12, quantity7 = ?13, quantity8 = ?14,
+                            total_quantity = ?15, efficiency1 = ?16, efficiency2 = ?17, efficiency3 = ?18, efficiency4 = ?19, efficiency5 = ?20, efficiency6 = ?21, efficiency7 = ?22, efficiency8 = ?23,
+                            production_status = ?24, notes = ?25,
+                            sync_status = CASE WHEN sync_status IN ('pending', 'local_only') THEN sync_status ELSE 'modified' END,
+                            updated_at = datetime('now')
+                        WHERE id = ?26",
+                        params![
+                            s.product_name, s.product_display_name, s.category, s.line, s.start_datetime, s.end_datetime,
+                            s.quantity1, s.quantity2, s.quantity3, s.quantity4, s.quantity5, s.quantity6, s.quantity7, s.quantity8,
+                            s.total_quantity, s.efficiency1, s.efficiency2, s.efficiency3, s.efficiency4, s.efficiency5, s.efficiency6, s.efficiency7, s.efficiency8,
+                            s.production_status, s.notes, id
+                        ],
+                    ))?;
+                    promoted.updated.push(id);
+                }
+                ("add", _, Some(s)) => {
+                    let schedule = LocalSchedule {
+                        id: None,
+                        kintone_record_id: None,
+                        schedule_number: None,
+                        kintone_revision: None,
+                        sync_status: if s.sync_status == "local_only" { "local_only".to_string() } else { "pending".to_string() },
+                        ..s.clone()
+                    };
+                    promoted.added.push(self.add_schedule(&schedule)?);
+                }
+                ("remove", Some(id), _) => {
+                    if let Some(schedule) = self.get_schedule(id)? {
+                        self.delete_schedule(id)?;
+                        promoted.removed.push(schedule);
+                    }
+                }
+                _ => eprintln!("不正なシナリオ変更をスキップ: id={}, type={}", change.id, change.change_type),
+            }
+        }
+
+        tx.execute("DELETE FROM scenario_changes WHERE scenario_id = ?1", params![scenario_id])?;
+        tx.execute("DELETE FROM scenarios WHERE id = ?1", params![scenario_id])?;
+        tx.commit()?;
+        Ok(promoted)
+    }
+
+    /// トランザクション外ならトランザクションを開始（既に内側なら呼び出し元のものに含める）
+    fn begin_if_autocommit(&self) -> Result<Option<Transaction<'_>>> {
+        if self.conn.is_autocommit() {
+            self.conn.unchecked_transaction().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// begin_if_autocommitで開始したトランザクションをコミット
+    fn commit_if_started(tx: Option<Transaction<'_>>) -> Result<()> {
+        tx.map_or(Ok(()), |tx| tx.commit())
+    }
+
+    /// スケジュール1件を変更し、変更前後の内容を履歴に記録（変更と履歴は同じトランザクションで書き込む）
+    fn with_history<T>(&self, id: i64, operation: &str, change: impl FnOnce() -> Result<T>) -> Result<T> {
+        let tx = self.begin_if_autocommit()?;
+        let before = self.get_schedule(id)?;
+        let result = change()?;
+        let after = self.get_schedule(id)?;
+        self.record_history(id, operation, before.as_ref(), after.as_ref())?;
+        Self::commit_if_started(tx)?;
+        Ok(result)
+    }
+
+    /// 変更履歴を1件記録（内容が変わっていない更新は記録しない）
+    fn record_history(&self, schedule_id: i64, operation: &str, before: Option<&LocalSchedule>, after: Option<&LocalSchedule>) -> Result<()> {
+        let changed_fields = match (before, after) {
+            (Some(b), Some(a)) => {
+                let fields = differing_fields(b, a);
+                if fields.is_empty() {
+                    return Ok(());
+                }
+                fields
+            }
+            (None, None) => return Ok(()),
+            _ => Vec::new(),
+        };
+        let to_json = |s: Option<&LocalSchedule>| s.map(|s| serde_json::to_string(s).unwrap_or_default());
+
+        self.conn.execute(
+            "INSERT INTO schedule_history (schedule_id, operation, before_data, after_data, changed_fields, app_mode, machine_name)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                schedule_id,
+                operation,
+                to_json(before),
+                to_json(after),
+                changed_fields.join(","),
+                self.actor.app_mode,
+                self.actor.machine_name,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// 変更履歴を条件で取得（新しい順、最大limit件。負数なら全件）
+    fn query_history(&self, condition: &str, values: &[&dyn rusqlite::ToSql], limit: i64) -> Result<Vec<ScheduleHistoryEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, schedule_id, operation, before_data, after_data, changed_fields, app_mode, machine_name, changed_at
+             FROM schedule_history WHERE {} ORDER BY changed_at DESC, id DESC LIMIT {}",
+            condition, limit
+        ))?;
+
+        let parse = |index: usize, value: Option<String>| -> Result<Option<LocalSchedule>> {
+            value
+                .map(|v| serde_json::from_str(&v))
+                .transpose()
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e)))
+        };
+
+        let rows = stmt.query_map(values, |row| {
+            let changed_fields: String = row.get(5)?;
+            Ok(ScheduleHistoryEntry {
+                id: row.get(0)?,
+                schedule_id: row.get(1)?,
+                operation: row.get(2)?,
+                before: parse(3, row.get(3)?)?,
+                after: parse(4, row.get(4)?)?,
+                changed_fields: changed_fields.split(',').filter(|f| !f.is_empty()).map(String::from).collect(),
+                app_mode: row.get(6)?,
+                machine_name: row.get(7)?,
+                changed_at: row.get(8)?,
+            })
+        })?;
+
+        rows.collect()
+    }
+
+    /// スケジュール1件の変更履歴（新しい順）
+    pub fn get_schedule_history(&self, schedule_id: i64) -> Result<Vec<ScheduleHistoryEntry>> {
+        self.query_history("schedule_id = ?1", &[&schedule_id], -1)
+    }
+
+    /// 期間内（YYYY-MM-DD、両端を含む）の全スケジュールの変更履歴（新しい順）
+    pub fn get_activity_feed(&self, from_date: &str, to_date: &str, limit: i64) -> Result<Vec<ScheduleHistoryEntry>> {
+        self.query_history("date(changed_at) BETWEEN ?1 AND ?2", &[&from_date, &to_date], limit)
+    }
+}
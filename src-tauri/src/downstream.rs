@@ -0,0 +1,125 @@
+//! ID354（山積表）・ID368（小袋実績）への連携
+//! スケジュール（ID506）の追加・変更・削除を、分類に応じた送信先アプリへ反映する
+//!
+//! - ベーラー → ID354（山積表）、小袋 → ID368（小袋実績）、それ以外は連携しない
+//! - 送信先のレコードはスケジュール番号で特定する
+//! - 送信するフィールドは対応付けファイル（mappings/yamazumi.json・kobukuro.json）で決める
+//! - 送信先で生産が進んでいる（生産状況が未生産でない）レコードは変更・削除しない
+//! - 新規登録はスケジュールが未生産のときだけ行う
+
+use crate::database::LocalSchedule;
+use crate::field_mapping::{AppMapping, FieldMappings};
+use crate::kintone_client::{SecondaryOutcome, SecondaryWrite};
+
+/// 連携先アプリ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownstreamApp {
+    /// ID354（山積表）
+    Yamazumi,
+    /// ID368（小袋実績）
+    Kobukuro,
+}
+
+impl DownstreamApp {
+    /// DB・設定で使う名前
+    pub fn name(self) -> &'static str {
+        match self {
+            DownstreamApp::Yamazumi => "yamazumi",
+            DownstreamApp::Kobukuro => "kobukuro",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "yamazumi" => Some(DownstreamApp::Yamazumi),
+            "kobukuro" => Some(DownstreamApp::Kobukuro),
+            _ => None,
+        }
+    }
+
+    /// 画面表示用の名前
+    pub fn label(self) -> &'static str {
+        match self {
+            DownstreamApp::Yamazumi => "ID354（山積表）",
+            DownstreamApp::Kobukuro => "ID368（小袋実績）",
+        }
+    }
+
+    pub fn is_kobukuro(self) -> bool {
+        self == DownstreamApp::Kobukuro
+    }
+
+    /// 送信先アプリの対応付け
+    pub fn mapping(self, mappings: &FieldMappings) -> &AppMapping {
+        match self {
+            DownstreamApp::Yamazumi => &mappings.yamazumi,
+            DownstreamApp::Kobukuro => &mappings.kobukuro,
+        }
+    }
+}
+
+/// 分類から連携先アプリを決める
+pub fn target_app(schedule: &LocalSchedule) -> Option<DownstreamApp> {
+    match schedule.category.as_deref() {
+        Some("小袋") => Some(DownstreamApp::Kobukuro),
+        Some("ベーラー") => Some(DownstreamApp::Yamazumi),
+        _ => None,
+    }
+}
+
+/// 連携先アプリへの送信1件
+#[derive(Debug)]
+pub struct DownstreamOp {
+    pub app: DownstreamApp,
+    pub write: SecondaryWrite,
+}
+
+/// 連携先アプリへの送信内容を決める
+/// previous_app は前回送信した連携先（分類が変わった場合は前回の連携先から削除する）
+pub fn plan(schedule: &LocalSchedule, deleted: bool, previous_app: Option<DownstreamApp>, mappings: &FieldMappings) -> Vec<DownstreamOp> {
+    let target = target_app(schedule);
+    let mut ops = Vec::new();
+
+    if deleted {
+        // 連携状態の記録がない（記録を始める前に送信した）場合も、分類の連携先から削除する
+        for app in [previous_app, target].into_iter().flatten() {
+            if !ops.iter().any(|op: &DownstreamOp| op.app == app) {
+                ops.push(DownstreamOp { app, write: SecondaryWrite::Delete });
+            }
+        }
+        return ops;
+    }
+
+    if let Some(previous) = previous_app.filter(|p| Some(*p) != target) {
+        ops.push(DownstreamOp { app: previous, write: SecondaryWrite::Delete });
+    }
+    if let Some(app) = target {
+        ops.push(DownstreamOp {
+            app,
+            write: SecondaryWrite::Upsert {
+                record: app.mapping(mappings).build_record(schedule),
+                create_if_missing: schedule.production_status == "未生産",
+            },
+        });
+    }
+    ops
+}
+
+/// 送信結果から記録する連携状態（連携先、状態、説明）を決める
+/// 連携先は次回の送信で「前回の連携先」として使うため、送信先にレコードが残っている場合だけ残す
+pub fn settle(app: DownstreamApp, write: &SecondaryWrite, outcome: &SecondaryOutcome) -> (Option<DownstreamApp>, &'static str, Option<String>) {
+    match (write, outcome) {
+        (_, SecondaryOutcome::Added | SecondaryOutcome::Updated) => (Some(app), "synced", None),
+        (_, SecondaryOutcome::Deleted) | (SecondaryWrite::Delete, SecondaryOutcome::NotFound) => (None, "deleted", None),
+        (SecondaryWrite::Upsert { .. }, SecondaryOutcome::NotFound) => (
+            None,
+            "skipped",
+            Some(format!("未生産ではないため{}に登録しません", app.label())),
+        ),
+        (_, SecondaryOutcome::Skipped { status }) => (
+            None,
+            "skipped",
+            Some(format!("{}の生産状況が「{}」のため変更しません", app.label(), status)),
+        ),
+    }
+}
@@ -0,0 +1,386 @@
+//! kintoneアプリとローカルのスケジュール（LocalSchedule）の項目の対応付け
+//! アプリごとの対応付けファイルで、フィールドコード・ローカルの列・値の変換・送受信の向きを定義する
+//!
+//! - 既定の対応付けは mappings/<アプリ>.json としてアプリに同梱する
+//! - データフォルダの mappings/<アプリ>.json があればそちらを使う（kintone側でフィールドコードを変えても再起動だけで済む）
+//! - 読み込めない・内容に誤りがあるファイルは使わず、同梱の既定で動かす（警告として表示）
+//! - 受信で同じ列の行が複数あるときは、上から順に最初に値があるものを使う
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::database::LocalSchedule;
+
+/// データフォルダ内の対応付けファイルの置き場所
+pub const MAPPING_DIR: &str = "mappings";
+
+/// 同梱の既定の対応付け
+const BUNDLED_MAIN: &str = include_str!("../mappings/main.json");
+const BUNDLED_MEMO: &str = include_str!("../mappings/memo.json");
+const BUNDLED_YAMAZUMI: &str = include_str!("../mappings/yamazumi.json");
+const BUNDLED_KOBUKURO: &str = include_str!("../mappings/kobukuro.json");
+const BUNDLED_LEGACY: &str = include_str!("../mappings/legacy.json");
+
+/// 送受信の向き
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// kintoneへ送信するだけ
+    Push,
+    /// kintoneから受信するだけ
+    Pull,
+    Both,
+}
+
+impl Direction {
+    pub fn pushes(self) -> bool {
+        self != Direction::Pull
+    }
+
+    pub fn pulls(self) -> bool {
+        self != Direction::Push
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Direction::Push => "送信",
+            Direction::Pull => "受信",
+            Direction::Both => "送受信",
+        }
+    }
+}
+
+/// 値の変換
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    /// 文字列（空欄は空文字で送信）
+    Text,
+    /// 数値（文字列にして送信し、受信時は数値に変換）
+    Number,
+    /// 日時（空欄はnullで送信）
+    Datetime,
+    /// ドロップダウン（選択肢の文字列、空欄は空文字で送信）
+    DropDown,
+}
+
+impl FieldType {
+    /// この変換で扱えるkintoneのフィールドの種類（受信だけなら計算フィールドなども読める）
+    pub fn kintone_types(self, direction: Direction) -> Vec<&'static str> {
+        let (writable, readable): (&[&str], &[&str]) = match self {
+            FieldType::Text => (
+                &["SINGLE_LINE_TEXT", "MULTI_LINE_TEXT", "RICH_TEXT", "LINK", "DROP_DOWN", "RADIO_BUTTON"],
+                &["CALC", "NUMBER", "RECORD_NUMBER"],
+            ),
+            FieldType::Number => (&["NUMBER"], &["CALC"]),
+            FieldType::Datetime => (&["DATETIME"], &["CREATED_TIME", "UPDATED_TIME"]),
+            FieldType::DropDown => (&["DROP_DOWN", "RADIO_BUTTON"], &[]),
+        };
+        let mut types = writable.to_vec();
+        if !direction.pushes() {
+            types.extend_from_slice(readable);
+        }
+        types
+    }
+
+    pub fn is_number(self) -> bool {
+        self == FieldType::Number
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            FieldType::Text => "text",
+            FieldType::Number => "number",
+            FieldType::Datetime => "datetime",
+            FieldType::DropDown => "drop_down",
+        }
+    }
+}
+
+/// フィールド1件の対応付け
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldMap {
+    /// kintoneのフィールドコード
+    pub field: String,
+    /// LocalScheduleの列名
+    pub column: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    pub direction: Direction,
+    /// 値が空のときに使う値（送信・受信とも）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// 空欄なら送信しない（kintone側の値を消さない）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_empty: bool,
+}
+
+/// アプリ1つ分の対応付け
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppMapping {
+    /// 設定上のアプリ名（main / memo / yamazumi / kobukuro / legacy）
+    pub app: String,
+    #[serde(default)]
+    pub description: String,
+    /// 差分取得に使う更新日時のフィールドコード
+    #[serde(default = "default_updated_time_field")]
+    pub updated_time_field: String,
+    pub fields: Vec<FieldMap>,
+    /// 読み込んだファイル（同梱の既定ならNone）
+    #[serde(default, skip_deserializing)]
+    pub source: Option<String>,
+}
+
+fn default_updated_time_field() -> String {
+    "更新日時".to_string()
+}
+
+/// ローカルの列の値の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnKind {
+    /// String / Option<String>
+    Text,
+    /// Option<f64>
+    Number,
+}
+
+/// 対応付けできるLocalScheduleの列
+const COLUMNS: &[(&str, ColumnKind)] = &[
+    ("schedule_number", ColumnKind::Text),
+    ("product_name", ColumnKind::Text),
+    ("product_display_name", ColumnKind::Text),
+    ("category", ColumnKind::Text),
+    ("line", ColumnKind::Text),
+    ("start_datetime", ColumnKind::Text),
+    ("end_datetime", ColumnKind::Text),
+    ("quantity1", ColumnKind::Number),
+    ("quantity2", ColumnKind::Number),
+    ("quantity3", ColumnKind::Number),
+    ("quantity4", ColumnKind::Number),
+    ("quantity5", ColumnKind::Number),
+    ("quantity6", ColumnKind::Number),
+    ("quantity7", ColumnKind::Number),
+    ("quantity8", ColumnKind::Number),
+    ("total_quantity", ColumnKind::Number),
+    ("efficiency1", ColumnKind::Text),
+    ("efficiency2", ColumnKind::Text),
+    ("efficiency3", ColumnKind::Text),
+    ("efficiency4", ColumnKind::Text),
+    ("efficiency5", ColumnKind::Text),
+    ("efficiency6", ColumnKind::Text),
+    ("efficiency7", ColumnKind::Text),
+    ("efficiency8", ColumnKind::Text),
+    ("production_status", ColumnKind::Text),
+    ("notes", ColumnKind::Text),
+];
+
+fn column_kind(column: &str) -> Option<ColumnKind> {
+    COLUMNS.iter().find(|(name, _)| *name == column).map(|(_, kind)| *kind)
+}
+
+/// アプリごとに必ず対応付ける列と向き
+fn required_columns(app: &str) -> &'static [(&'static str, Direction)] {
+    match app {
+        "main" | "memo" => &[("product_name", Direction::Both), ("start_datetime", Direction::Both)],
+        // スケジュール番号で送信先のレコードを探し、生産状況で変更してよいか判断する
+        "yamazumi" | "kobukuro" => &[("schedule_number", Direction::Both), ("production_status", Direction::Pull)],
+        "legacy" => &[("product_name", Direction::Pull), ("start_datetime", Direction::Pull)],
+        _ => &[],
+    }
+}
+
+/// kintoneのフィールド値を文字列として取得（空欄はNone）
+fn text_value(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(s) if s.is_empty() => None,
+        Value::String(s) => Some(s.clone()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+/// kintoneのフィールド値を数値として取得
+fn number_value(value: Option<&Value>) -> Option<f64> {
+    match value? {
+        Value::String(s) if !s.is_empty() => s.trim().replace(',', "").parse().ok(),
+        Value::Number(n) => n.as_f64(),
+        _ => None,
+    }
+}
+
+impl AppMapping {
+    /// 同梱の既定の対応付け
+    fn bundled(text: &str) -> Self {
+        serde_json::from_str(text).expect("同梱の対応付けファイルの形式が不正です")
+    }
+
+    /// 対応付けの内容をチェック（列名・値の変換・必須の列）
+    pub fn validate(&self) -> Result<(), String> {
+        let mut pushed_fields = HashSet::new();
+        let mut pushed_columns = HashSet::new();
+        for f in &self.fields {
+            if f.field.trim().is_empty() {
+                return Err(format!("列 {} のフィールドコードが空です", f.column));
+            }
+            let kind = column_kind(&f.column).ok_or_else(|| format!("対応付けできない列です: {}", f.column))?;
+            if f.field_type.is_number() != (kind == ColumnKind::Number) {
+                return Err(format!("列 {} には {} の変換を使えません", f.column, f.field_type.label()));
+            }
+            if f.direction.pushes() && !pushed_fields.insert(f.field.as_str()) {
+                return Err(format!("フィールド {} に複数の列を送信しています", f.field));
+            }
+            if f.direction.pushes() && !pushed_columns.insert(f.column.as_str()) {
+                return Err(format!("列 {} を複数のフィールドに送信しています", f.column));
+            }
+        }
+
+        for (column, direction) in required_columns(&self.app) {
+            let mapped = |pred: fn(Direction) -> bool| self.fields.iter().any(|f| f.column == *column && pred(f.direction));
+            if (direction.pushes() && !mapped(Direction::pushes)) || (direction.pulls() && !mapped(Direction::pulls)) {
+                return Err(format!("列 {} の{}の対応付けが必要です", column, direction.label()));
+            }
+        }
+        Ok(())
+    }
+
+    /// 受信する列（kintoneの値で上書きしてよい列）
+    pub fn pulled_columns(&self) -> Vec<&str> {
+        let mut columns: Vec<&str> = Vec::new();
+        for f in self.fields.iter().filter(|f| f.direction.pulls()) {
+            if !columns.contains(&f.column.as_str()) {
+                columns.push(f.column.as_str());
+            }
+        }
+        columns
+    }
+
+    /// 列を受信するフィールドコード（最初のもの）
+    pub fn pull_field(&self, column: &str) -> Option<&str> {
+        self.fields.iter().find(|f| f.column == column && f.direction.pulls()).map(|f| f.field.as_str())
+    }
+
+    /// スケジュールからkintone送信用のレコードを組み立てる
+    pub fn build_record(&self, schedule: &LocalSchedule) -> Value {
+        let local = serde_json::to_value(schedule).unwrap_or_default();
+        let mut record = serde_json::Map::new();
+        for f in self.fields.iter().filter(|f| f.direction.pushes()) {
+            let value = match local.get(&f.column) {
+                Some(Value::String(s)) if !s.is_empty() => Value::String(s.clone()),
+                Some(Value::Number(n)) => Value::String(n.as_f64().map(|v| v.to_string()).unwrap_or_default()),
+                _ => match &f.default {
+                    Some(default) => Value::String(default.clone()),
+                    None if f.skip_empty => continue,
+                    None => match f.field_type {
+                        FieldType::Text | FieldType::DropDown => Value::String(String::new()),
+                        FieldType::Number | FieldType::Datetime => Value::Null,
+                    },
+                },
+            };
+            record.insert(f.field.clone(), serde_json::json!({ "value": value }));
+        }
+        Value::Object(record)
+    }
+
+    /// kintoneのレコードの値をスケジュールに反映（対応付けのない列はbaseのまま）
+    pub fn parse_record(&self, record: &Value, base: LocalSchedule) -> Result<LocalSchedule, String> {
+        let mut local = serde_json::to_value(base).map_err(|e| e.to_string())?;
+        let mut filled = HashSet::new();
+        for f in self.fields.iter().filter(|f| f.direction.pulls()) {
+            if filled.contains(f.column.as_str()) {
+                continue;
+            }
+            let raw = record.get(&f.field).and_then(|v| v.get("value"));
+            let value = if f.field_type.is_number() {
+                number_value(raw).and_then(serde_json::Number::from_f64).map(Value::Number)
+            } else {
+                text_value(raw).map(Value::String)
+            };
+            if let Some(value) = value.or_else(|| f.default.clone().map(Value::String)) {
+                local[f.column.as_str()] = value;
+                filled.insert(f.column.as_str());
+            }
+        }
+        serde_json::from_value(local).map_err(|e| e.to_string())
+    }
+}
+
+/// 全アプリの対応付け
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldMappings {
+    /// ID506（スケジュール）
+    pub main: AppMapping,
+    /// ID507（メモ・図形）
+    pub memo: AppMapping,
+    /// ID354（山積表）
+    pub yamazumi: AppMapping,
+    /// ID368（小袋実績）
+    pub kobukuro: AppMapping,
+    /// App 351（移行元の旧アプリ）
+    pub legacy: AppMapping,
+    /// 読み込めなかった対応付けファイル（同梱の既定を使っている）
+    pub warnings: Vec<String>,
+}
+
+impl Default for FieldMappings {
+    fn default() -> Self {
+        Self {
+            main: AppMapping::bundled(BUNDLED_MAIN),
+            memo: AppMapping::bundled(BUNDLED_MEMO),
+            yamazumi: AppMapping::bundled(BUNDLED_YAMAZUMI),
+            kobukuro: AppMapping::bundled(BUNDLED_KOBUKURO),
+            legacy: AppMapping::bundled(BUNDLED_LEGACY),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl FieldMappings {
+    /// データフォルダの対応付けファイルを読み込む（ないアプリは同梱の既定）
+    pub fn load(data_dir: &Path) -> Self {
+        let mut mappings = Self::default();
+        let dir = data_dir.join(MAPPING_DIR);
+        let mut warnings = Vec::new();
+        for mapping in mappings.all_mut() {
+            let path = dir.join(format!("{}.json", mapping.app));
+            if !path.exists() {
+                continue;
+            }
+            let loaded = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| serde_json::from_str::<AppMapping>(&text).map_err(|e| e.to_string()))
+                .and_then(|loaded| {
+                    if loaded.app != mapping.app {
+                        return Err(format!("app が {} ではありません（{}）", mapping.app, loaded.app));
+                    }
+                    loaded.validate().map(|_| loaded)
+                });
+            match loaded {
+                Ok(mut loaded) => {
+                    eprintln!("=== Field mapping loaded: {} ===", path.display());
+                    loaded.source = Some(path.display().to_string());
+                    *mapping = loaded;
+                }
+                Err(e) => warnings.push(format!("{}: {}（同梱の既定を使います）", path.display(), e)),
+            }
+        }
+        mappings.warnings = warnings;
+        mappings
+    }
+
+    fn all_mut(&mut self) -> [&mut AppMapping; 5] {
+        [&mut self.main, &mut self.memo, &mut self.yamazumi, &mut self.kobukuro, &mut self.legacy]
+    }
+
+    /// 全アプリの対応付け
+    pub fn all(&self) -> [&AppMapping; 5] {
+        [&self.main, &self.memo, &self.yamazumi, &self.kobukuro, &self.legacy]
+    }
+
+    /// ID506またはID507の対応付け
+    pub fn schedule(&self, is_memo: bool) -> &AppMapping {
+        if is_memo { &self.memo } else { &self.main }
+    }
+}
@@ -1,48 +1,326 @@
 //! kintone API クライアントモジュール
 //! kintoneとの双方向連携を実現
 
-use reqwest::{Client, header};
+use reqwest::{Client, RequestBuilder, StatusCode, header};
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
-use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::time::Duration;
+use thiserror::Error;
 
 /// kintone接続設定
-#[derive(Clone, Debug, Serialize, Deserialize)]
-
+/// APIトークンは設定ファイルに書き出さない（credentialsモジュールで暗号化して別保存）
+#[derive(Clone, Serialize, Deserialize)]
 pub struct KintoneConfig {
     pub subdomain: String,
     pub app_id: u32,
+    #[serde(default, skip_serializing)]
     pub api_token: String,
     pub memo_app_id: Option<u32>,
+    #[serde(default, skip_serializing)]
     pub memo_api_token: Option<String>,
     pub yamazumi_app_id: Option<u32>,
+    #[serde(default, skip_serializing)]
     pub yamazumi_api_token: Option<String>,
     pub kobukuro_app_id: Option<u32>,
+    #[serde(default, skip_serializing)]
     pub kobukuro_api_token: Option<String>,
     pub tsumikomi_app_id: Option<u32>,
+    #[serde(default, skip_serializing)]
     pub tsumikomi_api_token: Option<String>,
+    /// 移行元の旧アプリ（App 351）
+    #[serde(default)]
+    pub legacy_app_id: Option<u32>,
+    #[serde(default, skip_serializing)]
+    pub legacy_api_token: Option<String>,
+}
+
+/// ログ出力用にAPIトークンを伏せ字にする
+fn mask_token(token: Option<&str>) -> &'static str {
+    match token {
+        Some(t) if !t.is_empty() => "********",
+        _ => "(未設定)",
+    }
+}
+
+impl std::fmt::Debug for KintoneConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KintoneConfig")
+            .field("subdomain", &self.subdomain)
+            .field("app_id", &self.app_id)
+            .field("api_token", &mask_token(Some(&self.api_token)))
+            .field("memo_app_id", &self.memo_app_id)
+            .field("memo_api_token", &mask_token(self.memo_api_token.as_deref()))
+            .field("yamazumi_app_id", &self.yamazumi_app_id)
+            .field("yamazumi_api_token", &mask_token(self.yamazumi_api_token.as_deref()))
+            .field("kobukuro_app_id", &self.kobukuro_app_id)
+            .field("kobukuro_api_token", &mask_token(self.kobukuro_api_token.as_deref()))
+            .field("tsumikomi_app_id", &self.tsumikomi_app_id)
+            .field("tsumikomi_api_token", &mask_token(self.tsumikomi_api_token.as_deref()))
+            .field("legacy_app_id", &self.legacy_app_id)
+            .field("legacy_api_token", &mask_token(self.legacy_api_token.as_deref()))
+            .finish()
+    }
+}
+
+/// フィールド単位の入力エラー
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    /// エラー対象（例: record.製綿能率.value）
+    pub field: String,
+    pub messages: Vec<String>,
+}
+
+/// kintone APIのエラー
+/// Display はそのまま画面に表示できる文言にする
+#[derive(Debug, Error)]
+pub enum KintoneError {
+    #[error("kintoneに接続できません。ネットワーク接続・プロキシ設定を確認してください（{0}）")]
+    Network(String),
+
+    #[error("kintoneの応答がタイムアウトしました。しばらくしてから再度お試しください")]
+    Timeout,
+
+    #[error("kintoneの認証に失敗しました。APIトークンとサブドメインを確認してください（{code}: {message}）")]
+    Auth { code: String, message: String },
+
+    #[error("kintoneアプリへのアクセス権限がありません。APIトークンの権限設定を確認してください（{code}: {message}）")]
+    Permission { code: String, message: String },
+
+    #[error("kintoneへの入力内容に誤りがあります: {message}{}", format_field_errors(.field_errors))]
+    Validation { code: String, message: String, field_errors: Vec<FieldError> },
+
+    #[error("kintoneのリクエスト数の上限に達しました。しばらく待ってから再度お試しください")]
+    RateLimited,
+
+    #[error("kintoneのレコードが他で更新されています。最新の内容を取得してから再度お試しください（{code}）")]
+    RevisionConflict { code: String },
+
+    #[error("kintoneのレコードが見つかりません（{code}: {message}）")]
+    NotFound { code: String, message: String },
+
+    #[error("kintone APIエラー（HTTP {status} {code}）: {message}")]
+    Api { status: u16, code: String, message: String },
+
+    #[error("kintoneの応答を解析できません: {0}")]
+    InvalidResponse(String),
+
+    #[error("kintone設定エラー: {0}")]
+    Config(String),
+}
+
+/// フィールドエラーを表示用に整形
+fn format_field_errors(errors: &[FieldError]) -> String {
+    if errors.is_empty() {
+        return String::new();
+    }
+    let details: Vec<String> = errors
+        .iter()
+        .map(|e| format!("{}: {}", e.field, e.messages.join(" / ")))
+        .collect();
+    format!("（{}）", details.join("、"))
+}
+
+impl KintoneError {
+    /// 時間をおいて再試行すれば成功する可能性のあるエラーか
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            KintoneError::Network(_) | KintoneError::Timeout | KintoneError::RateLimited => true,
+            KintoneError::Api { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// reqwestのエラーを変換
+    fn from_reqwest(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            KintoneError::Timeout
+        } else {
+            KintoneError::Network(e.to_string())
+        }
+    }
+
+    /// エラー応答（HTTPステータスと本文）から分類
+    fn from_response(status: StatusCode, text: &str) -> Self {
+        let json: serde_json::Value = serde_json::from_str(text).unwrap_or_default();
+        let code = json["code"].as_str().unwrap_or_default().to_string();
+        let message = json["message"]
+            .as_str()
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| text.chars().take(500).collect());
+
+        match code.as_str() {
+            "GAIA_CO02" => return KintoneError::RevisionConflict { code },
+            "GAIA_RE01" | "GAIA_AP01" => return KintoneError::NotFound { code, message },
+            "GAIA_NO01" | "CB_NO02" => return KintoneError::Permission { code, message },
+            "CB_VA01" => {
+                return KintoneError::Validation { code, message, field_errors: parse_field_errors(&json["errors"]) };
+            }
+            _ => {}
+        }
+
+        if status == StatusCode::UNAUTHORIZED
+            || code.starts_with("GAIA_IA")
+            || code.starts_with("CB_AU")
+            || code.starts_with("CB_WA")
+        {
+            return KintoneError::Auth { code, message };
+        }
+        if status == StatusCode::FORBIDDEN {
+            return KintoneError::Permission { code, message };
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return KintoneError::RateLimited;
+        }
+        if status == StatusCode::CONFLICT {
+            return KintoneError::RevisionConflict { code };
+        }
+
+        KintoneError::Api { status: status.as_u16(), code, message }
+    }
+}
+
+/// CB_VA01 の errors（{"record.xxx.value": {"messages": [...]}}）を変換
+fn parse_field_errors(errors: &serde_json::Value) -> Vec<FieldError> {
+    let Some(map) = errors.as_object() else { return Vec::new() };
+    map.iter()
+        .map(|(field, detail)| FieldError {
+            field: field.clone(),
+            messages: detail["messages"]
+                .as_array()
+                .map(|m| m.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+pub type KintoneResult<T> = std::result::Result<T, KintoneError>;
+
+/// 再試行の最大回数（初回を含まない）
+const MAX_RETRIES: u32 = 3;
+
+/// 再試行の初回待ち時間（以降は倍々で増やす）
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// 再試行できるエラーなら指数バックオフで待ってやり直す（最大MAX_RETRIES回）
+async fn with_retry<T, F, Fut>(label: &str, run: F) -> KintoneResult<T>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = KintoneResult<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        match run().await {
+            Err(e) if e.is_retryable() && attempt < MAX_RETRIES => {
+                let wait = INITIAL_BACKOFF * 2u32.pow(attempt);
+                attempt += 1;
+                eprintln!("=== kintone {}: {} / {}ms後に再試行 ({}/{}) ===", label, e, wait.as_millis(), attempt, MAX_RETRIES);
+                tokio::time::sleep(wait).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// カーソルAPIの1回あたりの取得件数（kintoneの上限は500件）
+const CURSOR_PAGE_SIZE: u32 = 500;
+
+/// クエリの文字列値をダブルクォートで囲む（値の中の \ と " はエスケープする）
+pub fn quote_query_value(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// クエリに limit / offset 指定が含まれるか（カーソルAPIでは使用不可）
+fn has_paging_clause(query: &str) -> bool {
+    query
+        .split_whitespace()
+        .any(|token| token.eq_ignore_ascii_case("limit") || token.eq_ignore_ascii_case("offset"))
+}
+
+/// 追加・更新後のレコードIDとリビジョン
+#[derive(Debug, Clone, Copy)]
+pub struct SavedRecord {
+    pub id: u32,
+    pub revision: Option<i64>,
+}
+
+/// 一括更新用のレコード
+#[derive(Debug, Clone)]
+pub struct BulkUpdateRecord {
+    pub id: u32,
+    pub revision: Option<i64>,
+    pub record: serde_json::Value,
+}
+
+/// 一括登録・更新APIの1リクエストあたりの上限件数
+pub const BULK_CHUNK_SIZE: usize = 100;
+
+/// 文字列で返るID・リビジョンを数値に変換
+fn parse_id<T: std::str::FromStr>(value: &serde_json::Value) -> Option<T> {
+    value.as_str().and_then(|v| v.parse().ok())
+}
+
+/// ID354（山積表）・ID368（小袋実績）への送信内容
+#[derive(Debug)]
+pub enum SecondaryWrite {
+    /// 登録・更新（create_if_missing が false ならレコードがない場合は登録しない）
+    Upsert { record: serde_json::Value, create_if_missing: bool },
+    /// 削除
+    Delete,
+}
+
+/// ID354（山積表）・ID368（小袋実績）への送信結果
+#[derive(Debug, Clone, PartialEq)]
+pub enum SecondaryOutcome {
+    Added,
+    Updated,
+    Deleted,
+    /// スケジュール番号に一致するレコードがない（登録もしていない）
+    NotFound,
+    /// 生産状況が未生産ではないため変更していない
+    Skipped { status: String },
+}
+
+/// フォームを確認するアプリ（設定上の名前）
+pub const SCHEMA_APPS: &[&str] = &["main", "memo", "yamazumi", "kobukuro", "tsumikomi", "legacy"];
+
+/// フォームのフィールド（app/form/fields.json の1件）
+#[derive(Debug, Clone, Serialize)]
+pub struct FormField {
+    pub code: String,
+    pub label: String,
+    /// フィールドの種類（SINGLE_LINE_TEXT / NUMBER / DROP_DOWN など）
+    pub field_type: String,
+    /// ドロップダウン・ラジオボタンの選択肢（表示順）
+    pub options: Vec<String>,
 }
 
-/// 生産スケジュールレコード
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProductionRecord {
-    pub record_number: Option<u32>,
-    pub product_name: String,
-    pub product_display_name: Option<String>,
-    pub category: Option<String>,
-    pub start_datetime: DateTime<Utc>,
-    pub end_datetime: Option<DateTime<Utc>>,
-    pub quantity1: Option<f64>,
-    pub quantity2: Option<f64>,
-    pub quantity3: Option<f64>,
-    pub quantity4: Option<f64>,
-    pub quantity5: Option<f64>,
-    pub quantity6: Option<f64>,
-    pub quantity7: Option<f64>,
-    pub quantity8: Option<f64>,
-    pub total_quantity: Option<f64>,
-    pub production_status: Option<String>,
-    pub line: Option<String>,
+/// app/form/fields.json の応答をフィールドコードごとにまとめる
+fn parse_form_fields(json: &serde_json::Value) -> HashMap<String, FormField> {
+    let Some(properties) = json["properties"].as_object() else {
+        return HashMap::new();
+    };
+    properties
+        .iter()
+        .map(|(code, property)| {
+            let mut options: Vec<(u32, String)> = property["options"]
+                .as_object()
+                .map(|options| {
+                    options
+                        .values()
+                        .map(|o| (parse_id(&o["index"]).unwrap_or(u32::MAX), o["label"].as_str().unwrap_or_default().to_string()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            options.sort();
+            let field = FormField {
+                code: code.clone(),
+                label: property["label"].as_str().unwrap_or_default().to_string(),
+                field_type: property["type"].as_str().unwrap_or_default().to_string(),
+                options: options.into_iter().map(|(_, label)| label).collect(),
+            };
+            (code.clone(), field)
+        })
+        .collect()
 }
 
 /// kintone APIクライアント
@@ -54,20 +332,55 @@ pub struct KintoneClient {
 
 impl KintoneClient {
     /// 新しいクライアントを作成
-    pub fn new(config: KintoneConfig) -> Result<Self> {
+    pub fn new(config: KintoneConfig) -> KintoneResult<Self> {
         let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .connect_timeout(std::time::Duration::from_secs(10))
-            .build()?;
+            .timeout(Duration::from_secs(30))
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| KintoneError::Config(e.to_string()))?;
 
         Ok(Self { client, config })
     }
 
+    /// 接続設定（APIトークンを含むため画面には返さないこと）
+    pub fn config(&self) -> &KintoneConfig {
+        &self.config
+    }
+
     /// kintoneのベースURL
     fn base_url(&self) -> String {
         format!("https://{}.cybozu.com/k/v1", self.config.subdomain)
     }
 
+    /// リクエストを送信してJSON応答を返す（再試行しない）
+    /// 再送すると二重に処理されうる要求（レコード追加のPOST・カーソルのページ取得など）に使う
+    async fn send(&self, label: &str, build: impl Fn() -> RequestBuilder) -> KintoneResult<serde_json::Value> {
+        self.send_once(label, build()).await
+    }
+
+    /// 何度送っても結果が変わらない要求（検索のGET・リビジョン指定のPUT・DELETE）を送信してJSON応答を返す
+    /// 通信エラー・タイムアウト・リクエスト制限・5xxは指数バックオフで再試行する
+    async fn send_idempotent(&self, label: &str, build: impl Fn() -> RequestBuilder) -> KintoneResult<serde_json::Value> {
+        with_retry(label, || self.send_once(label, build())).await
+    }
+
+    /// リクエストを1回送信
+    async fn send_once(&self, label: &str, request: RequestBuilder) -> KintoneResult<serde_json::Value> {
+        let response = request.send().await.map_err(KintoneError::from_reqwest)?;
+
+        let status = response.status();
+        let text = response.text().await.map_err(KintoneError::from_reqwest)?;
+
+        if !status.is_success() {
+            eprintln!("=== kintone {} error response ===", label);
+            eprintln!("Status: {}", status);
+            eprintln!("Body: {}", text.chars().take(2000).collect::<String>());
+            return Err(KintoneError::from_response(status, &text));
+        }
+
+        serde_json::from_str(&text).map_err(|e| KintoneError::InvalidResponse(e.to_string()))
+    }
+
     /// 対象のアプリIDとトークンを取得
     pub fn get_app_credentials(&self, is_memo: bool) -> (u32, String) {
         if is_memo {
@@ -80,41 +393,135 @@ impl KintoneClient {
         }
     }
 
-    /// レコードを取得
-    pub async fn get_records(&self, query: Option<&str>, is_memo: bool) -> Result<serde_json::Value> {
-        let url = format!("{}/records.json", self.base_url());
+    /// 条件に一致する全レコードを取得（500件を超える場合もカーソルAPIで全件取得）
+    pub async fn get_all_records(&self, query: Option<&str>, is_memo: bool) -> KintoneResult<Vec<serde_json::Value>> {
         let (app_id, api_token) = self.get_app_credentials(is_memo);
+        self.fetch_all_records(app_id, &api_token, query, &[]).await
+    }
 
-        let mut params = vec![
-            ("app", app_id.to_string()),
-        ];
+    /// アプリの全レコードのIDを取得（kintone側で削除されたレコードの検出に使う）
+    pub async fn get_all_record_ids(&self, is_memo: bool) -> KintoneResult<Vec<u32>> {
+        let (app_id, api_token) = self.get_app_credentials(is_memo);
+        let records = self.fetch_all_records(app_id, &api_token, Some("order by $id asc"), &["$id"]).await?;
+        Ok(records.iter().filter_map(|r| parse_id(&r["$id"]["value"])).collect())
+    }
 
+    /// カーソルAPIで全レコードを取得（fieldsが空なら全フィールド）
+    /// limit / offset 指定のクエリは通常のレコード取得で指定範囲のみ返す
+    /// カーソルのページ取得は再送できないため、途中で失敗した場合はカーソルを作り直して最初から取り直す
+    async fn fetch_all_records(&self, app_id: u32, api_token: &str, query: Option<&str>, fields: &[&str]) -> KintoneResult<Vec<serde_json::Value>> {
         if let Some(q) = query {
-            params.push(("query", q.to_string()));
+            if has_paging_clause(q) {
+                let json = self.get_records_page(app_id, api_token, q).await?;
+                return Ok(json["records"].as_array().cloned().unwrap_or_default());
+            }
         }
 
-        let response = self.client
-            .get(&url)
-            .header("X-Cybozu-API-Token", &api_token)
-            .query(&params)
-            .send()
-            .await?;
+        with_retry("fetch_all_records", || self.fetch_with_cursor(app_id, api_token, query, fields)).await
+    }
 
-        let status = response.status();
-        let text = response.text().await?;
-        
-        eprintln!("=== kintone API Response ===");
-        eprintln!("Status: {}", status);
-        eprintln!("Body: {}", &text[..text.len().min(2000)]);
-        eprintln!("============================");
+    /// カーソルを作成して最後のページまで取得
+    async fn fetch_with_cursor(&self, app_id: u32, api_token: &str, query: Option<&str>, fields: &[&str]) -> KintoneResult<Vec<serde_json::Value>> {
+        let cursor_id = self.create_cursor(app_id, api_token, query, fields).await?;
+
+        let mut all_records = Vec::new();
+        loop {
+            let json = match self.get_cursor_page(api_token, &cursor_id).await {
+                Ok(json) => json,
+                Err(e) => {
+                    // 途中で失敗した場合はカーソルを解放してからエラーを返す
+                    if let Err(del_err) = self.delete_cursor(api_token, &cursor_id).await {
+                        eprintln!("カーソルの削除に失敗: {}", del_err);
+                    }
+                    return Err(e);
+                }
+            };
+
+            if let Some(records) = json["records"].as_array() {
+                all_records.extend(records.iter().cloned());
+            }
+
+            eprintln!("=== Cursor {}: fetched {} records ===", cursor_id, all_records.len());
+
+            // 最終ページを読むとカーソルはkintone側で自動削除される
+            if !json["next"].as_bool().unwrap_or(false) {
+                break;
+            }
+        }
 
-        let json: serde_json::Value = serde_json::from_str(&text)?;
+        Ok(all_records)
+    }
+
+    /// 通常のレコード取得（1リクエスト分）
+    async fn get_records_page(&self, app_id: u32, api_token: &str, query: &str) -> KintoneResult<serde_json::Value> {
+        let url = format!("{}/records.json", self.base_url());
+
+        self.send_idempotent("get_records_page", || {
+            self.client
+                .get(&url)
+                .header("X-Cybozu-API-Token", api_token)
+                .query(&[("app", app_id.to_string()), ("query", query.to_string())])
+        }).await
+    }
+
+    /// カーソルを作成してカーソルIDを返す
+    async fn create_cursor(&self, app_id: u32, api_token: &str, query: Option<&str>, fields: &[&str]) -> KintoneResult<String> {
+        let url = format!("{}/records/cursor.json", self.base_url());
+
+        let mut body = serde_json::json!({
+            "app": app_id,
+            "size": CURSOR_PAGE_SIZE,
+        });
+        if let Some(q) = query {
+            body["query"] = serde_json::Value::String(q.to_string());
+        }
+        if !fields.is_empty() {
+            body["fields"] = serde_json::json!(fields);
+        }
+
+        let json = self.send("create_cursor", || {
+            self.client
+                .post(&url)
+                .header("X-Cybozu-API-Token", api_token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .json(&body)
+        }).await?;
+
+        json["id"]
+            .as_str()
+            .map(|s| s.to_string())
+            .ok_or_else(|| KintoneError::InvalidResponse(format!("カーソルIDがありません: {}", json)))
+    }
+
+    /// カーソルから次のページを取得
+    async fn get_cursor_page(&self, api_token: &str, cursor_id: &str) -> KintoneResult<serde_json::Value> {
+        let url = format!("{}/records/cursor.json", self.base_url());
+
+        self.send("get_cursor_page", || {
+            self.client
+                .get(&url)
+                .header("X-Cybozu-API-Token", api_token)
+                .query(&[("id", cursor_id)])
+        }).await
+    }
 
-        Ok(json)
+    /// カーソルを削除
+    async fn delete_cursor(&self, api_token: &str, cursor_id: &str) -> KintoneResult<()> {
+        let url = format!("{}/records/cursor.json", self.base_url());
+
+        self.send_idempotent("delete_cursor", || {
+            self.client
+                .delete(&url)
+                .header("X-Cybozu-API-Token", api_token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .json(&serde_json::json!({ "id": cursor_id }))
+        }).await?;
+
+        Ok(())
     }
 
     /// レコードを追加
-    pub async fn add_record(&self, record: serde_json::Value, is_memo: bool) -> Result<u32> {
+    pub async fn add_record(&self, record: serde_json::Value, is_memo: bool) -> KintoneResult<SavedRecord> {
         let url = format!("{}/record.json", self.base_url());
         let (app_id, api_token) = self.get_app_credentials(is_memo);
 
@@ -127,92 +534,169 @@ impl KintoneClient {
         eprintln!("URL: {}", url);
         eprintln!("Body: {}", serde_json::to_string_pretty(&body).unwrap_or_default());
 
-        let response = self.client
-            .post(&url)
-            .header("X-Cybozu-API-Token", &api_token)
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&body)
-            .send()
-            .await?;
-
-        let status = response.status();
-        let text = response.text().await?;
-        
-        eprintln!("=== kintone add_record response ===");
-        eprintln!("Status: {}", status);
-        eprintln!("Body: {}", text);
-
-        if !status.is_success() {
-            return Err(anyhow::anyhow!("kintone API error: {} - {}", status, text));
-        }
+        let json = self.send("add_record", || {
+            self.client
+                .post(&url)
+                .header("X-Cybozu-API-Token", &api_token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .json(&body)
+        }).await?;
 
-        let json: serde_json::Value = serde_json::from_str(&text)?;
-        let id = json["id"].as_str().unwrap_or("0").parse().unwrap_or(0);
+        let id = parse_id(&json["id"])
+            .ok_or_else(|| KintoneError::InvalidResponse(format!("レコードIDがありません: {}", json)))?;
+        let revision = parse_id(&json["revision"]);
         
         eprintln!("=== Created record ID: {} ===", id);
-        Ok(id)
+        Ok(SavedRecord { id, revision })
     }
 
     /// レコードを更新
-    pub async fn update_record(&self, record_id: u32, record: serde_json::Value, is_memo: bool) -> Result<()> {
+    /// revisionを指定した場合、kintone側が他で更新済みならRevisionConflictになる
+    pub async fn update_record(&self, record_id: u32, revision: Option<i64>, record: serde_json::Value, is_memo: bool) -> KintoneResult<SavedRecord> {
         let url = format!("{}/record.json", self.base_url());
         let (app_id, api_token) = self.get_app_credentials(is_memo);
 
-        let body = serde_json::json!({
+        let mut body = serde_json::json!({
             "app": app_id,
             "id": record_id,
             "record": record
         });
+        if let Some(rev) = revision {
+            body["revision"] = serde_json::json!(rev);
+        }
 
-        self.client
-            .put(&url)
-            .header("X-Cybozu-API-Token", &api_token)
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&body)
-            .send()
-            .await?;
+        let request = || {
+            self.client
+                .put(&url)
+                .header("X-Cybozu-API-Token", &api_token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .json(&body)
+        };
+        // リビジョン指定がなければ、再送で他の更新を上書きしうるため再試行しない
+        let json = if revision.is_some() {
+            self.send_idempotent("update_record", request).await?
+        } else {
+            self.send("update_record", request).await?
+        };
 
-        Ok(())
+        Ok(SavedRecord { id: record_id, revision: parse_id(&json["revision"]) })
     }
 
-    /// 複数レコードを一括更新
-    pub async fn update_records(&self, records: Vec<serde_json::Value>) -> Result<()> {
+    /// 複数レコードを一括追加（最大BULK_CHUNK_SIZE件、kintone側で全件成功か全件失敗）
+    /// 戻り値は引数と同じ順序のレコードIDとリビジョン
+    pub async fn add_records(&self, records: &[serde_json::Value], is_memo: bool) -> KintoneResult<Vec<SavedRecord>> {
+        if records.len() > BULK_CHUNK_SIZE {
+            return Err(KintoneError::Config(format!("一括追加は{}件までです（{}件）", BULK_CHUNK_SIZE, records.len())));
+        }
+
         let url = format!("{}/records.json", self.base_url());
+        let (app_id, api_token) = self.get_app_credentials(is_memo);
 
         let body = serde_json::json!({
-            "app": self.config.app_id,
+            "app": app_id,
             "records": records
         });
 
-        self.client
-            .put(&url)
-            .header("X-Cybozu-API-Token", &self.config.api_token)
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&body)
-            .send()
-            .await?;
+        let json = self.send("add_records", || {
+            self.client
+                .post(&url)
+                .header("X-Cybozu-API-Token", &api_token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .json(&body)
+        }).await?;
+
+        let empty_vec = vec![];
+        let ids = json["ids"].as_array().unwrap_or(&empty_vec);
+        let revisions = json["revisions"].as_array().unwrap_or(&empty_vec);
 
-        Ok(())
+        if ids.len() != records.len() {
+            return Err(KintoneError::InvalidResponse(format!(
+                "一括追加の結果件数が一致しません: {}件送信 / {}件作成", records.len(), ids.len()
+            )));
+        }
+
+        ids.iter().enumerate().map(|(i, id)| {
+            let id = parse_id(id)
+                .ok_or_else(|| KintoneError::InvalidResponse(format!("一括追加の結果にレコードIDがありません: {}", id)))?;
+            Ok(SavedRecord { id, revision: revisions.get(i).and_then(parse_id) })
+        }).collect()
+    }
+
+    /// 複数レコードを一括更新（最大BULK_CHUNK_SIZE件、kintone側で全件成功か全件失敗）
+    /// いずれかのリビジョンが一致しない場合はRevisionConflictになる
+    pub async fn update_records(&self, records: &[BulkUpdateRecord], is_memo: bool) -> KintoneResult<Vec<SavedRecord>> {
+        if records.len() > BULK_CHUNK_SIZE {
+            return Err(KintoneError::Config(format!("一括更新は{}件までです（{}件）", BULK_CHUNK_SIZE, records.len())));
+        }
+
+        let url = format!("{}/records.json", self.base_url());
+        let (app_id, api_token) = self.get_app_credentials(is_memo);
+
+        let body_records: Vec<serde_json::Value> = records.iter().map(|r| {
+            let mut item = serde_json::json!({
+                "id": r.id,
+                "record": r.record
+            });
+            if let Some(rev) = r.revision {
+                item["revision"] = serde_json::json!(rev);
+            }
+            item
+        }).collect();
+
+        let body = serde_json::json!({
+            "app": app_id,
+            "records": body_records
+        });
+
+        let request = || {
+            self.client
+                .put(&url)
+                .header("X-Cybozu-API-Token", &api_token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .json(&body)
+        };
+        let json = if records.iter().all(|r| r.revision.is_some()) {
+            self.send_idempotent("update_records", request).await?
+        } else {
+            self.send("update_records", request).await?
+        };
+
+        let empty_vec = vec![];
+        let updated = json["records"].as_array().unwrap_or(&empty_vec);
+
+        if updated.len() != records.len() {
+            return Err(KintoneError::InvalidResponse(format!(
+                "一括更新の結果件数が一致しません: {}件送信 / {}件更新", records.len(), updated.len()
+            )));
+        }
+
+        updated.iter().map(|r| {
+            let id = parse_id(&r["id"])
+                .ok_or_else(|| KintoneError::InvalidResponse(format!("一括更新の結果にレコードIDがありません: {}", r)))?;
+            Ok(SavedRecord { id, revision: parse_id(&r["revision"]) })
+        }).collect()
     }
 
     /// 単一レコードを取得
-    pub async fn get_record(&self, record_id: u32) -> Result<serde_json::Value> {
+    pub async fn get_record(&self, record_id: u32, is_memo: bool) -> KintoneResult<serde_json::Value> {
         let url = format!("{}/record.json", self.base_url());
+        let (app_id, api_token) = self.get_app_credentials(is_memo);
 
-        let response = self.client
-            .get(&url)
-            .header("X-Cybozu-API-Token", &self.config.api_token)
-            .query(&[
-                ("app", self.config.app_id.to_string()),
-                ("id", record_id.to_string()),
-            ])
-            .send()
-            .await?;    let json: serde_json::Value = response.json().await?;
-        Ok(json)
+        let json = self.send_idempotent("get_record", || {
+            self.client
+                .get(&url)
+                .header("X-Cybozu-API-Token", &api_token)
+                .query(&[
+                    ("app", app_id.to_string()),
+                    ("id", record_id.to_string()),
+                ])
+        }).await?;
+
+        Ok(json["record"].clone())
     }
 
     /// レコードを削除
-    pub async fn delete_record(&self, record_id: u32, is_memo: bool) -> Result<()> {
+    pub async fn delete_record(&self, record_id: u32, is_memo: bool) -> KintoneResult<()> {
         let url = format!("{}/records.json", self.base_url());
         let (app_id, api_token) = self.get_app_credentials(is_memo);
 
@@ -223,27 +707,17 @@ impl KintoneClient {
 
         eprintln!("=== kintone delete_record request ===");
         eprintln!("URL: {}", url);
-        eprintln!("Body: {}", serde_json::to_string_pretty(&body).unwrap());
-
-        let response = self.client
-            .delete(&url)
-            .header("X-Cybozu-API-Token", &api_token)
-            .header(reqwest::header::CONTENT_TYPE, "application/json")
-            .json(&body)
-            .send()
-            .await?;
+        eprintln!("Body: {}", serde_json::to_string_pretty(&body).unwrap_or_default());
 
-        let status = response.status();
-        let text = response.text().await?;
-        eprintln!("=== kintone delete_record response ===");
-        eprintln!("Status: {}", status);
-        eprintln!("Body: {}", text);
+        self.send_idempotent("delete_record", || {
+            self.client
+                .delete(&url)
+                .header("X-Cybozu-API-Token", &api_token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .json(&body)
+        }).await?;
 
-        if status.is_success() {
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Delete failed: {}", text))
-        }
+        Ok(())
     }
 
     /// 名前指定でアプリ認証情報を取得
@@ -266,153 +740,184 @@ impl KintoneClient {
                 self.config.tsumikomi_app_id.unwrap_or(514),
                 self.config.tsumikomi_api_token.clone().unwrap_or_default()
             )),
+            "legacy" => Some((
+                self.config.legacy_app_id.unwrap_or(351),
+                self.config.legacy_api_token.clone().unwrap_or_default()
+            )),
             _ => None
         }
     }
 
-    /// 名前指定でレコード取得（汎用）
-    pub async fn get_records_by_name(&self, app_name: &str, query: Option<&str>) -> Result<serde_json::Value> {
+    /// 名前指定で認証情報を取得（トークン未設定はエラー）
+    fn require_app_credentials(&self, app_name: &str) -> KintoneResult<(u32, String)> {
         let (app_id, api_token) = self.get_app_credentials_by_name(app_name)
-            .ok_or_else(|| anyhow::anyhow!("Unknown app: {}", app_name))?;
+            .ok_or_else(|| KintoneError::Config(format!("不明なアプリです: {}", app_name)))?;
 
         if api_token.is_empty() {
-            return Err(anyhow::anyhow!("APIトークンが未設定: {}", app_name));
+            return Err(KintoneError::Config(format!("APIトークンが未設定です: {}", app_name)));
         }
 
-        let url = format!("{}/records.json", self.base_url());
-        let mut params = vec![("app", app_id.to_string())];
-        if let Some(q) = query {
-            params.push(("query", q.to_string()));
-        }
+        Ok((app_id, api_token))
+    }
 
-        let response = self.client
-            .get(&url)
-            .header("X-Cybozu-API-Token", &api_token)
-            .query(&params)
-            .send()
-            .await?;
+    /// 名前指定で条件に一致する全レコードを取得（カーソルAPI使用）
+    pub async fn get_all_records_by_name(&self, app_name: &str, query: Option<&str>) -> KintoneResult<Vec<serde_json::Value>> {
+        let (app_id, api_token) = self.require_app_credentials(app_name)?;
 
-        let status = response.status();
-        let text = response.text().await?;
+        eprintln!("=== kintone get_all_records_by_name({}) ===", app_name);
+        self.fetch_all_records(app_id, &api_token, query, &[]).await
+    }
+
+    /// 名前指定でアプリのフォームのフィールド一覧を取得（フィールドコード → フィールド）
+    /// メモアプリ（memo）が未設定ならメインアプリを返す
+    pub async fn get_form_fields(&self, app_name: &str) -> KintoneResult<HashMap<String, FormField>> {
+        let (app_id, api_token) = if app_name == "memo" {
+            self.get_app_credentials(true)
+        } else {
+            self.require_app_credentials(app_name)?
+        };
 
-        eprintln!("=== kintone get_records_by_name({}) ===", app_name);
-        eprintln!("Status: {}", status);
-        eprintln!("Body: {}", &text[..text.len().min(500)]);
+        let url = format!("{}/app/form/fields.json", self.base_url());
+        let json = self.send_idempotent("get_form_fields", || {
+            self.client
+                .get(&url)
+                .header("X-Cybozu-API-Token", &api_token)
+                .query(&[("app", app_id.to_string())])
+        }).await?;
 
-        if !status.is_success() {
-            return Err(anyhow::anyhow!("kintone API error ({}): {} - {}", app_name, status, text));
+        Ok(parse_form_fields(&json))
+    }
+
+    /// 設定済みのアプリのID（メモアプリ未設定・APIトークン未設定ならNone）
+    pub fn configured_app_id(&self, app_name: &str) -> Option<u32> {
+        if app_name == "memo" {
+            return self.config.memo_app_id.map(|_| self.get_app_credentials(true).0);
         }
+        self.require_app_credentials(app_name).ok().map(|(app_id, _)| app_id)
+    }
 
-        let json: serde_json::Value = serde_json::from_str(&text)?;
-        Ok(json)
+    /// 設定済みの各アプリのフォームを取得（未設定のアプリは含めない）
+    pub async fn get_configured_forms(&self) -> Vec<(&'static str, u32, KintoneResult<HashMap<String, FormField>>)> {
+        let mut forms = Vec::new();
+        for &app_name in SCHEMA_APPS {
+            let Some(app_id) = self.configured_app_id(app_name) else { continue };
+            eprintln!("=== kintone get_form_fields({}) ===", app_name);
+            forms.push((app_name, app_id, self.get_form_fields(app_name).await));
+        }
+        forms
     }
 
     /// ID354（山積表）またはID368（小袋実績）に送信
-    /// スケジュール番号で検索し、未生産なら更新、なければ新規作成
+    /// スケジュール番号で検索し、未生産のレコードだけを更新・削除する（なければ登録するか何もしない）
+    /// key_field・status_field は送信先のスケジュール番号・生産状況のフィールドコード
     pub async fn sync_to_yamazumi_or_kobukuro(
         &self,
         schedule_number: &str,
-        record_data: serde_json::Value,
+        write: &SecondaryWrite,
         is_kobukuro: bool, // true=ID368, false=ID354
-    ) -> Result<()> {
-        let (app_id, api_token) = if is_kobukuro {
-            (
-                self.config.kobukuro_app_id.unwrap_or(368),
-                self.config.kobukuro_api_token.clone().unwrap_or_default()
-            )
-        } else {
-            (
-                self.config.yamazumi_app_id.unwrap_or(354),
-                self.config.yamazumi_api_token.clone().unwrap_or_default()
-            )
-        };
-
-        if api_token.is_empty() {
-            return Err(anyhow::anyhow!("APIトークンが設定されていません"));
-        }
+        key_field: &str,
+        status_field: &str,
+    ) -> KintoneResult<SecondaryOutcome> {
+        let (app_id, api_token) = self.require_app_credentials(if is_kobukuro { "kobukuro" } else { "yamazumi" })?;
+        let app_label = if is_kobukuro { "ID368" } else { "ID354" };
 
         // スケジュール番号で検索
-        let query = format!("スケジュール番号 = \"{}\"", schedule_number);
+        let query = format!("{} = {}", key_field, quote_query_value(schedule_number));
         let search_url = format!("{}/records.json", self.base_url());
         
-        let response = self.client
-            .get(&search_url)
-            .header("X-Cybozu-API-Token", &api_token)
-            .query(&[
-                ("app", app_id.to_string()),
-                ("query", query),
-            ])
-            .send()
-            .await?;
-
-        let json: serde_json::Value = response.json().await?;
+        let json = self.send_idempotent("search_secondary", || {
+            self.client
+                .get(&search_url)
+                .header("X-Cybozu-API-Token", &api_token)
+                .query(&[
+                    ("app", app_id.to_string()),
+                    ("query", query.clone()),
+                ])
+        }).await?;
+
         let empty_vec = vec![];
         let records = json["records"].as_array().unwrap_or(&empty_vec);
 
-        if let Some(existing_record) = records.first() {
-            // 既存レコードがある場合
-            let record_id = existing_record["レコード番号"]["value"]
-                .as_str()
-                .and_then(|s| s.parse::<u32>().ok())
-                .ok_or_else(|| anyhow::anyhow!("レコードIDの取得に失敗"))?;
+        let Some(existing_record) = records.first() else {
+            // 既存レコードがない場合は新規作成（登録しない指定・削除なら何もしない）
+            let SecondaryWrite::Upsert { record, create_if_missing: true } = write else {
+                eprintln!("=== No {} record for {} ===", app_label, schedule_number);
+                return Ok(SecondaryOutcome::NotFound);
+            };
+
+            let add_url = format!("{}/record.json", self.base_url());
+            let body = serde_json::json!({
+                "app": app_id,
+                "record": record
+            });
 
-            let status = existing_record["生産状況"]["value"].as_str().unwrap_or("");
+            eprintln!("=== Adding new {} record ===", app_label);
 
-            if status == "未生産" {
-                // 未生産なら更新
+            self.send("add_secondary", || {
+                self.client
+                    .post(&add_url)
+                    .header("X-Cybozu-API-Token", &api_token)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .json(&body)
+            }).await?;
+
+            eprintln!("=== Added successfully ===");
+            return Ok(SecondaryOutcome::Added);
+        };
+
+        // 既存レコードがある場合
+        let record_id: u32 = parse_id(&existing_record["$id"]["value"])
+            .ok_or_else(|| KintoneError::InvalidResponse("レコードIDの取得に失敗".to_string()))?;
+
+        let status = existing_record[status_field]["value"].as_str().unwrap_or("");
+        if status != "未生産" {
+            // 生産が進んでいるレコードは変更しない
+            eprintln!("=== Skipped (status: {}) ===", status);
+            return Ok(SecondaryOutcome::Skipped { status: status.to_string() });
+        }
+
+        match write {
+            SecondaryWrite::Upsert { record, .. } => {
                 let update_url = format!("{}/record.json", self.base_url());
                 let body = serde_json::json!({
                     "app": app_id,
                     "id": record_id,
-                    "record": record_data
+                    "record": record
                 });
 
-                eprintln!("=== Updating {} record: {} ===", if is_kobukuro { "ID368" } else { "ID354" }, record_id);
-
-                let response = self.client
-                    .put(&update_url)
-                    .header("X-Cybozu-API-Token", &api_token)
-                    .header(header::CONTENT_TYPE, "application/json")
-                    .json(&body)
-                    .send()
-                    .await?;
+                eprintln!("=== Updating {} record: {} ===", app_label, record_id);
 
-                if !response.status().is_success() {
-                    let text = response.text().await?;
-                    return Err(anyhow::anyhow!("更新に失敗しました: {}", text));
-                }
+                self.send("update_secondary", || {
+                    self.client
+                        .put(&update_url)
+                        .header("X-Cybozu-API-Token", &api_token)
+                        .header(header::CONTENT_TYPE, "application/json")
+                        .json(&body)
+                }).await?;
 
                 eprintln!("=== Updated successfully ===");
-            } else {
-                eprintln!("=== Skipped (status: {}) ===", status);
+                Ok(SecondaryOutcome::Updated)
             }
-        } else {
-            // 既存レコードがない場合は新規作成
-            let add_url = format!("{}/record.json", self.base_url());
-            let body = serde_json::json!({
-                "app": app_id,
-                "record": record_data
-            });
+            SecondaryWrite::Delete => {
+                let delete_url = format!("{}/records.json", self.base_url());
+                let body = serde_json::json!({
+                    "app": app_id,
+                    "ids": [record_id]
+                });
 
-            eprintln!("=== Adding new {} record ===", if is_kobukuro { "ID368" } else { "ID354" });
+                eprintln!("=== Deleting {} record: {} ===", app_label, record_id);
 
-            let response = self.client
-                .post(&add_url)
-                .header("X-Cybozu-API-Token", &api_token)
-                .header(header::CONTENT_TYPE, "application/json")
-                .json(&body)
-                .send()
-                .await?;
+                self.send_idempotent("delete_secondary", || {
+                    self.client
+                        .delete(&delete_url)
+                        .header("X-Cybozu-API-Token", &api_token)
+                        .header(header::CONTENT_TYPE, "application/json")
+                        .json(&body)
+                }).await?;
 
-            if !response.status().is_success() {
-                let text = response.text().await?;
-                return Err(anyhow::anyhow!("新規作成に失敗しました: {}", text));
+                eprintln!("=== Deleted successfully ===");
+                Ok(SecondaryOutcome::Deleted)
             }
-
-            eprintln!("=== Added successfully ===");
         }
-
-        Ok(())
     }
-}
-
+}
\ No newline at end of file
@@ -1,46 +1,30 @@
 //! 生産計画スケジューラー
 //! kintone連携対応のデスクトップアプリケーション
 
+mod audit;
+mod balance;
 mod kintone_client;
 mod database;
 mod commands;
+mod credentials;
+mod downstream;
+mod field_mapping;
+mod schema_check;
+mod mrp;
+mod scheduling;
+mod calendar;
+mod reschedule;
+mod changeover;
+mod optimizer;
+mod scenario;
+mod undo;
+mod products;
+mod validation;
 
 use std::sync::Mutex;
 use commands::AppState;
 use database::Database;
-use kintone_client::{KintoneClient, KintoneConfig};
-
-/// kintone接続設定を読み込み（ファイルがあればそこから、なければデフォルト）
-fn load_kintone_config() -> KintoneConfig {
-    // まずファイルから読み込みを試みる
-    if let Some(config_dir) = dirs::data_local_dir() {
-        let config_path = config_dir.join("production-scheduler").join("kintone_config.json");
-        if config_path.exists() {
-            if let Ok(content) = std::fs::read_to_string(&config_path) {
-                if let Ok(config) = serde_json::from_str::<KintoneConfig>(&content) {
-                    eprintln!("設定ファイルから読み込みました: {:?}", config_path);
-                    return config;
-                }
-            }
-        }
-    }
-    
-    // ファイルがなければデフォルト値
-    eprintln!("デフォルト設定を使用します");
-    KintoneConfig {
-        subdomain: "jfe-rockfiber".to_string(),
-        app_id: 506,
-        api_token: "3CakeA8SORFDrOawAcL3Y2UEY8TogZkLw52U5RBo".to_string(),
-        memo_app_id: Some(507),
-        memo_api_token: Some("hkVvZfY6j5dgNSda9OE8WPnLefezfrIoGsR387gL".to_string()),
-        yamazumi_app_id: Some(354),
-        yamazumi_api_token: Some("Qig2MiwdI0McEcbPZNbP2ORkg3UQoB15wx6bBJqC".to_string()),
-        kobukuro_app_id: Some(368),
-        kobukuro_api_token: Some("4U3hAsfb1bLbww5XT0ppcz4f9AcdOmp1SLIfyAIS".to_string()),
-        tsumikomi_app_id: Some(514),
-        tsumikomi_api_token: Some("nU2EcpjY1f7CQxKNs0PoPCnRRcdpl2xgnlK4GCOA".to_string()),
-    }
-}
+use kintone_client::KintoneClient;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -57,13 +41,37 @@ pub fn run() {
     let db = Database::open(db_path.to_str().unwrap())
         .expect("データベースの初期化に失敗しました");
 
-    // kintoneクライアントを初期化（ファイルから読み込み）
-    let kintone_client = KintoneClient::new(load_kintone_config())
-        .expect("kintoneクライアントの初期化に失敗しました");
+    // 保存期間を過ぎたゴミ箱のスケジュールを完全に削除
+    match db.purge_deleted_schedules(database::TRASH_RETENTION_DAYS) {
+        Ok(0) => {}
+        Ok(count) => eprintln!("=== ゴミ箱から{}件を完全に削除しました ===", count),
+        Err(e) => eprintln!("ゴミ箱の整理に失敗しました: {}", e),
+    }
+
+    // kintoneクライアントを初期化（未設定なら初回設定で作成）
+    let kintone_client = match credentials::load_kintone_config() {
+        Some(config) if credentials::is_configured(&config) => {
+            eprintln!("kintone設定: {:?}", config);
+            Some(KintoneClient::new(config).expect("kintoneクライアントの初期化に失敗しました"))
+        }
+        _ => {
+            eprintln!("kintone設定が未設定です（初回設定が必要）");
+            None
+        }
+    };
+
+    // kintoneアプリのフィールド対応付け（データフォルダのmappings/*.jsonで上書きできる）
+    let field_mappings = field_mapping::FieldMappings::load(&credentials::app_data_dir());
+    for warning in &field_mappings.warnings {
+        eprintln!("フィールド対応付けを読み込めませんでした: {}", warning);
+    }
 
     let state = AppState {
         db: Mutex::new(db),
-        kintone_client: Mutex::new(Some(kintone_client)),
+        kintone_client: Mutex::new(kintone_client),
+        undo: Mutex::new(undo::UndoStack::default()),
+        balance: Mutex::new(None),
+        field_mappings,
     };
 
     // ウィンドウタイトルをモードに応じて設定
@@ -85,12 +93,68 @@ pub fn run() {
             commands::add_schedule_with_kintone_sync,
             commands::update_schedule,
             commands::save_kintone_config,
+            commands::get_kintone_config,
             commands::fetch_from_kintone,
+            commands::migrate_from_legacy_app,
             commands::sync_to_kintone,
             commands::get_product_weight,
+            commands::calculate_schedule_duration,
+            commands::get_plant_calendar,
+            commands::add_shift_pattern,
+            commands::delete_shift_pattern,
+            commands::add_holiday,
+            commands::delete_holiday,
+            commands::add_maintenance_window,
+            commands::delete_maintenance_window,
+            commands::get_working_windows,
+            commands::get_schedule_overlaps,
+            commands::reschedule_line,
+            commands::get_changeovers,
+            commands::add_changeover,
+            commands::update_changeover,
+            commands::delete_changeover,
+            commands::get_changeover_blocks,
+            commands::optimize_orders,
+            commands::commit_draft_plan,
+            commands::get_scenarios,
+            commands::create_scenario,
+            commands::get_scenario_schedules,
+            commands::save_scenario_schedule,
+            commands::add_scenario_schedule,
+            commands::remove_scenario_schedule,
+            commands::revert_scenario_change,
+            commands::diff_scenario,
+            commands::discard_scenario,
+            commands::promote_scenario,
+            commands::get_products,
+            commands::add_product,
+            commands::update_product,
+            commands::set_product_active,
+            commands::import_products_csv,
+            commands::export_products_csv,
             commands::delete_schedule,
+            commands::get_trash,
+            commands::get_downstream_status,
+            commands::restore_deleted_schedule,
+            commands::purge_deleted_schedule,
+            commands::purge_trash,
             commands::get_app_mode,
+            commands::undo,
+            commands::redo,
+            commands::get_undo_state,
+            commands::get_schedule_history,
+            commands::get_activity_feed,
             commands::fetch_kintone_records,
+            commands::get_balance_projection,
+            commands::get_stock_alerts,
+            commands::get_stock_levels,
+            commands::save_stock_levels,
+            commands::suggest_production_orders,
+            commands::accept_production_suggestions,
+            commands::check_kintone_schema,
+            commands::get_schema_version,
+            commands::get_sync_conflicts,
+            commands::resolve_sync_conflict,
         ])
         .run(tauri::generate_context!())
         .expect("アプリケーションの実行中にエラーが発生しました");
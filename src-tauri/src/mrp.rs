@@ -0,0 +1,199 @@
+//! 生産提案（MRP）
+//! 需給予測で在庫が安全在庫を下回る日について、生産数量と開始日時を提案する
+//!
+//! - 不足数 = 安全在庫（未設定なら0） − 在庫予測（それより前の提案分を加えたもの）
+//! - 生産数量は不足数を生産ロットの倍数に切り上げる
+//! - 不足する生産日の開始（06:00）までに生産を終える。生産時間は稼働カレンダーで逆算し、
+//!   同じラインの既存スケジュール・他の提案と重ならない最も遅い開始日時を選ぶ
+//! - 現在からでは間に合わない場合は、現在以降で空いている最初の時間に置き、遅れとして示す
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::balance::ProductBalance;
+use crate::calendar::{self, PlantCalendar, Window};
+use crate::database::StockLevel;
+
+/// 不足とみなさない誤差
+const EPSILON: f64 = 1e-6;
+
+/// 在庫を補充する必要のある日と数量
+#[derive(Debug, Clone, PartialEq)]
+pub struct Requirement {
+    pub product: String,
+    /// 安全在庫を下回る日
+    pub date: NaiveDate,
+    /// それより前の提案分を加えた日末の在庫予測
+    pub projected: f64,
+    /// 補充の目標（安全在庫）
+    pub target: f64,
+    /// 生産数量（不足数を生産ロットの倍数に切り上げたもの）
+    pub quantity: f64,
+}
+
+/// 提案する生産時間帯
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slot {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    /// 不足する日までに間に合わない
+    pub late: bool,
+}
+
+/// 不足数を生産ロットの倍数に切り上げる（ロット未設定なら整数に切り上げる）
+pub fn round_up_to_lot(shortfall: f64, lot_size: Option<f64>) -> f64 {
+    match lot_size.filter(|lot| lot.is_finite() && *lot > 0.0) {
+        Some(lot) => ((shortfall - EPSILON) / lot).ceil().max(1.0) * lot,
+        None => shortfall.ceil(),
+    }
+}
+
+/// 在庫予測が安全在庫を下回る日ごとに必要な生産数量を求める
+/// 前の日の提案で補充した分は以降の日の在庫予測に加える
+pub fn requirements(balance: &ProductBalance, level: Option<&StockLevel>) -> Vec<Requirement> {
+    let target = level.and_then(|l| l.safety_stock).unwrap_or(0.0);
+    let lot_size = level.and_then(|l| l.lot_size);
+
+    let mut added = 0.0;
+    let mut result = Vec::new();
+    for day in &balance.days {
+        let projected = day.closing + added;
+        if projected >= target - EPSILON {
+            continue;
+        }
+        let Some(date) = calendar::parse_date(&day.date) else { continue };
+        let quantity = round_up_to_lot(target - projected, lot_size);
+        added += quantity;
+        result.push(Requirement {
+            product: balance.product.clone(),
+            date,
+            projected,
+            target,
+            quantity,
+        });
+    }
+    result
+}
+
+/// 時間帯と重なる使用中の時間帯
+fn conflicts(busy: &[Window], start: NaiveDateTime, end: NaiveDateTime) -> impl Iterator<Item = &Window> {
+    busy.iter().filter(move |(s, e)| *s < end && start < *e)
+}
+
+/// 期限までに終わる最も遅い生産時間帯（使用中の時間帯と重ならない）
+/// earliest より前に始めないと間に合わない場合は、earliest 以降の最初の空き時間帯（遅れ）を返す
+pub fn latest_slot(
+    calendar: &PlantCalendar,
+    due: NaiveDateTime,
+    minutes: i64,
+    line: &str,
+    busy: &[Window],
+    earliest: NaiveDateTime,
+) -> Option<Slot> {
+    let mut deadline = due;
+    for _ in 0..=busy.len() {
+        let Some(start) = calendar.subtract_working_minutes(deadline, minutes, line) else { break };
+        if start < earliest {
+            break;
+        }
+        let end = calendar.add_working_minutes(start, minutes, line)?;
+        // 重なる時間帯より後ろに終わる時間帯はすべて重なるため、最も早く始まるものの手前まで戻す
+        match conflicts(busy, start, end).map(|(s, _)| *s).min() {
+            Some(blocked) => deadline = blocked,
+            None => return Some(Slot { start, end, late: false }),
+        }
+    }
+
+    let mut ready = earliest;
+    for _ in 0..=busy.len() {
+        let start = calendar.next_working_time(ready, line)?;
+        let end = calendar.add_working_minutes(start, minutes, line)?;
+        match conflicts(busy, start, end).map(|(_, e)| *e).max() {
+            Some(released) => ready = released,
+            None => return Some(Slot { start, end, late: end > due }),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::balance::BalanceDay;
+
+    fn at(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn balance(closings: &[f64]) -> ProductBalance {
+        let days = closings
+            .iter()
+            .enumerate()
+            .map(|(i, closing)| BalanceDay {
+                date: format!("2026-03-{:02}", i + 1),
+                opening: 0.0,
+                production: 0.0,
+                shipping: 0.0,
+                closing: *closing,
+            })
+            .collect();
+        ProductBalance { product: "FS450D".to_string(), opening_stock: 0.0, days }
+    }
+
+    fn level(safety_stock: f64, lot_size: Option<f64>) -> StockLevel {
+        StockLevel { product: "FS450D".to_string(), safety_stock: Some(safety_stock), max_stock: None, lot_size }
+    }
+
+    #[test]
+    fn rounds_up_to_lot() {
+        assert_eq!(round_up_to_lot(250.0, Some(100.0)), 300.0);
+        assert_eq!(round_up_to_lot(200.0, Some(100.0)), 200.0);
+        assert_eq!(round_up_to_lot(0.5, Some(100.0)), 100.0);
+        assert_eq!(round_up_to_lot(12.3, None), 13.0);
+        assert_eq!(round_up_to_lot(12.3, Some(0.0)), 13.0);
+    }
+
+    #[test]
+    fn carries_earlier_suggestions_into_later_days() {
+        // 2日目: 80 → 不足20 → 50、3日目: 40 + 50 = 90 → 不足10 → 50、4日目: 90 + 100 で足りる
+        let result = requirements(&balance(&[120.0, 80.0, 40.0, 90.0]), Some(&level(100.0, Some(50.0))));
+        let summary: Vec<(String, f64, f64)> = result
+            .iter()
+            .map(|r| (r.date.to_string(), r.projected, r.quantity))
+            .collect();
+        assert_eq!(summary, vec![
+            ("2026-03-02".to_string(), 80.0, 50.0),
+            ("2026-03-03".to_string(), 90.0, 50.0),
+        ]);
+    }
+
+    #[test]
+    fn no_requirements_without_shortfall() {
+        assert!(requirements(&balance(&[5.0, 0.0]), None).is_empty());
+        assert_eq!(requirements(&balance(&[-3.0]), None)[0].quantity, 3.0);
+    }
+
+    #[test]
+    fn picks_latest_slot_before_due() {
+        let calendar = PlantCalendar::default();
+        let slot = latest_slot(&calendar, at("2026-03-02 06:00"), 120, "A", &[], at("2026-03-01 00:00")).unwrap();
+        assert_eq!(slot, Slot { start: at("2026-03-02 04:00"), end: at("2026-03-02 06:00"), late: false });
+    }
+
+    #[test]
+    fn blocked_window_pushes_slot_earlier() {
+        let calendar = PlantCalendar::default();
+        let busy = [(at("2026-03-02 04:00"), at("2026-03-02 05:00")), (at("2026-03-02 01:00"), at("2026-03-02 02:30"))];
+        // 04:00〜06:00 は1件目と重なり、02:00〜04:00 は2件目と重なるため 23:00〜01:00 になる
+        let slot = latest_slot(&calendar, at("2026-03-02 06:00"), 120, "A", &busy, at("2026-03-01 00:00")).unwrap();
+        assert_eq!(slot, Slot { start: at("2026-03-01 23:00"), end: at("2026-03-02 01:00"), late: false });
+    }
+
+    #[test]
+    fn falls_back_to_first_free_slot_when_late() {
+        let calendar = PlantCalendar::default();
+        let busy = [(at("2026-03-02 05:00"), at("2026-03-02 06:00"))];
+        // 05:00 からでは期限（06:00）までに120分取れないため、空いている最初の時間に遅れとして置く
+        let slot = latest_slot(&calendar, at("2026-03-02 06:00"), 120, "A", &busy, at("2026-03-02 05:00")).unwrap();
+        assert_eq!(slot, Slot { start: at("2026-03-02 06:00"), end: at("2026-03-02 08:00"), late: true });
+    }
+}
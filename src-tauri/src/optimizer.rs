@@ -0,0 +1,234 @@
+//! 生産順序の自動計画
+//! 未計画の受注をラインに割り付け、段取り替え時間と納期遅れが小さくなる順序と開始日時を求める
+//!
+//! 納期順の貪欲法で初期解を作り、移動・入れ替えの局所探索で改善する。
+//! 時間は稼働カレンダーの稼働時間で数えるため、シフト外・休日・保全には割り付けない
+
+use chrono::NaiveDateTime;
+
+use crate::calendar::PlantCalendar;
+use crate::changeover::ChangeoverMatrix;
+use crate::scheduling::SchedulingError;
+
+/// 納期遅れ1分あたりの重み（段取り時間1分を1とする）
+const LATENESS_WEIGHT: i64 = 2;
+
+/// 局所探索の最大反復回数
+pub const DEFAULT_MAX_ITERATIONS: usize = 200;
+
+/// 計画対象の作業（生産時間は算出済み）
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub product_name: String,
+    /// 生産時間（稼働時間の分）
+    pub minutes: i64,
+    pub due: Option<NaiveDateTime>,
+    /// 割り付け可能なライン（空なら全ライン）
+    pub lines: Vec<String>,
+}
+
+/// 計画開始時点のラインの状態
+#[derive(Debug, Clone)]
+pub struct LineState {
+    pub line: String,
+    /// これ以降なら割り付けられる日時
+    pub ready: NaiveDateTime,
+    /// 直前に生産している製品（段取り替えの判定用）
+    pub last_product: Option<String>,
+}
+
+/// 作業1件の割り付け結果
+#[derive(Debug, Clone)]
+pub struct Placement {
+    /// jobsのインデックス
+    pub job: usize,
+    pub line: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub changeover_minutes: i64,
+    pub lateness_minutes: i64,
+}
+
+/// 計画の評価値（小さいほど良い）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Cost {
+    penalty: i64,
+    finish: NaiveDateTime,
+}
+
+/// ラインごとの作業順
+type Sequence = Vec<Vec<usize>>;
+
+struct Planner<'a> {
+    jobs: &'a [Job],
+    lines: &'a [LineState],
+    calendar: &'a PlantCalendar,
+    matrix: &'a ChangeoverMatrix,
+}
+
+impl Planner<'_> {
+    /// 作業を割り付けられるか
+    fn allowed(&self, job: usize, line: usize) -> bool {
+        let lines = &self.jobs[job].lines;
+        lines.is_empty() || lines.iter().any(|l| l.trim() == self.lines[line].line.trim())
+    }
+
+    /// 稼働時間で分数を進めた日時（稼働中の時刻に合わせる）
+    fn advance(&self, from: NaiveDateTime, minutes: i64, line: &str) -> Result<NaiveDateTime, SchedulingError> {
+        let no_working_time = || SchedulingError::NoWorkingTime(from.format("%Y-%m-%d %H:%M").to_string());
+        let moved = self.calendar.add_working_minutes(from, minutes, line).ok_or_else(no_working_time)?;
+        self.calendar.next_working_time(moved, line).ok_or_else(no_working_time)
+    }
+
+    /// 作業順から開始・終了日時を求めて評価
+    fn simulate(&self, sequence: &Sequence) -> Result<(Cost, Vec<Placement>), SchedulingError> {
+        let mut placements = Vec::new();
+        let mut penalty = 0;
+        let mut finish = NaiveDateTime::MIN;
+
+        for (state, jobs) in self.lines.iter().zip(sequence) {
+            let line = state.line.as_str();
+            let mut time = state.ready;
+            let mut prev = state.last_product.as_deref();
+
+            for &index in jobs {
+                let job = &self.jobs[index];
+                let changeover = prev.map_or(0, |p| self.matrix.minutes(p, &job.product_name, line));
+                let start = self.advance(time, changeover, line)?;
+                let end = self
+                    .calendar
+                    .add_working_minutes(start, job.minutes, line)
+                    .ok_or_else(|| SchedulingError::NoWorkingTime(start.format("%Y-%m-%d %H:%M").to_string()))?;
+                let lateness = job.due.map_or(0, |due| (end - due).num_minutes().max(0));
+
+                penalty += changeover + LATENESS_WEIGHT * lateness;
+                finish = finish.max(end);
+                placements.push(Placement {
+                    job: index,
+                    line: state.line.clone(),
+                    start,
+                    end,
+                    changeover_minutes: changeover,
+                    lateness_minutes: lateness,
+                });
+
+                time = end;
+                prev = Some(&job.product_name);
+            }
+        }
+
+        Ok((Cost { penalty, finish }, placements))
+    }
+
+    fn cost(&self, sequence: &Sequence) -> Result<Cost, SchedulingError> {
+        self.simulate(sequence).map(|(cost, _)| cost)
+    }
+
+    /// 納期の早い順に、評価値が最も小さくなるラインの末尾へ割り付ける
+    fn greedy(&self, jobs: &[usize]) -> Result<Sequence, SchedulingError> {
+        let mut order = jobs.to_vec();
+        order.sort_by_key(|&i| (self.jobs[i].due.is_none(), self.jobs[i].due, -self.jobs[i].minutes));
+
+        let mut sequence: Sequence = vec![Vec::new(); self.lines.len()];
+        for job in order {
+            let mut best: Option<(Cost, usize)> = None;
+            for line in (0..self.lines.len()).filter(|&l| self.allowed(job, l)) {
+                sequence[line].push(job);
+                let cost = self.cost(&sequence)?;
+                sequence[line].pop();
+                if best.is_none_or(|(b, _)| cost < b) {
+                    best = Some((cost, line));
+                }
+            }
+            if let Some((_, line)) = best {
+                sequence[line].push(job);
+            }
+        }
+        Ok(sequence)
+    }
+
+    /// 作業の移動（別の位置・別のライン）と入れ替えで改善できる限り繰り返す
+    fn improve(&self, mut sequence: Sequence, max_iterations: usize) -> Result<Sequence, SchedulingError> {
+        let mut best = self.cost(&sequence)?;
+
+        for _ in 0..max_iterations {
+            match self.find_better(&sequence, best)? {
+                Some((candidate, cost)) => {
+                    sequence = candidate;
+                    best = cost;
+                }
+                None => break,
+            }
+        }
+        Ok(sequence)
+    }
+
+    /// 現在より評価値の小さい近傍解を1つ探す
+    fn find_better(&self, sequence: &Sequence, best: Cost) -> Result<Option<(Sequence, Cost)>, SchedulingError> {
+        let positions: Vec<(usize, usize)> = sequence
+            .iter()
+            .enumerate()
+            .flat_map(|(line, jobs)| (0..jobs.len()).map(move |pos| (line, pos)))
+            .collect();
+
+        // 移動
+        for &(from_line, from_pos) in &positions {
+            let job = sequence[from_line][from_pos];
+            for to_line in (0..self.lines.len()).filter(|&l| self.allowed(job, l)) {
+                let mut candidate = sequence.clone();
+                candidate[from_line].remove(from_pos);
+                for to_pos in 0..=candidate[to_line].len() {
+                    if to_line == from_line && to_pos == from_pos {
+                        continue;
+                    }
+                    candidate[to_line].insert(to_pos, job);
+                    let cost = self.cost(&candidate)?;
+                    if cost < best {
+                        return Ok(Some((candidate, cost)));
+                    }
+                    candidate[to_line].remove(to_pos);
+                }
+            }
+        }
+
+        // 入れ替え
+        for (i, &(a_line, a_pos)) in positions.iter().enumerate() {
+            for &(b_line, b_pos) in &positions[i + 1..] {
+                let (a, b) = (sequence[a_line][a_pos], sequence[b_line][b_pos]);
+                if !self.allowed(a, b_line) || !self.allowed(b, a_line) {
+                    continue;
+                }
+                let mut candidate = sequence.clone();
+                candidate[a_line][a_pos] = b;
+                candidate[b_line][b_pos] = a;
+                let cost = self.cost(&candidate)?;
+                if cost < best {
+                    return Ok(Some((candidate, cost)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// 作業をラインに割り付けて開始・終了日時を求める
+/// どのラインにも割り付けられない作業は結果に含まれない
+pub fn optimize(
+    jobs: &[Job],
+    lines: &[LineState],
+    calendar: &PlantCalendar,
+    matrix: &ChangeoverMatrix,
+    max_iterations: usize,
+) -> Result<Vec<Placement>, SchedulingError> {
+    let planner = Planner { jobs, lines, calendar, matrix };
+    let placeable: Vec<usize> = (0..jobs.len())
+        .filter(|&job| (0..lines.len()).any(|line| planner.allowed(job, line)))
+        .collect();
+
+    let sequence = planner.greedy(&placeable)?;
+    let sequence = planner.improve(sequence, max_iterations)?;
+    let (_, mut placements) = planner.simulate(&sequence)?;
+    placements.sort_by(|a, b| (&a.line, a.start).cmp(&(&b.line, b.start)));
+    Ok(placements)
+}
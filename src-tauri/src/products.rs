@@ -0,0 +1,156 @@
+//! 製品マスタの入力チェックとCSV入出力
+
+use crate::database::Product;
+use crate::scheduling;
+
+/// 製品の分類
+pub const CATEGORIES: &[&str] = &["ベーラー", "小袋"];
+
+/// CSVの列見出し（出力時の順序）
+const CSV_HEADERS: &[&str] = &["品番", "品名", "分類", "重量kg", "既定能率", "有効"];
+
+/// Excelで文字化けしないよう先頭に付けるBOM
+const UTF8_BOM: &str = "\u{feff}";
+
+/// 製品の入力値をチェックして正規化
+pub fn validate_product(product: &mut Product) -> Result<(), String> {
+    product.product_name = product.product_name.trim().to_string();
+    if product.product_name.is_empty() {
+        return Err("品番は必須です".to_string());
+    }
+
+    if !product.weight_kg.is_finite() || product.weight_kg <= 0.0 {
+        return Err(format!("{}: 重量は0より大きい値を入力してください", product.product_name));
+    }
+
+    product.display_name = normalize(product.display_name.take());
+    product.category = normalize(product.category.take());
+    if let Some(ref category) = product.category {
+        if !CATEGORIES.contains(&category.as_str()) {
+            return Err(format!("{}: 分類は「ベーラー」または「小袋」です（{}）", product.product_name, category));
+        }
+    }
+
+    product.default_efficiency = normalize(product.default_efficiency.take());
+    if let Some(ref efficiency) = product.default_efficiency {
+        let value = scheduling::parse_efficiency(efficiency)
+            .ok_or_else(|| format!("{}: 既定能率の値が不正です（{}）", product.product_name, efficiency))?;
+        product.default_efficiency = Some(format!("{:.2}", value));
+    }
+
+    Ok(())
+}
+
+/// 空文字をNoneにする
+fn normalize(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// 有効フラグの表記を解釈（空欄は有効）
+fn parse_active(value: &str) -> Option<bool> {
+    match value.trim() {
+        "" | "1" | "有効" | "true" | "TRUE" | "○" => Some(true),
+        "0" | "無効" | "false" | "FALSE" | "×" => Some(false),
+        _ => None,
+    }
+}
+
+/// 製品マスタをCSVに変換
+pub fn products_to_csv(products: &[Product]) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(CSV_HEADERS).map_err(|e| e.to_string())?;
+
+    for product in products {
+        writer
+            .write_record([
+                product.product_name.clone(),
+                product.display_name.clone().unwrap_or_default(),
+                product.category.clone().unwrap_or_default(),
+                product.weight_kg.to_string(),
+                product.default_efficiency.clone().unwrap_or_default(),
+                if product.active { "1" } else { "0" }.to_string(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    let body = String::from_utf8(bytes).map_err(|e| e.to_string())?;
+    Ok(format!("{}{}", UTF8_BOM, body))
+}
+
+/// CSVを製品マスタに変換
+/// 1行目は見出し行（品番・重量kgは必須列）。エラーのある行は行番号付きで返す
+pub fn parse_products_csv(content: &str) -> (Vec<Product>, Vec<String>) {
+    let content = content.trim_start_matches(UTF8_BOM);
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+
+    let mut products = Vec::new();
+    let mut errors = Vec::new();
+
+    let headers = match reader.headers() {
+        Ok(h) => h.clone(),
+        Err(e) => {
+            errors.push(format!("見出し行を読み込めません: {}", e));
+            return (products, errors);
+        }
+    };
+    let column = |name: &str| headers.iter().position(|h| h == name);
+    let (Some(name_col), Some(weight_col)) = (column("品番"), column("重量kg")) else {
+        errors.push("見出し行に「品番」「重量kg」の列が必要です".to_string());
+        return (products, errors);
+    };
+    let display_col = column("品名");
+    let category_col = column("分類");
+    let efficiency_col = column("既定能率");
+    let active_col = column("有効");
+
+    for (i, record) in reader.records().enumerate() {
+        // 見出し行が1行目
+        let line = i + 2;
+        let record = match record {
+            Ok(r) => r,
+            Err(e) => {
+                errors.push(format!("{}行目: {}", line, e));
+                continue;
+            }
+        };
+        let cell = |col: Option<usize>| col.and_then(|c| record.get(c)).unwrap_or("").to_string();
+
+        let product_name = cell(Some(name_col));
+        if product_name.is_empty() && record.iter().all(|v| v.is_empty()) {
+            continue;
+        }
+
+        let weight_kg = match cell(Some(weight_col)).parse::<f64>() {
+            Ok(w) => w,
+            Err(_) => {
+                errors.push(format!("{}行目: 重量kgが数値ではありません", line));
+                continue;
+            }
+        };
+        let Some(active) = parse_active(&cell(active_col)) else {
+            errors.push(format!("{}行目: 有効は 1 / 0 で入力してください", line));
+            continue;
+        };
+
+        let mut product = Product {
+            id: None,
+            product_name,
+            display_name: Some(cell(display_col)),
+            category: Some(cell(category_col)),
+            weight_kg,
+            default_efficiency: Some(cell(efficiency_col)),
+            active,
+            updated_at: None,
+        };
+        match validate_product(&mut product) {
+            Ok(()) => products.push(product),
+            Err(e) => errors.push(format!("{}行目: {}", line, e)),
+        }
+    }
+
+    (products, errors)
+}
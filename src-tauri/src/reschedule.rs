@@ -0,0 +1,140 @@
+//! ラインの連鎖リスケジュール
+//! スケジュールを移動・延長したとき、同じラインの後続スケジュールを順序と間隔を保ったまま前後にずらす
+//!
+//! 間隔・生産時間はいずれも稼働時間で数えるため、移動先でも休日・シフト外・保全は飛ばされる
+//! 品種が変わる箇所は段取り替えマトリクスの段取り時間以上の間隔を空ける
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::calendar::PlantCalendar;
+use crate::changeover::ChangeoverMatrix;
+use crate::database::LocalSchedule;
+use crate::scheduling::{self, SchedulingError};
+
+/// スケジュール1件分の移動内容
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleMove {
+    pub schedule_id: i64,
+    pub schedule_number: Option<String>,
+    pub product_name: String,
+    pub old_start: String,
+    pub old_end: Option<String>,
+    pub new_start: String,
+    pub new_end: Option<String>,
+    /// 開始日時の移動量（分、マイナスは前倒し）
+    pub shift_minutes: i64,
+}
+
+/// リスケジュール結果
+#[derive(Debug, Clone, Serialize)]
+pub struct ReschedulePlan {
+    pub line: String,
+    /// 先頭は移動したスケジュール自身。後続は変更のあるものだけ
+    pub moves: Vec<ScheduleMove>,
+    /// DBに反映したか（プレビューならfalse）
+    pub applied: bool,
+}
+
+/// 日時を解釈（不正ならエラー）
+fn parse(value: &str) -> Result<NaiveDateTime, SchedulingError> {
+    scheduling::parse_datetime(value).ok_or_else(|| SchedulingError::InvalidDatetime(value.to_string()))
+}
+
+/// 稼働時間で分数を進めた日時
+fn advance(calendar: &PlantCalendar, from: NaiveDateTime, minutes: i64, line: &str) -> Result<NaiveDateTime, SchedulingError> {
+    let no_working_time = || SchedulingError::NoWorkingTime(from.format("%Y-%m-%d %H:%M").to_string());
+    let moved = calendar.add_working_minutes(from, minutes, line).ok_or_else(no_working_time)?;
+    calendar.next_working_time(moved, line).ok_or_else(no_working_time)
+}
+
+/// 移動内容を作成（元の日時文字列と同じ形式で出力）
+fn build_move(
+    schedule: &LocalSchedule,
+    old_start: NaiveDateTime,
+    new_start: NaiveDateTime,
+    new_end: Option<NaiveDateTime>,
+) -> ScheduleMove {
+    let end_template = schedule.end_datetime.as_deref().unwrap_or(&schedule.start_datetime);
+    ScheduleMove {
+        schedule_id: schedule.id.unwrap_or_default(),
+        schedule_number: schedule.schedule_number.clone(),
+        product_name: schedule.product_name.clone(),
+        old_start: schedule.start_datetime.clone(),
+        old_end: schedule.end_datetime.clone(),
+        new_start: scheduling::format_datetime_like(&schedule.start_datetime, new_start),
+        new_end: new_end.map(|end| scheduling::format_datetime_like(end_template, end)),
+        shift_minutes: (new_start - old_start).num_minutes(),
+    }
+}
+
+/// 移動したスケジュールと、同じラインでそれ以降に始まるスケジュールの移動内容を算出
+/// 終了日時が未指定なら元の生産時間（稼働時間）を保つ
+pub fn plan_line(
+    anchor: &LocalSchedule,
+    new_start: &str,
+    new_end: Option<&str>,
+    schedules: &[LocalSchedule],
+    calendar: &PlantCalendar,
+    matrix: &ChangeoverMatrix,
+) -> Result<Vec<ScheduleMove>, SchedulingError> {
+    let line = anchor.line.trim();
+    let old_start = parse(&anchor.start_datetime)?;
+    let old_end = anchor.end_datetime.as_deref().map(parse).transpose()?;
+
+    let requested_start = parse(new_start)?;
+    let anchor_start = advance(calendar, requested_start, 0, line)?;
+    let anchor_end = match (new_end.filter(|e| !e.trim().is_empty()), old_end) {
+        (Some(end), _) => Some(parse(end)?),
+        (None, Some(end)) => {
+            let minutes = calendar.working_minutes_between(old_start, end, line);
+            Some(calendar.add_working_minutes(anchor_start, minutes, line).ok_or_else(|| {
+                SchedulingError::NoWorkingTime(new_start.to_string())
+            })?)
+        }
+        (None, None) => None,
+    };
+
+    let mut followers: Vec<(NaiveDateTime, &LocalSchedule)> = schedules
+        .iter()
+        .filter(|s| s.id.is_some() && s.id != anchor.id)
+        .filter(|s| s.line.trim() == line && !scheduling::is_fixed_duration(&s.product_name))
+        .filter_map(|s| scheduling::parse_datetime(&s.start_datetime).map(|start| (start, s)))
+        .filter(|(start, _)| *start >= old_start)
+        .collect();
+    followers.sort_by_key(|(start, s)| (*start, s.id));
+
+    let mut moves = vec![build_move(anchor, old_start, anchor_start, anchor_end)];
+    let mut prev_old_end = old_end.unwrap_or(old_start);
+    let mut prev_new_end = anchor_end.unwrap_or(anchor_start);
+    let mut prev_product = anchor.product_name.as_str();
+
+    for (start, schedule) in followers {
+        let end = schedule.end_datetime.as_deref().and_then(scheduling::parse_datetime);
+
+        // 前のスケジュールとの間隔（元から重なっていた場合は詰める）。品種が変わるなら段取り時間以上
+        let gap = calendar
+            .working_minutes_between(prev_old_end, start, line)
+            .max(matrix.minutes(prev_product, &schedule.product_name, line));
+        let moved_start = advance(calendar, prev_new_end, gap, line)?;
+        let moved_end = match end {
+            Some(end) => {
+                let minutes = calendar.working_minutes_between(start, end, line);
+                Some(calendar.add_working_minutes(moved_start, minutes, line).ok_or_else(|| {
+                    SchedulingError::NoWorkingTime(schedule.start_datetime.clone())
+                })?)
+            }
+            None => None,
+        };
+
+        if moved_start != start || moved_end != end {
+            moves.push(build_move(schedule, start, moved_start, moved_end));
+        }
+
+        prev_old_end = prev_old_end.max(end.unwrap_or(start));
+        prev_new_end = moved_end.unwrap_or(moved_start);
+        prev_product = &schedule.product_name;
+    }
+
+    Ok(moves)
+}
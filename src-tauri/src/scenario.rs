@@ -0,0 +1,162 @@
+//! 検討用シナリオ
+//! シナリオは本番スケジュールに対する変更（追加・変更・削除）だけを持ち、
+//! 表示時に本番へ重ねて組み立てる（コピーオンライト）
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::database::{differing_fields, LocalSchedule, ScenarioChange};
+use crate::validation::{self, ScheduleOverlap};
+
+/// 日時・ラインの変更を「移動」として扱うフィールド
+const MOVE_FIELDS: &[&str] = &["line", "start_datetime", "end_datetime"];
+
+/// シナリオ上のスケジュール
+#[derive(Debug, Clone, Serialize)]
+pub struct ScenarioSchedule {
+    /// シナリオ上の変更ID（本番のままならNone）
+    pub change_id: Option<i64>,
+    /// add / modify（本番のままならNone）
+    pub change_type: Option<String>,
+    pub schedule: LocalSchedule,
+}
+
+/// 本番とシナリオで内容が異なるスケジュール
+#[derive(Debug, Clone, Serialize)]
+pub struct ScenarioDiffEntry {
+    pub schedule_id: i64,
+    pub live: LocalSchedule,
+    pub scenario: LocalSchedule,
+    pub fields: Vec<String>,
+}
+
+/// 本番との差分
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScenarioDiff {
+    /// 日時・ラインが変わったスケジュール
+    pub moved: Vec<ScenarioDiffEntry>,
+    /// 日時・ライン以外（数量・製品など）だけが変わったスケジュール
+    pub modified: Vec<ScenarioDiffEntry>,
+    pub added: Vec<LocalSchedule>,
+    pub removed: Vec<LocalSchedule>,
+}
+
+/// 本番スケジュールにシナリオの変更を重ねる
+pub fn apply(live: &[LocalSchedule], changes: &[ScenarioChange]) -> Vec<ScenarioSchedule> {
+    let overrides: HashMap<i64, &ScenarioChange> = changes
+        .iter()
+        .filter_map(|c| c.schedule_id.map(|id| (id, c)))
+        .collect();
+
+    let mut schedules: Vec<ScenarioSchedule> = live
+        .iter()
+        .filter_map(|schedule| {
+            let change = schedule.id.and_then(|id| overrides.get(&id));
+            match change {
+                None => Some(ScenarioSchedule { change_id: None, change_type: None, schedule: schedule.clone() }),
+                Some(c) if c.change_type == "remove" => None,
+                Some(c) => Some(ScenarioSchedule {
+                    change_id: Some(c.id),
+                    change_type: Some(c.change_type.clone()),
+                    // IDと同期情報は本番のものを使う
+                    schedule: c
+                        .data
+                        .clone()
+                        .map(|data| LocalSchedule {
+                            id: schedule.id,
+                            kintone_record_id: schedule.kintone_record_id,
+                            kintone_revision: schedule.kintone_revision,
+                            sync_status: schedule.sync_status.clone(),
+                            ..data
+                        })
+                        .unwrap_or_else(|| schedule.clone()),
+                }),
+            }
+        })
+        .collect();
+
+    schedules.extend(changes.iter().filter(|c| c.change_type == "add").filter_map(|c| {
+        c.data.clone().map(|data| ScenarioSchedule {
+            change_id: Some(c.id),
+            change_type: Some(c.change_type.clone()),
+            schedule: LocalSchedule { id: None, ..data },
+        })
+    }));
+    schedules
+}
+
+/// 本番とシナリオの差分
+pub fn diff(live: &[LocalSchedule], changes: &[ScenarioChange]) -> ScenarioDiff {
+    let live_by_id: HashMap<i64, &LocalSchedule> = live.iter().filter_map(|s| s.id.map(|id| (id, s))).collect();
+    let mut result = ScenarioDiff::default();
+
+    for change in changes {
+        let live_schedule = change.schedule_id.and_then(|id| live_by_id.get(&id).copied());
+        match (change.change_type.as_str(), live_schedule, &change.data) {
+            ("add", _, Some(data)) => result.added.push(data.clone()),
+            ("remove", Some(live), _) => result.removed.push(live.clone()),
+            ("modify", Some(live), Some(data)) => {
+                let fields = differing_fields(live, data);
+                if fields.is_empty() {
+                    continue;
+                }
+                let entry = ScenarioDiffEntry {
+                    schedule_id: live.id.unwrap_or_default(),
+                    live: live.clone(),
+                    scenario: data.clone(),
+                    fields: fields.clone(),
+                };
+                if fields.iter().any(|f| MOVE_FIELDS.contains(&f.as_str())) {
+                    result.moved.push(entry);
+                } else {
+                    result.modified.push(entry);
+                }
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+/// シナリオ作成後に本番側で変更・削除されたスケジュール（昇格すると上書きしてしまうもの）
+pub fn conflicts(live: &[LocalSchedule], changes: &[ScenarioChange]) -> Vec<String> {
+    let live_by_id: HashMap<i64, &LocalSchedule> = live.iter().filter_map(|s| s.id.map(|id| (id, s))).collect();
+
+    changes
+        .iter()
+        .filter_map(|change| {
+            let id = change.schedule_id?;
+            match live_by_id.get(&id) {
+                None => Some(format!("スケジュール {} は本番で削除されています", id)),
+                Some(live) if change.base.as_ref().is_some_and(|base| !differing_fields(base, live).is_empty()) => Some(format!(
+                    "{}（{}）はシナリオ作成後に本番で変更されています",
+                    live.product_name,
+                    live.schedule_number.as_deref().unwrap_or("番号なし")
+                )),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// 本番にシナリオを重ねたとき、シナリオで変更・追加したスケジュールが関わる重複
+/// 追加分には本番のIDがないため、変更IDの負数を仮のIDにして調べる
+pub fn overlaps(live: &[LocalSchedule], changes: &[ScenarioChange]) -> Vec<ScheduleOverlap> {
+    let mut changed = HashSet::new();
+    let schedules: Vec<LocalSchedule> = apply(live, changes)
+        .into_iter()
+        .map(|s| {
+            let id = s.schedule.id.or(s.change_id.map(|c| -c));
+            if s.change_id.is_some() {
+                changed.extend(id);
+            }
+            LocalSchedule { id, ..s.schedule }
+        })
+        .collect();
+
+    validation::find_all_overlaps(&schedules)
+        .into_iter()
+        .filter(|o| o.schedule_id.is_some_and(|id| changed.contains(&id)) || changed.contains(&o.other_id))
+        .collect()
+}
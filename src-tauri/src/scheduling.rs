@@ -0,0 +1,250 @@
+//! 生産時間計算モジュール
+//! 製品重量・生産数量・製綿能率(t/h)からスケジュールの終了日時を算出する
+//!
+//! 生産時間(h) = Σ 生産数量n × 重量(kg) / 1000 / 製綿能率n
+//! 終了日時は稼働カレンダーの稼働時間だけを数えて求める（休日・シフト外・保全は飛ばす）
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::calendar::PlantCalendar;
+
+/// 生産工程の数（生産数量1〜8・製綿能率1〜8）
+pub const MAX_SEGMENTS: usize = 8;
+
+/// 生産時間の計算エラー
+#[derive(Debug, Error)]
+pub enum SchedulingError {
+    #[error("製品 {0} の重量が製品マスタに登録されていません")]
+    UnknownWeight(String),
+
+    #[error("生産数量{0}に対応する製綿能率が未設定です")]
+    MissingEfficiency(usize),
+
+    #[error("製綿能率{0}の値が不正です: {1}")]
+    InvalidEfficiency(usize, String),
+
+    #[error("生産数量{0}の値が不正です: {1}")]
+    InvalidQuantity(usize, f64),
+
+    #[error("日時の形式が不正です: {0}")]
+    InvalidDatetime(String),
+
+    #[error("{0} 以降に稼働時間がありません（稼働カレンダーを確認してください）")]
+    NoWorkingTime(String),
+}
+
+/// 生産数量と製綿能率の組（1工程分）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProductionSegment {
+    pub quantity: f64,
+    /// 製綿能率 (t/h)
+    pub efficiency: f64,
+}
+
+/// 生産時間の計算結果
+#[derive(Debug, Clone, Serialize)]
+pub struct ProductionEstimate {
+    /// 生産時間（時間）
+    pub production_hours: f64,
+    /// 生産時間（分、端数切り捨て）
+    pub production_minutes: i64,
+    /// 生産数量の合計
+    pub total_quantity: f64,
+    /// 算出した終了日時（開始日時が未指定ならNone）
+    pub end_datetime: Option<String>,
+}
+
+/// 終了日時を手入力する製品（メモ・図形）か
+pub fn is_fixed_duration(product_name: &str) -> bool {
+    product_name == "MMO" || product_name == "SHAP"
+}
+
+/// 製綿能率のドロップダウン値を数値化（"5.00" / "5.00t/h" など）
+pub fn parse_efficiency(value: &str) -> Option<f64> {
+    let trimmed = value.trim().trim_end_matches("t/h").trim();
+    trimmed.parse::<f64>().ok().filter(|v| v.is_finite() && *v > 0.0)
+}
+
+/// 生産数量1〜8と製綿能率1〜8から工程を組み立てる
+/// 製綿能率nが未設定の工程は製綿能率1を使う
+pub fn build_segments(
+    quantities: &[Option<f64>],
+    efficiencies: &[Option<String>],
+) -> Result<Vec<ProductionSegment>, SchedulingError> {
+    let fallback = efficiencies.first().cloned().flatten().filter(|e| !e.trim().is_empty());
+    let mut segments = Vec::new();
+
+    for (i, quantity) in quantities.iter().take(MAX_SEGMENTS).enumerate() {
+        let index = i + 1;
+        let quantity = match quantity {
+            Some(q) if *q == 0.0 => continue,
+            Some(q) if !q.is_finite() || *q < 0.0 => return Err(SchedulingError::InvalidQuantity(index, *q)),
+            Some(q) => *q,
+            None => continue,
+        };
+
+        let raw = efficiencies
+            .get(i)
+            .cloned()
+            .flatten()
+            .filter(|e| !e.trim().is_empty())
+            .or_else(|| fallback.clone())
+            .ok_or(SchedulingError::MissingEfficiency(index))?;
+        let efficiency = parse_efficiency(&raw)
+            .ok_or_else(|| SchedulingError::InvalidEfficiency(index, raw.clone()))?;
+
+        segments.push(ProductionSegment { quantity, efficiency });
+    }
+
+    Ok(segments)
+}
+
+/// 生産時間（時間）を算出
+pub fn production_hours(weight_kg: f64, segments: &[ProductionSegment]) -> f64 {
+    segments
+        .iter()
+        .map(|s| s.quantity * weight_kg / 1000.0 / s.efficiency)
+        .sum()
+}
+
+/// 日時文字列をローカル時刻として解釈
+/// kintone形式（"2026-02-02T08:00:00Z"）はローカル時刻に変換する
+pub fn parse_datetime(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.with_timezone(&Local).naive_local());
+    }
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(value, fmt).ok())
+}
+
+/// 元の日時文字列と同じ形式で整形
+pub fn format_datetime_like(template: &str, value: NaiveDateTime) -> String {
+    let template = template.trim();
+    if DateTime::parse_from_rfc3339(template).is_ok() {
+        let utc = Local
+            .from_local_datetime(&value)
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or_else(|| Utc.from_utc_datetime(&value));
+        return utc.format("%Y-%m-%dT%H:%M:00Z").to_string();
+    }
+
+    let separator = if template.contains('T') { "T" } else { " " };
+    let has_seconds = template.matches(':').count() >= 2;
+    let fmt = if has_seconds {
+        format!("%Y-%m-%d{}%H:%M:00", separator)
+    } else {
+        format!("%Y-%m-%d{}%H:%M", separator)
+    };
+    value.format(&fmt).to_string()
+}
+
+/// 開始日時から稼働時間で生産時間を進めた終了日時（分未満は切り捨て）
+pub fn add_working_minutes(
+    start: &str,
+    minutes: i64,
+    calendar: &PlantCalendar,
+    line: &str,
+) -> Result<String, SchedulingError> {
+    let start_dt = parse_datetime(start).ok_or_else(|| SchedulingError::InvalidDatetime(start.to_string()))?;
+    let end_dt = calendar
+        .add_working_minutes(start_dt, minutes, line)
+        .ok_or_else(|| SchedulingError::NoWorkingTime(start.to_string()))?;
+    Ok(format_datetime_like(start, end_dt))
+}
+
+/// 生産時間と終了日時を算出
+pub fn estimate(
+    weight_kg: f64,
+    start_datetime: Option<&str>,
+    quantities: &[Option<f64>],
+    efficiencies: &[Option<String>],
+    calendar: &PlantCalendar,
+    line: &str,
+) -> Result<ProductionEstimate, SchedulingError> {
+    let segments = build_segments(quantities, efficiencies)?;
+    let hours = production_hours(weight_kg, &segments);
+    let minutes = (hours * 60.0 + 1e-9).floor() as i64;
+    let total_quantity = segments.iter().map(|s| s.quantity).sum();
+
+    let end_datetime = match start_datetime.filter(|s| !s.trim().is_empty()) {
+        Some(start) if !segments.is_empty() => Some(add_working_minutes(start, minutes, calendar, line)?),
+        _ => None,
+    };
+
+    Ok(ProductionEstimate {
+        production_hours: hours,
+        production_minutes: minutes,
+        total_quantity,
+        end_datetime,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Holiday;
+
+    fn efficiencies(values: &[&str]) -> Vec<Option<String>> {
+        values.iter().map(|v| Some(v.to_string())).collect()
+    }
+
+    #[test]
+    fn parses_efficiency_values() {
+        assert_eq!(parse_efficiency("5.00"), Some(5.0));
+        assert_eq!(parse_efficiency(" 2.5t/h "), Some(2.5));
+        assert_eq!(parse_efficiency("0"), None);
+        assert_eq!(parse_efficiency("-1"), None);
+        assert_eq!(parse_efficiency("速い"), None);
+    }
+
+    #[test]
+    fn sums_hours_over_segments() {
+        // 10個 × 500kg = 5t を 2.5t/h → 2h、4個 × 500kg = 2t を 1t/h → 2h
+        let segments = build_segments(&[Some(10.0), Some(4.0)], &efficiencies(&["2.5", "1.0"])).unwrap();
+        assert!((production_hours(500.0, &segments) - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn missing_efficiency_falls_back_to_first() {
+        let segments = build_segments(&[Some(10.0), Some(10.0)], &[Some("5.00".to_string()), None]).unwrap();
+        assert_eq!(segments.iter().map(|s| s.efficiency).collect::<Vec<_>>(), vec![5.0, 5.0]);
+    }
+
+    #[test]
+    fn skips_empty_quantities_and_rejects_bad_input() {
+        let segments = build_segments(&[None, Some(0.0), Some(3.0)], &efficiencies(&["1", "1", "1"])).unwrap();
+        assert_eq!(segments, vec![ProductionSegment { quantity: 3.0, efficiency: 1.0 }]);
+
+        assert!(matches!(build_segments(&[Some(1.0)], &[None]), Err(SchedulingError::MissingEfficiency(1))));
+        assert!(matches!(build_segments(&[Some(-1.0)], &efficiencies(&["1"])), Err(SchedulingError::InvalidQuantity(1, _))));
+        assert!(matches!(build_segments(&[Some(1.0)], &efficiencies(&["x"])), Err(SchedulingError::InvalidEfficiency(1, _))));
+    }
+
+    #[test]
+    fn estimates_end_in_start_format() {
+        // 6個 × 250kg = 1.5t を 1t/h → 90分
+        let calendar = PlantCalendar::default();
+        let result = estimate(250.0, Some("2026-02-02 08:00:00"), &[Some(6.0)], &efficiencies(&["1.00"]), &calendar, "A").unwrap();
+        assert_eq!(result.production_minutes, 90);
+        assert_eq!(result.total_quantity, 6.0);
+        assert_eq!(result.end_datetime.as_deref(), Some("2026-02-02 09:30:00"));
+
+        // 開始日時がなければ時間だけ
+        let without_start = estimate(250.0, None, &[Some(6.0)], &efficiencies(&["1.00"]), &calendar, "A").unwrap();
+        assert_eq!(without_start.end_datetime, None);
+    }
+
+    #[test]
+    fn end_skips_holidays() {
+        // 2026-02-03 の生産日（06:00〜翌06:00）が休日なら、その分だけ後ろにずれる
+        let holiday = Holiday { id: None, date: "2026-02-03".to_string(), name: None };
+        let calendar = PlantCalendar::new(&[], &[holiday], &[]);
+        let end = add_working_minutes("2026-02-03T05:00", 120, &calendar, "A").unwrap();
+        assert_eq!(end, "2026-02-04T07:00");
+    }
+}
@@ -0,0 +1,239 @@
+//! kintoneアプリのフォームの確認
+//! 各アプリのフォーム（app/form/fields.json）を、このアプリが読み書きするフィールドと照らし合わせる
+//!
+//! - 確認するフィールドは対応付けファイルのフィールドと、需給バランスで読むフィールド（ID354の在庫・ID514の積込予定）
+//! - フィールドがない・種類が合わないものを問題として報告する
+//! - ドロップダウン・ラジオボタンには、送信する値（ローカルのスケジュールから組み立てたレコードの値・製品マスタの既定能率など）が
+//!   すべて選択肢にあるかを確認する（選択肢にない値を送ると400エラーになる）
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::Serialize;
+
+use crate::balance;
+use crate::database::LocalSchedule;
+use crate::field_mapping::AppMapping;
+use crate::kintone_client::FormField;
+
+/// 問題の一覧に載せる値の最大数
+const MAX_LISTED_VALUES: usize = 10;
+
+/// 文字列として読むフィールドの種類（kintoneのREST APIは数値・計算も文字列で返す）
+const TEXT_READABLE: &[&str] = &[
+    "SINGLE_LINE_TEXT", "MULTI_LINE_TEXT", "RICH_TEXT", "LINK", "DROP_DOWN", "RADIO_BUTTON", "CALC", "NUMBER", "RECORD_NUMBER",
+];
+
+/// 選択肢を持つフィールドの種類
+const CHOICE_TYPES: &[&str] = &["DROP_DOWN", "RADIO_BUTTON"];
+
+/// アプリの表示名
+pub fn app_label(app: &str) -> &'static str {
+    match app {
+        "main" => "ID506（スケジュール）",
+        "memo" => "ID507（メモ・図形）",
+        "yamazumi" => "ID354（山積表）",
+        "kobukuro" => "ID368（小袋実績）",
+        "tsumikomi" => "ID514（積込予定）",
+        "legacy" => "App 351（移行元の旧アプリ）",
+        _ => "不明なアプリ",
+    }
+}
+
+/// このアプリが読み書きするフィールド1件
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpectedField {
+    /// kintoneのフィールドコード
+    pub field: String,
+    /// 用途（ローカルの列名など、問題の説明に使う）
+    pub usage: String,
+    /// 使えるkintoneのフィールドの種類
+    pub types: Vec<&'static str>,
+    /// 送信する値・検索に使う値（選択肢にあるべき値）
+    pub values: BTreeSet<String>,
+}
+
+impl ExpectedField {
+    fn new(field: &str, usage: &str, types: &[&'static str]) -> Self {
+        Self {
+            field: field.to_string(),
+            usage: usage.to_string(),
+            types: types.to_vec(),
+            values: BTreeSet::new(),
+        }
+    }
+
+    /// 同じフィールドの別の用途をまとめる（種類はどちらでも使えるものに絞る）
+    fn merge(&mut self, other: ExpectedField) {
+        if !self.usage.split(", ").any(|u| u == other.usage) {
+            self.usage = format!("{}, {}", self.usage, other.usage);
+        }
+        self.types.retain(|t| other.types.contains(t));
+        self.values.extend(other.values);
+    }
+}
+
+/// 問題の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// フィールドがない
+    Missing,
+    /// フィールドの種類が合わない
+    WrongType,
+    /// 送信する値が選択肢にない
+    MissingOptions,
+}
+
+/// フォームの問題1件
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaIssue {
+    pub field: String,
+    pub kind: IssueKind,
+    pub message: String,
+    /// 選択肢にない値（MissingOptionsのみ）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<String>,
+}
+
+/// アプリ1つ分の確認結果
+#[derive(Debug, Clone, Serialize)]
+pub struct AppSchemaReport {
+    /// 設定上のアプリ名
+    pub app: String,
+    pub label: String,
+    pub app_id: u32,
+    /// 使った対応付けファイル（同梱の既定ならNone）
+    pub mapping_source: Option<String>,
+    /// フォームを取得できなかった理由
+    pub error: Option<String>,
+    pub issues: Vec<SchemaIssue>,
+}
+
+/// 全アプリの確認結果
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaReport {
+    /// 設定済みのアプリごとの結果
+    pub apps: Vec<AppSchemaReport>,
+    /// 読み込めなかった対応付けファイル
+    pub mapping_warnings: Vec<String>,
+    /// 問題の件数（フォームを取得できなかったアプリ・対応付けの警告を含む）
+    pub problem_count: usize,
+}
+
+impl SchemaReport {
+    pub fn new(apps: Vec<AppSchemaReport>, mapping_warnings: Vec<String>) -> Self {
+        let problem_count = mapping_warnings.len()
+            + apps.iter().map(|a| a.issues.len() + usize::from(a.error.is_some())).sum::<usize>();
+        Self { apps, mapping_warnings, problem_count }
+    }
+}
+
+/// 同じフィールドコードの行をまとめて追加
+fn push_expected(expected: &mut Vec<ExpectedField>, field: ExpectedField) {
+    match expected.iter_mut().find(|e| e.field == field.field) {
+        Some(existing) => existing.merge(field),
+        None => expected.push(field),
+    }
+}
+
+/// 対応付けファイルから、読み書きするフィールドと送信する値を求める
+/// schedules はこのアプリに送信するスケジュール、extra_values は列ごとに送信しうる値（製品マスタの既定能率など）
+pub fn mapping_fields(
+    mapping: &AppMapping,
+    schedules: &[&LocalSchedule],
+    extra_values: &HashMap<&str, Vec<String>>,
+) -> Vec<ExpectedField> {
+    let records: Vec<serde_json::Value> = schedules.iter().map(|s| mapping.build_record(s)).collect();
+
+    let mut expected = Vec::new();
+    for f in &mapping.fields {
+        let mut field = ExpectedField::new(&f.field, &f.column, &f.field_type.kintone_types(f.direction));
+        if f.direction.pushes() && !f.field_type.is_number() {
+            let sent = records.iter().filter_map(|r| r[f.field.as_str()]["value"].as_str());
+            let extra = extra_values.get(f.column.as_str()).into_iter().flatten().map(String::as_str);
+            field.values = sent
+                .chain(extra)
+                .chain(f.default.as_deref())
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        push_expected(&mut expected, field);
+    }
+    expected
+}
+
+/// 需給バランスで読むフィールド（ID354の在庫・ID514の積込予定）
+pub fn balance_fields(app: &str) -> Vec<ExpectedField> {
+    match app {
+        "yamazumi" => {
+            let mut status = ExpectedField::new("山状況", "在庫の山状況", TEXT_READABLE);
+            status.values = balance::INVENTORY_STATUSES.iter().map(|s| s.to_string()).collect();
+            vec![
+                ExpectedField::new("品番", "在庫の品番", TEXT_READABLE),
+                status,
+                ExpectedField::new("総個数", "在庫数", TEXT_READABLE),
+                ExpectedField::new("総個数_数値", "一部出荷済の残数", TEXT_READABLE),
+            ]
+        }
+        "tsumikomi" => vec![
+            ExpectedField::new("planDate", "積込予定の開始日", &["DATE"]),
+            ExpectedField::new("scheduleJson", "積込予定の内容", &["MULTI_LINE_TEXT", "SINGLE_LINE_TEXT"]),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+/// 一覧表示用に値を並べる（多い場合は省略）
+fn list_values(values: &[String]) -> String {
+    let mut listed = values.iter().take(MAX_LISTED_VALUES).cloned().collect::<Vec<_>>().join("、");
+    if values.len() > MAX_LISTED_VALUES {
+        listed.push_str(&format!(" ほか{}件", values.len() - MAX_LISTED_VALUES));
+    }
+    listed
+}
+
+/// 読み書きするフィールドとフォームを照らし合わせる
+pub fn compare(expected: &[ExpectedField], form: &HashMap<String, FormField>) -> Vec<SchemaIssue> {
+    let mut issues = Vec::new();
+    for e in expected {
+        let Some(field) = form.get(&e.field) else {
+            issues.push(SchemaIssue {
+                field: e.field.clone(),
+                kind: IssueKind::Missing,
+                message: format!("フィールド「{}」（{}）がありません", e.field, e.usage),
+                values: Vec::new(),
+            });
+            continue;
+        };
+
+        if !e.types.contains(&field.field_type.as_str()) {
+            issues.push(SchemaIssue {
+                field: e.field.clone(),
+                kind: IssueKind::WrongType,
+                message: format!(
+                    "フィールド「{}」の種類が {} です（{} には {} が必要です）",
+                    e.field, field.field_type, e.usage, e.types.join(" / ")
+                ),
+                values: Vec::new(),
+            });
+            continue;
+        }
+
+        if CHOICE_TYPES.contains(&field.field_type.as_str()) {
+            let missing: Vec<String> = e.values.iter().filter(|v| !field.options.contains(v)).cloned().collect();
+            if !missing.is_empty() {
+                issues.push(SchemaIssue {
+                    field: e.field.clone(),
+                    kind: IssueKind::MissingOptions,
+                    message: format!(
+                        "フィールド「{}」の選択肢にない値があります: {}（選択肢: {}）",
+                        e.field, list_values(&missing), list_values(&field.options)
+                    ),
+                    values: missing,
+                });
+            }
+        }
+    }
+    issues
+}
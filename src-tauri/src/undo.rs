@@ -0,0 +1,126 @@
+//! スケジュール編集の取り消し・やり直し
+//! 編集操作ごとに変更前後のスケジュールを記録し、取り消しでは変更前へ、やり直しでは変更後へ戻す
+//!
+//! 取り消し・やり直しは記録時の状態から変わっていないスケジュールにだけ適用する
+//! （同期の取込や別の編集で変わっていれば矛盾するため適用しない）
+
+use serde::Serialize;
+
+use crate::database::{differing_fields, LocalSchedule};
+
+/// 記録しておく操作の最大数（古いものから破棄）
+const MAX_ENTRIES: usize = 50;
+
+/// スケジュール1件分の変更
+#[derive(Debug, Clone)]
+pub struct ScheduleChange {
+    pub schedule_id: i64,
+    /// 変更前（追加ならNone）
+    pub before: Option<LocalSchedule>,
+    /// 変更後（削除ならNone）
+    pub after: Option<LocalSchedule>,
+}
+
+/// 1回の編集操作（連鎖リスケジュールなど複数件の変更をまとめて扱う）
+#[derive(Debug, Clone)]
+pub struct UndoEntry {
+    pub label: String,
+    pub changes: Vec<ScheduleChange>,
+}
+
+impl UndoEntry {
+    /// 逆向きの操作（変更前と変更後を入れ替える）
+    pub fn inverse(&self) -> UndoEntry {
+        UndoEntry {
+            label: self.label.clone(),
+            changes: self
+                .changes
+                .iter()
+                .map(|c| ScheduleChange {
+                    schedule_id: c.schedule_id,
+                    before: c.after.clone(),
+                    after: c.before.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// 取り消し・やり直しの状態（画面のボタン表示用）
+#[derive(Debug, Clone, Serialize)]
+pub struct UndoState {
+    pub undo_label: Option<String>,
+    pub redo_label: Option<String>,
+    pub undo_count: usize,
+    pub redo_count: usize,
+}
+
+/// 取り消し・やり直しの操作履歴
+#[derive(Debug, Default)]
+pub struct UndoStack {
+    undo: Vec<UndoEntry>,
+    redo: Vec<UndoEntry>,
+}
+
+impl UndoStack {
+    /// 新しい編集操作を記録（やり直し履歴は破棄）
+    pub fn record(&mut self, entry: UndoEntry) {
+        if entry.changes.is_empty() {
+            return;
+        }
+        self.undo.push(entry);
+        if self.undo.len() > MAX_ENTRIES {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    pub fn pop_undo(&mut self) -> Option<UndoEntry> {
+        self.undo.pop()
+    }
+
+    pub fn pop_redo(&mut self) -> Option<UndoEntry> {
+        self.redo.pop()
+    }
+
+    /// 取り消した操作をやり直し履歴へ
+    pub fn push_redo(&mut self, entry: UndoEntry) {
+        self.redo.push(entry);
+    }
+
+    /// やり直した操作を取り消し履歴へ（やり直し履歴は残す）
+    pub fn push_undo(&mut self, entry: UndoEntry) {
+        self.undo.push(entry);
+    }
+
+    pub fn state(&self) -> UndoState {
+        UndoState {
+            undo_label: self.undo.last().map(|e| e.label.clone()),
+            redo_label: self.redo.last().map(|e| e.label.clone()),
+            undo_count: self.undo.len(),
+            redo_count: self.redo.len(),
+        }
+    }
+}
+
+/// 現在のスケジュールが記録時の状態（before）のままか
+pub fn matches_recorded(current: Option<&LocalSchedule>, recorded: Option<&LocalSchedule>) -> bool {
+    match (current, recorded) {
+        (None, None) => true,
+        (Some(current), Some(recorded)) => differing_fields(current, recorded).is_empty(),
+        _ => false,
+    }
+}
+
+/// 内容を戻したあとの同期状態
+/// 現在の内容がkintoneに送信済みなら、戻した内容を送信し直す必要がある（modified）
+pub fn restored_sync_status(current: &LocalSchedule, target: &LocalSchedule) -> String {
+    if current.kintone_record_id.is_none() {
+        return current.sync_status.clone();
+    }
+    if current.sync_status == "synced" || current.kintone_revision != target.kintone_revision {
+        "modified".to_string()
+    } else {
+        target.sync_status.clone()
+    }
+}
@@ -0,0 +1,121 @@
+//! スケジュールの重複チェック
+//! 1つのラインで同時に生産できるのは1件のため、同じラインで時間帯が重なるスケジュールを検出する
+//! ラインが未設定のスケジュールはライン未割り当てとして扱い、重複チェックの対象にしない
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::database::LocalSchedule;
+use crate::scheduling;
+
+/// 重複チェック対象の時間枠
+#[derive(Debug, Clone, Copy)]
+pub struct ScheduleSlot<'a> {
+    /// 新規追加時はNone
+    pub id: Option<i64>,
+    pub line: &'a str,
+    pub product_name: &'a str,
+    pub start_datetime: &'a str,
+    pub end_datetime: Option<&'a str>,
+}
+
+impl<'a> From<&'a LocalSchedule> for ScheduleSlot<'a> {
+    fn from(schedule: &'a LocalSchedule) -> Self {
+        Self {
+            id: schedule.id,
+            line: &schedule.line,
+            product_name: &schedule.product_name,
+            start_datetime: &schedule.start_datetime,
+            end_datetime: schedule.end_datetime.as_deref(),
+        }
+    }
+}
+
+/// 同じラインで時間帯が重なるスケジュール
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleOverlap {
+    /// チェック対象のスケジュール（新規追加時はNone）
+    pub schedule_id: Option<i64>,
+    /// 重なっている相手のスケジュール
+    pub other_id: i64,
+    pub other_schedule_number: Option<String>,
+    pub other_product_name: String,
+    pub line: String,
+    /// 重なっている時間帯
+    pub overlap_start: String,
+    pub overlap_end: String,
+    pub overlap_minutes: i64,
+}
+
+/// 時間枠を取得（メモ・図形や終了日時のないスケジュールは対象外）
+fn interval(slot: &ScheduleSlot) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    if scheduling::is_fixed_duration(slot.product_name) {
+        return None;
+    }
+    let start = scheduling::parse_datetime(slot.start_datetime)?;
+    let end = scheduling::parse_datetime(slot.end_datetime?)?;
+    (end > start).then_some((start, end))
+}
+
+/// 同じラインか（ライン未設定のスケジュールはどのラインとも重複させない）
+fn same_line(a: &str, b: &str) -> bool {
+    let (a, b) = (a.trim(), b.trim());
+    !a.is_empty() && a == b
+}
+
+/// 指定の時間枠と重なるスケジュールを返す
+pub fn find_overlaps(slot: &ScheduleSlot, schedules: &[LocalSchedule]) -> Vec<ScheduleOverlap> {
+    let Some((start, end)) = interval(slot) else {
+        return Vec::new();
+    };
+
+    schedules
+        .iter()
+        .filter(|other| other.id.is_some() && other.id != slot.id)
+        .filter(|other| same_line(&other.line, slot.line))
+        .filter_map(|other| {
+            let (other_start, other_end) = interval(&ScheduleSlot::from(other))?;
+            let overlap_start = start.max(other_start);
+            let overlap_end = end.min(other_end);
+            (overlap_start < overlap_end).then(|| ScheduleOverlap {
+                schedule_id: slot.id,
+                other_id: other.id.unwrap_or_default(),
+                other_schedule_number: other.schedule_number.clone(),
+                other_product_name: other.product_name.clone(),
+                line: other.line.clone(),
+                overlap_start: overlap_start.format("%Y-%m-%d %H:%M:%S").to_string(),
+                overlap_end: overlap_end.format("%Y-%m-%d %H:%M:%S").to_string(),
+                overlap_minutes: (overlap_end - overlap_start).num_minutes(),
+            })
+        })
+        .collect()
+}
+
+/// 全スケジュールの重複を返す（同じ組み合わせは1件のみ）
+pub fn find_all_overlaps(schedules: &[LocalSchedule]) -> Vec<ScheduleOverlap> {
+    schedules
+        .iter()
+        .flat_map(|schedule| {
+            find_overlaps(&ScheduleSlot::from(schedule), schedules)
+                .into_iter()
+                .filter(|o| o.schedule_id.is_some_and(|id| id < o.other_id))
+        })
+        .collect()
+}
+
+/// 重複内容をメッセージにまとめる
+pub fn describe_overlaps(overlaps: &[ScheduleOverlap]) -> String {
+    let details: Vec<String> = overlaps
+        .iter()
+        .map(|o| {
+            format!(
+                "{}（{}）と {} 〜 {} が重なっています",
+                o.other_product_name,
+                o.other_schedule_number.as_deref().unwrap_or("番号なし"),
+                o.overlap_start,
+                o.overlap_end,
+            )
+        })
+        .collect();
+    format!("同じラインのスケジュールと時間が重複しています: {}", details.join(" / "))
+}
@@ -6,7 +6,7 @@ const { invoke } = window.__TAURI__.core;
 
 
 
-// 製品重量マスタ
+// 製品重量マスタ（起動時に製品マスタから読み込んだ値で上書き）
 
 const productWeights = {
 
@@ -41,7 +41,8 @@ function getSyncStatusText(status) {
     const map = {
         "pending": "未同期",
         "synced": "同期済み",
-        "modified": "変更あり"
+        "modified": "変更あり",
+        "conflict": "競合あり"
     };
     return map[status] || status;
 }
@@ -58,6 +59,1066 @@ function setStatus(message, isrror = false) {
 
 
 
+// 製品マスタ（品番 → 製品）
+let productMaster = {};
+
+// 製品マスタを読み込み、入力フォームの製品候補と重量表を更新
+async function loadProductMaster() {
+    try {
+        const response = await invoke("get_products", { includeInactive: false });
+        if (!response.success) {
+            console.error("製品マスタの読み込みに失敗:", response.error);
+            return;
+        }
+
+        productMaster = {};
+        const select = document.getElementById("product-name");
+        const current = select.value;
+        const memoOption = select.querySelector('option[value="MEMO"]');
+        select.innerHTML = '<option value="">選択してください</option>';
+
+        response.data.forEach(product => {
+            productMaster[product.product_name] = product;
+            productWeights[product.product_name] = product.weight_kg;
+
+            const option = document.createElement("option");
+            option.value = product.product_name;
+            option.textContent = `${product.product_name} (${product.weight_kg}kg)` +
+                (product.display_name ? ` ${product.display_name}` : "");
+            select.appendChild(option);
+        });
+        if (memoOption) select.appendChild(memoOption);
+        select.value = current;
+
+        // 段取り替え入力用の品番候補
+        const datalist = document.getElementById("product-list");
+        if (datalist) {
+            datalist.innerHTML = "";
+            response.data.forEach(product => {
+                const option = document.createElement("option");
+                option.value = product.product_name;
+                datalist.appendChild(option);
+            });
+        }
+    } catch (error) {
+        console.error("製品マスタの読み込みに失敗:", error);
+    }
+}
+
+// 製品を選んだら既定の製綿能率を反映
+function applyProductDefaults() {
+    const product = productMaster[document.getElementById("product-name").value];
+    const efficiencySelect = document.getElementById("efficiency");
+    if (product && product.default_efficiency && efficiencySelect) {
+        const exists = Array.from(efficiencySelect.options).some(o => o.value === product.default_efficiency);
+        if (exists) efficiencySelect.value = product.default_efficiency;
+    }
+}
+
+// 自動計画モーダル初期化
+function initPlannerModal() {
+    const modal = document.getElementById("planner-modal");
+    const btnOpen = document.getElementById("btn-planner");
+    if (!modal || !btnOpen) return;
+
+    const field = (id) => document.getElementById(id);
+    const splitLines = (value) => value.split(",").map(l => l.trim()).filter(l => l);
+    let draftItems = [];
+
+    // 受注の入力行を追加
+    const addOrderRow = () => {
+        const tr = document.createElement("tr");
+        tr.innerHTML = `
+            <td><input type="text" class="planner-product" list="product-list" placeholder="品番"></td>
+            <td><input type="number" class="planner-quantity" min="0" step="1"></td>
+            <td><input type="datetime-local" class="planner-due"></td>
+            <td><input type="text" class="planner-allowed-lines" placeholder="空欄は全ライン"></td>
+            <td><button type="button" class="btn btn-secondary">削除</button></td>`;
+        tr.querySelector("button").addEventListener("click", () => tr.remove());
+        field("planner-order-tbody").appendChild(tr);
+    };
+
+    const readOrders = () => Array.from(field("planner-order-tbody").querySelectorAll("tr"))
+        .map(tr => ({
+            product_name: tr.querySelector(".planner-product").value.trim(),
+            quantity: parseFloat(tr.querySelector(".planner-quantity").value) || 0,
+            due_datetime: tr.querySelector(".planner-due").value || null,
+            allowed_lines: splitLines(tr.querySelector(".planner-allowed-lines").value)
+        }))
+        .filter(o => o.product_name);
+
+    const renderDraft = (plan) => {
+        draftItems = plan.items;
+        field("planner-result").style.display = "block";
+        field("planner-summary").textContent =
+            `${plan.items.length}件を割り付けました（段取り合計 ${plan.total_changeover_minutes}分・納期遅れ合計 ${plan.total_lateness_minutes}分）`;
+
+        const tbody = field("planner-draft-tbody");
+        tbody.innerHTML = "";
+        plan.items.forEach(item => {
+            const tr = document.createElement("tr");
+            if (item.lateness_minutes > 0) tr.style.color = "#FF3B30";
+            [
+                item.line || "-",
+                item.product_name,
+                item.quantity,
+                formatDateTime(item.start_datetime),
+                formatDateTime(item.end_datetime),
+                item.changeover_minutes ? `${item.changeover_minutes}分` : "",
+                item.lateness_minutes ? `${item.lateness_minutes}分` : ""
+            ].forEach(text => {
+                const td = document.createElement("td");
+                td.textContent = text;
+                tr.appendChild(td);
+            });
+            tbody.appendChild(tr);
+        });
+
+        const unplaced = field("planner-unplaced");
+        unplaced.innerHTML = "";
+        plan.unplaced.forEach(u => {
+            const li = document.createElement("li");
+            li.textContent = `${u.product_name}: ${u.reason}`;
+            unplaced.appendChild(li);
+        });
+        field("planner-commit").disabled = plan.items.length === 0;
+    };
+
+    btnOpen.addEventListener("click", () => {
+        if (!field("planner-start").value) {
+            const start = new Date(currentDate);
+            start.setHours(6, 0, 0, 0);
+            field("planner-start").value = formatDateTimeForInput(start);
+        }
+        if (!field("planner-order-tbody").children.length) addOrderRow();
+        modal.classList.add("active");
+    });
+
+    field("planner-modal-close").addEventListener("click", () => {
+        modal.classList.remove("active");
+    });
+
+    field("planner-add-order").addEventListener("click", addOrderRow);
+
+    field("planner-run").addEventListener("click", async () => {
+        const orders = readOrders();
+        if (orders.length === 0) {
+            setStatus("受注を入力してください", true);
+            return;
+        }
+        const request = {
+            orders,
+            start_datetime: field("planner-start").value,
+            lines: splitLines(field("planner-lines").value)
+        };
+        setStatus("計画案を作成中...");
+        const response = await invoke("optimize_orders", { request });
+        if (response.success) {
+            renderDraft(response.data);
+            setStatus("計画案を作成しました");
+        } else {
+            setStatus("自動計画エラー: " + response.error, true);
+        }
+    });
+
+    field("planner-commit").addEventListener("click", async () => {
+        if (draftItems.length === 0) return;
+        if (!confirm(`計画案の${draftItems.length}件をスケジュールに登録しますか？`)) return;
+
+        const response = await invoke("commit_draft_plan", { items: draftItems });
+        if (response.success) {
+            setStatus(`${response.data.length}件のスケジュールを登録しました（kintoneへは送信ボタンで同期）`);
+            draftItems = [];
+            field("planner-result").style.display = "none";
+            field("planner-order-tbody").innerHTML = "";
+            modal.classList.remove("active");
+            await loadSchedules();
+            renderGantt();
+        } else {
+            setStatus("計画の確定エラー: " + response.error, true);
+        }
+    });
+}
+
+// 取り消し・やり直しボタンの表示を更新（シナリオ表示中は本番の編集を戻さない）
+async function refreshUndoState() {
+    const btnUndo = document.getElementById("btn-undo");
+    const btnRedo = document.getElementById("btn-redo");
+    if (!btnUndo || !btnRedo) return;
+
+    const response = await invoke("get_undo_state");
+    const state = response.success ? response.data : null;
+    const inScenario = activeScenarioId !== null;
+    btnUndo.disabled = inScenario || !state || state.undo_count === 0;
+    btnRedo.disabled = inScenario || !state || state.redo_count === 0;
+    btnUndo.title = state && state.undo_label ? `取り消し: ${state.undo_label}（Ctrl+Z）` : "取り消し（Ctrl+Z）";
+    btnRedo.title = state && state.redo_label ? `やり直し: ${state.redo_label}（Ctrl+Y）` : "やり直し（Ctrl+Y）";
+}
+
+// 直前の編集を取り消す（redoがtrueならやり直す）
+async function runUndoRedo(redo) {
+    if (activeScenarioId !== null) return;
+    const response = await invoke(redo ? "redo" : "undo");
+    if (response.data) {
+        const result = response.data;
+        const message = `${redo ? "やり直しました" : "取り消しました"}: ${result.label}` +
+            (result.synced > 0 ? `（kintone反映${result.synced}件）` : "");
+        setStatus(response.error ? `${message} / ${response.error}` : message, !response.success);
+    } else {
+        setStatus((redo ? "やり直しエラー: " : "取り消しエラー: ") + response.error, true);
+    }
+    await loadSchedules();
+    renderGantt();
+}
+
+// 取り消し・やり直しの初期化
+function initUndoControls() {
+    const btnUndo = document.getElementById("btn-undo");
+    const btnRedo = document.getElementById("btn-redo");
+    if (!btnUndo || !btnRedo) return;
+
+    btnUndo.addEventListener("click", () => runUndoRedo(false));
+    btnRedo.addEventListener("click", () => runUndoRedo(true));
+
+    document.addEventListener("keydown", (e) => {
+        if (!(e.ctrlKey || e.metaKey)) return;
+        const target = e.target;
+        if (target && (target.tagName === "INPUT" || target.tagName === "TEXTAREA" || target.tagName === "SELECT" || target.isContentEditable)) return;
+        if (btnUndo.offsetParent === null) return;
+
+        const key = e.key.toLowerCase();
+        if (key === "z" && !e.shiftKey) {
+            e.preventDefault();
+            if (!btnUndo.disabled) runUndoRedo(false);
+        } else if (key === "y" || (key === "z" && e.shiftKey)) {
+            e.preventDefault();
+            if (!btnRedo.disabled) runUndoRedo(true);
+        }
+    });
+}
+
+// ゴミ箱モーダル初期化
+function initTrashModal() {
+    const modal = document.getElementById("trash-modal");
+    const btnOpen = document.getElementById("btn-trash");
+    if (!modal || !btnOpen) return;
+
+    const field = (id) => document.getElementById(id);
+
+    const renderTrash = async () => {
+        const response = await invoke("get_trash");
+        const tbody = field("trash-list");
+        tbody.innerHTML = "";
+        if (!response.success) {
+            setStatus("ゴミ箱取得エラー: " + response.error, true);
+            return;
+        }
+        if (response.data.length === 0) {
+            tbody.innerHTML = '<tr><td colspan="6">ゴミ箱は空です</td></tr>';
+            return;
+        }
+
+        response.data.forEach(({ schedule, deleted_at, kintone_delete_error }) => {
+            const tr = document.createElement("tr");
+            let kintoneStatus = "-";
+            if (schedule.kintone_record_id) {
+                kintoneStatus = kintone_delete_error ? `削除失敗: ${kintone_delete_error}` : "削除待ち（次回の送信で削除）";
+            }
+            [
+                `${schedule.product_name}（${schedule.schedule_number || "番号なし"}）`,
+                schedule.line || "-",
+                `${formatDateTime(schedule.start_datetime)}〜${formatDateTime(schedule.end_datetime)}`,
+                deleted_at,
+                kintoneStatus
+            ].forEach(text => {
+                const td = document.createElement("td");
+                td.textContent = text;
+                tr.appendChild(td);
+            });
+
+            const actions = document.createElement("td");
+            const btnRestore = document.createElement("button");
+            btnRestore.type = "button";
+            btnRestore.className = "btn btn-primary";
+            btnRestore.textContent = "復元";
+            btnRestore.addEventListener("click", async () => {
+                const result = await invoke("restore_deleted_schedule", { id: schedule.id });
+                if (result.data) {
+                    const overlaps = result.data.overlaps.length > 0 ? "（同じラインのスケジュールと重なっています）" : "";
+                    setStatus(`「${schedule.product_name}」を復元しました${overlaps}` + (result.error ? " / " + result.error : ""), !result.success);
+                    await loadSchedules();
+                    renderGantt();
+                } else {
+                    setStatus("復元エラー: " + result.error, true);
+                }
+                renderTrash();
+            });
+
+            const btnPurge = document.createElement("button");
+            btnPurge.type = "button";
+            btnPurge.className = "btn btn-danger";
+            btnPurge.textContent = "完全に削除";
+            btnPurge.addEventListener("click", async () => {
+                if (!confirm(`「${schedule.product_name}」を完全に削除しますか？（元に戻せません）`)) return;
+                const result = await invoke("purge_deleted_schedule", { id: schedule.id });
+                if (!result.success) setStatus("削除エラー: " + result.error, true);
+                renderTrash();
+            });
+
+            actions.append(btnRestore, btnPurge);
+            tr.appendChild(actions);
+            tbody.appendChild(tr);
+        });
+    };
+
+    btnOpen.addEventListener("click", () => {
+        modal.classList.add("active");
+        renderTrash();
+    });
+
+    field("trash-purge-old").addEventListener("click", async () => {
+        const response = await invoke("purge_trash", { olderThanDays: null });
+        if (response.success) {
+            setStatus(`保存期間を過ぎた${response.data}件を完全に削除しました`);
+        } else {
+            setStatus("ゴミ箱の整理エラー: " + response.error, true);
+        }
+        renderTrash();
+    });
+
+    field("trash-modal-close").addEventListener("click", () => modal.classList.remove("active"));
+}
+
+// 在庫アラートの表示名
+const STOCK_ALERT_LABELS = { shortage: "安全在庫割れ", overstock: "最大在庫超過" };
+
+// 在庫アラートのバッジを更新
+function renderStockAlertBadge(report) {
+    const badge = document.getElementById("btn-stock-alerts");
+    if (!badge) return;
+    const alerts = report && report.alerts ? report.alerts : [];
+    stockAlerts = alerts;
+    badge.hidden = alerts.length === 0;
+    document.getElementById("stock-alert-count").textContent = alerts.length;
+    badge.title = alerts
+        .map(a => `${a.product} ${a.date} ${STOCK_ALERT_LABELS[a.kind]}（予測 ${a.projected.toLocaleString()} / 基準 ${a.threshold.toLocaleString()}）`)
+        .join("\n");
+}
+
+// 直近の在庫アラート
+let stockAlerts = [];
+
+// 在庫アラート・在庫基準モーダル初期化
+// アラートは同期のたびにバックエンドで判定され、stock-alerts イベントで届く
+function initStockAlerts() {
+    const modal = document.getElementById("stock-alert-modal");
+    if (!modal) return;
+
+    const field = (id) => document.getElementById(id);
+
+    const renderAlerts = () => {
+        const tbody = field("stock-alert-list");
+        tbody.innerHTML = "";
+        if (stockAlerts.length === 0) {
+            tbody.innerHTML = '<tr><td colspan="4">在庫アラートはありません</td></tr>';
+            return;
+        }
+        stockAlerts.forEach(a => {
+            const tr = document.createElement("tr");
+            tr.className = "stock-alert-" + a.kind;
+            [a.product, a.date, STOCK_ALERT_LABELS[a.kind], `${a.projected.toLocaleString()}（基準 ${a.threshold.toLocaleString()}）`].forEach(text => {
+                const td = document.createElement("td");
+                td.textContent = text;
+                tr.appendChild(td);
+            });
+            tbody.appendChild(tr);
+        });
+    };
+
+    const renderLevels = async () => {
+        const tbody = field("stock-level-list");
+        tbody.innerHTML = "";
+        const response = await invoke("get_stock_levels");
+        if (!response.success) {
+            setStatus("在庫基準取得エラー: " + response.error, true);
+            return;
+        }
+        response.data.forEach(level => {
+            const tr = document.createElement("tr");
+            tr.dataset.product = level.product;
+            const name = document.createElement("td");
+            name.textContent = level.product;
+            tr.appendChild(name);
+            ["safety_stock", "max_stock", "lot_size"].forEach(key => {
+                const td = document.createElement("td");
+                const input = document.createElement("input");
+                input.type = "number";
+                input.min = "0";
+                input.dataset.key = key;
+                input.value = level[key] ?? "";
+                td.appendChild(input);
+                tr.appendChild(td);
+            });
+            tbody.appendChild(tr);
+        });
+    };
+
+    const open = () => {
+        modal.classList.add("active");
+        renderAlerts();
+        renderLevels();
+    };
+
+    field("btn-stock-alerts").addEventListener("click", open);
+    const btnLevels = field("btn-stock-levels");
+    if (btnLevels) btnLevels.addEventListener("click", open);
+
+    field("stock-level-save").addEventListener("click", async () => {
+        const levels = Array.from(field("stock-level-list").querySelectorAll("tr")).map(tr => {
+            const value = (key) => {
+                const text = tr.querySelector(`input[data-key="${key}"]`).value.trim();
+                return text === "" ? null : Number(text);
+            };
+            return {
+                product: tr.dataset.product,
+                safety_stock: value("safety_stock"),
+                max_stock: value("max_stock"),
+                lot_size: value("lot_size")
+            };
+        });
+        const response = await invoke("save_stock_levels", { levels });
+        if (response.success) {
+            setStatus("在庫基準を保存しました");
+        } else {
+            setStatus("在庫基準の保存エラー: " + response.error, true);
+        }
+    });
+
+    field("stock-alert-modal-close").addEventListener("click", () => modal.classList.remove("active"));
+
+    window.__TAURI__.event.listen("stock-alerts", (event) => {
+        renderStockAlertBadge(event.payload);
+        if (modal.classList.contains("active")) renderAlerts();
+    });
+
+    invoke("get_stock_alerts").then(response => {
+        if (response.success) renderStockAlertBadge(response.data);
+    });
+}
+
+// 生産提案モーダル初期化
+// 需給予測で安全在庫を下回る日に向けた生産案をバックエンドで作成し、選んだものをまとめて登録する
+function initProductionSuggestions() {
+    const modal = document.getElementById("suggestion-modal");
+    const btnOpen = document.getElementById("btn-production-suggestions");
+    if (!modal || !btnOpen) return;
+
+    const field = (id) => document.getElementById(id);
+    let suggestions = [];
+
+    const checkedDrafts = () => Array.from(field("suggestion-list").querySelectorAll("input[type=checkbox]:checked"))
+        .map(input => suggestions[Number(input.dataset.index)].draft);
+
+    const updateAcceptButton = () => {
+        const count = checkedDrafts().length;
+        field("suggestion-accept").disabled = count === 0;
+        field("suggestion-accept").textContent = `✅ 選択した${count}件を一括登録`;
+    };
+
+    const renderSuggestions = (report) => {
+        suggestions = report.suggestions;
+        const tbody = field("suggestion-list");
+        tbody.innerHTML = "";
+        if (suggestions.length === 0) {
+            tbody.innerHTML = '<tr><td colspan="8">在庫不足の見込みはありません</td></tr>';
+        }
+        suggestions.forEach((s, index) => {
+            const tr = document.createElement("tr");
+            if (s.late) tr.className = "suggestion-late";
+            const check = document.createElement("td");
+            const input = document.createElement("input");
+            input.type = "checkbox";
+            input.checked = true;
+            input.dataset.index = index;
+            input.addEventListener("change", updateAcceptButton);
+            check.appendChild(input);
+            tr.appendChild(check);
+            [
+                s.draft.product_name,
+                s.draft.line || "-",
+                s.draft.quantity1.toLocaleString(),
+                formatDateTime(s.draft.start_datetime),
+                formatDateTime(s.draft.end_datetime),
+                `${s.shortage_date}（予測 ${s.projected.toLocaleString()} / 安全在庫 ${s.safety_stock.toLocaleString()}）`,
+                s.late ? "間に合いません" : ""
+            ].forEach(text => {
+                const td = document.createElement("td");
+                td.textContent = text;
+                tr.appendChild(td);
+            });
+            tbody.appendChild(tr);
+        });
+
+        const warnings = field("suggestion-warnings");
+        warnings.innerHTML = "";
+        report.warnings.forEach(w => {
+            const li = document.createElement("li");
+            li.textContent = w;
+            warnings.appendChild(li);
+        });
+        field("suggestion-select-all").checked = suggestions.length > 0;
+        updateAcceptButton();
+    };
+
+    btnOpen.addEventListener("click", async () => {
+        modal.classList.add("active");
+        field("suggestion-list").innerHTML = '<tr><td colspan="8">生産提案を作成中...</td></tr>';
+        field("suggestion-warnings").innerHTML = "";
+        field("suggestion-accept").disabled = true;
+        try {
+            const response = await invokeWithTimeout("suggest_production_orders", {}, 40000);
+            if (response.success) {
+                renderSuggestions(response.data);
+            } else {
+                field("suggestion-list").innerHTML = "";
+                setStatus("生産提案エラー: " + response.error, true);
+            }
+        } catch (error) {
+            field("suggestion-list").innerHTML = "";
+            setStatus("生産提案エラー: " + (error.message || error), true);
+        }
+    });
+
+    field("suggestion-select-all").addEventListener("change", (event) => {
+        field("suggestion-list").querySelectorAll("input[type=checkbox]").forEach(input => {
+            input.checked = event.target.checked;
+        });
+        updateAcceptButton();
+    });
+
+    field("suggestion-accept").addEventListener("click", async () => {
+        const drafts = checkedDrafts();
+        if (drafts.length === 0) return;
+        if (!confirm(`生産提案の${drafts.length}件をスケジュールに登録しますか？`)) return;
+
+        const response = await invoke("accept_production_suggestions", { drafts });
+        if (response.success) {
+            setStatus(`${response.data.length}件のスケジュールを登録しました（kintoneへは送信ボタンで同期）`);
+            modal.classList.remove("active");
+            await loadSchedules();
+            renderGantt();
+            await refreshUndoState();
+            balanceData = null;
+            await renderBalanceView();
+        } else {
+            setStatus("生産提案の登録エラー: " + response.error, true);
+        }
+    });
+
+    field("suggestion-modal-close").addEventListener("click", () => modal.classList.remove("active"));
+}
+
+// kintoneアプリのフォーム確認の問題の種類
+const SCHEMA_ISSUE_LABELS = {
+    missing: "フィールドなし",
+    wrong_type: "種類の不一致",
+    missing_options: "選択肢なし"
+};
+
+// kintoneアプリのフォームを確認（起動時・設定の保存後）
+// 問題があればモーダルで表示する。showAlways なら問題がなくても結果を表示する
+async function checkKintoneSchema(showAlways = false) {
+    const modal = document.getElementById("schema-modal");
+    if (!modal) return;
+
+    let report;
+    try {
+        const response = await invoke("check_kintone_schema");
+        if (!response.success) {
+            if (showAlways) setStatus("kintoneアプリの確認エラー: " + response.error, true);
+            return;
+        }
+        report = response.data;
+    } catch (e) {
+        console.error("kintoneアプリの確認エラー:", e);
+        return;
+    }
+
+    if (report.problem_count > 0) {
+        setStatus(`kintoneアプリのフォームに${report.problem_count}件の問題があります`, true);
+    } else if (!showAlways) {
+        return;
+    }
+
+    const tbody = document.getElementById("schema-list");
+    tbody.innerHTML = "";
+    const addRow = (cells, className) => {
+        const tr = document.createElement("tr");
+        if (className) tr.className = className;
+        cells.forEach(text => {
+            const td = document.createElement("td");
+            td.textContent = text;
+            tr.appendChild(td);
+        });
+        tbody.appendChild(tr);
+    };
+
+    report.mapping_warnings.forEach(w => addRow(["対応付けファイル", "", "読み込みエラー", w], "schema-problem"));
+    report.apps.forEach(app => {
+        const name = `${app.label}（アプリ${app.app_id}）`;
+        if (app.error) {
+            addRow([name, "", "取得エラー", app.error], "schema-problem");
+        } else if (app.issues.length === 0) {
+            addRow([name, "", "OK", app.mapping_source ? "対応付け: " + app.mapping_source : ""]);
+        }
+        app.issues.forEach(issue => {
+            addRow([name, issue.field, SCHEMA_ISSUE_LABELS[issue.kind] || issue.kind, issue.message], "schema-problem");
+        });
+    });
+    if (report.apps.length === 0) {
+        addRow(["", "", "", "確認できるアプリがありません"]);
+    }
+
+    document.getElementById("schema-summary").textContent = report.problem_count > 0
+        ? `${report.problem_count}件の問題があります。kintoneへの送信が失敗する原因になるため、アプリの設定か対応付けファイルを修正してください。`
+        : "設定済みのすべてのアプリで問題は見つかりませんでした。";
+    modal.classList.add("active");
+}
+
+// 変更履歴の表示名
+const HISTORY_OPERATION_LABELS = {
+    insert: "追加",
+    update: "変更",
+    delete: "削除",
+    restore: "復元",
+    sync_import: "kintone取込"
+};
+
+const HISTORY_FIELD_LABELS = {
+    product_name: "品名",
+    line: "ライン",
+    start_datetime: "開始",
+    end_datetime: "終了",
+    total_quantity: "総数",
+    production_status: "状況",
+    notes: "備考"
+};
+
+// 変更履歴を一覧に表示
+function renderHistory(entries) {
+    const tbody = document.getElementById("history-list");
+    tbody.innerHTML = "";
+    if (entries.length === 0) {
+        tbody.innerHTML = '<tr><td colspan="5">履歴はありません</td></tr>';
+        return;
+    }
+
+    entries.forEach(entry => {
+        const schedule = entry.after || entry.before;
+        const changes = entry.changed_fields.map(field => {
+            const label = HISTORY_FIELD_LABELS[field] || field;
+            return `${label}: ${entry.before?.[field] ?? "-"} → ${entry.after?.[field] ?? "-"}`;
+        });
+        const cells = [
+            entry.changed_at,
+            HISTORY_OPERATION_LABELS[entry.operation] || entry.operation,
+            schedule ? `${schedule.product_name}（${schedule.schedule_number || "番号なし"}）` : `#${entry.schedule_id}`,
+            changes.join(" / ") || (schedule ? `${formatDateTime(schedule.start_datetime)}〜${formatDateTime(schedule.end_datetime)}` : ""),
+            `${entry.machine_name}（${entry.app_mode === "worker" ? "作業者" : "管理者"}）`
+        ];
+        const tr = document.createElement("tr");
+        cells.forEach(text => {
+            const td = document.createElement("td");
+            td.textContent = text;
+            tr.appendChild(td);
+        });
+        tbody.appendChild(tr);
+    });
+}
+
+// スケジュール1件の変更履歴を表示
+async function openScheduleHistory(scheduleId) {
+    const response = await invoke("get_schedule_history", { scheduleId });
+    if (!response.success) {
+        setStatus("履歴取得エラー: " + response.error, true);
+        return;
+    }
+    document.getElementById("history-title").textContent = "📜 スケジュールの変更履歴";
+    document.getElementById("history-range").style.display = "none";
+    renderHistory(response.data);
+    document.getElementById("history-modal").classList.add("active");
+}
+
+// 変更履歴（アクティビティフィード）モーダル初期化
+function initHistoryModal() {
+    const modal = document.getElementById("history-modal");
+    const btnOpen = document.getElementById("btn-activity");
+    if (!modal || !btnOpen) return;
+
+    const field = (id) => document.getElementById(id);
+
+    const loadFeed = async () => {
+        const response = await invoke("get_activity_feed", {
+            fromDate: field("history-from").value,
+            toDate: field("history-to").value,
+            limit: null
+        });
+        if (!response.success) {
+            setStatus("履歴取得エラー: " + response.error, true);
+            return;
+        }
+        renderHistory(response.data);
+    };
+
+    btnOpen.addEventListener("click", () => {
+        const today = new Date();
+        const weekAgo = new Date(today.getTime() - 6 * 24 * 60 * 60 * 1000);
+        const toDateString = (d) => `${d.getFullYear()}-${String(d.getMonth() + 1).padStart(2, "0")}-${String(d.getDate()).padStart(2, "0")}`;
+        field("history-from").value = toDateString(weekAgo);
+        field("history-to").value = toDateString(today);
+        field("history-title").textContent = "📜 変更履歴";
+        field("history-range").style.display = "";
+        modal.classList.add("active");
+        loadFeed();
+    });
+
+    field("history-load").addEventListener("click", loadFeed);
+    field("history-modal-close").addEventListener("click", () => modal.classList.remove("active"));
+}
+
+// 稼働カレンダーモーダル初期化
+function initCalendarModal() {
+    const modal = document.getElementById("calendar-modal");
+    const btnOpen = document.getElementById("btn-calendar");
+    if (!modal || !btnOpen) return;
+
+    const field = (id) => document.getElementById(id);
+    const weekdayNames = ["日", "月", "火", "水", "木", "金", "土"];
+
+    // 一覧の1行を作成（最後の列に削除ボタン）
+    const appendRow = (tbody, cells, onDelete) => {
+        const tr = document.createElement("tr");
+        cells.forEach(text => {
+            const td = document.createElement("td");
+            td.textContent = text;
+            tr.appendChild(td);
+        });
+        const actions = document.createElement("td");
+        const btnDelete = document.createElement("button");
+        btnDelete.type = "button";
+        btnDelete.className = "btn btn-secondary";
+        btnDelete.textContent = "削除";
+        btnDelete.addEventListener("click", onDelete);
+        actions.appendChild(btnDelete);
+        tr.appendChild(actions);
+        tbody.appendChild(tr);
+    };
+
+    const renderLists = async () => {
+        const response = await invoke("get_plant_calendar");
+        if (!response.success) {
+            setStatus("稼働カレンダー取得エラー: " + response.error, true);
+            return;
+        }
+        const { shifts, holidays, maintenance_windows } = response.data;
+
+        const shiftBody = field("shift-tbody");
+        shiftBody.innerHTML = "";
+        shifts.forEach(shift => {
+            appendRow(shiftBody, [
+                shift.name,
+                shift.weekday === null ? "毎日" : weekdayNames[shift.weekday],
+                shift.line || "全ライン",
+                `${shift.start_time}〜${shift.end_time}`
+            ], () => removeEntry("delete_shift_pattern", shift.id));
+        });
+
+        const holidayBody = field("holiday-tbody");
+        holidayBody.innerHTML = "";
+        holidays.forEach(holiday => {
+            appendRow(holidayBody, [holiday.date, holiday.name || ""],
+                () => removeEntry("delete_holiday", holiday.id));
+        });
+
+        const maintenanceBody = field("maintenance-tbody");
+        maintenanceBody.innerHTML = "";
+        maintenance_windows.forEach(window => {
+            appendRow(maintenanceBody, [
+                window.line || "全ライン",
+                window.start_datetime.replace("T", " "),
+                window.end_datetime.replace("T", " "),
+                window.reason || ""
+            ], () => removeEntry("delete_maintenance_window", window.id));
+        });
+    };
+
+    // 登録・削除後は一覧とガントチャートの非稼働表示を更新
+    const afterChange = async (response, message) => {
+        if (response.success) {
+            setStatus(message);
+            await renderLists();
+            renderGantt();
+        } else {
+            setStatus("稼働カレンダー更新エラー: " + response.error, true);
+        }
+    };
+
+    const removeEntry = async (command, id) => {
+        await afterChange(await invoke(command, { id }), "稼働カレンダーから削除しました");
+    };
+
+    btnOpen.addEventListener("click", async () => {
+        modal.classList.add("active");
+        await renderLists();
+    });
+
+    field("calendar-modal-close").addEventListener("click", () => {
+        modal.classList.remove("active");
+    });
+
+    field("shift-add").addEventListener("click", async () => {
+        const weekday = field("shift-weekday").value;
+        const shift = {
+            id: null,
+            name: field("shift-name").value.trim() || "シフト",
+            weekday: weekday === "" ? null : parseInt(weekday, 10),
+            line: field("shift-line").value.trim() || null,
+            start_time: field("shift-start").value,
+            end_time: field("shift-end").value
+        };
+        await afterChange(await invoke("add_shift_pattern", { shift }), "シフトを追加しました");
+    });
+
+    field("holiday-add").addEventListener("click", async () => {
+        const holiday = {
+            id: null,
+            date: field("holiday-date").value,
+            name: field("holiday-name").value.trim() || null
+        };
+        await afterChange(await invoke("add_holiday", { holiday }), "休日を追加しました");
+    });
+
+    field("maintenance-add").addEventListener("click", async () => {
+        const window = {
+            id: null,
+            line: field("maintenance-line").value.trim() || null,
+            start_datetime: field("maintenance-start").value,
+            end_datetime: field("maintenance-end").value,
+            reason: field("maintenance-reason").value.trim() || null
+        };
+        await afterChange(await invoke("add_maintenance_window", { window }), "保全時間帯を追加しました");
+    });
+}
+
+// 製品マスタモーダル初期化
+function initProductModal() {
+    const modal = document.getElementById("product-modal");
+    const btnOpen = document.getElementById("btn-products");
+    if (!modal || !btnOpen) return;
+
+    const field = (id) => document.getElementById(id);
+
+    const clearForm = () => {
+        field("product-id").value = "";
+        field("product-code").value = "";
+        field("product-display-name").value = "";
+        field("product-category").value = "";
+        field("product-weight").value = "";
+        field("product-default-efficiency").value = "";
+        field("product-active").checked = true;
+    };
+
+    const renderList = async () => {
+        const response = await invoke("get_products", { includeInactive: true });
+        const tbody = field("product-tbody");
+        tbody.innerHTML = "";
+        if (!response.success) {
+            setStatus("製品マスタ取得エラー: " + response.error, true);
+            return;
+        }
+
+        response.data.forEach(product => {
+            const tr = document.createElement("tr");
+            if (!product.active) tr.style.color = "#999";
+            [
+                product.product_name,
+                product.display_name || "",
+                product.category || "",
+                product.weight_kg + "kg",
+                product.default_efficiency || "",
+                product.active ? "有効" : "無効"
+            ].forEach(text => {
+                const td = document.createElement("td");
+                td.textContent = text;
+                tr.appendChild(td);
+            });
+
+            const actions = document.createElement("td");
+            const btnEdit = document.createElement("button");
+            btnEdit.type = "button";
+            btnEdit.className = "btn btn-secondary";
+            btnEdit.textContent = "編集";
+            btnEdit.addEventListener("click", () => {
+                field("product-id").value = product.id;
+                field("product-code").value = product.product_name;
+                field("product-display-name").value = product.display_name || "";
+                field("product-category").value = product.category || "";
+                field("product-weight").value = product.weight_kg;
+                field("product-default-efficiency").value = product.default_efficiency || "";
+                field("product-active").checked = product.active;
+            });
+            const btnToggle = document.createElement("button");
+            btnToggle.type = "button";
+            btnToggle.className = "btn btn-secondary";
+            btnToggle.textContent = product.active ? "無効化" : "有効化";
+            btnToggle.addEventListener("click", async () => {
+                const res = await invoke("set_product_active", { id: product.id, active: !product.active });
+                if (!res.success) {
+                    setStatus("製品マスタ更新エラー: " + res.error, true);
+                }
+                await renderList();
+                await loadProductMaster();
+            });
+            actions.appendChild(btnEdit);
+            actions.appendChild(btnToggle);
+            tr.appendChild(actions);
+            tbody.appendChild(tr);
+        });
+    };
+
+    btnOpen.addEventListener("click", async () => {
+        clearForm();
+        modal.classList.add("active");
+        await renderList();
+    });
+
+    field("product-modal-close").addEventListener("click", () => {
+        modal.classList.remove("active");
+    });
+
+    field("product-clear").addEventListener("click", clearForm);
+
+    field("product-save").addEventListener("click", async () => {
+        const id = parseInt(field("product-id").value, 10);
+        const request = {
+            product_name: field("product-code").value.trim(),
+            display_name: field("product-display-name").value.trim() || null,
+            category: field("product-category").value || null,
+            weight_kg: parseFloat(field("product-weight").value) || 0,
+            default_efficiency: field("product-default-efficiency").value || null,
+            active: field("product-active").checked
+        };
+
+        const response = Number.isNaN(id)
+            ? await invoke("add_product", { request })
+            : await invoke("update_product", { id, request });
+        if (response.success) {
+            setStatus("製品マスタを保存しました: " + request.product_name);
+            clearForm();
+            await renderList();
+            await loadProductMaster();
+        } else {
+            setStatus("製品マスタ保存エラー: " + response.error, true);
+        }
+    });
+
+    const renderChangeovers = async () => {
+        const response = await invoke("get_changeovers");
+        const tbody = field("changeover-tbody");
+        tbody.innerHTML = "";
+        if (!response.success) {
+            setStatus("段取り替え取得エラー: " + response.error, true);
+            return;
+        }
+
+        response.data.forEach(changeover => {
+            const tr = document.createElement("tr");
+            [
+                changeover.from_product,
+                changeover.to_product,
+                changeover.line || "全ライン",
+                changeover.minutes + "分",
+                changeover.notes || ""
+            ].forEach(text => {
+                const td = document.createElement("td");
+                td.textContent = text;
+                tr.appendChild(td);
+            });
+            const actions = document.createElement("td");
+            const btnDelete = document.createElement("button");
+            btnDelete.type = "button";
+            btnDelete.className = "btn btn-secondary";
+            btnDelete.textContent = "削除";
+            btnDelete.addEventListener("click", async () => {
+                const res = await invoke("delete_changeover", { id: changeover.id });
+                if (!res.success) {
+                    setStatus("段取り替え削除エラー: " + res.error, true);
+                }
+                await renderChangeovers();
+                await loadChangeoverBlocks();
+                renderGantt();
+            });
+            actions.appendChild(btnDelete);
+            tr.appendChild(actions);
+            tbody.appendChild(tr);
+        });
+    };
+
+    btnOpen.addEventListener("click", renderChangeovers);
+
+    field("changeover-save").addEventListener("click", async () => {
+        const changeover = {
+            id: null,
+            from_product: field("changeover-from").value.trim(),
+            to_product: field("changeover-to").value.trim(),
+            line: field("changeover-line").value.trim() || null,
+            minutes: parseInt(field("changeover-minutes").value, 10) || 0,
+            notes: field("changeover-notes").value.trim() || null
+        };
+        const response = await invoke("add_changeover", { changeover });
+        if (response.success) {
+            setStatus(`段取り替え時間を保存しました: ${changeover.from_product} → ${changeover.to_product}`);
+            await renderChangeovers();
+            await loadChangeoverBlocks();
+            renderGantt();
+        } else {
+            setStatus("段取り替え保存エラー: " + response.error, true);
+        }
+    });
+
+    field("product-export").addEventListener("click", async () => {
+        const response = await invoke("export_products_csv");
+        if (!response.success) {
+            setStatus("CSV出力エラー: " + response.error, true);
+            return;
+        }
+        const blob = new Blob([response.data], { type: "text/csv" });
+        const link = document.createElement("a");
+        link.href = URL.createObjectURL(blob);
+        link.download = "product_master.csv";
+        link.click();
+        URL.revokeObjectURL(link.href);
+    });
+
+    field("product-import").addEventListener("click", () => field("product-import-file").click());
+
+    field("product-import-file").addEventListener("change", async (e) => {
+        const file = e.target.files[0];
+        e.target.value = "";
+        if (!file) return;
+
+        const content = await file.text();
+        const response = await invoke("import_products_csv", { content });
+        if (response.success) {
+            setStatus(`製品マスタを取り込みました（追加 ${response.data.inserted}件 / 更新 ${response.data.updated}件）`);
+            await renderList();
+            await loadProductMaster();
+        } else {
+            alert("CSV取込エラー:\n" + response.error);
+        }
+    });
+}
+
 // メモモーダル初期化
 
 function initMemoModal() {
@@ -141,7 +1202,7 @@ function initMemoModal() {
 
         try {
 
-            const response = await invoke("add_schedule", { request: formData });
+            const response = await invokeScheduleSave("add_schedule", formData);
 
             if (response.success) {
 
@@ -360,7 +1421,7 @@ function initShapeModal() {
 
         try {
 
-            const response = await invoke("add_schedule", { request: formData });
+            const response = await invokeScheduleSave("add_schedule", formData);
 
             if (response.success) {
 
@@ -489,120 +1550,114 @@ async function initAppMode() {
         passwordInput.addEventListener('keypress', (e) => {
             if (e.key === 'Enter') btnAdmin.click();
         });
-    });
-}
-
-// 3分間隔でkintoneからデータを自動取得（作業者モード用）
-function startPolling() {
-    if (pollingInterval) clearInterval(pollingInterval);
-    
-    const POLLING_INTERVAL_MS = 3 * 60 * 1000; // 3分
-    
-    pollingInterval = setInterval(async () => {
-        console.log('自動更新: kintoneからデータ取得中...');
-        try {
-            await handleSyncFromKintone();
-            console.log('自動更新: 完了');
-        } catch (err) {
-            console.error('自動更新エラー:', err);
-        }
-    }, POLLING_INTERVAL_MS);
-    
-    console.log('自動更新開始: 3分間隔');
-}
-
-function stopPolling() {
-    if (pollingInterval) {
-        clearInterval(pollingInterval);
-        pollingInterval = null;
-        console.log('自動更新停止');
-    }
-}
-
-// kintone設定を初期化（デフォルト値を設定！
-
-async function initKintoneConfig() {
-
-    const defaultConfig = {
-
-        subdomain: "jfe-rockfiber",
-
-        app_id: 506,
-
-        api_token: "3CakeA8SORFDrOawAcL3Y2UEY8TogZkLw52U5RBo",
-
-        memo_app_id: 507,
-
-        memo_api_token: "hkVvZfY6j5dgNSda9OE8WPnLefezfrIoGsR387gL",
-
-        yamazumi_app_id: 354,
-
-        yamazumi_api_token: "Qig2MiwdI0McEcbPZNbP2ORkg3UQoB15wx6bBJqC",
-
-        kobukuro_app_id: 368,
-
-        kobukuro_api_token: "4U3hAsfb1bLbww5XT0ppcz4f9AcdOmp1SLIfyAIS",
-
-        tsumikomi_app_id: 514,
-
-        tsumikomi_api_token: "nU2EcpjY1f7CQxKNs0PoPCnRRcdpl2xgnlK4GCOA"
-
-    };
-
-    
-
-    // フォームに初期値を設定
-
-    const subdomainl = document.getElementById("subdomain");
-
-    const appIdl = document.getElementById("app-id");
-
-    const apiTokenl = document.getElementById("api-token");
-
-    const memoAppIdl = document.getElementById("memo-app-id");
-
-    const memoApiTokenl = document.getElementById("memo-api-token");
-
-    const tsumikomiAppIdl = document.getElementById("tsumikomi-app-id");
-
-    const tsumikomiApiTokenl = document.getElementById("tsumikomi-api-token");
-
-    
-
-    if (subdomainl) subdomainl.value = defaultConfig.subdomain;
-
-    if (appIdl) appIdl.value = defaultConfig.app_id;
-
-    if (apiTokenl) apiTokenl.value = defaultConfig.api_token;
-
-    if (memoAppIdl) memoAppIdl.value = defaultConfig.memo_app_id;
-
-    if (memoApiTokenl) memoApiTokenl.value = defaultConfig.memo_api_token;
-
-    if (tsumikomiAppIdl) tsumikomiAppIdl.value = defaultConfig.tsumikomi_app_id;
-
-    if (tsumikomiApiTokenl) tsumikomiApiTokenl.value = defaultConfig.tsumikomi_api_token;
+    });
+}
 
+// 3分間隔でkintoneからデータを自動取得（作業者モード用）
+function startPolling() {
+    if (pollingInterval) clearInterval(pollingInterval);
+    
+    const POLLING_INTERVAL_MS = 3 * 60 * 1000; // 3分
+    
+    pollingInterval = setInterval(async () => {
+        console.log('自動更新: kintoneからデータ取得中...');
+        try {
+            await handleSyncFromKintone();
+            console.log('自動更新: 完了');
+        } catch (err) {
+            console.error('自動更新エラー:', err);
+        }
+    }, POLLING_INTERVAL_MS);
     
+    console.log('自動更新開始: 3分間隔');
+}
 
-    // 自動的にkintone設定を保存
+function stopPolling() {
+    if (pollingInterval) {
+        clearInterval(pollingInterval);
+        pollingInterval = null;
+        console.log('自動更新停止');
+    }
+}
 
+// kintone設定を初期化（保存済みの設定をフォームへ反映、未設定なら初回設定を表示）
+async function initKintoneConfig() {
+    let view = null;
     try {
-
-        const response = await invoke("save_kintone_config", { config: defaultConfig });
-
+        const response = await invoke("get_kintone_config");
         if (response.success) {
-
-            console.log("kintone設定を初期化しました");
-
+            view = response.data;
         }
-
     } catch (error) {
+        console.error("kintone設定の取得に失敗", error);
+    }
+
+    if (view) {
+        const setValue = (id, value) => {
+            const el = document.getElementById(id);
+            if (el && value !== null && value !== undefined) el.value = value;
+        };
+        const setTokenPlaceholder = (id, hasToken) => {
+            const el = document.getElementById(id);
+            if (el) {
+                el.value = "";
+                el.placeholder = hasToken ? "保存済み（変更する場合のみ入力）" : "APIトークンを入力";
+            }
+        };
 
-        console.error("kintone設定�初期化に失敗", error);
+        setValue("subdomain", view.subdomain);
+        setValue("app-id", view.app_id);
+        setValue("memo-app-id", view.memo_app_id);
+        setValue("yamazumi-app-id", view.yamazumi_app_id);
+        setValue("kobukuro-app-id", view.kobukuro_app_id);
+        setValue("tsumikomi-app-id", view.tsumikomi_app_id);
+        setValue("legacy-app-id", view.legacy_app_id);
+        setTokenPlaceholder("api-token", view.has_api_token);
+        setTokenPlaceholder("memo-api-token", view.has_memo_api_token);
+        setTokenPlaceholder("yamazumi-api-token", view.has_yamazumi_api_token);
+        setTokenPlaceholder("kobukuro-api-token", view.has_kobukuro_api_token);
+        setTokenPlaceholder("tsumikomi-api-token", view.has_tsumikomi_api_token);
+        setTokenPlaceholder("legacy-api-token", view.has_legacy_api_token);
+    }
 
+    if (view && view.configured) {
+        const syncStatus = document.getElementById("sync-status");
+        if (syncStatus) syncStatus.textContent = "同期: 接続済み";
+        // kintoneアプリの変更でフィールドが合わなくなっていないか確認（起動時・設定の保存後）
+        checkKintoneSchema();
+        return;
     }
 
+    // 初回起動: 接続設定を入力してもらう
+    const setupNotice = document.getElementById("settings-setup-notice");
+    if (setupNotice) setupNotice.style.display = "block";
+    const modal = document.getElementById("settings-modal");
+    if (modal) modal.classList.add("active");
+}
+
+// 設定フォームの値を読み取る（APIトークンが空欄なら保存済みのものを使う）
+function readKintoneConfigForm() {
+    const intValue = (id) => {
+        const value = parseInt(document.getElementById(id).value, 10);
+        return Number.isNaN(value) ? null : value;
+    };
+    const tokenValue = (id) => document.getElementById(id).value.trim() || null;
+
+    return {
+        subdomain: document.getElementById("subdomain").value.trim(),
+        app_id: intValue("app-id") || 0,
+        api_token: tokenValue("api-token"),
+        memo_app_id: intValue("memo-app-id"),
+        memo_api_token: tokenValue("memo-api-token"),
+        yamazumi_app_id: intValue("yamazumi-app-id"),
+        yamazumi_api_token: tokenValue("yamazumi-api-token"),
+        kobukuro_app_id: intValue("kobukuro-app-id"),
+        kobukuro_api_token: tokenValue("kobukuro-api-token"),
+        tsumikomi_app_id: intValue("tsumikomi-app-id"),
+        tsumikomi_api_token: tokenValue("tsumikomi-api-token"),
+        legacy_app_id: intValue("legacy-app-id"),
+        legacy_api_token: tokenValue("legacy-api-token")
+    };
 }
 
 
@@ -623,6 +1678,13 @@ function applyAppMode() {
 
         "btn-sync-to-kintone",
         "btn-copy-prev-shapes",
+        "btn-products",
+        "btn-planner",
+        "btn-undo",
+        "btn-trash",
+        "btn-redo",
+        "scenario-controls",
+        "btn-calendar",
         "btn-settings"
 
     ];
@@ -876,31 +1938,7 @@ async function handleSaveSettings(e) {
 
 
 
-    const config = {
-
-        subdomain: document.getElementById("subdomain").value,
-
-        app_id: parseInt(document.getElementById("app-id").value),
-
-        api_token: document.getElementById("api-token").value,
-
-        memo_app_id: parseInt(document.getElementById("memo-app-id").value || "0"),
-
-        memo_api_token: document.getElementById("memo-api-token").value,
-
-        yamazumi_app_id: 354,
-
-        yamazumi_api_token: "Qig2MiwdI0McEcbPZNbP2ORkg3UQoB15wx6bBJqC",
-
-        kobukuro_app_id: 368,
-
-        kobukuro_api_token: "4U3hAsfb1bLbww5XT0ppcz4f9AcdOmp1SLIfyAIS",
-
-        tsumikomi_app_id: parseInt(document.getElementById("tsumikomi-app-id").value || "514"),
-
-        tsumikomi_api_token: document.getElementById("tsumikomi-api-token").value || ""
-
-    };
+    const config = readKintoneConfigForm();
 
 
 
@@ -912,6 +1950,10 @@ async function handleSaveSettings(e) {
 
             setStatus("kintone設定を保存しました");
 
+            document.getElementById("settings-setup-notice").style.display = "none";
+
+            await initKintoneConfig();
+
             elements.settingsModal.classList.remove("active");
 
             elements.syncStatus.textContent = "同期: 接続済み";
@@ -941,22 +1983,16 @@ async function handleTestConnection() {
     btn.disabled = true;
 
     // まず現在の設定を保存
-    const config = {
-        subdomain: document.getElementById('subdomain').value,
-        app_id: parseInt(document.getElementById('app-id').value),
-        api_token: document.getElementById('api-token').value,
-        memo_app_id: parseInt(document.getElementById('memo-app-id').value || '0'),
-        memo_api_token: document.getElementById('memo-api-token').value,
-        yamazumi_app_id: 354,
-        yamazumi_api_token: 'Qig2MiwdI0McEcbPZNbP2ORkg3UQoB15wx6bBJqC',
-        kobukuro_app_id: 368,
-        kobukuro_api_token: '4U3hAsfb1bLbww5XT0ppcz4f9AcdOmp1SLIfyAIS',
-        tsumikomi_app_id: parseInt(document.getElementById('tsumikomi-app-id').value || '514'),
-        tsumikomi_api_token: document.getElementById('tsumikomi-api-token').value || ''
-    };
+    const config = readKintoneConfigForm();
 
     try {
-        await invoke('save_kintone_config', { config });
+        const saved = await invoke('save_kintone_config', { config });
+        if (!saved.success) {
+            alert('❌ 設定を保存できません: ' + saved.error);
+            btn.textContent = originalText;
+            btn.disabled = false;
+            return;
+        }
     } catch (e) {
         console.error('設定保存エラー:', e);
     }
@@ -1096,7 +2132,13 @@ async function handleSyncFromKintone() {
 
         if (response.success) {
 
-            setStatus(`${response.data}件のレコードを同期しました`);
+            const report = response.data;
+            const removed = report.removed > 0 ? `（kintoneで削除された${report.removed}件をゴミ箱へ移動）` : "";
+            const overlaps = report.overlaps.length > 0
+                ? ` / 同じラインで時間が重なるスケジュールが${report.overlaps.length}件あります: ` +
+                  report.overlaps.map(o => `${o.other_product_name}（${o.line} ${o.overlap_start}〜${o.overlap_end}）`).join("、")
+                : "";
+            setStatus(`${report.imported}件のレコードを同期しました${removed}${overlaps}`, report.overlaps.length > 0);
 
             await loadSchedules();
 
@@ -1126,9 +2168,13 @@ async function handleSyncToKintone() {
 
         const response = await invoke("sync_to_kintone");
 
-        if (response.success) {
+        if (response.data) {
+
+            const deleted = response.data.deletions.filter(d => d.success).length;
+
+            const message = `${response.data.synced}件のスケジュールを同期しました` + (deleted > 0 ? `（kintoneから${deleted}件削除）` : "");
 
-            setStatus(`${response.data}件のスケジュールを同期しました`);
+            setStatus(response.success ? message : `${message} / 送信エラー: ${response.error}`, !response.success);
 
             await loadSchedules();
 
@@ -1302,6 +2348,10 @@ function initEventListeners() {
 
     document.getElementById("efficiency").addEventListener("change", calculateTotals);
 
+    document.getElementById("product-name").addEventListener("change", applyProductDefaults);
+
+    loadProductMaster();
+
 
 
     elements.btnSettings.addEventListener("click", () => {
@@ -1344,6 +2394,16 @@ function initEventListeners() {
         btnTestConnection.addEventListener("click", handleTestConnection);
     }
 
+    // kintoneアプリのフォーム確認ボタン
+    const btnCheckSchema = document.getElementById("check-kintone-schema");
+    if (btnCheckSchema) {
+        btnCheckSchema.addEventListener("click", async () => {
+            btnCheckSchema.disabled = true;
+            await checkKintoneSchema(true);
+            btnCheckSchema.disabled = false;
+        });
+    }
+
     if (elements.btnTestData) {
 
         elements.btnTestData.addEventListener("click", handleGenerateTestData);
@@ -1361,6 +2421,21 @@ function initEventListeners() {
     // メモモーダル関連
 
     initMemoModal();
+    initProductModal();
+    initCalendarModal();
+    initPlannerModal();
+    initScenarioControls();
+    initHistoryModal();
+    initUndoControls();
+    initTrashModal();
+    initStockAlerts();
+    initProductionSuggestions();
+
+    // kintoneアプリのフォーム確認モーダル
+    const schemaModalClose = document.getElementById("schema-modal-close");
+    if (schemaModalClose) {
+        schemaModalClose.addEventListener("click", () => document.getElementById("schema-modal").classList.remove("active"));
+    }
 
     initShapeModal();
 
@@ -1798,7 +2873,7 @@ async function handleGlobalMouseUp(e) {
 
             };
 
-            const response = await invoke("update_schedule", { request });
+            const response = await saveScheduleTimes(request);
 
             if (response.success) {
 
@@ -1920,13 +2995,313 @@ function updateGanttDate() {
 
 
 
+// 同じラインで時間が重複しているスケジュールのID
+let overlapScheduleIds = new Set();
+
+// 重複しているスケジュールを取得（ガントチャートで強調表示）
+async function loadScheduleOverlaps() {
+    try {
+        const response = await invoke("get_schedule_overlaps");
+        if (response.success) {
+            overlapScheduleIds = new Set();
+            (response.data || []).forEach(o => {
+                if (o.schedule_id !== null) overlapScheduleIds.add(o.schedule_id);
+                overlapScheduleIds.add(o.other_id);
+            });
+        }
+    } catch (error) {
+        console.error("重複チェックエラー:", error);
+    }
+}
+
+// 段取り替え時間帯（ガントチャート表示用）
+let changeoverBlocks = [];
+
+// 段取り替え時間帯を取得
+async function loadChangeoverBlocks() {
+    try {
+        const response = await invoke("get_changeover_blocks");
+        if (response.success) {
+            changeoverBlocks = response.data || [];
+        }
+    } catch (error) {
+        console.error("段取り替え取得エラー:", error);
+    }
+}
+
+// 段取り替え不足の警告を表示
+function warnChangeoverShortfalls(response) {
+    const shortfalls = response.data && response.data.changeover_shortfalls ? response.data.changeover_shortfalls : [];
+    if (!response.success || shortfalls.length === 0) return;
+    const details = shortfalls
+        .map(b => `${b.from_product} → ${b.to_product}: 段取り${b.required_minutes}分に対して${b.available_minutes}分`)
+        .join("\n");
+    alert("段取り替え時間が不足しています。\n\n" + details);
+}
+
+// スケジュールを保存（同じラインの重複で保存できなかった場合は確認のうえ重複を許可して再送信）
+async function invokeScheduleSave(command, request) {
+    if (activeScenarioId !== null) {
+        return saveToScenario(command, request);
+    }
+    let response = await invoke(command, { request });
+    const overlaps = response.data && response.data.overlaps ? response.data.overlaps : [];
+    if (!response.success && overlaps.length > 0) {
+        overlaps.forEach(o => overlapScheduleIds.add(o.other_id));
+        renderGantt();
+        if (confirm(response.error + "\n\nこのまま保存しますか？")) {
+            response = await invoke(command, { request: { ...request, allow_overlap: true } });
+        }
+    }
+    warnChangeoverShortfalls(response);
+    if (response.success && response.error) {
+        // 保存はできたがID354・ID368への反映に失敗（次回のkintone送信で再送される）
+        alert("保存しました。ただし連携先への反映に失敗しました。\n\n" + response.error);
+    }
+    return response;
+}
+
+// ID354（山積表）・ID368（小袋実績）への連携状態を表示
+const DOWNSTREAM_APP_LABELS = { yamazumi: "ID354（山積表）", kobukuro: "ID368（小袋実績）" };
+const DOWNSTREAM_STATUS_LABELS = { synced: "反映済み", skipped: "変更なし", deleted: "削除済み", error: "反映失敗（次回のkintone送信で再送）" };
+async function showDownstreamStatus(scheduleId) {
+    const el = document.getElementById("edit-downstream-status");
+    if (!el) return;
+    el.textContent = "";
+    if (activeScenarioId !== null || !(scheduleId > 0)) return;
+    const response = await invoke("get_downstream_status", { scheduleId });
+    if (!response.success || !response.data) return;
+    const { app, status, message, updated_at } = response.data;
+    const target = app ? DOWNSTREAM_APP_LABELS[app] + ": " : "連携先: ";
+    el.textContent = `${target}${DOWNSTREAM_STATUS_LABELS[status] || status}（${updated_at}）` + (message ? ` ${message}` : "");
+    el.classList.toggle("error", status === "error");
+}
+
+// 表示中のシナリオ（nullなら本番）
+let activeScenarioId = null;
+
+// シナリオ上のスケジュールを取得
+// シナリオで追加したスケジュールは本番IDがないため、変更IDをマイナスにしてIDとして扱う
+async function loadScenarioSchedules(scenarioId) {
+    const response = await invoke("get_scenario_schedules", { scenarioId });
+    if (!response.success) return response;
+    return {
+        ...response,
+        data: response.data.map(entry => ({
+            ...entry.schedule,
+            id: entry.schedule.id !== null ? entry.schedule.id : -entry.change_id,
+            scenario_change_id: entry.change_id,
+            scenario_change_type: entry.change_type
+        }))
+    };
+}
+
+// シナリオ表示中の保存（本番には反映しない）
+async function saveToScenario(command, request) {
+    if (command === "add_schedule") {
+        const response = await invoke("add_scenario_schedule", { scenarioId: activeScenarioId, request });
+        return { ...response, data: { id: response.data, overlaps: [], changeover_shortfalls: [] } };
+    }
+
+    const current = schedules.find(s => s.id === request.id);
+    if (!current) {
+        return { success: false, data: null, error: "スケジュールが見つかりません" };
+    }
+    const { allow_overlap, scenario_change_id, scenario_change_type, ...merged } = { ...current, ...request };
+    const isAddition = current.id < 0;
+    const schedule = { ...merged, id: isAddition ? null : current.id };
+    for (const key of Object.keys(request)) {
+        if (request[key] === null || request[key] === undefined) schedule[key] = current[key] ?? null;
+    }
+
+    const response = await invoke("save_scenario_schedule", {
+        scenarioId: activeScenarioId,
+        changeId: isAddition ? -current.id : null,
+        schedule
+    });
+    return { ...response, data: { id: request.id, overlaps: [], changeover_shortfalls: [] } };
+}
+
+// スケジュールを削除（シナリオ表示中はシナリオ上だけで削除）
+async function invokeDeleteSchedule(id) {
+    if (activeScenarioId === null) {
+        return invoke("delete_schedule", { id });
+    }
+    return invoke("remove_scenario_schedule", {
+        scenarioId: activeScenarioId,
+        scheduleId: id > 0 ? id : null,
+        changeId: id < 0 ? -id : null
+    });
+}
+
+// シナリオの選択肢を更新
+async function loadScenarioList() {
+    const select = document.getElementById("scenario-select");
+    if (!select) return;
+    const response = await invoke("get_scenarios");
+    if (!response.success) {
+        setStatus("シナリオ取得エラー: " + response.error, true);
+        return;
+    }
+    select.innerHTML = '<option value="">本番スケジュール</option>';
+    response.data.forEach(scenario => {
+        const option = document.createElement("option");
+        option.value = scenario.id;
+        option.textContent = `🧪 ${scenario.name}（変更${scenario.change_count}件）`;
+        select.appendChild(option);
+    });
+    select.value = activeScenarioId === null ? "" : String(activeScenarioId);
+}
+
+// 表示するシナリオを切り替え
+async function switchScenario(scenarioId) {
+    activeScenarioId = scenarioId;
+    document.body.classList.toggle("scenario-mode", scenarioId !== null);
+    ["btn-scenario-diff", "btn-scenario-promote", "btn-scenario-discard"].forEach(id => {
+        const el = document.getElementById(id);
+        if (el) el.disabled = scenarioId === null;
+    });
+    await loadScenarioList();
+    await loadSchedules();
+    renderGantt();
+}
+
+// シナリオ操作の初期化
+function initScenarioControls() {
+    const select = document.getElementById("scenario-select");
+    if (!select) return;
+    const field = (id) => document.getElementById(id);
+
+    select.addEventListener("change", () => {
+        switchScenario(select.value === "" ? null : parseInt(select.value, 10));
+    });
+
+    field("btn-scenario-new").addEventListener("click", async () => {
+        const name = prompt("シナリオ名を入力してください（本番スケジュールを元に作成します）");
+        if (!name) return;
+        const response = await invoke("create_scenario", { name, description: null });
+        if (response.success) {
+            setStatus(`シナリオ「${name}」を作成しました`);
+            await switchScenario(response.data);
+        } else {
+            setStatus("シナリオ作成エラー: " + response.error, true);
+        }
+    });
+
+    field("btn-scenario-diff").addEventListener("click", async () => {
+        if (activeScenarioId === null) return;
+        const response = await invoke("diff_scenario", { scenarioId: activeScenarioId });
+        if (!response.success) {
+            setStatus("比較エラー: " + response.error, true);
+            return;
+        }
+        renderScenarioDiff(response.data);
+        field("scenario-diff-modal").classList.add("active");
+    });
+
+    field("scenario-diff-close").addEventListener("click", () => {
+        field("scenario-diff-modal").classList.remove("active");
+    });
+
+    field("btn-scenario-promote").addEventListener("click", async () => {
+        if (activeScenarioId === null) return;
+        if (!confirm("このシナリオを本番スケジュールに反映し、kintoneへ送信しますか？")) return;
+        setStatus("シナリオを本番に反映中...");
+        let response = await invoke("promote_scenario", { scenarioId: activeScenarioId, allowOverlap: false });
+        const overlaps = response.data && response.data.overlaps ? response.data.overlaps : [];
+        if (!response.success && overlaps.length > 0) {
+            if (!confirm(response.error + "\n\nこのまま本番に反映しますか？")) {
+                setStatus("シナリオの反映を中止しました");
+                return;
+            }
+            response = await invoke("promote_scenario", { scenarioId: activeScenarioId, allowOverlap: true });
+        }
+        if (response.data) {
+            const r = response.data;
+            setStatus(`本番に反映しました（変更${r.updated}件・追加${r.added}件・削除${r.removed}件、kintone送信${r.synced}件）` +
+                (response.error ? " / " + response.error : ""), !response.success);
+            await switchScenario(null);
+        } else {
+            setStatus("昇格エラー: " + response.error, true);
+        }
+    });
+
+    field("btn-scenario-discard").addEventListener("click", async () => {
+        if (activeScenarioId === null) return;
+        if (!confirm("このシナリオを破棄しますか？（本番スケジュールは変更されません）")) return;
+        const response = await invoke("discard_scenario", { scenarioId: activeScenarioId });
+        if (response.success) {
+            setStatus("シナリオを破棄しました");
+            await switchScenario(null);
+        } else {
+            setStatus("破棄エラー: " + response.error, true);
+        }
+    });
+
+    loadScenarioList();
+}
+
+// シナリオと本番の差分を表示
+function renderScenarioDiff(diff) {
+    const container = document.getElementById("scenario-diff-body");
+    container.innerHTML = "";
+
+    const section = (title, items, describe) => {
+        const h3 = document.createElement("h3");
+        h3.textContent = `${title}（${items.length}件）`;
+        container.appendChild(h3);
+        const ul = document.createElement("ul");
+        items.forEach(item => {
+            const li = document.createElement("li");
+            li.textContent = describe(item);
+            ul.appendChild(li);
+        });
+        container.appendChild(ul);
+    };
+    const label = (s) => `${s.product_name}${s.line ? `（${s.line}）` : ""}`;
+
+    section("移動", diff.moved, e =>
+        `${label(e.live)}: ${formatDateTime(e.live.start_datetime)}〜${formatDateTime(e.live.end_datetime)} → ` +
+        `${formatDateTime(e.scenario.start_datetime)}〜${formatDateTime(e.scenario.end_datetime)}`);
+    section("変更", diff.modified, e => `${label(e.live)}: ${e.fields.join(", ")}`);
+    section("追加", diff.added, s => `${label(s)}: ${formatDateTime(s.start_datetime)}〜${formatDateTime(s.end_datetime)}`);
+    section("削除", diff.removed, s => `${label(s)}: ${formatDateTime(s.start_datetime)}〜${formatDateTime(s.end_datetime)}`);
+}
+
+// 日時の変更を保存（同じラインの後続スケジュールがずれる場合は確認のうえまとめて移動）
+async function saveScheduleTimes(request) {
+    const schedule = schedules.find(s => s.id === request.id);
+    if (!schedule || activeScenarioId !== null || schedule.product_name === "MMO" || schedule.product_name === "SHAP") {
+        return invokeScheduleSave("update_schedule", request);
+    }
+
+    const rescheduleRequest = {
+        schedule_id: request.id,
+        start_datetime: request.start_datetime,
+        end_datetime: request.end_datetime
+    };
+    const preview = await invoke("reschedule_line", { request: { ...rescheduleRequest, preview: true } });
+    if (preview.success && preview.data.moves.length > 1) {
+        const followers = preview.data.moves.slice(1);
+        const list = followers
+            .map(m => `${m.product_name}: ${formatDateTime(m.old_start)} → ${formatDateTime(m.new_start)}`)
+            .join("\n");
+        if (confirm(`同じラインの後続スケジュール ${followers.length}件も移動します。\n\n${list}\n\n「キャンセル」を押すとこのスケジュールだけを変更します。`)) {
+            return invoke("reschedule_line", { request: { ...rescheduleRequest, preview: false } });
+        }
+    }
+    return invokeScheduleSave("update_schedule", request);
+}
+
 async function loadSchedules() {
 
     try {
 
         console.log("=== loadSchedules called ===");
 
-        const response = await invoke("get_schedules");
+        const response = activeScenarioId === null
+            ? await invoke("get_schedules")
+            : await loadScenarioSchedules(activeScenarioId);
 
         console.log("=== get_schedules response:", response);
 
@@ -1934,6 +3309,12 @@ async function loadSchedules() {
 
             schedules = response.data || [];
 
+            await loadScheduleOverlaps();
+
+            await loadChangeoverBlocks();
+
+            await refreshUndoState();
+
             console.log("=== Loaded schedules count:", schedules.length);
 
             renderScheduleTable();
@@ -2002,7 +3383,7 @@ async function handleAddSchedule(e) {
 
     try {
 
-        const response = await invoke("add_schedule", { request: formData });
+        const response = await invokeScheduleSave("add_schedule", formData);
 
         if (response.success) {
 
@@ -2076,8 +3457,6 @@ function calculateTotals() {
 
     const productName = productNameSelect.value;
 
-    const efficiency = parseFloat(efficiencySelect.value) || 1;
-
     const weight = productWeights[productName] || 0;
 
 
@@ -2136,22 +3515,21 @@ function calculateTotals() {
 
 
 
-        // 自動計算ロジック
-
-        if (startDatetime && quantity > 0 && weight > 0 && efficiency > 0) {
-
-            const productionTime = (quantity * weight / 1000) / efficiency * 60;
-
-            const startDate = new Date(startDatetime);
-
-            const endDate = new Date(startDate.getTime() + productionTime * 60 * 1000);
-
-
-
-            const endStr = formatDateTimeForInput(endDate);
-
-            endDatetimeInput.value = endStr;
-
+        // 終了日時はバックエンドで算出（製品重量 × 数量 / 製綿能率）
+        if (startDatetime && quantity > 0 && weight > 0) {
+            const request = {
+                product_name: productName,
+                start_datetime: startDatetime,
+                quantities: [quantity],
+                efficiencies: [efficiencySelect.value || null]
+            };
+            invoke("calculate_schedule_duration", { request })
+                .then((response) => {
+                    if (response.success && response.data && response.data.end_datetime) {
+                        endDatetimeInput.value = formatDateTimeForInput(response.data.end_datetime);
+                    }
+                })
+                .catch((error) => console.error("生産時間の計算エラー:", error));
         }
 
     }
@@ -2276,6 +3654,8 @@ function openEditModal(schedule) {
 
     document.getElementById("edit-status").value = schedule.production_status || "未生産";
 
+    showDownstreamStatus(schedule.id);
+
     
 
     document.getElementById("edit-modal").classList.add("active");
@@ -2392,12 +3772,16 @@ function createEditModal() {
 
                 </div>
 
+                <p id="edit-downstream-status" class="downstream-status"></p>
+
                 <div class="form-actions">
 
                     <button type="submit" class="btn btn-primary">💾 保存</button>
 
                     <button type="button" class="btn btn-secondary" id="edit-modal-cancel">キャンセル</button>
 
+                    <button type="button" class="btn btn-secondary" id="edit-modal-history">📜 履歴</button>
+
                 </div>
 
             </form>
@@ -2416,6 +3800,10 @@ function createEditModal() {
 
     document.getElementById("edit-modal-cancel").addEventListener("click", closeEditModal);
 
+    document.getElementById("edit-modal-history").addEventListener("click", () => {
+        openScheduleHistory(parseInt(document.getElementById("edit-id").value, 10));
+    });
+
     document.getElementById("edit-schedule-form").addEventListener("submit", handleEditSchedule);
 
 }
@@ -2492,7 +3880,7 @@ async function handleEditSchedule(e) {
 
         
 
-        const response = await invoke("update_schedule", { request });
+        const response = await saveScheduleTimes(request);
 
         console.log("Response:", response);
 
@@ -2529,17 +3917,17 @@ async function handleEditSchedule(e) {
 // スケジュールを削除
 
 async function handleDeleteSchedule(id, productName) {
-    if (!confirm("このスケジュールを削除しますか？")) return;
+    if (!confirm("このスケジュールを削除しますか？（ゴミ箱から復元できます）")) return;
 
     
 
     try {
 
-        const response = await invoke("delete_schedule", { id: id });
+        const response = await invokeDeleteSchedule(id);
 
         if (response.success) {
 
-            setStatus("スケジュールを削除しました");
+            setStatus("スケジュールを削除しました" + (response.error ? " / " + response.error : ""), !!response.error);
 
             await loadSchedules();
 
@@ -2687,6 +4075,8 @@ function renderGantt() {
 
 
 
+        renderChangeoverBlocks(contentDiv, rowStart, rowEnd);
+
         const lanes = calculateLanes(daySchedules);
 
         const laneCount = lanes.length > 0 ? lanes.length : 1;
@@ -2717,6 +4107,63 @@ function renderGantt() {
 
     renderOverlayItems(container, startDate);
 
+    renderOffHours(rows);
+
+}
+
+// 段取り替え時間帯をガントチャートの行に描画（不足している箇所は赤）
+function renderChangeoverBlocks(contentDiv, rowStart, rowEnd) {
+    changeoverBlocks.forEach(block => {
+        const start = new Date(block.start_datetime.replace(" ", "T"));
+        const end = new Date(block.end_datetime.replace(" ", "T"));
+        if (start >= rowEnd || end <= rowStart) return;
+
+        const from = Math.max(start, rowStart);
+        const to = Math.min(end, rowEnd);
+        const div = document.createElement("div");
+        div.className = "gantt-changeover" + (block.sufficient ? "" : " is-short");
+        div.style.left = `${((from - rowStart) / (60 * 60 * 1000)) * currentHourPx}px`;
+        div.style.width = `${Math.max(2, ((to - from) / (60 * 60 * 1000)) * currentHourPx)}px`;
+        div.title = `段取り替え ${block.from_product} → ${block.to_product}` +
+            `${block.line ? `（${block.line}）` : ""}: ${block.required_minutes}分` +
+            (block.sufficient ? "" : `（確保 ${block.available_minutes}分・不足）`);
+        contentDiv.appendChild(div);
+    });
+}
+
+// 稼働カレンダーの非稼働時間帯（シフト外・休日・保全）をガントチャートに網掛け表示
+async function renderOffHours(rows) {
+    const rowElements = Array.from(rows.querySelectorAll(".gantt-row"));
+    for (const row of rowElements) {
+        const date = row.dataset.date;
+        const response = await invoke("get_working_windows", { date, line: "" });
+        if (!response.success || !response.data.length) continue;
+
+        const contentDiv = row.querySelector(".gantt-row-content");
+        if (!contentDiv || !contentDiv.isConnected) continue;
+
+        const rowStart = new Date(`${date}T06:00:00`);
+        const toPx = (value) => ((new Date(value.replace(" ", "T")) - rowStart) / (60 * 60 * 1000)) * currentHourPx;
+
+        // 稼働時間帯の隙間を非稼働として描画
+        let cursor = 0;
+        const gaps = [];
+        response.data[0].windows.forEach(window => {
+            const left = toPx(window.start);
+            if (left > cursor) gaps.push([cursor, left]);
+            cursor = Math.max(cursor, toPx(window.end));
+        });
+        if (cursor < currentHourPx * 24) gaps.push([cursor, currentHourPx * 24]);
+
+        gaps.forEach(([from, to]) => {
+            const shade = document.createElement("div");
+            shade.className = "gantt-offhours";
+            shade.style.left = `${from}px`;
+            shade.style.width = `${to - from}px`;
+            shade.title = response.data[0].is_holiday ? "休日" : "非稼働";
+            contentDiv.insertBefore(shade, contentDiv.firstChild);
+        });
+    }
 }
 
 
@@ -3244,6 +4691,13 @@ function createGanttBar(schedule, dayStart6AM, laneIndex) {
     const bar = document.createElement("div");
     bar.className = "gantt-bar";
     bar.dataset.id = schedule.id;
+    if (overlapScheduleIds.has(schedule.id)) {
+        bar.classList.add("has-overlap");
+        bar.title = "同じラインのスケジュールと時間が重複しています";
+    }
+    if (schedule.scenario_change_type) {
+        bar.classList.add("scenario-changed");
+    }
 
     // 行の範囲（当日6:00〜翌6:00）
     const rowStartMs = dayStart6AM.getTime();
@@ -3410,7 +4864,7 @@ if (schedule.product_name === "MMO") {
 
                   try {
 
-                      await window.__TAURI__.core.invoke("delete_schedule", { id: schedule.id });
+                      await invokeDeleteSchedule(schedule.id);
 
                       await loadSchedules(); renderGantt();
 
@@ -3572,7 +5026,7 @@ if (schedule.product_name === "MMO") {
 
                   try {
 
-                      await window.__TAURI__.core.invoke("delete_schedule", { id: schedule.id });
+                      await invokeDeleteSchedule(schedule.id);
 
                       await loadSchedules(); renderGantt();
 
@@ -3722,7 +5176,9 @@ function getSyncStatusText(status) {
 
         "synced": "同期済み",
 
-        "modified": "変更あり"
+        "modified": "変更あり",
+
+        "conflict": "競合あり"
 
     };
 
@@ -3732,49 +5188,9 @@ function getSyncStatusText(status) {
 
 // ========== 需給バランスビュー ==========
 
-// 追跡対象の主要製品
-const BALANCE_PRODUCTS = ['FS450NR', 'FS450K', 'FS450S', 'FS450D', 'FS250C', 'FS250CE', 'FS360F'];
-
-// 積込予定 item → 在庫品番 マッピング
-const SHIPPING_PRODUCT_MAP = {
-    '450NR': 'FS450NR',
-    '450K': 'FS450K',
-    '高ダイ': 'FS450K',
-    '低ショット': 'FS450S',
-    '大建': 'FS450D',
-    'FS250CE': 'FS250CE',
-    'FS250C': 'FS250C',
-    'FS360F': 'FS360F'
-};
-
 // バランスデータ状態
 let balanceData = null;
 
-/**
- * セル値から出荷数量を抽出（shipping_check_plugin.js と同じロジック）
- */
-function extractShippingTotal(cellVal) {
-    if (!cellVal) return 0;
-    let texts = [];
-    if (typeof cellVal === 'object' && !Array.isArray(cellVal)) {
-        texts.push(String(cellVal.left || ''));
-        texts.push(String(cellVal.center || ''));
-        texts.push(String(cellVal.right || ''));
-    } else {
-        texts.push(String(cellVal || ''));
-    }
-    let total = 0;
-    texts.forEach(t => {
-        const cleaned = t.replace(/※/g, ' ').replace(/　/g, ' ');
-        const matches = cleaned.match(/[+-]?\d[\d,]*(?:\.\d+)?/g) || [];
-        matches.forEach(m => {
-            const num = Number(m.replace(/,/g, ''));
-            if (!isNaN(num)) total += num;
-        });
-    });
-    return total;
-}
-
 /**
  * タイムアウト付きinvokeラッパー
  */
@@ -3788,198 +5204,51 @@ function invokeWithTimeout(cmd, args, timeoutMs = 20000) {
 }
 
 /**
- * kintone Apps 354/514 + ローカルスケジュールからバランスデータを取得
+ * 需給バランスの予測を取得（在庫 App 354 / 出荷予定 App 514 / ローカルの生産予定をRust側で集計）
  */
 async function fetchBalanceData() {
     const statusEl = document.getElementById('balance-status');
-    const contentEl = document.getElementById('balance-content');
     const updateStatus = (msg, color) => {
         if (statusEl) { statusEl.textContent = msg; statusEl.style.color = color || '#888'; }
     };
-    updateStatus('在庫データ取得中 (App 354)...');
+    updateStatus('在庫・出荷予定データ取得中 (App 354 / 514)...');
 
     try {
-        // 1. 在庫データ取得 (App 354)
-        console.log('[Balance] 在庫データ取得開始 (App 354)');
-        let invResponse;
-        try {
-            invResponse = await invokeWithTimeout('fetch_kintone_records', {
-                appName: 'yamazumi',
-                query: '山状況 in ("出荷待ち", "一部出荷済") order by $id asc limit 500'
-            });
-        } catch (e) {
-            console.error('[Balance] invoke失敗(354):', e);
-            invResponse = { success: false, error: String(e.message || e) };
-        }
-        console.log('[Balance] 在庫レスポンス:', invResponse.success, invResponse.error || '',
-            invResponse.data && invResponse.data.records ? invResponse.data.records.length + '件' : 'データなし');
-
-        if (!invResponse.success) {
-            const errMsg = '在庫データ取得エラー(354): ' + (invResponse.error || '不明なエラー');
-            console.error('[Balance]', errMsg);
-            updateStatus(errMsg, '#ff9500');
-        }
-
-        // 2. 出荷予定データ取得 (App 514)
-        updateStatus('出荷予定データ取得中 (App 514)...');
-        console.log('[Balance] 出荷予定データ取得開始 (App 514)');
-        let shipResponse;
-        try {
-            shipResponse = await invokeWithTimeout('fetch_kintone_records', {
-                appName: 'tsumikomi',
-                query: 'order by planDate desc limit 10'
-            });
-        } catch (e) {
-            console.error('[Balance] invoke失敗(514):', e);
-            shipResponse = { success: false, error: String(e.message || e) };
-        }
-        console.log('[Balance] 出荷レスポンス:', shipResponse.success, shipResponse.error || '',
-            shipResponse.data && shipResponse.data.records ? shipResponse.data.records.length + '件' : 'データなし');
-
-        if (!shipResponse.success) {
-            const errMsg = '出荷予定データ取得エラー(514): ' + (shipResponse.error || '不明なエラー');
-            console.error('[Balance]', errMsg);
-            updateStatus(errMsg, '#ff9500');
-        }
-
-        updateStatus('データ集計中...');
-
-        // 3. 生産データ = ローカルschedules（loadSchedulesで取得済み）
-        const today = new Date();
-        today.setHours(0, 0, 0, 0);
-
-        // === 在庫集計 ===
-        const inventory = {};
-        BALANCE_PRODUCTS.forEach(p => inventory[p] = 0);
-
-        if (invResponse.success && invResponse.data && invResponse.data.records) {
-            invResponse.data.records.forEach(r => {
-                const product = (r['品番'] && r['品番'].value || '').trim();
-                const status = r['山状況'] && r['山状況'].value;
-                let qty = 0;
-                if (status === '一部出荷済') {
-                    qty = parseInt(r['総個数_数値'] && r['総個数_数値'].value || 0) || 0;
-                } else {
-                    qty = parseInt(r['総個数'] && r['総個数'].value || 0) || 0;
-                }
-                // 製品マッチング
-                BALANCE_PRODUCTS.forEach(bp => {
-                    if (product === bp || product.indexOf(bp) >= 0) {
-                        inventory[bp] += qty;
-                    }
-                });
-            });
-        }
-
-        // === 出荷予定集計 ===
-        const shipping = {};
-        BALANCE_PRODUCTS.forEach(p => shipping[p] = new Array(14).fill(0));
-
-        if (shipResponse.success && shipResponse.data && shipResponse.data.records) {
-            shipResponse.data.records.forEach(record => {
-                const pd = record['planDate'] && record['planDate'].value;
-                if (!pd) return;
-                const planDate = new Date(pd + 'T00:00:00');
-                if (isNaN(planDate.getTime())) return;
-
-                const jsonStr = record['scheduleJson'] && record['scheduleJson'].value;
-                if (!jsonStr) return;
-
-                try {
-                    const parsed = JSON.parse(jsonStr);
-                    if (!parsed || !Array.isArray(parsed.rows)) return;
-
-                    parsed.rows.forEach(row => {
-                        if (row.group === '日本ロック') return;
-                        const item = (row.item || '').trim();
-                        if (!item) return;
-
-                        let productCode = null;
-                        for (const [key, code] of Object.entries(SHIPPING_PRODUCT_MAP)) {
-                            if (item === key || item.indexOf(key) >= 0) {
-                                productCode = code;
-                                break;
-                            }
-                        }
-                        if (!productCode || !shipping[productCode]) return;
-
-                        const values = Array.isArray(row.values) ? row.values : [];
-                        for (let col = 0; col < 7 && col < values.length; col++) {
-                            const dayDate = new Date(planDate);
-                            dayDate.setDate(dayDate.getDate() + col);
-                            const diffDays = Math.floor((dayDate - today) / 86400000);
-                            if (diffDays >= 0 && diffDays < 14) {
-                                shipping[productCode][diffDays] += extractShippingTotal(values[col]);
-                            }
-                        }
-                    });
-                } catch (e) {
-                    console.error('[Balance] scheduleJson解析エラー:', e);
-                }
-            });
+        const response = await invokeWithTimeout('get_balance_projection', { days: 14 }, 40000);
+        if (!response.success) {
+            throw new Error(response.error || '不明なエラー');
         }
 
-        // === 生産予定集計 ===
-        const production = {};
-        BALANCE_PRODUCTS.forEach(p => production[p] = new Array(14).fill(0));
-
-        schedules.forEach(s => {
-            const product = s.product_name;
-            if (!BALANCE_PRODUCTS.includes(product)) return;
-            // 完了済みは在庫に反映済みなのでスキップ
-            if (s.production_status === '完了') return;
-
-            const startDate = new Date(s.start_datetime);
-            if (isNaN(startDate.getTime())) return;
-            const diffDays = Math.floor((startDate - today) / 86400000);
-
-            if (diffDays >= 0 && diffDays < 14) {
-                const qty = s.total_quantity || s.quantity1 || 0;
-                production[product][diffDays] += qty;
-            }
-        });
-
-        // === 日付ラベル生成 ===
+        const projection = response.data;
         const dayNames = ['日', '月', '火', '水', '木', '金', '土'];
-        const dates = [];
-        for (let i = 0; i < 14; i++) {
-            const d = new Date(today);
-            d.setDate(d.getDate() + i);
-            dates.push({
+        const dates = (projection.products[0] ? projection.products[0].days : []).map((day, i) => {
+            const d = new Date(day.date + 'T00:00:00');
+            return {
                 date: d,
                 label: `${d.getMonth()+1}/${d.getDate()}`,
                 dayName: dayNames[d.getDay()],
                 isToday: i === 0,
                 isWeekend: d.getDay() === 0 || d.getDay() === 6
-            });
-        }
+            };
+        });
 
-        balanceData = { inventory, production, shipping, dates };
+        balanceData = { products: projection.products, dates };
 
-        // 集計結果サマリー
-        const invCount = invResponse.success && invResponse.data && invResponse.data.records ? invResponse.data.records.length : 0;
-        const shipCount = shipResponse.success && shipResponse.data && shipResponse.data.records ? shipResponse.data.records.length : 0;
-        const prodCount = schedules.filter(s => BALANCE_PRODUCTS.includes(s.product_name)).length;
-        const totalInv = Object.values(inventory).reduce((a, b) => a + b, 0);
-        console.log(`[Balance] 集計完了: 在庫${invCount}件(計${totalInv}個), 出荷${shipCount}件, 生産${prodCount}件`);
+        const { inventory_records: invCount, shipping_records: shipCount, production_schedules: prodCount, warnings } = projection;
+        console.log(`[Balance] 集計完了: 在庫${invCount}件, 出荷${shipCount}件, 生産${prodCount}件`, warnings);
 
-        if (statusEl) {
-            if (invCount === 0 && shipCount === 0 && prodCount === 0) {
-                statusEl.textContent = '⚠️ データが見つかりません（設定を確認してください）';
-                statusEl.style.color = '#ff9500';
-            } else {
-                statusEl.textContent = `✅ 取得完了 (在庫:${invCount}件 出荷:${shipCount}件 生産:${prodCount}件)`;
-                statusEl.style.color = '#34c759';
-            }
+        if (warnings.length > 0) {
+            updateStatus('⚠️ ' + warnings.join(' / '), '#ff9500');
+        } else if (invCount === 0 && shipCount === 0 && prodCount === 0) {
+            updateStatus('⚠️ データが見つかりません（設定を確認してください）', '#ff9500');
+        } else {
+            updateStatus(`✅ 取得完了 (在庫:${invCount}件 出荷:${shipCount}件 生産:${prodCount}件)`, '#34c759');
         }
         return balanceData;
 
     } catch (error) {
         console.error('[Balance] データ取得エラー:', error);
-        if (statusEl) {
-            statusEl.textContent = 'エラー: ' + (error.message || error);
-            statusEl.style.color = '#ff3b30';
-        }
+        updateStatus('エラー: ' + (error.message || error), '#ff3b30');
         throw error;
     }
 }
@@ -4015,7 +5284,7 @@ async function renderBalanceView() {
         }
 
         console.log('[Balance] レンダリング開始');
-        const { inventory, production, shipping, dates } = balanceData;
+        const { products, dates } = balanceData;
 
         let html = '<table class="balance-table">';
 
@@ -4031,28 +5300,24 @@ async function renderBalanceView() {
 
         // ===== ボディ =====
         html += '<tbody>';
-        BALANCE_PRODUCTS.forEach(product => {
-            const inv = inventory[product] || 0;
-            let runningBalance = inv;
-
+        products.forEach(({ product, opening_stock: inv, days }) => {
             html += '<tr class="balance-row">';
             html += `<td class="balance-product-col"><strong>${product}</strong></td>`;
             html += `<td class="balance-inv-col">${inv.toLocaleString()}</td>`;
 
-            dates.forEach((d, i) => {
-                const prod = production[product][i] || 0;
-                const ship = shipping[product][i] || 0;
-                runningBalance += prod - ship;
+            days.forEach((day, i) => {
+                const d = dates[i];
+                const { production: prod, shipping: ship, closing } = day;
 
                 const dayCls = d.isToday ? 'balance-today' : (d.isWeekend ? 'balance-weekend' : '');
-                const valCls = runningBalance < 0 ? 'balance-negative'
-                    : (runningBalance < inv * 0.3 ? 'balance-warning' : 'balance-positive');
+                const valCls = closing < 0 ? 'balance-negative'
+                    : (closing < inv * 0.3 ? 'balance-warning' : 'balance-positive');
 
-                const tooltip = `${product} ${d.label}(${d.dayName})\n在庫: ${inv}\n生産: +${prod}\n出荷: -${ship}\n残: ${runningBalance}`;
+                const tooltip = `${product} ${d.label}(${d.dayName})\n日初: ${day.opening}\n生産: +${prod}\n出荷: -${ship}\n残: ${closing}`;
 
                 html += `<td class="${dayCls} ${valCls}" title="${tooltip}">`;
                 html += '<div class="balance-cell">';
-                html += `<span class="balance-val">${runningBalance.toLocaleString()}</span>`;
+                html += `<span class="balance-val">${closing.toLocaleString()}</span>`;
                 if (prod > 0 || ship > 0) {
                     html += '<span class="balance-detail">';
                     if (prod > 0) html += `<span class="balance-prod">+${prod}</span>`;